@@ -0,0 +1,143 @@
+// evaluation benchmarks, run with `cargo bench --bench interpreter`
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ruschm::{
+    interpreter::Interpreter,
+    values::{ArgVec, Number, Value},
+};
+
+fn interpreter(definitions: &str) -> Interpreter<'static, f32> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval_str(definitions).unwrap();
+    interpreter
+}
+
+fn lookup(c: &mut Criterion) {
+    let definitions = "(define x 0)".to_string() + &"(define x (+ x 1))".repeat(100000);
+    let mut it = interpreter("");
+    c.bench_function("100000 top level definitions", |b| {
+        b.iter(|| {
+            it.eval_str(&definitions).unwrap();
+            assert_eq!(
+                it.eval_str("x").unwrap(),
+                Some(Value::Number(Number::Integer(100000)))
+            );
+        })
+    });
+
+    let mut it = interpreter(
+        "
+        (define x 0)
+        (define (loop n)
+            (if (> n 0)
+                ((lambda ()
+                    (set! x (+ x 1))
+                    (loop (- n 1))))))",
+    );
+    c.bench_function("300000 iterations of a loop", |b| {
+        b.iter(|| it.eval_str("(loop 300000)").unwrap())
+    });
+}
+
+fn fib(c: &mut Criterion) {
+    let mut it = interpreter("(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))");
+    c.bench_function("(fib 25)", |b| {
+        b.iter(|| {
+            assert_eq!(
+                it.eval_str("(fib 25)").unwrap(),
+                Some(Value::Number(Number::Integer(75025)))
+            )
+        })
+    });
+}
+
+fn vector(c: &mut Criterion) {
+    let mut it = interpreter(
+        "
+        (define v (make-vector 1000 1))
+        (define (sum-vector v)
+            (define (loop i sum)
+                (if (< i (vector-length v))
+                    (loop (+ i 1) (+ sum (vector-ref v i)))
+                    sum))
+            (loop 0 0))
+        (define (repeat n total)
+            (if (> n 0)
+                (repeat (- n 1) (+ total (sum-vector v)))
+                total))",
+    );
+    c.bench_function("200 sums of a 1000 elements vector", |b| {
+        b.iter(|| {
+            assert_eq!(
+                it.eval_str("(repeat 200 0)").unwrap(),
+                Some(Value::Number(Number::Integer(200000)))
+            )
+        })
+    });
+}
+
+fn closure(c: &mut Criterion) {
+    let body = "(set! x (+ x 1))".repeat(50);
+    let mut it = interpreter(&format!(
+        "
+        (define (make-procedure x) (lambda () {} x))
+        (define (loop n sum)
+            (if (> n 0)
+                (loop (- n 1) (+ sum ((make-procedure 0))))
+                sum))",
+        body
+    ));
+    c.bench_function("10000 closures of a procedure with a large body", |b| {
+        b.iter(|| {
+            assert_eq!(
+                it.eval_str("(loop 10000 0)").unwrap(),
+                Some(Value::Number(Number::Integer(500000)))
+            )
+        })
+    });
+}
+
+fn vector_ref(c: &mut Criterion) {
+    let mut it = interpreter(
+        "
+        (define v (vector \"a string\" 2 3))
+        (define (loop n)
+            (if (> n 0)
+                (begin
+                    (vector-ref v 0)
+                    (loop (- n 1)))))",
+    );
+    c.bench_function("1000000 calls of (vector-ref v 0)", |b| {
+        b.iter(|| it.eval_str("(loop 1000000)").unwrap())
+    });
+}
+
+// arguments are passed in an ArgVec, which holds up to 4 of them without allocating
+fn arguments(c: &mut Criterion) {
+    let mut it = interpreter("");
+    let plus = match it.eval_str("+").unwrap() {
+        Some(Value::Procedure(plus)) => plus,
+        other => panic!("+ is {:?}", other),
+    };
+    let env = it.environment().clone();
+    let mut group = c.benchmark_group("apply + to an ArgVec");
+    // unlike the programs above, an iteration is a single application
+    group.sample_size(100);
+    for count in [1, 4, 5, 8].iter().copied() {
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| {
+                let arguments = (0..count).map(Value::from).collect::<ArgVec<f32>>();
+                assert_eq!(arguments.spilled(), count > 4);
+                Interpreter::apply_procedure(&plus, arguments, &env).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    // each iteration evaluates a whole program
+    config = Criterion::default().sample_size(10);
+    targets = lookup, fib, vector, closure, vector_ref, arguments
+}
+criterion_main!(benches);
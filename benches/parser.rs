@@ -0,0 +1,30 @@
+// parser benchmarks, run with `cargo bench --bench parser`
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ruschm::parser::{Lexer, Parser};
+
+fn parse(c: &mut Criterion) {
+    let unit = "(define (f-{n} x . rest)
+    (define y '(a \"b\" #\\c 1.5 #(1 2 3)))
+    (if (> x {n}) (set! x (- x 1)) (f-{n} (+ x 1) 'symbol #(x y \"z\")))
+    (let ((z (* x 2)) (w rest)) (cond ((null? w) z) (else (car w)))))
+";
+    let source = (0..20000)
+        .map(|n| unit.replace("{n}", &n.to_string()))
+        .collect::<String>();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.sample_size(10);
+    group.bench_function("20000 definitions", |b| {
+        b.iter(|| {
+            let count = Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+                .len();
+            assert_eq!(count, 20000);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
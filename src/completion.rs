@@ -0,0 +1,255 @@
+//! Identifier completion, shared by the REPL and by editors embedding the interpreter.
+//!
+//! Candidates are the bindings visible in an environment, the names exported by `(scheme base)`
+//! and the keywords of special forms. Bindings made by the user come first, then the others,
+//! each group sorted by name. Names starting with `%` are internal to the interpreter and never
+//! completed.
+use crate::environment::Environment;
+use crate::interpreter::Interpreter;
+use crate::parser::{syntactic_keywords, ExportSpec, Lexer, LibraryDeclaration, Parser, Statement};
+use crate::values::{Procedure, RealNumberInternalTrait, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Procedure,
+    Syntax,
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub name: String,
+    pub kind: CompletionKind,
+    /// `(name parameter ...)` for procedures whose parameters are known
+    pub signature: Option<String>,
+    /// bound by the user, not by the interpreter or a library
+    pub user: bool,
+}
+
+impl fmt::Display for Completion {
+    /// the signature if there is one, otherwise the name
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.signature {
+            Some(signature) => write!(f, "{}", signature),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+thread_local! {
+    static BASE_NAMES: Vec<(String, CompletionKind)> = base_library_names();
+}
+
+// names exported by (scheme base) and the kinds of their bindings, read from an interpreter
+// importing it
+fn base_library_names() -> Vec<(String, CompletionKind)> {
+    let base = Interpreter::<f32>::new_with_stdlib();
+    let source = include_str!("interpreter/library/include/scheme/base.sld");
+    Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+        .filter_map(|statement| match statement {
+            Ok(Statement::LibraryDefinition(library)) => Some(library.data.1),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|declaration| match declaration.data {
+            LibraryDeclaration::Export(specs) => Some(specs),
+            _ => None,
+        })
+        .flatten()
+        .map(|spec| match spec.data {
+            ExportSpec::Direct(name) | ExportSpec::Rename(_, name) => name,
+        })
+        .map(|name| {
+            let kind = match base.environment().get(name.as_str()).as_deref() {
+                Some(value) => kind(value),
+                None => CompletionKind::Variable,
+            };
+            (name, kind)
+        })
+        .collect()
+}
+
+fn kind<R: RealNumberInternalTrait>(value: &Value<R>) -> CompletionKind {
+    match value {
+        Value::Procedure(_) => CompletionKind::Procedure,
+        Value::Transformer(_) => CompletionKind::Syntax,
+        _ => CompletionKind::Variable,
+    }
+}
+
+fn top_level<R: RealNumberInternalTrait>(env: &Environment<R>) -> &Environment<R> {
+    let mut env = env;
+    while let Some(parent) = env.parent() {
+        env = parent;
+    }
+    env
+}
+
+// a binding of `env`, procedures defined by libraries are closed over their own top level,
+// other bindings are made by the user unless they are `known` keywords or names of (scheme base)
+fn binding<R: RealNumberInternalTrait>(
+    name: String,
+    value: &Value<R>,
+    env: &Environment<R>,
+    known: bool,
+) -> Completion {
+    let (signature, user) = match value {
+        Value::Procedure(procedure) => (
+            match procedure {
+                Procedure::Parameter(_) => None,
+                _ => Some(procedure.signature()),
+            },
+            match procedure {
+                Procedure::User(_, closure) => std::ptr::eq(top_level(closure), top_level(env)),
+                Procedure::Builtin(_) => false,
+                Procedure::Parameter(_) => !known,
+            },
+        ),
+        _ => (None, !known),
+    };
+    Completion {
+        name,
+        kind: kind(value),
+        signature,
+        user,
+    }
+}
+
+/// the identifiers starting with `prefix` which can be used in `env`: bindings made by the user
+/// first, then builtin procedures, library bindings, `(scheme base)` names and syntactic
+/// keywords, each group sorted by name
+pub fn complete<R: RealNumberInternalTrait>(prefix: &str, env: &Environment<R>) -> Vec<Completion> {
+    let mut completions = BTreeMap::new();
+    for keyword in syntactic_keywords() {
+        completions.insert(
+            keyword.clone(),
+            Completion {
+                name: keyword,
+                kind: CompletionKind::Syntax,
+                signature: None,
+                user: false,
+            },
+        );
+    }
+    BASE_NAMES.with(|names| {
+        for (name, kind) in names {
+            completions.insert(
+                name.clone(),
+                Completion {
+                    name: name.clone(),
+                    kind: *kind,
+                    signature: None,
+                    user: false,
+                },
+            );
+        }
+    });
+    // bindings shadow keywords and names of (scheme base) which are not imported
+    for (name, value) in env.bindings() {
+        let known = completions.contains_key(&name);
+        completions.insert(name.clone(), binding(name, &value, env, known));
+    }
+    let (mut user, builtin): (Vec<_>, Vec<_>) = completions
+        .into_values()
+        .filter(|completion| {
+            completion.name.starts_with(prefix) && !completion.name.starts_with('%')
+        })
+        .partition(|completion| completion.user);
+    user.extend(builtin);
+    user
+}
+
+#[test]
+fn completions() -> Result<(), crate::error::SchemeError> {
+    use crate::interpreter::Interpreter;
+    use crate::values::Number;
+    use std::rc::Rc;
+
+    let top = Rc::new(Environment::<f32>::new());
+    top.define(
+        "cadr",
+        Value::Procedure(Procedure::new_builtin_pure(
+            "cadr".to_string(),
+            param_fixed!["pair"],
+            |_| Ok(Value::Void),
+        )),
+    );
+    top.define("cadence", Value::Number(Number::Integer(4)));
+    let local = Environment::new_child(top);
+    local.define("cadmium", Value::Boolean(true));
+    let variable = |name: &str| Completion {
+        name: name.to_string(),
+        kind: CompletionKind::Variable,
+        signature: None,
+        user: true,
+    };
+    let base = |name: &str| Completion {
+        name: name.to_string(),
+        kind: CompletionKind::Procedure,
+        signature: None,
+        user: false,
+    };
+    assert_eq!(
+        complete("cad", &local),
+        [
+            variable("cadence"),
+            variable("cadmium"),
+            base("cadar"),
+            base("caddr"),
+            Completion {
+                signature: Some("(cadr pair)".to_string()),
+                ..base("cadr")
+            },
+        ]
+    );
+    assert!(
+        complete("lambda", &local)
+            .iter()
+            .any(|completion| completion.name == "lambda"
+                && completion.kind == CompletionKind::Syntax)
+    );
+    assert_eq!(complete("no-such-", &local), []);
+
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str(
+        "(define (letter c) c)
+         (define letters '(a b))",
+    )?;
+    let completions = complete("let", &it.env);
+    assert_eq!(
+        completions[..2],
+        [
+            Completion {
+                name: "letter".to_string(),
+                kind: CompletionKind::Procedure,
+                signature: Some("(letter c)".to_string()),
+                user: true,
+            },
+            variable("letters"),
+        ]
+    );
+    assert!(completions[2..]
+        .iter()
+        .all(|completion| !completion.user && completion.kind == CompletionKind::Syntax));
+    assert!(completions[2..]
+        .windows(2)
+        .all(|pair| pair[0].name < pair[1].name));
+    // the names bound by the interpreter for its own use are not completed
+    assert_eq!(complete("%", &it.env), []);
+    // names of (scheme base) which are not imported have the kind of their binding
+    assert_eq!(
+        complete("current-output", &local)[0].kind,
+        complete("current-output", &it.env)[0].kind
+    );
+    // procedures of libraries written in scheme are not bound by the user
+    assert_eq!(
+        complete("caar", &it.env),
+        [Completion {
+            signature: Some("(caar x)".to_string()),
+            ..base("caar")
+        }]
+    );
+    Ok(())
+}
@@ -1,77 +1,267 @@
 #[cfg(test)]
 use crate::interpreter::Interpreter;
+use crate::symbol::{BuildSymbolHasher, Symbol};
 use crate::values::Value;
 use crate::{error::*, interpreter::error::LogicError};
 use cell::{Ref, RefCell, RefMut, RefVal};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 #[cfg(test)]
 use std::error::Error;
 use std::rc::Rc;
 
-pub type DefinitionIter<'a, V> = Box<dyn 'a + Iterator<Item = (&'a String, &'a V)>>;
+pub type DefinitionIter<'a, V> = Box<dyn 'a + Iterator<Item = (&'a Symbol, &'a V)>>;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Definitions<V> {
+    // bindings of a procedure frame, in the order lexical addresses refer to them, the names are
+    // shared by every frame of the procedure
+    names: Rc<[Symbol]>,
+    slots: Vec<V>,
+    // bindings added by define, which is how the top level and library scopes grow
+    named: HashMap<Symbol, V, BuildSymbolHasher>,
+}
+
+impl<V> Definitions<V> {
+    fn new(names: Rc<[Symbol]>, slots: Vec<V>) -> Self {
+        debug_assert_eq!(names.len(), slots.len());
+        Self {
+            names,
+            slots,
+            named: HashMap::default(),
+        }
+    }
+
+    fn position(&self, name: &Symbol) -> Option<usize> {
+        self.names.iter().rposition(|slot| slot == name)
+    }
+
+    fn get(&self, name: &Symbol) -> Option<&V> {
+        match self.position(name) {
+            Some(slot) => Some(&self.slots[slot]),
+            None => self.named.get(name),
+        }
+    }
+
+    fn get_mut(&mut self, name: &Symbol) -> Option<&mut V> {
+        match self.position(name) {
+            Some(slot) => Some(&mut self.slots[slot]),
+            None => self.named.get_mut(name),
+        }
+    }
+
+    fn insert(&mut self, name: Symbol, value: V) -> Option<V> {
+        match self.position(&name) {
+            Some(slot) => Some(std::mem::replace(&mut self.slots[slot], value)),
+            None => self.named.insert(name, value),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Symbol, &V)> {
+        self.names
+            .iter()
+            .zip(self.slots.iter())
+            .chain(self.named.iter())
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct LexicalScope<V> {
     parent: Option<Rc<LexicalScope<V>>>,
-    definitions: RefCell<HashMap<String, V>>,
+    definitions: RefCell<Definitions<V>>,
 }
 
 impl<V> LexicalScope<V> {
     pub fn new() -> Self {
         Self {
             parent: None,
-            definitions: RefCell::new(HashMap::new()),
+            definitions: RefCell::new(Definitions::new(Rc::new([]), Vec::new())),
         }
     }
     pub fn new_child(parent: Rc<LexicalScope<V>>) -> Self {
+        Self::new_frame(parent, Rc::new([]), Vec::new())
+    }
+    /// child scope of a procedure call, slots are addressed by position as well as by the name
+    /// at the same position of `names`
+    pub fn new_frame(parent: Rc<LexicalScope<V>>, names: Rc<[Symbol]>, slots: Vec<V>) -> Self {
         Self {
             parent: Some(parent),
-            definitions: RefCell::new(HashMap::new()),
+            definitions: RefCell::new(Definitions::new(names, slots)),
         }
     }
 
-    pub fn define(&self, name: String, value: V) {
-        self.definitions.borrow_mut().insert(name, value);
+    pub fn define(&self, name: impl Into<Symbol>, value: V) {
+        self.definitions.borrow_mut().insert(name.into(), value);
+    }
+
+    /// like define, returns the value name was bound to in this scope
+    pub fn redefine(&self, name: impl Into<Symbol>, value: V) -> Option<V> {
+        self.definitions.borrow_mut().insert(name.into(), value)
+    }
+
+    pub fn parent(&self) -> Option<&Rc<LexicalScope<V>>> {
+        self.parent.as_ref()
     }
 
-    pub fn get(&self, name: &str) -> Option<Ref<V>> {
-        if self.definitions.borrow().contains_key(name) {
-            Some(Ref::map(self.definitions.borrow(), |definitions| {
+    /// remove every definition of this scope
+    pub fn take_definitions(&self) -> Vec<V> {
+        let mut definitions = self.definitions.borrow_mut();
+        definitions.names = Rc::new([]);
+        let slots = std::mem::take(&mut definitions.slots);
+        slots
+            .into_iter()
+            .chain(definitions.named.drain().map(|(_, value)| value))
+            .collect()
+    }
+
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<Ref<V>> {
+        self.get_defined(&name.into())
+    }
+
+    fn get_defined(&self, name: &Symbol) -> Option<Ref<V>> {
+        let definitions = self.definitions.borrow();
+        if definitions.get(name).is_some() {
+            Some(Ref::map(definitions, |definitions| {
                 definitions.get(name).unwrap()
             }))
         } else {
             match &self.parent {
-                Some(parent) => parent.get(name),
+                Some(parent) => parent.get_defined(name),
                 None => None,
             }
         }
     }
-    pub fn get_mut(&self, name: &str) -> Option<RefMut<V>> {
-        if self.definitions.borrow().contains_key(name) {
-            Some(RefMut::map(self.definitions.borrow_mut(), |definitions| {
+
+    pub fn get_mut(&self, name: impl Into<Symbol>) -> Option<RefMut<V>> {
+        self.get_defined_mut(&name.into())
+    }
+
+    fn get_defined_mut(&self, name: &Symbol) -> Option<RefMut<V>> {
+        let mut definitions = self.definitions.borrow_mut();
+        if definitions.get_mut(name).is_some() {
+            Some(RefMut::map(definitions, |definitions| {
                 definitions.get_mut(name).unwrap()
             }))
         } else {
             match &self.parent {
-                Some(parent) => parent.get_mut(name),
+                Some(parent) => parent.get_defined_mut(name),
                 None => None,
             }
         }
     }
 
-    pub fn set(&self, name: &str, value: V) -> Result<(), SchemeError> {
+    fn frame(&self, depth: usize) -> &Self {
+        let mut scope = self;
+        for _ in 0..depth {
+            scope = scope
+                .parent
+                .as_deref()
+                .expect("lexical address out of the scope chain");
+        }
+        scope
+    }
+
+    /// the binding at a lexical address, which must be resolved against this scope
+    pub fn get_slot(&self, depth: usize, slot: usize) -> Ref<V> {
+        Ref::map(self.frame(depth).definitions.borrow(), |definitions| {
+            &definitions.slots[slot]
+        })
+    }
+
+    /// like replace, for the binding at a lexical address
+    pub fn replace_slot(&self, depth: usize, slot: usize, value: V) -> V {
+        let mut definitions = self.frame(depth).definitions.borrow_mut();
+        std::mem::replace(&mut definitions.slots[slot], value)
+    }
+
+    pub fn set(&self, name: impl Into<Symbol>, value: V) -> Result<(), SchemeError> {
+        self.replace(name, value).map(|_| ())
+    }
+
+    /// like set, returns the value replaced
+    pub fn replace(&self, name: impl Into<Symbol>, value: V) -> Result<V, SchemeError> {
+        let name = name.into();
+        match self.replace_defined(&name, value) {
+            Some(old) => Ok(old),
+            None => Err(ErrorData::Logic(LogicError::UnboundedSymbol(
+                name.to_string(),
+                self.similar_names(&name),
+            ))
+            .no_locate()),
+        }
+    }
+
+    fn replace_defined(&self, name: &Symbol, value: V) -> Option<V> {
         match self.definitions.borrow_mut().get_mut(name) {
             None => match &self.parent {
-                None => {
-                    return Err(
-                        ErrorData::Logic(LogicError::UnboundedSymbol(name.to_string())).no_locate(),
-                    );
-                }
-                Some(parent) => parent.set(name, value)?,
+                None => None,
+                Some(parent) => parent.replace_defined(name, value),
             },
-            Some(variable) => *variable = value,
-        };
-        Ok(())
+            Some(variable) => Some(std::mem::replace(variable, value)),
+        }
+    }
+
+    // names bound in this scope or its ancestors which look like a misspelling of name,
+    // at most 3, prefix matches first, then by edit distance
+    pub fn similar_names(&self, name: &str) -> Vec<String> {
+        // bound the search so that errors stay cheap in huge environments
+        const MAX_CANDIDATES: usize = 4096;
+        let mut candidates = vec![];
+        let mut scanned = 0;
+        let mut scope = Some(self);
+        while let Some(current) = scope {
+            for (candidate, _) in current.definitions.borrow().iter() {
+                if scanned == MAX_CANDIDATES {
+                    break;
+                }
+                scanned += 1;
+                if let Some(rank) = similarity(name, candidate) {
+                    candidates.push((rank, candidate.to_string()));
+                }
+            }
+            scope = current.parent.as_deref();
+        }
+        candidates.sort();
+        candidates.dedup_by(|(_, a), (_, b)| a == b);
+        candidates
+            .into_iter()
+            .take(3)
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+
+    /// names bound in this scope, not in its parents, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self
+            .definitions
+            .borrow()
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// bindings visible in this scope, including those of its parents unless shadowed, sorted by
+    /// name
+    pub fn bindings(&self) -> Vec<(String, V)>
+    where
+        V: Clone,
+    {
+        let mut bindings = BTreeMap::new();
+        let mut scope = Some(self);
+        while let Some(current) = scope {
+            let definitions = current.definitions.borrow();
+            // a later slot of the same name shadows an earlier one
+            let local = definitions.iter().collect::<Vec<_>>();
+            for (name, value) in local.into_iter().rev() {
+                bindings
+                    .entry(name.to_string())
+                    .or_insert_with(|| value.clone());
+            }
+            scope = current.parent.as_deref();
+        }
+        bindings.into_iter().collect()
     }
 
     pub fn iter_local_definitions<'a, 'b: 'a>(&'b self) -> RefVal<'a, DefinitionIter<'b, V>> {
@@ -82,6 +272,50 @@ impl<V> LexicalScope<V> {
     }
 }
 
+// 0 for prefix matches, otherwise the edit distance if it is at most 2 and smaller than the
+// length of `name`
+fn similarity(name: &str, candidate: &str) -> Option<usize> {
+    if name == candidate {
+        return None;
+    }
+    let (shorter, longer) = if name.len() < candidate.len() {
+        (name, candidate)
+    } else {
+        (candidate, name)
+    };
+    if shorter.chars().count() >= 3 && longer.starts_with(shorter) {
+        return Some(0);
+    }
+    let name = name.chars().collect::<Vec<_>>();
+    let candidate = candidate.chars().collect::<Vec<_>>();
+    if (name.len() as isize - candidate.len() as isize).abs() > 2 {
+        return None;
+    }
+    // levenshtein distance
+    let mut previous = (0..=candidate.len()).collect::<Vec<_>>();
+    for (i, a) in name.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in candidate.iter().enumerate() {
+            let substitution = previous[j] + if a == b { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    let distance = previous[candidate.len()];
+    // short names are too easy to confuse with each other
+    let max_distance = if name.len().min(candidate.len()) <= 4 {
+        1
+    } else {
+        2
+    };
+    // a name all characters of which are edited is not a typo of the candidate, like x and +
+    if distance <= max_distance && distance < name.len() {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
 pub type Environment<R> = LexicalScope<Value<R>>;
 
 #[test]
@@ -90,7 +324,7 @@ fn iter_envs() -> Result<(), Box<dyn std::error::Error>> {
     {
         it.env.define("a".to_string(), Value::Void);
     }
-    let env = it.env;
+    let env = it.env.clone();
     {
         let mut definitions = env.iter_local_definitions();
         assert_ne!(definitions.find(|(name, _)| *name == "a"), None);
@@ -103,6 +337,27 @@ fn iter_envs() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+#[test]
+fn bindings() {
+    let parent = Rc::new(Environment::<f32>::new());
+    parent.define("y", Value::from(0));
+    parent.define("x", Value::from(0));
+    let names: Rc<[Symbol]> = vec!["x".into(), "z".into(), "x".into()].into();
+    let frame = Environment::new_frame(parent.clone(), names, vec![1.into(), 2.into(), 3.into()]);
+    frame.define("a", Value::from(4));
+    assert_eq!(frame.names(), vec!["a", "x", "z"]);
+    assert_eq!(parent.names(), vec!["x", "y"]);
+    assert_eq!(
+        frame.bindings(),
+        vec![
+            ("a".to_string(), Value::from(4)),
+            ("x".to_string(), Value::from(3)),
+            ("y".to_string(), Value::from(0)),
+            ("z".to_string(), Value::from(2)),
+        ]
+    );
+}
+
 #[test]
 fn get_mut() -> Result<(), Box<dyn Error>> {
     use crate::values::Number;
@@ -126,3 +381,41 @@ fn get_mut() -> Result<(), Box<dyn Error>> {
     );
     Ok(())
 }
+#[test]
+fn frame() {
+    let parent = Rc::new(Environment::<f32>::new());
+    parent.define("x", Value::from(0));
+    let names: Rc<[Symbol]> = vec!["x".into(), "y".into(), "x".into()].into();
+    let frame = Environment::new_frame(parent, names, vec![1.into(), 2.into(), 3.into()]);
+    // a later slot of the same name shadows an earlier one
+    assert_eq!(*frame.get("x").unwrap(), Value::from(3));
+    assert_eq!(*frame.get_slot(0, 0), Value::from(1));
+    assert_eq!(frame.replace_slot(0, 1, 4.into()), Value::from(2));
+    assert_eq!(*frame.get("y").unwrap(), Value::from(4));
+    frame.set("y", 5.into()).unwrap();
+    assert_eq!(*frame.get_slot(0, 1), Value::from(5));
+    // frames grow by name
+    frame.define("z", Value::from(6));
+    assert_eq!(*frame.get("z").unwrap(), Value::from(6));
+    assert_eq!(frame.iter_local_definitions().by_ref().count(), 4);
+}
+
+#[test]
+fn similar_names() {
+    let parent = Rc::new(Environment::<f32>::new());
+    parent.define("lost-tab".to_string(), Value::Void);
+    parent.define("lisr-ta".to_string(), Value::Void);
+    let env = Environment::new_child(parent);
+    env.define("list-tail".to_string(), Value::Void);
+    env.define("unrelated".to_string(), Value::Void);
+    env.define("+".to_string(), Value::Void);
+    assert_eq!(
+        env.similar_names("list-ta"),
+        vec![
+            "list-tail".to_string(),
+            "lisr-ta".to_string(),
+            "lost-tab".to_string()
+        ]
+    );
+    assert_eq!(env.similar_names("x"), Vec::<String>::new());
+}
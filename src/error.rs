@@ -1,20 +1,136 @@
 use thiserror::Error;
 
 use std::fmt;
-use std::{error::Error, fmt::Debug, ops::Deref, ops::DerefMut};
+use std::{error::Error, fmt::Debug, io, ops::Deref, ops::DerefMut, sync::Arc, sync::Mutex};
 
 use fmt::Display;
 
-use crate::{interpreter::error::LogicError, parser::error::SyntaxError};
+use crate::{
+    interpreter::error::{Backtrace, LogicError},
+    parser::error::SyntaxError,
+};
+
+// names of the sources locations refer to, indexed by source id and never freed
+static SOURCE_TABLE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The file or input a location refers to, like a file path, `<string>` or `<repl-1>`.
+///
+/// Names are kept in a table shared by all threads and registered once, so locations stay small
+/// and `Copy`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceId(u32);
+
+impl SourceId {
+    pub fn new(name: &str) -> Self {
+        let mut table = SOURCE_TABLE.lock().unwrap();
+        match table.iter().position(|registered| registered == name) {
+            Some(index) => Self(index as u32),
+            None => {
+                table.push(name.to_string());
+                Self(table.len() as u32 - 1)
+            }
+        }
+    }
+
+    /// source of programs evaluated from strings
+    pub fn string() -> Self {
+        Self::new("<string>")
+    }
+
+    /// source of the `input`th input of the REPL, counted from 1
+    pub fn repl(input: usize) -> Self {
+        Self::new(&format!("<repl-{}>", input))
+    }
+
+    pub fn name(&self) -> String {
+        SOURCE_TABLE.lock().unwrap()[self.0 as usize].clone()
+    }
+}
+
+impl Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Debug for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+// sources are serialized by name, which is registered again when deserialized
+#[cfg(feature = "serde")]
+impl serde::Serialize for SourceId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SourceId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(deserializer).map(|name| Self::new(&name))
+    }
+}
+
+/// Line and column in a source, both counted from 1. The lexer locates a token at the column
+/// after its last character, `length` is the number of characters of the token on that line,
+/// 0 if it is not known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Location {
+    pub source: SourceId,
+    pub line: u32,
+    pub column: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub length: u32,
+}
+
+impl Location {
+    pub fn new(source: SourceId, [line, column]: [u32; 2]) -> Self {
+        Self {
+            source,
+            line,
+            column,
+            length: 0,
+        }
+    }
+
+    /// the location spanning the `length` characters before it
+    pub fn with_length(self, length: u32) -> Self {
+        Self { length, ..self }
+    }
+
+    /// the column of the first character of the span, which is the last character if the
+    /// length is not known
+    pub fn start_column(&self) -> u32 {
+        self.column.saturating_sub(self.length.max(1)).max(1)
+    }
+}
+
+// a position in a program evaluated from a string
+impl From<[u32; 2]> for Location {
+    fn from(position: [u32; 2]) -> Self {
+        Self::new(SourceId::string(), position)
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source, self.line, self.column)
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Located<T> {
     pub data: T,
-    pub location: Option<[u32; 2]>,
+    pub location: Option<Location>,
 }
 
 pub trait ToLocated {
-    fn locate(self, location: Option<[u32; 2]>) -> Located<Self>
+    fn locate(self, location: Option<Location>) -> Located<Self>
     where
         Self: Sized,
     {
@@ -80,17 +196,403 @@ pub enum ErrorData {
     #[error(transparent)]
     Logic(#[from] LogicError),
     #[error("io error: {0}")]
-    IO(String), // std::io::Error does not implement PartialEq and Clone, so use display message directly
+    IO(IoError),
+    // not an actual error, requested by `exit` or `emergency-exit` and unwinds to the embedder
+    #[error("exit with code {code}")]
+    Exit { code: i32, emergency: bool },
+    #[error("evaluation budget exceeded")]
+    FuelExhausted,
+    #[error("evaluation interrupted")]
+    Interrupted,
+    // raised by `(debug)` when the debugger aborts the evaluation
+    #[error("evaluation aborted in the debugger")]
+    DebugAborted,
+    #[error("recursion deeper than {0} levels")]
+    RecursionTooDeep(usize),
+}
+
+/// An io error, optionally with what was being done when it happened.
+///
+/// `std::io::Error` does not implement `PartialEq` and `Clone`, so it is shared by the clones and
+/// errors of the same kind and message are equal.
+#[derive(Debug, Clone)]
+pub struct IoError {
+    pub context: Option<String>,
+    error: Arc<io::Error>,
+}
+
+impl IoError {
+    pub fn new(context: impl Into<String>, error: io::Error) -> Self {
+        Self {
+            context: Some(context.into()),
+            error: Arc::new(error),
+        }
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        self.error.kind()
+    }
+
+    pub fn io_error(&self) -> &io::Error {
+        &self.error
+    }
+}
+
+impl From<io::Error> for IoError {
+    fn from(error: io::Error) -> Self {
+        Self {
+            context: None,
+            error: Arc::new(error),
+        }
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.context == other.context
+            && self.kind() == other.kind()
+            && self.error.to_string() == other.error.to_string()
+    }
+}
+
+impl Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.context {
+            Some(context) => write!(f, "{}: {}", context, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in a program without evaluating it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// what the problem is, like `unused-parameter`, see `ErrorData::kind` for errors
+    pub kind: &'static str,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl Diagnostic {
+    pub fn warning(
+        kind: &'static str,
+        message: impl Into<String>,
+        location: Option<Location>,
+    ) -> Self {
+        Self {
+            severity: Severity::Warning,
+            kind,
+            message: message.into(),
+            location,
+        }
+    }
+
+    /// The diagnostic as a JSON object on a single line, with the fields
+    /// - `severity`: `"error"` or `"warning"`
+    /// - `kind`: the kind of the problem
+    /// - `message`: the message printed by `Display`, without location and severity
+    /// - `file`: the name of the source, null for diagnostics without location
+    /// - `start` and `end`: objects with `line` and `column`, both 1-based, null without location.
+    ///   `start` is the first character of the span and `end` the position after it, spans of
+    ///   unknown length are the character before the location.
+    ///
+    /// ```
+    /// use ruschm::error::{Diagnostic, Location, SourceId};
+    /// let location = Location::new(SourceId::new("a.scm"), [1, 13]);
+    /// assert_eq!(
+    ///     Diagnostic::warning("unused-parameter", "parameter x is never used", Some(location))
+    ///         .to_json(),
+    ///     r#"{"severity":"warning","kind":"unused-parameter","message":"parameter x is never used","file":"a.scm","start":{"line":1,"column":12},"end":{"line":1,"column":13}}"#
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let position = |line, column| format!("{{\"line\":{},\"column\":{}}}", line, column);
+        let (file, start, end) = match self.location {
+            Some(location) => (
+                json_string(&location.source.name()),
+                position(location.line, location.start_column()),
+                position(location.line, location.column),
+            ),
+            None => ("null".to_string(), "null".to_string(), "null".to_string()),
+        };
+        format!(
+            "{{\"severity\":\"{}\",\"kind\":\"{}\",\"message\":{},\"file\":{},\"start\":{},\"end\":{}}}",
+            self.severity,
+            self.kind,
+            json_string(&self.message),
+            file,
+            start,
+            end
+        )
+    }
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::with_capacity(string.len() + 2);
+    json.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl From<SchemeError> for Diagnostic {
+    fn from(error: SchemeError) -> Self {
+        Self {
+            severity: Severity::Error,
+            kind: error.data.kind(),
+            message: error.data.to_string(),
+            location: error.location,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(location) = self.location {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// An error with where it is raised and the procedure applications it is raised in.
+#[derive(Clone)]
+pub struct SchemeError {
+    pub data: ErrorData,
+    pub location: Option<Location>,
+    /// the applications in progress when the error is raised, the innermost one first, None for
+    /// errors raised outside of procedures
+    pub backtrace: Option<Backtrace>,
+}
+
+impl From<io::Error> for SchemeError {
+    fn from(io_error: io::Error) -> Self {
+        ErrorData::IO(io_error.into()).no_locate()
+    }
+}
+
+impl PartialEq for SchemeError {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl Display for SchemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.data, f)
+    }
 }
 
-pub type SchemeError = Located<ErrorData>;
-impl From<std::io::Error> for SchemeError {
-    fn from(io_error: std::io::Error) -> Self {
-        ErrorData::IO(format!("{}", io_error)).no_locate()
+impl Debug for SchemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchemeError")
+            .field("data", &self.data)
+            .field("location", &self.location)
+            .finish()
     }
 }
 
-impl ToLocated for ErrorData {}
+impl Deref for SchemeError {
+    type Target = ErrorData;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl ErrorData {
+    pub fn locate(self, location: Option<Location>) -> SchemeError {
+        SchemeError {
+            data: self,
+            location,
+            backtrace: None,
+        }
+    }
+
+    pub fn no_locate(self) -> SchemeError {
+        self.locate(None)
+    }
+
+    /// a short name of the variant, reported as the kind of diagnostics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ErrorData::Syntax(_) => "syntax",
+            ErrorData::Logic(_) => "logic",
+            ErrorData::IO(_) => "io",
+            ErrorData::Exit { .. } => "exit",
+            ErrorData::FuelExhausted => "fuel-exhausted",
+            ErrorData::Interrupted => "interrupted",
+            ErrorData::DebugAborted => "debug-aborted",
+            ErrorData::RecursionTooDeep(_) => "recursion-too-deep",
+        }
+    }
+
+    /// whether `guard` can catch the error, requests to stop evaluating always unwind to the
+    /// embedder
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            ErrorData::Exit { .. }
+                | ErrorData::FuelExhausted
+                | ErrorData::Interrupted
+                | ErrorData::DebugAborted
+        )
+    }
+}
+
+impl SchemeError {
+    // the innermost location wins, only errors without location are stamped
+    pub fn or_locate(self, location: Option<Location>) -> Self {
+        match self.location {
+            Some(_) => self,
+            None => Self { location, ..self },
+        }
+    }
+
+    /// the error as a JSON object, see `Diagnostic::to_json`
+    pub fn to_json(&self) -> String {
+        Diagnostic::from(self.clone()).to_json()
+    }
+
+    /// Render the error like rustc does: the message, then the line of `source` it is located at
+    /// with carets under the span of the location. `source` is the text of the source the location refers
+    /// to, errors without location or beyond the source are rendered without the line.
+    ///
+    /// ```
+    /// use ruschm::interpreter::DefaultInterpreter;
+    /// let source = "(define x 1)\n(car x)";
+    /// let error = DefaultInterpreter::new_with_stdlib().eval_str(source).unwrap_err();
+    /// assert_eq!(
+    ///     error.render(source),
+    ///     "error: car: expect a pair, got 1\n --> <string>:2:2\n  |\n2 | (car x)\n  |  ^^^\n"
+    /// );
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        self.render_styled(source, false)
+    }
+
+    /// like `render`, highlighted by ANSI escape sequences for terminals
+    pub fn render_colored(&self, source: &str) -> String {
+        self.render_styled(source, true)
+    }
+
+    fn render_styled(&self, source: &str, colored: bool) -> String {
+        let style = |code: &str, text: &str| match colored {
+            true => format!("\x1b[{}m{}\x1b[0m", code, text),
+            false => text.to_string(),
+        };
+        let mut rendered = format!(
+            "{}: {}\n",
+            style("1;31", "error"),
+            style("1", &self.data.to_string())
+        );
+        let location = match self.location {
+            Some(location) => location,
+            None => return rendered,
+        };
+        let gutter = " ".repeat(location.line.to_string().len());
+        // the column the caret points at, rather than the one after the span
+        rendered.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            style("1;34", "-->"),
+            location.source,
+            location.line,
+            location.start_column()
+        ));
+        let line = match source.lines().nth((location.line as usize).wrapping_sub(1)) {
+            Some(line) => line,
+            None => return rendered,
+        };
+        let (snippet, offset, width) = snippet(
+            line,
+            location.start_column() as usize,
+            location.column as usize,
+        );
+        let bar = style("1;34", "|");
+        rendered.push_str(&format!("{} {}\n", gutter, bar));
+        rendered.push_str(&format!(
+            "{} {} {}\n",
+            style("1;34", &location.line.to_string()),
+            bar,
+            snippet.iter().collect::<String>()
+        ));
+        // tabs are kept so that the caret is aligned however they are displayed
+        let indent = snippet[..offset]
+            .iter()
+            .map(|c| if *c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        rendered.push_str(&format!(
+            "{} {} {}{}\n",
+            gutter,
+            bar,
+            indent,
+            style("1;31", &"^".repeat(width))
+        ));
+        rendered
+    }
+}
+
+// the longest part of a line rendered around the location of an error
+const SNIPPET_WIDTH: usize = 80;
+
+// part of the line rendered, the offset of the span in it and its width, at least 1. Columns are
+// counted in characters from 1, `start` is the one of the first character of the span and `end`
+// the one after its last character, like the lexer locates tokens.
+fn snippet(line: &str, start: usize, end: usize) -> (Vec<char>, usize, usize) {
+    let chars = line.chars().collect::<Vec<_>>();
+    let caret = start.saturating_sub(1).min(chars.len());
+    let width = end
+        .saturating_sub(1)
+        .min(chars.len())
+        .saturating_sub(caret)
+        .max(1);
+    if chars.len() <= SNIPPET_WIDTH {
+        return (chars, caret, width);
+    }
+    let first = caret
+        .saturating_sub(SNIPPET_WIDTH / 2)
+        .min(chars.len() - SNIPPET_WIDTH);
+    let last = first + SNIPPET_WIDTH;
+    let mut snippet = vec![];
+    let mut offset = caret - first;
+    if first > 0 {
+        snippet.extend("...".chars());
+        offset += 3;
+    }
+    snippet.extend_from_slice(&chars[first..last]);
+    if last < chars.len() {
+        snippet.extend("...".chars());
+    }
+    // spans longer than the snippet are cut at its end
+    (snippet, offset, width.min(last - caret))
+}
 
 impl Debug for ErrorData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -98,12 +600,68 @@ impl Debug for ErrorData {
     }
 }
 
+// io errors are the source of the errors they cause, errors of loading a file are caused by the
+// errors of evaluating it
 impl Error for SchemeError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.data)
+        match &self.data {
+            ErrorData::IO(error) | ErrorData::Logic(LogicError::FileError(_, error)) => {
+                Some(error.io_error())
+            }
+            ErrorData::Logic(LogicError::LoadError(_, error)) => Some(error.as_ref()),
+            _ => None,
+        }
     }
 }
 
+#[test]
+fn render() {
+    let error = |line, column| {
+        ErrorData::from(SyntaxError::UnrecognizedToken).locate(Some([line, column].into()))
+    };
+    assert_eq!(
+        error(1, 3).render("(#a)"),
+        "error: syntax error: unrecognized token\n --> <string>:1:2\n  |\n1 | (#a)\n  |  ^\n"
+    );
+    // tabs are kept and characters are counted instead of bytes
+    assert_eq!(
+        error(10, 8).render(&format!("{}\t\"λ\" #a", "\n".repeat(9))),
+        "error: syntax error: unrecognized token\n  --> <string>:10:7\n   |\n10 | \t\"λ\" #a\n   | \t     ^\n"
+    );
+    // long lines are truncated around the location
+    let line = format!("(list {} #a {})", "1 ".repeat(50), "2 ".repeat(50));
+    assert_eq!(
+        error(1, 109).render(&line),
+        format!(
+            "error: syntax error: unrecognized token\n --> <string>:1:108\n  |\n1 | ...{}...\n  | {}^\n",
+            &line[67..147],
+            " ".repeat(43)
+        )
+    );
+    // spans are underlined as a whole, up to the end of the line
+    let span = |column, length| {
+        ErrorData::from(SyntaxError::UnrecognizedToken)
+            .locate(Some(Location::from([1, column]).with_length(length)))
+    };
+    assert_eq!(
+        span(5, 3).render("(car x)"),
+        "error: syntax error: unrecognized token\n --> <string>:1:2\n  |\n1 | (car x)\n  |  ^^^\n"
+    );
+    assert_eq!(
+        span(10, 4).render("(car \"x"),
+        "error: syntax error: unrecognized token\n --> <string>:1:6\n  |\n1 | (car \"x\n  |      ^^\n"
+    );
+    // locations beyond the source and errors without location
+    assert_eq!(
+        error(2, 1).render("(#a)"),
+        "error: syntax error: unrecognized token\n --> <string>:2:1\n"
+    );
+    assert_eq!(
+        ErrorData::Interrupted.no_locate().render_colored(""),
+        "\x1b[1;31merror\x1b[0m: \x1b[1mevaluation interrupted\x1b[0m\n"
+    );
+}
+
 #[cfg(test)]
 pub(crate) fn convert_located<T>(datas: Vec<T>) -> Vec<Located<T>> {
     datas.into_iter().map(|d| Located::from(d)).collect()
@@ -111,12 +669,38 @@ pub(crate) fn convert_located<T>(datas: Vec<T>) -> Vec<Located<T>> {
 
 macro_rules! error {
     ($arg:expr) => {
-        Err(ErrorData::from($arg).no_locate());
+        Err(ErrorData::from($arg).no_locate())
     };
 }
 
 macro_rules! located_error {
     ($arg:expr, $loc:expr) => {
-        Err(ErrorData::from($arg).locate($loc));
+        Err(ErrorData::from($arg).locate($loc))
     };
 }
+
+#[test]
+fn diagnostics_json() {
+    let error = ErrorData::from(SyntaxError::UnknownEscape('q')).locate(Some(Location::new(
+        SourceId::new("dir/a \"b\".scm"),
+        [3, 7],
+    )));
+    assert_eq!(
+        error.to_json(),
+        r#"{"severity":"error","kind":"syntax","message":"syntax error: unknown escape character","file":"dir/a \"b\".scm","start":{"line":3,"column":6},"end":{"line":3,"column":7}}"#
+    );
+    // spans of known length start at their first character
+    let error = ErrorData::from(SyntaxError::UnrecognizedToken)
+        .locate(Some(Location::from([1, 5]).with_length(3)));
+    assert!(error
+        .to_json()
+        .ends_with(r#""start":{"line":1,"column":2},"end":{"line":1,"column":5}}"#));
+    assert_eq!(
+        ErrorData::FuelExhausted.no_locate().to_json(),
+        r#"{"severity":"error","kind":"fuel-exhausted","message":"evaluation budget exceeded","file":null,"start":null,"end":null}"#
+    );
+    assert_eq!(
+        Diagnostic::warning("unused-parameter", "tab\tand\u{1}", None).to_json(),
+        r#"{"severity":"warning","kind":"unused-parameter","message":"tab\tand\u0001","file":null,"start":null,"end":null}"#
+    );
+}
@@ -0,0 +1,363 @@
+use crate::{
+    error::*,
+    io::split_shebang,
+    parser::{error::SyntaxError, written_symbol, Lexer, Token, TokenData},
+    stack,
+};
+
+type Result<T> = std::result::Result<T, SchemeError>;
+
+/// the width lines are kept within by `format_source`, unless a single token is longer
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Reformat scheme source with `DEFAULT_WIDTH`, see `format_source_with_width`.
+///
+/// ```
+/// assert_eq!(
+///     ruschm::format_source("(define (square x)\n(* x x))   ; squared\n")?,
+///     "(define (square x)\n  (* x x)) ; squared\n"
+/// );
+/// # Ok::<(), ruschm::error::SchemeError>(())
+/// ```
+pub fn format_source(source: &str) -> Result<String> {
+    format_source_with_width(source, DEFAULT_WIDTH)
+}
+
+/// Reformat scheme source, keeping its comments and the blank lines separating forms:
+/// - lists written on one line stay on one line if they fit within `width`
+/// - the bodies of special forms like `define`, `lambda` and `let` are indented by 2 columns
+///   after the parts on the first line, like the name and the parameters of `define`
+/// - the arguments of other calls are aligned under the first one
+/// - the elements of lists not starting with a symbol are aligned under the first one
+/// - consecutive atoms in calls and data are packed on a line while they fit, instead of being put
+///   on a line each
+///
+/// Only the tokens are checked, forms like `(if)` are formatted as they are. Formatting is
+/// idempotent: formatting the result again does not change it.
+pub fn format_source_with_width(source: &str, width: usize) -> Result<String> {
+    // the interpreter directive of a script is kept as it is
+    let (shebang, source) = split_shebang(source);
+    let mut lexer = Lexer::from_char_stream(source.chars());
+    lexer.set_keep_comments(true);
+    let mut reader = Reader { tokens: lexer };
+    let mut nodes = vec![];
+    while let Some(token) = reader.tokens.next() {
+        reader.node(token?, &mut nodes)?;
+    }
+    let mut printer = Printer {
+        width,
+        output: String::new(),
+    };
+    printer.items(&nodes, 0, 0, false);
+    if !printer.output.is_empty() {
+        printer.output.push('\n');
+    }
+    Ok(match shebang {
+        "" => printer.output,
+        shebang => format!("{}\n{}", shebang, printer.output),
+    })
+}
+
+enum NodeKind {
+    Atom(String),
+    Comment(String),
+    // the opening parenthesis, like "(" or "#("
+    List(&'static str, Vec<Node>),
+    // a quote or one of its abbreviations
+    Prefix(&'static str, Box<Node>),
+}
+
+struct Node {
+    kind: NodeKind,
+    line: u32,
+    end_line: u32,
+}
+
+impl Node {
+    fn is_comment(&self) -> bool {
+        matches!(self.kind, NodeKind::Comment(_))
+    }
+
+    // None if the node contains comments, which end lines
+    fn flat(&self) -> Option<String> {
+        stack::guard(|| match &self.kind {
+            NodeKind::Atom(text) => Some(text.clone()),
+            NodeKind::Comment(_) => None,
+            NodeKind::List(open, items) => Some(format!(
+                "{}{})",
+                open,
+                items
+                    .iter()
+                    .map(Node::flat)
+                    .collect::<Option<Vec<_>>>()?
+                    .join(" ")
+            )),
+            NodeKind::Prefix(prefix, inner) => Some(format!("{}{}", prefix, inner.flat()?)),
+        })
+    }
+}
+
+fn line(token: &Token) -> u32 {
+    token.location.map_or(0, |location| location.line)
+}
+
+struct Reader<TokenIter: Iterator<Item = Result<Token>>> {
+    tokens: TokenIter,
+}
+
+impl<TokenIter: Iterator<Item = Result<Token>>> Reader<TokenIter> {
+    fn next_token(&mut self, location: Option<Location>) -> Result<Token> {
+        match self.tokens.next() {
+            Some(token) => token,
+            None => located_error!(SyntaxError::UnexpectedEnd, location),
+        }
+    }
+
+    // comments between a quote and the quoted datum are moved before the quote
+    fn node(&mut self, token: Token, nodes: &mut Vec<Node>) -> Result<()> {
+        stack::guard(|| {
+            let line = line(&token);
+            let kind = match token.data {
+                TokenData::LeftParen => return self.list("(", token, nodes),
+                TokenData::VecConsIntro => return self.list("#(", token, nodes),
+                TokenData::ByteVecConsIntro => return self.list("#u8(", token, nodes),
+                TokenData::RightParen => {
+                    return located_error!(SyntaxError::UnmatchedParentheses, token.location)
+                }
+                TokenData::Quote => return self.prefix("'", token, nodes),
+                TokenData::Quasiquote => return self.prefix("`", token, nodes),
+                TokenData::Unquote => return self.prefix(",", token, nodes),
+                TokenData::UnquoteSplicing => return self.prefix(",@", token, nodes),
+                TokenData::Period => NodeKind::Atom(".".to_string()),
+                TokenData::Comment(comment) => NodeKind::Comment(comment.trim_end().to_string()),
+                TokenData::Identifier(symbol) => NodeKind::Atom(written_symbol(&symbol)),
+                TokenData::Primitive(primitive) => NodeKind::Atom(primitive.written()),
+            };
+            nodes.push(Node {
+                kind,
+                line,
+                end_line: line,
+            });
+            Ok(())
+        })
+    }
+
+    fn list(&mut self, open: &'static str, token: Token, nodes: &mut Vec<Node>) -> Result<()> {
+        let mut items = vec![];
+        loop {
+            let next = self.next_token(token.location)?;
+            if next.data == TokenData::RightParen {
+                nodes.push(Node {
+                    kind: NodeKind::List(open, items),
+                    line: line(&token),
+                    end_line: line(&next),
+                });
+                break Ok(());
+            }
+            self.node(next, &mut items)?;
+        }
+    }
+
+    fn prefix(&mut self, prefix: &'static str, token: Token, nodes: &mut Vec<Node>) -> Result<()> {
+        let mut quoted = vec![];
+        while quoted.last().map_or(true, Node::is_comment) {
+            let next = self.next_token(token.location)?;
+            self.node(next, &mut quoted)?;
+        }
+        let inner = quoted.pop().unwrap();
+        nodes.extend(quoted);
+        nodes.push(Node {
+            line: line(&token),
+            end_line: inner.end_line,
+            kind: NodeKind::Prefix(prefix, Box::new(inner)),
+        });
+        Ok(())
+    }
+}
+
+// special forms whose bodies are indented by 2 columns, with the number of parts before the body
+fn body_form(keyword: &str) -> Option<usize> {
+    Some(match keyword {
+        "begin" | "case-lambda" | "delay" | "delay-force" => 0,
+        "define" | "define-values" | "define-syntax" | "define-library" | "lambda" | "let*"
+        | "letrec" | "letrec*" | "let-values" | "let*-values" | "let-syntax" | "letrec-syntax"
+        | "syntax-rules" | "parameterize" | "when" | "unless" | "case" | "guard" => 1,
+        "define-record-type" | "do" => 2,
+        _ => return None,
+    })
+}
+
+struct Printer {
+    width: usize,
+    output: String,
+}
+
+impl Printer {
+    fn column(&self) -> usize {
+        match self.output.rfind('\n') {
+            Some(newline) => self.output[newline + 1..].chars().count(),
+            None => self.output.chars().count(),
+        }
+    }
+
+    fn newline(&mut self, indent: usize) {
+        let trimmed = self.output.trim_end_matches(' ').len();
+        self.output.truncate(trimmed);
+        self.output.push('\n');
+        self.output.extend(std::iter::repeat(' ').take(indent));
+    }
+
+    fn node(&mut self, node: &Node) {
+        stack::guard(|| match &node.kind {
+            NodeKind::Atom(text) | NodeKind::Comment(text) => self.output.push_str(text),
+            NodeKind::Prefix(prefix, inner) => {
+                self.output.push_str(prefix);
+                self.node(inner);
+            }
+            NodeKind::List(open, items) => {
+                let column = self.column();
+                // lists broken by their authors are kept broken
+                if let (true, Some(flat)) = (node.line == node.end_line, node.flat()) {
+                    if column + flat.chars().count() <= self.width {
+                        self.output.push_str(&flat);
+                        return;
+                    }
+                }
+                self.output.push_str(open);
+                let first = column + open.chars().count();
+                let (first_line, indent, fill) = match items.first().map(|item| &item.kind) {
+                    Some(NodeKind::Atom(head)) if *open == "(" => {
+                        match (head.as_str(), body_form(head)) {
+                            // named let
+                            ("let", _) => match items.get(1).map(|item| &item.kind) {
+                                Some(NodeKind::Atom(_)) => (3, column + 2, false),
+                                _ => (2, column + 2, false),
+                            },
+                            (_, Some(parts)) => (parts + 1, column + 2, false),
+                            (head, None) => {
+                                let aligned = first + head.chars().count() + 1;
+                                match aligned > self.width / 2 {
+                                    true => (1, column + 2, true),
+                                    false => (2, aligned, true),
+                                }
+                            }
+                        }
+                    }
+                    _ => (1, first, true),
+                };
+                self.items(items, first_line, indent, fill);
+                if items.last().map_or(false, Node::is_comment) {
+                    self.newline(indent);
+                }
+                self.output.push(')');
+            }
+        })
+    }
+
+    // the first `first_line` items are put on the current line, unless a comment ends it,
+    // others on their own lines indented by `indent`. With `fill`, atoms following atoms are
+    // put on the same line while they fit, like the names of an export list.
+    fn items(&mut self, items: &[Node], first_line: usize, indent: usize, fill: bool) {
+        let mut line_ended = false;
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                let previous = &items[index - 1];
+                if item.is_comment() && item.line == previous.end_line {
+                    self.output.push(' ');
+                } else if index < first_line && !line_ended {
+                    self.output.push(' ');
+                } else if let (true, NodeKind::Atom(_), NodeKind::Atom(text)) =
+                    (fill, &previous.kind, &item.kind)
+                {
+                    match self.column() + 1 + text.chars().count() <= self.width {
+                        true => self.output.push(' '),
+                        false => self.newline(indent),
+                    }
+                } else {
+                    // a blank line is kept, but not more
+                    if item.line > previous.end_line + 1 {
+                        self.newline(0);
+                    }
+                    self.newline(indent);
+                }
+            }
+            self.node(item);
+            line_ended |= item.is_comment();
+        }
+    }
+}
+
+#[cfg(test)]
+fn format(source: &str, width: usize) -> String {
+    format_source_with_width(source, width).unwrap()
+}
+
+#[test]
+fn special_forms() {
+    assert_eq!(
+        format(
+            "(define (f x)\n(let loop ((i 0))\n(if (< i x) (loop (+ i 1)) i)))",
+            80
+        ),
+        "(define (f x)\n  (let loop ((i 0))\n    (if (< i x) (loop (+ i 1)) i)))\n"
+    );
+    assert_eq!(
+        format("(lambda (x) (when (> x 0) (display x) (newline)))", 30),
+        "(lambda (x)\n  (when (> x 0)\n    (display x)\n    (newline)))\n"
+    );
+    assert_eq!(
+        format("(if (pair? lst) (car lst) (error \"empty list\" lst))", 30),
+        "(if (pair? lst)\n    (car lst)\n    (error \"empty list\" lst))\n"
+    );
+}
+
+#[test]
+fn calls_and_data() {
+    assert_eq!(
+        format("(list 1 2 3)\n(+ (* 1 2)\n(* 3 4))", 80),
+        "(list 1 2 3)\n(+ (* 1 2)\n   (* 3 4))\n"
+    );
+    assert_eq!(
+        format("'((a . 1) (b . 2) (c . 3)) #(1 2 3 4)", 16),
+        "'((a . 1)\n  (b . 2)\n  (c . 3))\n#(1 2 3 4)\n"
+    );
+    assert_eq!(
+        format("(call-with-current-continuation (lambda (k) k))", 20),
+        "(call-with-current-continuation\n  (lambda (k) k))\n"
+    );
+    assert_eq!(
+        format(
+            "(display \"a \\\"quote\\\"\\n\" |a symbol| #\\x 1.50 1/2)",
+            80
+        ),
+        "(display \"a \\\"quote\\\"\\n\" |a symbol| #\\x 1.50 1/2)\n"
+    );
+    assert_eq!(
+        format("(export car cdr\ncons list vector\n(rename my-list list*) append)", 24),
+        "(export car cdr cons\n        list vector\n        (rename my-list\n          list*)\n        append)\n"
+    );
+}
+
+#[test]
+fn comments_and_blank_lines() {
+    assert_eq!(
+        format(
+            ";; header\n\n\n(define x 1) ; one\n(define (f) ; no parameters\n  ; body\n  x\n  ; end\n  )\n",
+            80
+        ),
+        ";; header\n\n(define x 1) ; one\n(define (f) ; no parameters\n  ; body\n  x\n  ; end\n  )\n"
+    );
+    assert_eq!(format("'; quoted\n(a)", 80), "; quoted\n'(a)\n");
+    assert_eq!(format("", 80), "");
+    assert_eq!(
+        format("#!/usr/bin/env ruschm\n(display\n1)", 80),
+        "#!/usr/bin/env ruschm\n(display 1)\n"
+    );
+}
+
+#[test]
+fn token_errors() {
+    assert!(format_source("(define x").is_err());
+    assert!(format_source("x)").is_err());
+    assert!(format_source("#z").is_err());
+}
@@ -0,0 +1,58 @@
+//! Entry points which turn any input into a `SchemeError` instead of panicking, for fuzzers.
+//! A `cargo fuzz` target only needs to forward its data:
+//!
+//! ```ignore
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| ruschm::fuzz::run(data));
+//! ```
+//!
+//! Inputs crashing the crate are kept in `tests/fuzz_corpus`, which is replayed by `cargo test`.
+use crate::{
+    error::*,
+    interpreter::{DefaultInterpreter, LibraryFactory},
+    parser::{Lexer, LibraryName, Parser, Statement},
+    values::Value,
+};
+use std::{cell::RefCell, io, rc::Rc};
+
+/// applications of procedures allowed to each input of `run`
+pub const DEFAULT_FUEL: u64 = 100_000;
+
+/// nesting of expressions allowed to programs of `eval_str_bounded`, lower than the default of
+/// the interpreter to keep each run fast
+pub const MAX_DEPTH: usize = 10_000;
+
+/// parse a whole program, deep nesting is rejected with `SyntaxError::TooDeeplyNested`
+pub fn parse_str(source: &str) -> Result<Vec<Statement>, SchemeError> {
+    Parser::from_lexer(Lexer::from_char_stream(source.chars())).collect()
+}
+
+/// evaluate a program with the standard libraries, aborting with `ErrorData::FuelExhausted` after
+/// `fuel` procedure applications, so that it terminates, or with `ErrorData::RecursionTooDeep`
+/// beyond `MAX_DEPTH`
+///
+/// The program runs without side effects: input is empty and output is discarded, while loading
+/// and file ports are not available. Allocations are not bounded, so `(make-vector 2000000000)`
+/// still runs out of memory.
+pub fn eval_str_bounded(source: &str, fuel: u64) -> Result<Option<Value<f32>>, SchemeError> {
+    let mut interpreter = DefaultInterpreter::new();
+    for library in &["file", "load"] {
+        interpreter.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", *library),
+            Box::new(Vec::new),
+        ));
+    }
+    interpreter.set_input(io::empty());
+    interpreter.set_output(Rc::new(RefCell::new(io::sink())));
+    interpreter.eval_str("(import (scheme base) (scheme write))")?;
+    interpreter.set_fuel(fuel);
+    interpreter.set_max_depth(MAX_DEPTH);
+    interpreter.eval_str(source)
+}
+
+/// the body of a fuzz target: parse `data` and evaluate it with `DEFAULT_FUEL`
+pub fn run(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    if parse_str(&source).is_ok() {
+        let _ = eval_str_bounded(&source, DEFAULT_FUEL);
+    }
+}
@@ -0,0 +1,394 @@
+use super::{error::*, resolve_statement, Result};
+use crate::{
+    error::*,
+    parser::{visit::*, *},
+    stack,
+    symbol::Symbol,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Find the problems of a program without evaluating it: syntax errors, which are recovered from,
+/// and warnings for
+/// - parameters never referenced by the body of their procedure, unless their names start with `_`
+/// - definitions shadowing procedures of the base library, whose names are `builtins`
+/// - clauses of `cond` and `case` following the `else` clause, which are never taken
+///
+/// Macros defined by the program are only bound in `parser`, so the analysis does not change how
+/// the program is evaluated afterwards.
+pub fn analyze_program<TokenIter: Iterator<Item = Result<Token>>>(
+    parser: &mut Parser<TokenIter>,
+    builtins: &HashSet<String>,
+) -> Vec<Diagnostic> {
+    analyze_statements(parser, builtins).0
+}
+
+/// like `analyze_program`, also returning the statements parsed successfully, resolved
+pub fn analyze_statements<TokenIter: Iterator<Item = Result<Token>>>(
+    parser: &mut Parser<TokenIter>,
+    builtins: &HashSet<String>,
+) -> (Vec<Diagnostic>, Vec<Statement>) {
+    let mut diagnostics = vec![];
+    let mut statements = vec![];
+    loop {
+        let datum = match parser.parse_datum() {
+            Ok(Some(datum)) => datum,
+            Ok(None) => break,
+            Err(error) => {
+                diagnostics.push(error.into());
+                parser.skip_unclosed();
+                continue;
+            }
+        };
+        check_else_clauses(&datum, &mut diagnostics);
+        let location = datum.location;
+        match parser.transform_to_statement(datum, &parser.syntax_env) {
+            Ok(mut statement) => {
+                resolve_statement(&mut statement);
+                Analyzer {
+                    builtins,
+                    frames: vec![],
+                    diagnostics: &mut diagnostics,
+                }
+                .visit_statement(&statement);
+                statements.push(statement);
+            }
+            Err(error) => diagnostics.push(error.or_locate(location).into()),
+        }
+    }
+    (diagnostics, statements)
+}
+
+/// Find the errors of resolved `statements` which are certain to be raised if they are evaluated:
+/// - references to and assignments of variables which are neither in `globals` nor defined at the
+///   top level of the program, unless `globals` is None, when the bound names are not known
+/// - calls of procedures defined at the top level with a wrong number of arguments, unless the
+///   procedures are defined more than once or assigned
+///
+/// Libraries defined by the program are not checked.
+pub fn check_program(
+    statements: &[Statement],
+    globals: Option<&HashSet<String>>,
+) -> Vec<Diagnostic> {
+    let mut defined = globals.cloned();
+    let mut procedures = HashMap::new();
+    let mut redefined = HashSet::new();
+    for statement in statements {
+        if let Statement::Definition(definition) = statement {
+            let DefinitionBody(name, value) = &definition.data;
+            if let Some(defined) = &mut defined {
+                defined.insert(name.to_string());
+            }
+            match &value.data {
+                ExpressionBody::Procedure(SchemeProcedure(formals, ..))
+                    if procedures.insert(name.to_string(), formals.len()).is_none() => {}
+                _ => {
+                    redefined.insert(name.to_string());
+                }
+            }
+        }
+    }
+    let mut checker = Checker {
+        defined: defined.as_ref(),
+        procedures,
+        diagnostics: vec![],
+    };
+    // assignments are found first, calls before them would be checked otherwise
+    statements
+        .iter()
+        .for_each(|statement| AssignmentFinder(&mut redefined).visit_statement(statement));
+    for name in redefined {
+        checker.procedures.remove(&name);
+    }
+    statements
+        .iter()
+        .for_each(|statement| checker.visit_statement(statement));
+    checker.diagnostics
+}
+
+struct AssignmentFinder<'a>(&'a mut HashSet<String>);
+
+impl Visitor for AssignmentFinder<'_> {
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let ExpressionBody::Assignment(name, _) = &expression.data {
+            self.0.insert(name.to_string());
+        }
+        walk_expression(self, expression);
+    }
+}
+
+struct Checker<'a> {
+    defined: Option<&'a HashSet<String>>,
+    // the number of fixed parameters and whether there is a variadic one
+    procedures: HashMap<String, (usize, bool)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, error: LogicError, location: Option<Location>) {
+        self.diagnostics
+            .push(ErrorData::Logic(error).locate(location).into());
+    }
+}
+
+impl Visitor for Checker<'_> {
+    fn visit_library_definition(&mut self, _library: &Located<LibraryDefinition>) {}
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.data {
+            ExpressionBody::Symbol(name) | ExpressionBody::Assignment(name, _) if matches!(self.defined, Some(defined) if !defined.contains(name.as_str())) => {
+                self.error(
+                    LogicError::UnboundedSymbol(name.to_string(), vec![]),
+                    expression.location,
+                )
+            }
+            ExpressionBody::ProcedureCall(operator, arguments) => {
+                if let ExpressionBody::Symbol(name) = &operator.data {
+                    match self.procedures.get(name.as_str()) {
+                        Some(&(fixed, variadic))
+                            if arguments.len() < fixed
+                                || (!variadic && arguments.len() > fixed) =>
+                        {
+                            self.error(
+                                wrong_arity(name, (fixed, variadic), arguments.len()),
+                                expression.location,
+                            )
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+        walk_expression(self, expression);
+    }
+}
+
+// the derived forms are expanded before statements are analyzed, so clauses are checked in data
+fn check_else_clauses(datum: &Datum, diagnostics: &mut Vec<Diagnostic>) {
+    stack::guard(|| {
+        let list = match &datum.data {
+            DatumBody::Pair(list) => list,
+            DatumBody::Vector(vector) => {
+                vector
+                    .iter()
+                    .for_each(|datum| check_else_clauses(datum, diagnostics));
+                return;
+            }
+            _ => return,
+        };
+        let mut items = list.iter();
+        let clauses = match items.next().map(|first| &first.data) {
+            Some(DatumBody::Symbol(keyword)) if keyword == "quote" => return,
+            Some(DatumBody::Symbol(keyword)) if keyword == "cond" => list.iter().skip(1),
+            Some(DatumBody::Symbol(keyword)) if keyword == "case" => list.iter().skip(2),
+            _ => list.iter().skip(list.len()),
+        };
+        let mut after_else = false;
+        for clause in clauses {
+            if after_else {
+                diagnostics.push(Diagnostic::warning(
+                    "unreachable-clause",
+                    "clause after else clause is never taken",
+                    clause.location,
+                ));
+                break;
+            }
+            after_else = matches!(
+                &clause.data,
+                DatumBody::Pair(clause) if matches!(
+                    clause.iter().next().map(|first| &first.data),
+                    Some(DatumBody::Symbol(keyword)) if keyword == "else"
+                )
+            );
+        }
+        list.iter()
+            .for_each(|datum| check_else_clauses(datum, diagnostics));
+    })
+}
+
+struct Analyzer<'a> {
+    builtins: &'a HashSet<String>,
+    // whether the slots of the frames of enclosing procedures are referenced, innermost last
+    frames: Vec<Vec<bool>>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl Visitor for Analyzer<'_> {
+    fn visit_definition(&mut self, definition: &Definition) {
+        let DefinitionBody(name, _) = &definition.data;
+        if self.builtins.contains(name.as_str()) {
+            self.diagnostics.push(Diagnostic::warning(
+                "shadowed-builtin",
+                format!(
+                    "definition of {} shadows the procedure of the base library",
+                    name
+                ),
+                definition.location,
+            ));
+        }
+        walk_definition(self, definition);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match &expression.data {
+            ExpressionBody::LocalVariable(_, address) => {
+                let depth = self.frames.len() - 1 - address.depth;
+                self.frames[depth][address.slot] = true;
+            }
+            _ => walk_expression(self, expression),
+        }
+    }
+
+    fn visit_procedure(&mut self, procedure: &SchemeProcedure) {
+        let SchemeProcedure(formals, definitions, ..) = procedure;
+        // the same layout as resolve_statement assigns
+        let mut parameters = vec![];
+        if let Some(variadic) = formals.iter_to_last(|fixed| parameters.push(fixed.clone())) {
+            parameters.push(variadic.clone());
+        }
+        self.frames
+            .push(vec![false; parameters.len() + definitions.len()]);
+        walk_procedure(self, procedure);
+        let used = self.frames.pop().unwrap();
+        for (parameter, used) in parameters.iter().zip(used) {
+            let name: Symbol = parameter.as_name();
+            if !used && !name.starts_with('_') {
+                self.diagnostics.push(Diagnostic::warning(
+                    "unused-parameter",
+                    format!("parameter {} is never used", name),
+                    parameter.location,
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn warnings(source: &str) -> Vec<String> {
+    let builtins = ["list", "max"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+    analyze_program(&mut parser, &builtins)
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        .map(|diagnostic| {
+            let location = diagnostic.location.unwrap();
+            format!(
+                "{}:{} {}",
+                location.line, location.column, diagnostic.message
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn unused_parameters() {
+    assert_eq!(
+        warnings("(define (f x y . rest) (lambda (y) y))"),
+        vec![
+            "1:13 parameter x is never used",
+            "1:15 parameter y is never used",
+            "1:22 parameter rest is never used"
+        ]
+    );
+    assert!(warnings("(define (f x y . rest) (lambda (z) (+ x y z rest)))").is_empty());
+    assert!(warnings("(lambda (_ignored x) (define (g) x) g)").is_empty());
+    assert_eq!(
+        warnings("(let ((a 1) (b 2)) a)"),
+        vec!["1:15 parameter b is never used"]
+    );
+}
+
+#[test]
+fn shadowed_builtins() {
+    assert_eq!(
+        warnings("(define list 1)\n(define (f) (define max 2) max)"),
+        vec![
+            "1:2 definition of list shadows the procedure of the base library",
+            "2:14 definition of max shadows the procedure of the base library"
+        ]
+    );
+    assert!(warnings("(define lst 1) (set! list 2)").is_empty());
+}
+
+#[test]
+fn clauses_after_else() {
+    assert_eq!(
+        warnings("(cond ((> 1 2) 1)\n      (else 2)\n      (#t 3))"),
+        vec!["3:8 clause after else clause is never taken"]
+    );
+    assert_eq!(
+        warnings("(lambda (x) (case x ((1) 'one) (else 'other) ((2) 'two)))"),
+        vec!["1:47 clause after else clause is never taken"]
+    );
+    assert!(warnings("(cond ((> 1 2) 1) (else 2))").is_empty());
+    assert!(warnings("'(cond (else 1) (2))").is_empty());
+}
+
+#[cfg(test)]
+fn errors(source: &str, globals: Option<&HashSet<String>>) -> Vec<String> {
+    let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+    let (_, statements) = analyze_statements(&mut parser, &HashSet::new());
+    check_program(&statements, globals)
+        .into_iter()
+        .map(|diagnostic| {
+            let location = diagnostic.location.unwrap();
+            format!(
+                "{}:{} {}",
+                location.line, location.column, diagnostic.message
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn unbound_variables() {
+    let globals = ["+", "display"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    assert_eq!(
+        errors(
+            "(define (f x) (+ x y (g)))\n(set! z 1)\n(define (g) (display f))",
+            Some(&globals)
+        ),
+        vec!["1:21 unbound symbol y", "2:2 unbound symbol z"]
+    );
+    assert!(errors("(lambda (x) (define y x) (+ x y))", Some(&globals)).is_empty());
+    assert!(errors("(f y)", None).is_empty());
+}
+
+#[test]
+fn arity_mismatches() {
+    assert_eq!(
+        errors(
+            "(define (f a b) a)\n(define (g a . rest) a)\n(f 1 2 3)\n(g)\n(g 1 2 3)\n(lambda (f) (f 1))",
+            None
+        ),
+        vec![
+            "3:2 procedure f expects 2 arguments, got 3",
+            "4:2 procedure g expects at least 1 argument, got 0"
+        ]
+    );
+    // procedures which may change are not checked
+    assert!(errors("(define (f) 1)\n(f 1)\n(set! f car)", None).is_empty());
+    assert!(errors("(define (f) 1)\n(f 1)\n(define (f x) x)", None).is_empty());
+}
+
+#[test]
+fn syntax_errors() {
+    let mut parser = Parser::from_lexer(Lexer::from_char_stream("(f . . x)\n(if)".chars()));
+    let diagnostics = analyze_program(&mut parser, &HashSet::new());
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|diagnostic| (
+                diagnostic.severity,
+                diagnostic.location.map(|location| location.line)
+            ))
+            .collect::<Vec<_>>(),
+        vec![(Severity::Error, Some(1)), (Severity::Error, Some(2))]
+    );
+}
@@ -0,0 +1,300 @@
+use super::{error::LogicError, Result};
+use crate::{error::*, parser::error::SyntaxError, parser::*, stack, symbol::Symbol, values::*};
+use std::{fmt, rc::Rc};
+
+pub type Node<R> = Located<NodeBody<R>>;
+
+/// Expressions lowered for evaluation: literals are converted to values once, variables are
+/// either global names or lexical addresses and procedures know the layout of their frames,
+/// so the evaluator neither re-reads the syntax tree nor clones it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeBody<R: RealNumberInternalTrait> {
+    Constant(Value<R>),
+    Global(Symbol),
+    Local(Symbol, LexicalAddress),
+    SetGlobal(Symbol, Box<Node<R>>),
+    SetLocal(Symbol, LexicalAddress, Box<Node<R>>),
+    // shared by the procedures created from the lambda expression
+    Lambda(Rc<Lambda<R>>),
+    Call(Box<Node<R>>, Vec<Node<R>>),
+    If(Box<(Node<R>, Node<R>, Option<Node<R>>)>),
+    // a call of builtin procedures on constants evaluated by `optimize_node`
+    Folded(Box<Folded<R>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Folded<R: RealNumberInternalTrait> {
+    pub value: Value<R>,
+    // the names the value is computed with, which must still be bound to the builtin procedures
+    // of the same names when it is used, otherwise the call is evaluated
+    pub builtins: Vec<Symbol>,
+    pub call: Node<R>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda<R: RealNumberInternalTrait> {
+    pub formals: ParameterFormals,
+    pub fixed: usize,
+    pub variadic: bool,
+    // names of the frame slots: fixed parameters, the variadic parameter, then internal definitions
+    pub slots: Rc<[Symbol]>,
+    // initializers of internal definitions, in the order of their slots
+    pub definitions: Vec<Node<R>>,
+    pub body: Vec<Node<R>>,
+    pub name: Option<Symbol>, // name, for procedures defined by (define (name ...) ...)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompiledStatement<R: RealNumberInternalTrait> {
+    Expression(Node<R>),
+    Definition(Symbol, Node<R>),
+}
+
+/// Lower an expression or a definition, other statements are evaluated by the interpreter directly.
+/// Local variables are only recognized if the statement went through `resolve_statement`.
+pub fn compile_statement<R: RealNumberInternalTrait>(
+    statement: &Statement,
+) -> Result<CompiledStatement<R>> {
+    Ok(match statement {
+        Statement::Expression(expression) => {
+            CompiledStatement::Expression(compile_expression(expression)?)
+        }
+        Statement::Definition(Definition {
+            data: DefinitionBody(name, expression),
+            ..
+        }) => CompiledStatement::Definition(name.clone(), compile_expression(expression)?),
+        other => located_error!(
+            SyntaxError::ExpectSomething(
+                "expression/definition".to_string(),
+                "other statement".to_string(),
+            ),
+            other.location()
+        )?,
+    })
+}
+
+pub fn compile_expression<R: RealNumberInternalTrait>(expression: &Expression) -> Result<Node<R>> {
+    stack::guard(|| {
+        let data = match &expression.data {
+            ExpressionBody::Primitive(primitive) => NodeBody::Constant(
+                primitive_value(primitive).map_err(|error| error.or_locate(expression.location))?,
+            ),
+            ExpressionBody::Datum(datum) => NodeBody::Constant(datum_value(datum)?),
+            ExpressionBody::Quote(datum) => NodeBody::Constant(datum_value(datum)?),
+            ExpressionBody::Symbol(name) => NodeBody::Global(name.clone()),
+            ExpressionBody::LocalVariable(name, address) => NodeBody::Local(name.clone(), *address),
+            ExpressionBody::Assignment(name, value) => {
+                NodeBody::SetGlobal(name.clone(), Box::new(compile_expression(value)?))
+            }
+            ExpressionBody::LocalAssignment(name, address, value) => {
+                NodeBody::SetLocal(name.clone(), *address, Box::new(compile_expression(value)?))
+            }
+            ExpressionBody::Procedure(procedure) => {
+                NodeBody::Lambda(Rc::new(compile_procedure(procedure)?))
+            }
+            ExpressionBody::ProcedureCall(procedure, arguments) => NodeBody::Call(
+                Box::new(compile_expression(procedure)?),
+                arguments
+                    .iter()
+                    .map(compile_expression)
+                    .collect::<Result<_>>()?,
+            ),
+            ExpressionBody::Conditional(conditional) => {
+                let (test, consequent, alternative) = conditional.as_ref();
+                NodeBody::If(Box::new((
+                    compile_expression(test)?,
+                    compile_expression(consequent)?,
+                    alternative.as_ref().map(compile_expression).transpose()?,
+                )))
+            }
+            ExpressionBody::Period => {
+                return located_error!(
+                    LogicError::UnexpectedExpression(expression.clone()),
+                    expression.location
+                );
+            }
+        };
+        Ok(Node {
+            data,
+            location: expression.location,
+        })
+    })
+}
+
+fn compile_procedure<R: RealNumberInternalTrait>(procedure: &SchemeProcedure) -> Result<Lambda<R>> {
+    let SchemeProcedure(formals, definitions, expressions, name) = procedure;
+    let (fixed, variadic) = formals.len();
+    // the same layout as resolve_statement assigns
+    let mut slots = Vec::with_capacity(fixed + variadic as usize + definitions.len());
+    if let Some(variadic) = formals.iter_to_last(|formal| slots.push(formal.as_name())) {
+        slots.push(variadic.as_name());
+    }
+    slots.extend(
+        definitions
+            .iter()
+            .map(|definition| definition.data.0.clone()),
+    );
+    Ok(Lambda {
+        formals: formals.clone(),
+        fixed,
+        variadic,
+        slots: slots.into(),
+        definitions: definitions
+            .iter()
+            .map(|definition| compile_expression(&definition.data.1))
+            .collect::<Result<_>>()?,
+        body: expressions
+            .iter()
+            .map(compile_expression)
+            .collect::<Result<_>>()?,
+        name: name.clone(),
+    })
+}
+
+/// Convert a primitive to its value, reals of syntax trees built by hand may not be valid numbers.
+pub fn primitive_value<R: RealNumberInternalTrait>(primitive: &Primitive) -> Result<Value<R>> {
+    Ok(match primitive {
+        Primitive::Character(c) => Value::Character(*c),
+        Primitive::String(string) => Value::String(string.as_str().into()),
+        Primitive::Boolean(value) => Value::Boolean(*value),
+        Primitive::Integer(value) => Value::Number(Number::Integer(*value)),
+        Primitive::Real(number_literal) => {
+            match number_literal.parse::<f64>().ok().and_then(R::from) {
+                Some(real) => Value::Number(Number::Real(real)),
+                None => return error!(SyntaxError::InvalidNumber(number_literal.clone())),
+            }
+        }
+        // TODO: apply gcd here.
+        Primitive::Rational(a, b) => Value::Number(Number::Rational(*a, *b as i32)),
+    })
+}
+
+pub fn datum_value<R: RealNumberInternalTrait>(datum: &Datum) -> Result<Value<R>> {
+    stack::guard(|| match &datum.data {
+        DatumBody::Primitive(primitive) => {
+            primitive_value(primitive).map_err(|error| error.or_locate(datum.location))
+        }
+        DatumBody::Symbol(name) => Ok(Value::Symbol(name.clone())),
+        DatumBody::Pair(list) => Ok(Value::Pair(Box::new(list.map_ok_ref(&mut datum_value)?))),
+        DatumBody::Vector(vec) => Ok(Value::Vector(ValueReference::new_immutable(
+            vec.iter().map(datum_value).collect::<Result<_>>()?,
+        ))),
+    })
+}
+
+fn write_nodes<R: RealNumberInternalTrait>(
+    f: &mut fmt::Formatter<'_>,
+    nodes: &[Node<R>],
+) -> fmt::Result {
+    nodes
+        .iter()
+        .try_for_each(|node| write!(f, " {}", node.data))
+}
+
+impl<R: RealNumberInternalTrait> fmt::Display for NodeBody<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeBody::Constant(value) => write!(f, "(const {})", value.written()),
+            NodeBody::Global(name) => write!(f, "(global {})", name),
+            NodeBody::Local(name, address) => {
+                write!(f, "(local {} {} {})", name, address.depth, address.slot)
+            }
+            NodeBody::SetGlobal(name, value) => write!(f, "(set-global {} {})", name, value.data),
+            NodeBody::SetLocal(name, address, value) => write!(
+                f,
+                "(set-local {} {} {} {})",
+                name, address.depth, address.slot, value.data
+            ),
+            NodeBody::Lambda(lambda) => {
+                write!(f, "(lambda {} (frame", lambda.formals)?;
+                lambda
+                    .slots
+                    .iter()
+                    .try_for_each(|name| write!(f, " {}", name))?;
+                write!(f, ")")?;
+                write_nodes(f, &lambda.definitions)?;
+                write_nodes(f, &lambda.body)?;
+                write!(f, ")")
+            }
+            NodeBody::Call(procedure, arguments) => {
+                write!(f, "(call {} {}", arguments.len(), procedure.data)?;
+                write_nodes(f, arguments)?;
+                write!(f, ")")
+            }
+            NodeBody::If(branches) => {
+                let (test, consequent, alternative) = branches.as_ref();
+                write!(f, "(if {} {}", test.data, consequent.data)?;
+                if let Some(alternative) = alternative {
+                    write!(f, " {}", alternative.data)?;
+                }
+                write!(f, ")")
+            }
+            NodeBody::Folded(folded) => write!(
+                f,
+                "(folded {} {})",
+                folded.value.written(),
+                folded.call.data
+            ),
+        }
+    }
+}
+
+impl<R: RealNumberInternalTrait> fmt::Display for CompiledStatement<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompiledStatement::Expression(node) => write!(f, "{}", node.data),
+            CompiledStatement::Definition(name, node) => {
+                write!(f, "(define {} {})", name, node.data)
+            }
+        }
+    }
+}
+
+impl<R: RealNumberInternalTrait> fmt::Display for Lambda<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(lambda {})", self.formals)
+    }
+}
+
+#[test]
+fn compile() -> Result<()> {
+    let mut statement = Parser::from_lexer(Lexer::from_char_stream(
+        "(define (f x . rest) (define y '(1 #(2))) (if (g x) (set! x y)) (h x rest 2.5))".chars(),
+    ))
+    .next()
+    .unwrap()?;
+    super::resolve_statement(&mut statement);
+    let compiled = compile_statement::<f32>(&statement)?;
+    assert_eq!(
+        compiled.to_string(),
+        "(define f (lambda (x . rest) (frame x rest y) (const (1 #(2))) \
+         (if (call 1 (global g) (local x 0 0)) (set-local x 0 0 (local y 0 2))) \
+         (call 3 (global h) (local x 0 0) (local rest 0 1) (const 2.5))))"
+    );
+    let lambda = match compiled {
+        CompiledStatement::Definition(
+            name,
+            Node {
+                data: NodeBody::Lambda(lambda),
+                ..
+            },
+        ) if name == "f" => lambda,
+        other => panic!("unexpected statement {:?}", other),
+    };
+    assert_eq!(lambda.to_string(), "(lambda (x . rest))");
+    assert_eq!(lambda.name, Some("f".into()));
+    Ok(())
+}
+
+#[test]
+fn malformed_real() {
+    let real = Primitive::Real("1.2.3".to_string());
+    assert_eq!(real.to_string(), "1.2.3");
+    let expression = ExpressionBody::Primitive(real).locate(Some([1, 6].into()));
+    let error = compile_expression::<f32>(&expression).unwrap_err();
+    assert_eq!(
+        error.data,
+        ErrorData::from(SyntaxError::InvalidNumber("1.2.3".to_string()))
+    );
+    assert_eq!(error.location, Some([1, 6].into()));
+}
@@ -0,0 +1,70 @@
+use super::{error::Backtrace, Result};
+use crate::{environment::Environment, values::RealNumberInternalTrait};
+use std::{any::Any, cell::RefCell, marker::PhantomData, rc::Rc};
+
+/// where `(debug)` paused evaluation, passed to the debugger set by `Interpreter::set_debugger`
+pub struct Breakpoint<R: RealNumberInternalTrait> {
+    /// the lexical environment `(debug)` is called in, with the parameters and local
+    /// definitions of the procedures around the call
+    pub env: Rc<Environment<R>>,
+    /// the applications in progress, the innermost one, which is `debug`, first
+    pub backtrace: Backtrace,
+}
+
+/// evaluation resumes when the debugger returns, an error is raised by `(debug)` instead
+pub type Debugger<R> = Box<dyn FnMut(Breakpoint<R>) -> Result<()>>;
+
+thread_local! {
+    // the debugger of the interpreter evaluating on this thread, a Debugger<R>
+    static DEBUGGER: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+// run the debugger on the breakpoint built by `breakpoint`, None if there is no debugger or if
+// it is already running
+pub(crate) fn pause<R: RealNumberInternalTrait>(
+    breakpoint: impl FnOnce() -> Breakpoint<R>,
+) -> Option<Result<()>> {
+    DEBUGGER.with(|debugger| {
+        let mut debugger = debugger.try_borrow_mut().ok()?;
+        let debugger = debugger.as_mut()?.downcast_mut::<Debugger<R>>()?;
+        Some(debugger(breakpoint()))
+    })
+}
+
+// make `debugger` run on the breakpoints of this thread until the returned guard is uninstalled
+// or dropped, which reinstalls the debugger it replaced
+pub(crate) fn install<R: RealNumberInternalTrait>(debugger: Option<Debugger<R>>) -> Installed<R> {
+    Installed {
+        outer: Some(reinstall(
+            debugger.map(|debugger| Box::new(debugger) as Box<dyn Any>),
+        )),
+        debugger: PhantomData,
+    }
+}
+
+fn reinstall(debugger: Option<Box<dyn Any>>) -> Option<Box<dyn Any>> {
+    DEBUGGER.with(|installed| installed.replace(debugger))
+}
+
+pub(crate) struct Installed<R: RealNumberInternalTrait> {
+    // the debugger replaced by `install`, None once it is reinstalled
+    outer: Option<Option<Box<dyn Any>>>,
+    debugger: PhantomData<Debugger<R>>,
+}
+
+impl<R: RealNumberInternalTrait> Installed<R> {
+    // the debugger installed by `install`
+    pub(crate) fn uninstall(mut self) -> Option<Debugger<R>> {
+        reinstall(self.outer.take().unwrap())
+            .and_then(|debugger| debugger.downcast::<Debugger<R>>().ok())
+            .map(|debugger| *debugger)
+    }
+}
+
+impl<R: RealNumberInternalTrait> Drop for Installed<R> {
+    fn drop(&mut self) {
+        if let Some(outer) = self.outer.take() {
+            reinstall(outer);
+        }
+    }
+}
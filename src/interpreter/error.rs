@@ -1,39 +1,227 @@
+use std::{fmt, path::PathBuf};
 use thiserror::Error;
 
 use crate::{
-    parser::error::SyntaxError, parser::Expression, parser::ParameterFormals, values::Type,
+    parser::error::SyntaxError,
+    parser::Expression,
+    values::{RealNumberInternalTrait, Type, Value},
 };
 
-use crate::parser::LibraryName;
+use crate::{error::IoError, error::Location, error::SchemeError, parser::LibraryName};
+
+// the location of an error in a loaded file, which is in another source if it is raised by
+// procedures defined there
+fn format_load_location(file: &str, location: &Option<Location>) -> String {
+    match location {
+        Some(location) if location.source.name() == file => {
+            format!(":{}:{}", location.line, location.column)
+        }
+        Some(location) => format!(": {}", location),
+        None => String::new(),
+    }
+}
+
+// `count` followed by `noun`, which is plural unless count is 1
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+fn format_suggestions(suggestions: &[String]) -> String {
+    match suggestions.split_last() {
+        None => String::new(),
+        Some((last, [])) => format!("; did you mean `{}`?", last),
+        Some((last, others)) => format!(
+            "; did you mean {} or `{}`?",
+            others
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(", "),
+            last
+        ),
+    }
+}
+
+fn format_paths(paths: &[PathBuf]) -> String {
+    match paths.is_empty() {
+        true => String::new(),
+        false => format!(
+            ", tried {}",
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
 
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum LogicError {
-    #[error("unbound symbol {0}")]
-    UnboundedSymbol(String),
-    #[error("{0} is not {1:?}")]
+    #[error("unbound symbol {0}{}", format_suggestions(.1))]
+    UnboundedSymbol(String, /* similar bound names */ Vec<String>),
+    #[error("variable {0} is used before its definition is initialized")]
+    UninitializedVariable(String),
+    #[error("expect {1}, got {0}")]
     TypeMisMatch(/* value string */ String, Type),
+    #[error("{0}: expect {1}, got {2}")]
+    WrongType(
+        /* procedure */ String,
+        Type,
+        /* value string */ String,
+    ),
     #[error("unexpect statement {0:?}")]
     UnexpectedExpression(Expression),
     #[error("division by exact zero")]
     DivisionByZero,
+    #[error("{0}: division by zero")]
+    DivisionByZeroIn(/* procedure */ String),
     #[error("{0} cannot be converted to an exact number")]
     InExactConversion(String),
     #[error("expect a proper list, encounter inproper list {0}")]
     InproperList(String),
     #[error("expect a non-negative length")]
     NegativeLength,
-    #[error("vector index out of bound")]
-    VectorIndexOutOfBounds,
-    #[error("expect parameters {0}, got arguments {1}")]
-    ArgumentMissMatch(ParameterFormals, String),
-    #[error("requires {0} to be mutable")]
-    RequiresMutable(String),
+    #[error("{0}: expect a non-negative count, got {1}")]
+    NegativeCount(/* procedure */ String, i32),
+    #[error("format: the format string has {} taking arguments, got {}", plural(*.0, "directive"), plural(*.1, "argument"))]
+    FormatArguments(/* directives */ usize, /* arguments */ usize),
+    #[error("format: unknown directive {0} at index {1}")]
+    UnknownFormatDirective(/* directive */ String, /* index */ usize),
+    #[error("{0}: index {1} is out of range for length {2}")]
+    IndexOutOfRange(
+        /* procedure */ String,
+        /* index */ i32,
+        /* length */ usize,
+    ),
+    #[error("procedure {0} expects {}{}, got {}", if *.2 { "at least " } else { "" }, plural(*.1, "argument"), .3)]
+    ArgumentMissMatch(
+        /* procedure signature */ String,
+        /* fixed parameters */ usize,
+        /* variadic */ bool,
+        /* arguments */ usize,
+    ),
+    #[error("attempt to modify a literal {0}")]
+    LiteralMutation(String),
     #[error(transparent)]
     MetaCircularSyntax(#[from] SyntaxError),
+    #[error("assertion failed: {0}{}{}", .1.as_ref().map(|location| format!(" at {}", location)).unwrap_or_default(), .2.as_ref().map(|message| format!(", {}", message)).unwrap_or_default())]
+    AssertionFailed(
+        /* expression */ String,
+        /* location */ Option<String>,
+        /* message */ Option<String>,
+    ),
     #[error("{0}")]
     Extension(String),
-    #[error("library {0} not found")]
-    LibraryNotFound(LibraryName),
+    #[error("library {0} not found{}", format_paths(.1))]
+    LibraryNotFound(LibraryName, /* paths tried */ Vec<PathBuf>),
     #[error("detect import cyclic while importing library {0}")]
     LibraryImportCyclic(LibraryName),
+    #[error("{1} is not exported by library {0}")]
+    NotExported(LibraryName, String),
+    #[error("identifier {0} is imported more than once")]
+    DuplicateImport(String),
+    #[error("{0}{}: {1}", format_load_location(.0, &.1.location))]
+    LoadError(String, Box<SchemeError>),
+    #[error("detect cyclic loading of file {0}")]
+    LoadCyclic(String),
+    #[error("port {0} is closed")]
+    PortClosed(String),
+    #[error("cannot open file {0}: {1}")]
+    FileError(String, IoError),
+    #[error("cannot snapshot {}, which are not data", .0.join(", "))]
+    NotSnapshotData(/* names */ Vec<String>),
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("{0}{}", .1.iter().map(|irritant| format!(" {}", irritant)).collect::<String>())]
+    Raised(
+        /* message */ String,
+        /* written irritants */ Vec<String>,
+    ),
+}
+
+/// `procedure` is given `value`, which is not of the `expected` type, the value is reported as
+/// `write` writes it
+pub fn wrong_type<R: RealNumberInternalTrait>(
+    procedure: &str,
+    expected: Type,
+    value: &Value<R>,
+) -> LogicError {
+    LogicError::WrongType(procedure.to_string(), expected, value.written().to_string())
+}
+
+/// `procedure`, whose signature is given, takes `expected` arguments as returned by
+/// `Procedure::arity` but is applied to `got` ones
+pub fn wrong_arity(procedure: &str, expected: (usize, bool), got: usize) -> LogicError {
+    LogicError::ArgumentMissMatch(procedure.to_string(), expected.0, expected.1, got)
+}
+
+/// `procedure` is given `index` of a vector or a string of `length` elements
+pub fn index_out_of_range(procedure: &str, index: i32, length: usize) -> LogicError {
+    LogicError::IndexOutOfRange(procedure.to_string(), index, length)
+}
+
+impl LogicError {
+    // type errors raised by a builtin procedure without knowing its name, when checking the type
+    // of an argument, are reported as errors of the procedure
+    pub(crate) fn in_procedure(self, procedure: &str) -> Self {
+        match self {
+            LogicError::TypeMisMatch(value, expected) => {
+                LogicError::WrongType(procedure.to_string(), expected, value)
+            }
+            other => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub procedure: String,
+    pub location: Option<Location>,
+    // tail calls replace the frame instead of pushing a new one
+    pub tail_calls: usize,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.procedure)?;
+        if let Some(location) = self.location {
+            write!(f, " at {}", location)?;
+        }
+        if self.tail_calls > 0 {
+            write!(f, " (after {} tail calls)", self.tail_calls)?;
+        }
+        Ok(())
+    }
+}
+
+// call stack of an error, innermost frame first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backtrace {
+    pub frames: Vec<StackFrame>,
+    pub omitted: usize,
+}
+
+impl Backtrace {
+    pub fn new(mut frames: Vec<StackFrame>, max_depth: usize) -> Self {
+        let omitted = frames.len().saturating_sub(max_depth);
+        frames.truncate(max_depth);
+        Self { frames, omitted }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "backtrace:")?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            write!(f, "\n  {}: {}", index, frame)?;
+        }
+        if self.omitted > 0 {
+            write!(f, "\n  ... {} more frames", self.omitted)?;
+        }
+        Ok(())
+    }
 }
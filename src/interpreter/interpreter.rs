@@ -1,29 +1,58 @@
 #![allow(dead_code)]
 
-use crate::{import_library_direct, parser::*, values::Value};
+use crate::{import_library_direct, parser::pair::GenericPair, parser::*, values::Value};
 use error::SyntaxError;
-use itertools::Itertools;
 
 use crate::error::*;
 use crate::values::Procedure;
 use crate::{
     environment::*, io::file_char_stream, library_factory::GenericLibraryFactory, library_name,
-    values::*,
+    port::*, stack, values::*,
 };
 
 #[cfg(test)]
 use crate::parser::pair::*;
-
-use std::{collections::HashMap, ops::Deref, path::Path, rc::Rc};
+#[cfg(test)]
+use smallvec::smallvec;
+
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::{BufRead, Write},
+    ops::Deref,
+    path::Path,
+    rc::{Rc, Weak},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use std::{collections::HashSet, iter::Iterator};
 use std::{marker::PhantomData, path::PathBuf};
 
+#[cfg(test)]
+use super::error::wrong_type;
 use super::Result;
-use super::{error::LogicError, library::native};
+use super::{
+    analyze_program, analyze_statements, check_program, compile_expression, compile_statement,
+    compiler::datum_value,
+    debug::{self, Breakpoint, Debugger},
+    error::{wrong_arity, Backtrace, LogicError, StackFrame},
+    library::native,
+    optimize_statement,
+    optimizer::is_builtin,
+    resolve_statement,
+    snapshot::{copy_data, is_data, Snapshot},
+    trace::{self, trace, TraceEvent, Tracer},
+    CompiledStatement, Lambda, Node, NodeBody,
+};
 use crate::interpreter::library::Library;
 use crate::parser::LibraryName;
 
 pub type LibraryFactory<'a, R> = GenericLibraryFactory<'a, Value<R>>;
+/// interpreter using single precision real numbers, as the REPL does
+pub type DefaultInterpreter<'a> = Interpreter<'a, f32>;
 
 #[test]
 fn library_factory() -> Result<()> {
@@ -54,28 +83,179 @@ fn library_factory() -> Result<()> {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-enum TailExpressionResult<'a, R: RealNumberInternalTrait> {
-    TailCall(TailCall<'a, R>),
-    Value(Value<R>),
-}
-#[derive(Debug, Clone, PartialEq)]
-enum TailCall<'a, R: RealNumberInternalTrait> {
-    Ref(&'a Expression, &'a [Expression], Rc<Environment<R>>),
-    Owned(
-        Expression,
-        Vec<Expression>,
+enum TailExpressionResult<R: RealNumberInternalTrait> {
+    // procedure and arguments of a call in tail position, the location of the call and the
+    // environment it is made in, which builtins are applied in
+    TailCall(
+        Procedure<R>,
+        ArgVec<R>,
+        Option<Location>,
         Rc<Environment<R>>,
-        PhantomData<R>,
     ),
+    Value(Value<R>),
+}
+thread_local! {
+    // frames of procedures being applied, innermost last
+    static CALL_STACK: RefCell<Vec<StackFrame>> = RefCell::new(Vec::new());
+    // procedure applications left for the running statement, None means unlimited
+    static FUEL: Cell<Option<u64>> = Cell::new(None);
+    // procedure applications on this thread, which `time` reports
+    static APPLICATIONS: Cell<u64> = const { Cell::new(0) };
+    // nesting of expressions left for the running statement, including the expressions of
+    // procedures applied by them, and the limit it started from
+    static DEPTH_LEFT: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+    static MAX_DEPTH: Cell<usize> = Cell::new(DEFAULT_MAX_DEPTH);
+    // interrupt flag of the interpreter running on this thread
+    static INTERRUPT: RefCell<Option<Arc<AtomicBool>>> = RefCell::new(None);
+    // environments of closures replaced by define or set!, which may be kept alive by reference cycles
+    static CYCLE_CANDIDATES: RefCell<Vec<Weak<dyn Any>>> = RefCell::new(Vec::new());
+}
+
+fn add_cycle_candidate<R: RealNumberInternalTrait>(replaced: Option<Value<R>>) {
+    if let Some(Value::Procedure(Procedure::User(_, env))) = replaced {
+        let env: Weak<dyn Any> = Rc::downgrade(&(env as Rc<dyn Any>));
+        CYCLE_CANDIDATES.with(|candidates| candidates.borrow_mut().push(env));
+    }
+}
+
+// environments referred by value, vectors shared with other values are skipped
+// as their references cannot be attributed to value
+fn referred_environments<R: RealNumberInternalTrait>(
+    value: &Value<R>,
+    found: &mut Vec<Rc<Environment<R>>>,
+) {
+    // recurses once per level of nesting of the cars and vectors
+    stack::guard(|| match value {
+        Value::Procedure(Procedure::User(_, env)) => found.push(env.clone()),
+        Value::Pair(pair) => {
+            if let GenericPair::Some(car, cdr) = pair.as_ref() {
+                referred_environments(car, found);
+                referred_environments(cdr, found);
+            }
+        }
+        Value::Vector(ValueReference::Immutable(vector)) if Rc::strong_count(vector) == 1 => {
+            for element in vector.iter() {
+                referred_environments(element, found);
+            }
+        }
+        Value::Vector(ValueReference::Mutable(vector)) if Rc::strong_count(vector) == 1 => {
+            for element in vector.borrow().iter() {
+                referred_environments(element, found);
+            }
+        }
+        _ => (),
+    })
 }
-impl<'a, R: RealNumberInternalTrait> TailCall<'a, R> {
-    pub fn as_ref(&'a self) -> (&'a Expression, &'a [Expression], &Rc<Environment<R>>) {
-        match self {
-            TailCall::Ref(procedure_expr, arguments, env) => (procedure_expr, arguments, env),
-            TailCall::Owned(procedure_expr, arguments, env, _) => (procedure_expr, arguments, env),
+
+// trial deletion: an environment is garbage if all of its references come from environments
+// reachable from the candidates, and it is not reachable from an environment referred from elsewhere
+fn collect_cycles<R: RealNumberInternalTrait>(
+    candidates: Vec<Rc<Environment<R>>>,
+    root: &Rc<Environment<R>>,
+) {
+    let mut environments: Vec<Rc<Environment<R>>> = vec![];
+    let mut indices = HashMap::new();
+    let mut edges: Vec<Vec<usize>> = vec![];
+    let mut internal_references: Vec<usize> = vec![];
+    for candidate in candidates {
+        if !Rc::ptr_eq(&candidate, root) && !indices.contains_key(&Rc::as_ptr(&candidate)) {
+            indices.insert(Rc::as_ptr(&candidate), environments.len());
+            environments.push(candidate);
+            edges.push(vec![]);
+            internal_references.push(0);
+        }
+    }
+    let mut index = 0;
+    while index < environments.len() {
+        let mut found = vec![];
+        if let Some(parent) = environments[index].parent() {
+            found.push(parent.clone());
+        }
+        for (_, value) in &mut *environments[index].iter_local_definitions() {
+            referred_environments(value, &mut found);
+        }
+        for env in found {
+            if Rc::ptr_eq(&env, root) {
+                continue;
+            }
+            let target = *indices.entry(Rc::as_ptr(&env)).or_insert_with(|| {
+                environments.push(env);
+                edges.push(vec![]);
+                internal_references.push(0);
+                environments.len() - 1
+            });
+            internal_references[target] += 1;
+            edges[index].push(target);
+        }
+        index += 1;
+    }
+    // one reference of each environment is held by `environments`
+    let mut live = environments
+        .iter()
+        .zip(internal_references.iter())
+        .map(|(env, internal)| Rc::strong_count(env) - 1 > *internal)
+        .collect::<Vec<_>>();
+    let mut pending = (0..environments.len())
+        .filter(|index| live[*index])
+        .collect::<Vec<_>>();
+    while let Some(index) = pending.pop() {
+        for target in &edges[index] {
+            if !live[*target] {
+                live[*target] = true;
+                pending.push(*target);
+            }
         }
     }
+    // values are dropped after all definitions are taken, so that no scope is borrowed meanwhile
+    let garbage = environments
+        .iter()
+        .zip(live)
+        .filter(|(_, live)| !live)
+        .map(|(env, _)| env.take_definitions())
+        .collect::<Vec<_>>();
+    drop(garbage);
+}
+
+/// default limit of nested evaluation, see `Interpreter::set_max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 100_000;
+
+fn check_interrupt() -> Result<()> {
+    INTERRUPT.with(|interrupt| match &*interrupt.borrow() {
+        Some(flag) if flag.load(Ordering::Relaxed) => error!(ErrorData::Interrupted),
+        _ => Ok(()),
+    })
+}
+
+fn consume_fuel() -> Result<()> {
+    APPLICATIONS.with(|applications| applications.set(applications.get() + 1));
+    FUEL.with(|fuel| match fuel.get() {
+        Some(0) => error!(ErrorData::FuelExhausted),
+        Some(left) => {
+            fuel.set(Some(left - 1));
+            Ok(())
+        }
+        None => Ok(()),
+    })
+}
+
+/// number of procedure applications evaluated on this thread
+pub(crate) fn applications() -> u64 {
+    APPLICATIONS.with(Cell::get)
+}
+
+// run the debugger on `(debug)` called in `env`, None without a debugger
+pub(crate) fn debug_breakpoint<R: RealNumberInternalTrait>(
+    env: Rc<Environment<R>>,
+) -> Option<Result<()>> {
+    debug::pause(|| Breakpoint {
+        env,
+        backtrace: Backtrace::new(
+            CALL_STACK.with(|stack| stack.borrow().iter().rev().cloned().collect()),
+            usize::MAX,
+        ),
+    })
 }
+
 pub struct LibraryLoader<'a, R: RealNumberInternalTrait> {
     lib_factories: HashMap<LibraryName, Rc<LibraryFactory<'a, R>>>,
 }
@@ -126,12 +306,69 @@ impl<'a, R: RealNumberInternalTrait> LibraryLoader<'a, R> {
     }
 }
 
+// what `cond-expand` is matched against in the programs of an interpreter: its features and the
+// libraries it can import, which are updated before each program is parsed
+struct Availability {
+    features: Rc<RefCell<Vec<String>>>,
+    libraries: RefCell<HashSet<LibraryName>>,
+    // directories searched for the files of libraries
+    directories: RefCell<Vec<PathBuf>>,
+}
+
+impl Features for Availability {
+    fn has_feature(&self, feature: &str) -> bool {
+        self.features.borrow().iter().any(|f| f == feature)
+    }
+
+    fn has_library(&self, name: &LibraryName) -> bool {
+        self.libraries.borrow().contains(name)
+            || self.directories.borrow().iter().any(|directory| {
+                ["sld", "scm"].iter().any(|extension| {
+                    directory
+                        .join(name.path())
+                        .with_extension(extension)
+                        .exists()
+                })
+            })
+    }
+}
+
+// directories listed in RUSCHM_LIBRARY_PATH, separated like PATH
+fn default_library_paths() -> Vec<PathBuf> {
+    match std::env::var_os("RUSCHM_LIBRARY_PATH") {
+        Some(paths) => std::env::split_paths(&paths).collect(),
+        None => vec![],
+    }
+}
+
+/// An interpreter and its values share state through `Rc`, so they are neither `Send` nor `Sync`.
+/// To evaluate on another thread, create the interpreter on that thread and exchange plain Rust
+/// data converted from and into `Value`, the interrupt flag is the only handle shared across threads.
 pub struct Interpreter<'a, R: RealNumberInternalTrait> {
     pub env: Rc<Environment<R>>,
     lib_loader: LibraryLoader<'a, R>,
     imported_library: HashSet<LibraryName>,
+    // evaluated libraries, a library body is evaluated once however many times it is imported
+    libraries: HashMap<LibraryName, Library<R>>,
+    library_paths: Vec<PathBuf>,
     import_end: bool, // indicate program's import declaration part end
     pub program_directory: Option<PathBuf>,
+    command_line: Rc<RefCell<Vec<String>>>,
+    features: Rc<RefCell<Vec<String>>>,
+    available: Rc<Availability>,
+    random: Rc<RefCell<native::random::Xoshiro256>>,
+    backtrace_depth: usize,
+    fuel: Option<u64>,
+    max_depth: usize,
+    interrupt: Arc<AtomicBool>,
+    current_input_port: Rc<Parameter<R>>,
+    current_output_port: Rc<Parameter<R>>,
+    // the ports flushed by `flush_output_ports`
+    output_ports: Rc<OutputPorts>,
+    dump_ir: bool,
+    optimize: bool,
+    tracer: Option<Tracer<R>>,
+    debugger: Option<Debugger<R>>,
     _marker: PhantomData<R>,
 }
 
@@ -147,49 +384,327 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
     }
 
     pub fn with_environment(environment: Rc<Environment<R>>) -> Self {
+        let features = Rc::new(RefCell::new(default_features()));
+        let output_ports = Rc::new(OutputPorts::default());
         let mut interpreter = Self {
             env: environment,
             lib_loader: LibraryLoader::new(),
             imported_library: HashSet::new(),
+            libraries: HashMap::new(),
+            library_paths: default_library_paths(),
             import_end: false,
             program_directory: None,
+            command_line: Rc::new(RefCell::new(Vec::new())),
+            available: Rc::new(Availability {
+                features: features.clone(),
+                libraries: RefCell::new(HashSet::new()),
+                directories: RefCell::new(vec![]),
+            }),
+            features,
+            random: Rc::new(RefCell::new(native::random::Xoshiro256::new())),
+            backtrace_depth: 32,
+            fuel: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            current_input_port: Rc::new(Parameter::new(
+                Value::Port(Port::new_input(InputPort::stdin())),
+                None,
+            )),
+            current_output_port: Rc::new(Parameter::new(
+                Value::Port(
+                    output_ports.open(OutputPort::from_writer("stdout", std::io::stdout())),
+                ),
+                None,
+            )),
+            output_ports,
+            dump_ir: false,
+            optimize: false,
+            tracer: None,
+            debugger: None,
             _marker: PhantomData,
         };
         interpreter.register_stdlib_factories();
+        interpreter.define_intrinsics(interpreter.env.clone());
         interpreter
     }
 
+    /// bind the procedures derived forms like `guard` expand to in `env`, which is done for the
+    /// top level environment and the environments of libraries. Environments replacing `env`
+    /// need them too.
+    pub fn define_intrinsics(&mut self, env: Rc<Environment<R>>) {
+        self.eval_import(
+            &ImportDeclaration(vec![import_library_direct!("ruschm", "intrinsics")]),
+            env,
+        )
+        .unwrap();
+    }
+
     pub fn import_stdlib(&mut self) {
         self.eval_import(
             &ImportDeclaration(vec![
                 import_library_direct!("scheme", "base"),
+                import_library_direct!("scheme", "file"),
+                import_library_direct!("scheme", "load"),
+                import_library_direct!("scheme", "process-context"),
+                import_library_direct!("scheme", "read"),
                 import_library_direct!("scheme", "write"),
+                import_library_direct!("ruschm", "debug"),
+                import_library_direct!("ruschm", "random"),
+                import_library_direct!("ruschm", "reflection"),
             ]),
             self.env.clone(),
         )
         .unwrap();
     }
 
+    /// set the list returned by `(command-line)`, the first element should be the script name
+    pub fn set_command_line(&mut self, arguments: impl IntoIterator<Item = String>) {
+        *self.command_line.borrow_mut() = arguments.into_iter().collect();
+    }
+
+    /// advertise a feature in `(features)` and make `cond-expand` match it
+    pub fn add_feature(&mut self, feature: impl Into<String>) {
+        let feature = feature.into();
+        let mut features = self.features.borrow_mut();
+        if !features.contains(&feature) {
+            features.push(feature);
+        }
+    }
+
+    /// maximum number of frames kept in the backtraces of errors, the innermost frames are kept
+    pub fn set_backtrace_depth(&mut self, depth: usize) {
+        self.backtrace_depth = depth;
+    }
+
+    /// limit the number of procedure applications of following evaluations,
+    /// exceeding it aborts evaluation with `ErrorData::FuelExhausted`
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel = Some(fuel);
+    }
+
+    /// limit the nesting of expressions being evaluated, counting those in the bodies of
+    /// procedures called in non-tail positions, exceeding it aborts evaluation with
+    /// `ErrorData::RecursionTooDeep`
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// remove the limit set by `set_fuel`, which is the default
+    pub fn clear_fuel(&mut self) {
+        self.fuel = None;
+    }
+
+    /// fuel left, None if unlimited
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// print the compiled form of each evaluated expression or definition to stderr
+    pub fn set_dump_ir(&mut self, dump_ir: bool) {
+        self.dump_ir = dump_ir;
+    }
+
+    /// simplify expressions and definitions before evaluating them, see `optimize_statement`
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// pass the events of following evaluations to `tracer`, see `TraceEvent`. Without a
+    /// tracer, which is the default, events are not built.
+    pub fn set_tracer(&mut self, tracer: Tracer<R>) {
+        self.tracer = Some(tracer);
+    }
+
+    /// remove the tracer set by `set_tracer`
+    pub fn clear_tracer(&mut self) -> Option<Tracer<R>> {
+        self.tracer.take()
+    }
+
+    /// run `debugger` when following evaluations call `(debug)`. Without a debugger, which is
+    /// the default, `(debug)` warns and evaluation continues.
+    pub fn set_debugger(&mut self, debugger: Debugger<R>) {
+        self.debugger = Some(debugger);
+    }
+
+    /// remove the debugger set by `set_debugger`
+    pub fn clear_debugger(&mut self) -> Option<Debugger<R>> {
+        self.debugger.take()
+    }
+
+    /// setting the flag aborts the running evaluation with `ErrorData::Interrupted`,
+    /// it can be set from another thread or a signal handler, it is cleared when an evaluation
+    /// starts
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// search `directory` for library files after the directory of the importing file
+    /// and the directories added before
+    pub fn add_library_path(&mut self, directory: impl Into<PathBuf>) {
+        self.library_paths.push(directory.into());
+    }
+
+    /// directories searched for library files, initialized from `RUSCHM_LIBRARY_PATH`
+    pub fn library_paths(&self) -> &[PathBuf] {
+        &self.library_paths
+    }
+
+    /// define a builtin procedure taking `arity` arguments in the top-level environment
+    ///
+    /// ```
+    /// use ruschm::{interpreter::DefaultInterpreter, values::Value};
+    /// use std::{cell::RefCell, rc::Rc};
+    /// let logs = Rc::new(RefCell::new(Vec::new()));
+    /// let mut it = DefaultInterpreter::new_with_stdlib();
+    /// let host_logs = logs.clone();
+    /// it.register_fn("host-log", 1, move |arguments| {
+    ///     host_logs.borrow_mut().push(arguments[0].to_string());
+    ///     Ok(Value::Void)
+    /// });
+    /// it.eval_str("(host-log \"started\") (for-each host-log '(a b))")?;
+    /// assert_eq!(*logs.borrow(), vec!["started", "a", "b"]);
+    /// # Ok::<(), ruschm::error::SchemeError>(())
+    /// ```
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<String>,
+        arity: usize,
+        closure: impl Fn(ArgVec<R>) -> Result<Value<R>> + 'static,
+    ) {
+        let name = name.into();
+        let parameters = ParameterFormals::new_non_located(
+            (1..=arity).map(|index| format!("arg{}", index).into()),
+            None,
+        );
+        self.env.define(
+            name.clone(),
+            Value::Procedure(Procedure::new_builtin_closure(name, parameters, closure)),
+        );
+    }
+
+    /// read the input of scheme programs from `reader` instead of stdin,
+    /// by replacing the value of `current-input-port`
+    pub fn set_input(&mut self, reader: impl BufRead + 'static) {
+        self.current_input_port
+            .replace(Value::Port(Port::new_input(InputPort::from_reader(
+                "input", reader,
+            ))));
+    }
+
+    /// write the output of scheme programs to `writer` instead of stdout,
+    /// by replacing the value of `current-output-port`
+    pub fn set_output(&mut self, writer: Rc<RefCell<dyn Write>>) {
+        self.current_output_port.replace(Value::Port(
+            self.output_ports
+                .open(OutputPort::from_shared_writer("output", writer)),
+        ));
+    }
+
+    /// flush the output ports opened by this interpreter which are still alive: its standard
+    /// output and error and the files opened by the program. Call it before the process exits,
+    /// buffered data is dropped silently otherwise.
+    pub fn flush_output_ports(&self) -> Result<()> {
+        self.output_ports.flush()
+    }
+
+    /// free closures no longer reachable but kept alive by referring to themselves, like the
+    /// procedure of `(define f ((lambda () (define (g) g) g)))` after f is redefined. Only closures
+    /// replaced by define or set! are considered, it runs after each top-level statement.
+    pub fn collect_cycles(&self) {
+        let candidates = CYCLE_CANDIDATES.with(|candidates| candidates.replace(Vec::new()));
+        if !candidates.is_empty() {
+            collect_cycles(
+                candidates
+                    .into_iter()
+                    .filter_map(|env| env.upgrade())
+                    .filter_map(|env| env.downcast::<Environment<R>>().ok())
+                    .collect(),
+                &self.env,
+            );
+        }
+    }
+
     pub fn get_lib_loader(&self) -> &LibraryLoader<R> {
         &self.lib_loader
     }
     pub fn append_lib_loader(&mut self, lib_loader: LibraryLoader<'a, R>) {
+        for name in lib_loader.lib_factories.keys() {
+            self.libraries.remove(name);
+        }
         self.lib_loader
             .lib_factories
             .extend(lib_loader.lib_factories.into_iter());
     }
     pub fn register_library_factory(&mut self, library_factory: LibraryFactory<'a, R>) {
+        self.libraries.remove(library_factory.get_library_name());
         self.lib_loader.register_library_factory(library_factory);
     }
 
     fn register_stdlib_factories(&mut self) {
+        let command_line = self.command_line.clone();
+        let features = self.features.clone();
+        let available = self.available.clone();
+        let random = self.random.clone();
+        let current_input_port = self.current_input_port.clone();
+        let current_output_port = self.current_output_port.clone();
+        let output_ports = self.output_ports.clone();
+        let current_error_port = Rc::new(Parameter::new(
+            Value::Port(
+                self.output_ports
+                    .open(OutputPort::from_writer("stderr", std::io::stderr())),
+            ),
+            None,
+        ));
+        let intrinsics_error_port = current_error_port.clone();
         self.register_library_factory(LibraryFactory::Native(
             library_name!("ruschm", "base"),
-            Box::new(native::base::library_map),
+            Box::new(move || native::base::library_map(features.clone())),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "debug"),
+            Box::new(native::debug::library_map),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "file"),
+            Box::new(move || native::file::library_map(output_ports.clone())),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "intrinsics"),
+            Box::new(move || native::intrinsics::library_map(intrinsics_error_port.clone())),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "list"),
+            Box::new(native::list::library_map),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "load"),
+            Box::new(move || native::load::library_map(available.clone())),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "process-context"),
+            Box::new(move || native::process_context::library_map(command_line.clone())),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "random"),
+            Box::new(move || native::random::library_map(random.clone())),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "reflection"),
+            Box::new(native::reflection::library_map),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "string"),
+            Box::new(native::string::library_map),
+        ));
+        self.register_library_factory(LibraryFactory::Native(
+            library_name!("ruschm", "read"),
+            Box::new(move || native::read::library_map(current_input_port.clone())),
         ));
         self.register_library_factory(LibraryFactory::Native(
             library_name!("ruschm", "write"),
-            Box::new(native::write::library_map),
+            Box::new(move || {
+                native::write::library_map(current_output_port.clone(), current_error_port.clone())
+            }),
         ));
         self.register_library_factory(
             LibraryFactory::from_char_stream(
@@ -198,6 +713,48 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
             )
             .unwrap(),
         );
+        self.register_library_factory(
+            LibraryFactory::from_char_stream(
+                &library_name!("scheme", "file"),
+                include_str!("library/include/scheme/file.sld").chars(),
+            )
+            .unwrap(),
+        );
+        self.register_library_factory(
+            LibraryFactory::from_char_stream(
+                &library_name!("scheme", "list"),
+                include_str!("library/include/scheme/list.sld").chars(),
+            )
+            .unwrap(),
+        );
+        self.register_library_factory(
+            LibraryFactory::from_char_stream(
+                &library_name!("srfi", 1),
+                include_str!("library/include/srfi/1.sld").chars(),
+            )
+            .unwrap(),
+        );
+        self.register_library_factory(
+            LibraryFactory::from_char_stream(
+                &library_name!("scheme", "load"),
+                include_str!("library/include/scheme/load.sld").chars(),
+            )
+            .unwrap(),
+        );
+        self.register_library_factory(
+            LibraryFactory::from_char_stream(
+                &library_name!("scheme", "process-context"),
+                include_str!("library/include/scheme/process-context.sld").chars(),
+            )
+            .unwrap(),
+        );
+        self.register_library_factory(
+            LibraryFactory::from_char_stream(
+                &library_name!("scheme", "read"),
+                include_str!("library/include/scheme/read.sld").chars(),
+            )
+            .unwrap(),
+        );
         self.register_library_factory(
             LibraryFactory::from_char_stream(
                 &library_name!("scheme", "write"),
@@ -207,39 +764,34 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
         );
     }
 
-    fn apply_scheme_procedure<'b>(
-        formals: &ParameterFormals,
-        internal_definitions: &[Definition],
-        expressions: &'b [Expression],
+    fn apply_scheme_procedure(
+        lambda: &Lambda<R>,
         closure: Rc<Environment<R>>,
         args: ArgVec<R>,
-    ) -> Result<TailExpressionResult<'b, R>> {
-        let local_env = Rc::new(Environment::new_child(closure.clone()));
+    ) -> Result<TailExpressionResult<R>> {
+        // slots are laid out as resolve_statement expects: fixed parameters, the variadic
+        // parameter, then internal definitions
+        let mut slots = Vec::with_capacity(lambda.slots.len());
         let mut arg_iter = args.into_iter();
-        match formals.iter_to_last(|formal| {
-            let arg = arg_iter.next().unwrap();
-            local_env.define(formal.as_name(), arg);
-        }) {
-            Some(variadic) => {
-                let list = arg_iter.collect::<Pair<R>>();
-                local_env.define(variadic.as_name(), Value::Pair(Box::new(list)));
-            }
-            None => {}
+        slots.extend(arg_iter.by_ref().take(lambda.fixed));
+        if lambda.variadic {
+            slots.push(Value::Pair(Box::new(arg_iter.collect())));
         }
-        // if let Some(variadic) = &formals.1 {
-        //     let list = arg_iter.collect();
-        //     local_env.define(variadic.clone(), Value::Pair(list));
-        // }
-        for DefinitionBody(name, expr) in internal_definitions.into_iter().map(|d| &d.data) {
-            let value = Self::eval_expression(&expr, &local_env)?;
-            local_env.define(name.clone(), value)
+        // r7rs 5.3.2: internal definitions have letrec* semantics, all names are bound
+        // before any initializer is evaluated
+        let first_definition = slots.len();
+        slots.resize_with(lambda.slots.len(), || Value::Uninitialized);
+        let local_env = Rc::new(Environment::new_frame(closure, lambda.slots.clone(), slots));
+        for (slot, definition) in lambda.definitions.iter().enumerate() {
+            let value = Self::eval_node(definition, &local_env)?;
+            local_env.replace_slot(0, first_definition + slot, value);
         }
-        match expressions.split_last() {
+        match lambda.body.split_last() {
             Some((last, other)) => {
-                for expr in other {
-                    Self::eval_expression(&expr, &local_env)?;
+                for node in other {
+                    Self::eval_node(node, &local_env)?;
                 }
-                Self::eval_tail_expression(last, local_env)
+                Self::eval_tail_node(last, &local_env)
             }
             None => unreachable!(),
         }
@@ -249,222 +801,299 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
         Self::eval_expression(&expression, &self.env)
     }
 
-    fn eval_procedure_call(
-        procedure_expr: &Expression,
-        arguments: &[Expression],
+    fn eval_call(
+        procedure_node: &Node<R>,
+        arguments: &[Node<R>],
         env: &Rc<Environment<R>>,
     ) -> Result<(Procedure<R>, ArgVec<R>)> {
-        let first = Self::eval_expression(procedure_expr, env)?;
-        let evaluated_args_result = arguments
+        let first = Self::eval_node(procedure_node, env)?;
+        let evaluated_args = arguments
             .iter()
-            .map(|arg| Self::eval_expression(arg, env))
+            .map(|arg| Self::eval_node(arg, env))
             .collect::<Result<ArgVec<_>>>()?;
-        Ok((first.expect_procedure()?, evaluated_args_result))
+        match first {
+            Value::Procedure(procedure) => Ok((procedure, evaluated_args)),
+            other => located_error!(
+                LogicError::TypeMisMatch(other.written().to_string(), Type::Procedure),
+                procedure_node.location
+            ),
+        }
+    }
+
+    fn check_arity(
+        procedure: &Procedure<R>,
+        args: &ArgVec<R>,
+        location: Option<Location>,
+    ) -> Result<()> {
+        let (fixed_len, has_variadic) = procedure.arity();
+        if args.len() < fixed_len || (args.len() > fixed_len && !has_variadic) {
+            return located_error!(
+                wrong_arity(
+                    &procedure.signature(),
+                    (fixed_len, has_variadic),
+                    args.len()
+                ),
+                location
+            );
+        }
+        Ok(())
     }
 
     pub fn apply_procedure<'b>(
+        initial_procedure: &Procedure<R>,
+        args: ArgVec<R>,
+        env: &Rc<Environment<R>>,
+    ) -> Result<Value<R>> {
+        Self::apply_procedure_at(initial_procedure, args, env, None)
+    }
+
+    // call_location is the location of the call site, which is reported by arity errors
+    fn apply_procedure_at(
+        initial_procedure: &Procedure<R>,
+        args: ArgVec<R>,
+        env: &Rc<Environment<R>>,
+        call_location: Option<Location>,
+    ) -> Result<Value<R>> {
+        let depth = CALL_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            stack.push(StackFrame {
+                procedure: initial_procedure.name().to_string(),
+                location: call_location,
+                tail_calls: 0,
+            });
+            stack.len()
+        });
+        trace(|| TraceEvent::EnterCall {
+            name: initial_procedure.name().to_string(),
+            arguments: args.to_vec(),
+            location: call_location,
+            depth,
+        });
+        let mut result =
+            Self::apply_procedure_in_frame(initial_procedure, args, env, call_location);
+        CALL_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            trace(|| TraceEvent::ExitCall {
+                name: stack.last().unwrap().procedure.clone(),
+                result: result.clone(),
+                depth,
+            });
+            // the stack is recorded when the error leaves its innermost frame
+            if let Err(error) = &mut result {
+                if error.backtrace.is_none() {
+                    error.backtrace = Some(Backtrace::new(
+                        stack.iter().rev().cloned().collect(),
+                        usize::MAX,
+                    ));
+                }
+            }
+            stack.pop();
+        });
+        result
+    }
+
+    fn apply_procedure_in_frame(
         initial_procedure: &Procedure<R>,
         mut args: ArgVec<R>,
         env: &Rc<Environment<R>>,
+        mut call_location: Option<Location>,
     ) -> Result<Value<R>> {
-        let formals = initial_procedure.get_parameters();
-        // let args = args.into_iter();
-        // formals.iter_to_last(|formal| args.next)
-        let (fixed_len, has_variadic) = formals.len();
-        if args.len() < fixed_len || (args.len() > fixed_len && !has_variadic) {
-            return error!(LogicError::ArgumentMissMatch(
-                formals.clone(),
-                format!("{}", args.iter().join(" "))
-            ));
-        }
-        let mut current_procedure = None;
+        let mut tail_procedure;
+        let mut procedure = initial_procedure;
+        let mut tail_env;
+        let mut env = env;
         loop {
-            match if current_procedure.is_none() {
-                initial_procedure
-            } else {
-                current_procedure.as_ref().unwrap()
-            } {
-                Procedure::Builtin(BuiltinProcedure { body, .. }) => {
-                    break body.apply(args, env);
+            check_interrupt()?;
+            consume_fuel()?;
+            Self::check_arity(procedure, &args, call_location)?;
+            match procedure {
+                Procedure::Builtin(builtin) => {
+                    break builtin.body.apply(args, env).map_err(|e| {
+                        match e {
+                            SchemeError {
+                                data: ErrorData::Logic(error),
+                                location,
+                                backtrace,
+                            } => SchemeError {
+                                data: ErrorData::Logic(error.in_procedure(&builtin.name)),
+                                location,
+                                backtrace,
+                            },
+                            other => other,
+                        }
+                        .or_locate(call_location)
+                    });
                 }
-                Procedure::User(SchemeProcedure(formals, definitions, expressions), closure) => {
-                    let apply_result = Self::apply_scheme_procedure(
-                        formals,
-                        definitions,
-                        expressions,
-                        closure.clone(),
-                        args,
-                    )?;
-                    match apply_result {
-                        TailExpressionResult::TailCall(tail_call) => {
-                            let (tail_procedure_expr, tail_arguments, last_env) =
-                                tail_call.as_ref();
-                            let (tail_procedure, tail_args) = Self::eval_procedure_call(
-                                tail_procedure_expr,
-                                tail_arguments,
-                                &last_env,
-                            )?;
-                            current_procedure = Some(tail_procedure);
-                            args = tail_args;
+                Procedure::Parameter(parameter) => {
+                    break Ok(parameter.get());
+                }
+                Procedure::User(lambda, closure) => {
+                    match Self::apply_scheme_procedure(lambda, closure.clone(), args)? {
+                        TailExpressionResult::TailCall(
+                            next_procedure,
+                            next_args,
+                            location,
+                            next_env,
+                        ) => {
+                            call_location = location;
+                            CALL_STACK.with(|stack| {
+                                let mut stack = stack.borrow_mut();
+                                let depth = stack.len();
+                                if let Some(frame) = stack.last_mut() {
+                                    frame.procedure = next_procedure.name().to_string();
+                                    frame.location = call_location;
+                                    frame.tail_calls += 1;
+                                }
+                                trace(|| TraceEvent::TailCall {
+                                    name: next_procedure.name().to_string(),
+                                    arguments: next_args.to_vec(),
+                                    location: call_location,
+                                    depth,
+                                });
+                            });
+                            tail_procedure = next_procedure;
+                            procedure = &tail_procedure;
+                            args = next_args;
+                            tail_env = next_env;
+                            env = &tail_env;
                         }
                         TailExpressionResult::Value(return_value) => {
                             break Ok(return_value);
                         }
-                    };
+                    }
                 }
             };
         }
     }
 
-    fn eval_tail_expression<'b>(
-        expression: &'b Expression,
-        env: Rc<Environment<R>>,
-    ) -> Result<TailExpressionResult<'b, R>> {
-        Ok(match &expression.data {
-            ExpressionBody::ProcedureCall(procedure_expr, arguments) => {
-                TailExpressionResult::TailCall(TailCall::Ref(
-                    procedure_expr.as_ref(),
-                    arguments,
-                    env,
-                ))
+    // a call in tail position is returned instead of applied, so that apply_procedure_in_frame
+    // can run it in the current frame
+    fn eval_tail_node(node: &Node<R>, env: &Rc<Environment<R>>) -> Result<TailExpressionResult<R>> {
+        Ok(match &node.data {
+            NodeBody::Call(procedure_node, arguments) => {
+                let (procedure, args) = Self::eval_call(procedure_node, arguments, env)
+                    .map_err(|e| e.or_locate(node.location))?;
+                TailExpressionResult::TailCall(
+                    procedure,
+                    args,
+                    procedure_node.location,
+                    env.clone(),
+                )
             }
-            ExpressionBody::Conditional(cond) => {
-                let (test, consequent, alternative) = cond.as_ref();
-                let condition = Self::eval_expression(&test, &env)?.as_boolean();
-                if condition {
-                    Self::eval_tail_expression(consequent, env)?
+            NodeBody::If(branches) => {
+                let (test, consequent, alternative) = branches.as_ref();
+                if Self::eval_node(test, env)?.as_boolean() {
+                    Self::eval_tail_node(consequent, env)?
                 } else {
                     match alternative {
-                        Some(alter) => Self::eval_tail_expression(alter, env)?,
+                        Some(alternative) => Self::eval_tail_node(alternative, env)?,
                         None => TailExpressionResult::Value(Value::Void),
                     }
                 }
             }
-            _ => TailExpressionResult::Value(Self::eval_expression(&expression, &env)?),
+            _ => TailExpressionResult::Value(Self::eval_node(node, env)?),
         })
     }
-    // during eval_tail_expression, some expression will expand to owned expression
-    // it has to repeat this logic for owned expression, no other workaround
-    fn eval_owned_tail_expression<'b>(
-        expression: Expression,
-        env: Rc<Environment<R>>,
-    ) -> Result<TailExpressionResult<'b, R>> {
-        Ok(match &expression.data {
-            ExpressionBody::ProcedureCall(..) | ExpressionBody::Conditional(_) => {
-                match expression.extract_data() {
-                    ExpressionBody::ProcedureCall(procedure_expr, arguments) => {
-                        TailExpressionResult::TailCall(TailCall::Owned(
-                            *procedure_expr,
-                            arguments,
-                            env,
-                            PhantomData,
-                        ))
-                    }
-                    ExpressionBody::Conditional(cond) => {
-                        let (test, consequent, alternative) = *cond;
-                        let condition = Self::eval_expression(&test, &env)?.as_boolean();
-                        if condition {
-                            Self::eval_owned_tail_expression(consequent, env)?
-                        } else {
-                            match alternative {
-                                Some(alter) => Self::eval_owned_tail_expression(alter, env)?,
-                                None => TailExpressionResult::Value(Value::Void),
-                            }
-                        }
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            _ => TailExpressionResult::Value(Self::eval_expression(&expression, &env)?),
-        })
+
+    pub fn read_literal(datum: &Datum, _env: &Rc<Environment<R>>) -> Result<Value<R>> {
+        datum_value(datum)
     }
-    pub fn read_literal(datum: &Datum, env: &Rc<Environment<R>>) -> Result<Value<R>> {
-        match &datum.data {
-            DatumBody::Primitive(primitive) => Self::eval_primitive(&primitive),
-            DatumBody::Symbol(name) => Ok(Value::Symbol(name.clone())),
-            DatumBody::Pair(list) => Ok(Value::Pair(Box::new(
-                list.map_ok_ref(&mut |i| Self::read_literal(i, env))?,
-            ))),
-            DatumBody::Vector(vec) => Ok(Value::Vector(ValueReference::new_immutable(
-                vec.into_iter()
-                    .map(|i| Self::read_literal(i, env))
-                    .collect::<Result<_>>()?,
-            ))),
+
+    /// compile and evaluate an expression, local variables must have been resolved
+    pub fn eval_expression(expression: &Expression, env: &Rc<Environment<R>>) -> Result<Value<R>> {
+        Self::eval_node(&compile_expression(expression)?, env)
+    }
+
+    fn eval_node(node: &Node<R>, env: &Rc<Environment<R>>) -> Result<Value<R>> {
+        trace::<R>(|| TraceEvent::EvalExpression {
+            location: node.location,
+        });
+        match &node.data {
+            NodeBody::Constant(_)
+            | NodeBody::Global(_)
+            | NodeBody::Local(..)
+            | NodeBody::Lambda(_) => Self::eval_node_data(node, env),
+            _ => Self::eval_nested_node(node, env),
         }
+        .map_err(|e| e.or_locate(node.location))
     }
 
-    fn eval_primitive(datum: &Primitive) -> Result<Value<R>> {
-        Ok(match &datum {
-            Primitive::Character(c) => Value::Character(*c),
-            Primitive::String(string) => Value::String(string.clone()),
-            Primitive::Boolean(value) => Value::Boolean(*value),
-            Primitive::Integer(value) => Value::Number(Number::Integer(*value)),
-            Primitive::Real(number_literal) => Value::Number(Number::Real(
-                R::from(number_literal.parse::<f64>().unwrap()).unwrap(),
-            )),
-            // TODO: apply gcd here.
-            Primitive::Rational(a, b) => Value::Number(Number::Rational(*a, *b as i32)),
-        })
+    // nodes evaluating other nodes count towards the depth limit
+    fn eval_nested_node(node: &Node<R>, env: &Rc<Environment<R>>) -> Result<Value<R>> {
+        let depth_left = DEPTH_LEFT.with(|left| left.replace(left.get().saturating_sub(1)));
+        // the stack is checked every few levels, which the red zone of stack::guard leaves room for
+        let result = if depth_left == 0 {
+            error!(ErrorData::RecursionTooDeep(MAX_DEPTH.with(Cell::get)))
+        } else if depth_left % 4 == 0 {
+            stack::guard(|| Self::eval_node_data(node, env))
+        } else {
+            Self::eval_node_data(node, env)
+        };
+        DEPTH_LEFT.with(|left| left.set(depth_left));
+        result
     }
 
-    pub fn eval_expression(expression: &Expression, env: &Rc<Environment<R>>) -> Result<Value<R>> {
-        Ok(match &expression.data {
-            ExpressionBody::Primitive(datum) => Self::eval_primitive(datum)?,
-            ExpressionBody::Datum(datum) => Self::read_literal(datum, env)?,
-            ExpressionBody::ProcedureCall(procedure_expr, arguments) => {
-                let first = Self::eval_expression(procedure_expr, env)?;
-                let evaluated_args: Result<ArgVec<_>> = arguments
-                    .into_iter()
-                    .map(|arg| Self::eval_expression(arg, env))
-                    .collect();
-                match first {
-                    Value::Procedure(procedure) => {
-                        Self::apply_procedure(&procedure, evaluated_args?, env)?
-                    }
-                    other => {
-                        return located_error!(
-                            LogicError::TypeMisMatch(other.to_string(), Type::Procedure),
-                            procedure_expr.location
-                        )
-                    }
+    fn eval_node_data(node: &Node<R>, env: &Rc<Environment<R>>) -> Result<Value<R>> {
+        Ok(match &node.data {
+            NodeBody::Constant(value) => value.clone(),
+            NodeBody::Global(ident) => match env.get(ident) {
+                Some(value) if matches!(*value, Value::Uninitialized) => {
+                    return located_error!(
+                        LogicError::UninitializedVariable(ident.to_string()),
+                        node.location
+                    )
                 }
+                Some(value) => value.clone(),
+                None => {
+                    return located_error!(
+                        LogicError::UnboundedSymbol(ident.to_string(), env.similar_names(ident)),
+                        node.location
+                    )
+                }
+            },
+            NodeBody::Local(ident, address) => match &*env.get_slot(address.depth, address.slot) {
+                Value::Uninitialized => {
+                    return located_error!(
+                        LogicError::UninitializedVariable(ident.to_string()),
+                        node.location
+                    )
+                }
+                value => value.clone(),
+            },
+            NodeBody::SetGlobal(name, value_node) => {
+                let value = Self::eval_node(value_node, env)?;
+                add_cycle_candidate(Some(env.replace(name, value)?));
+                Value::Void
             }
-            ExpressionBody::Period => {
-                return located_error!(
-                    LogicError::UnexpectedExpression(expression.clone()),
-                    expression.location
-                );
-            }
-            ExpressionBody::Assignment(name, value_expr) => {
-                let value = Self::eval_expression(value_expr, env)?;
-                env.set(name, value)?;
+            NodeBody::SetLocal(_, address, value_node) => {
+                let value = Self::eval_node(value_node, env)?;
+                add_cycle_candidate(Some(env.replace_slot(address.depth, address.slot, value)));
                 Value::Void
             }
-            ExpressionBody::Procedure(scheme) => {
-                Value::Procedure(Procedure::User(scheme.clone(), env.clone()))
+            NodeBody::Lambda(lambda) => {
+                Value::Procedure(Procedure::User(lambda.clone(), env.clone()))
+            }
+            NodeBody::Folded(folded) => {
+                match folded.builtins.iter().all(|name| is_builtin(name, env)) {
+                    true => folded.value.clone(),
+                    false => Self::eval_node(&folded.call, env)?,
+                }
+            }
+            NodeBody::Call(procedure_node, arguments) => {
+                let (procedure, args) = Self::eval_call(procedure_node, arguments, env)?;
+                Self::apply_procedure_at(&procedure, args, env, procedure_node.location)?
             }
-            ExpressionBody::Conditional(cond) => {
-                let &(test, consequent, alternative) = &cond.as_ref();
-                if Self::eval_expression(&test, &env)?.as_boolean() {
-                    Self::eval_expression(&consequent, env)?
+            NodeBody::If(branches) => {
+                let (test, consequent, alternative) = branches.as_ref();
+                if Self::eval_node(test, env)?.as_boolean() {
+                    Self::eval_node(consequent, env)?
                 } else {
                     match alternative {
-                        Some(alter) => Self::eval_expression(&alter, env)?,
+                        Some(alternative) => Self::eval_node(alternative, env)?,
                         None => Value::Void,
                     }
                 }
             }
-            ExpressionBody::Quote(inner) => Self::read_literal(inner.as_ref(), env)?,
-
-            ExpressionBody::Symbol(ident) => match env.get(ident.as_str()) {
-                Some(value) => value.clone(),
-                None => {
-                    return located_error!(
-                        LogicError::UnboundedSymbol(ident.clone()),
-                        expression.location
-                    )
-                }
-            },
         })
     }
 
@@ -475,32 +1104,62 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
     ) -> Result<()> {
         let mut definitions = HashMap::new();
         for import in &imports.0 {
-            definitions.extend(self.eval_import_set(import)?.into_iter());
+            for (name, value) in self.eval_import_set(import)? {
+                // the same name may come from several import sets only if it is the same binding
+                match definitions.get(&name) {
+                    Some(imported) if imported != &value => {
+                        return located_error!(LogicError::DuplicateImport(name), import.location);
+                    }
+                    _ => {
+                        definitions.insert(name, value);
+                    }
+                }
+            }
         }
         for (name, value) in definitions {
             env.define(name, value);
         }
         Ok(())
     }
-    fn file_library_factory(&self, name: &Located<LibraryName>) -> Result<LibraryFactory<'a, R>> {
+    // the directory of the importing file, then each library path
+    fn library_directories(&self) -> Result<Vec<PathBuf>> {
         let base_directory = if let Some(program_directory) = &self.program_directory {
             program_directory.clone()
         } else {
             std::env::current_dir()?
         };
-        // TODO: file extension, file system variants
-        let path = base_directory
-            .join(name.deref().path())
-            .with_extension("sld");
-        if path.exists() {
-            let char_stream = file_char_stream(&path)?;
-            LibraryFactory::from_char_stream(name.deref(), char_stream)
-        } else {
-            located_error!(
-                LogicError::LibraryNotFound(name.deref().clone()),
-                name.location.clone()
-            )
+        Ok(std::iter::once(base_directory)
+            .chain(self.library_paths.iter().cloned())
+            .collect())
+    }
+    // (import (a b)) is searched as a/b.sld then a/b.scm in the library directories
+    fn file_library_factory(
+        &self,
+        name: &Located<LibraryName>,
+    ) -> Result<(PathBuf, LibraryFactory<'a, R>)> {
+        let mut tried = vec![];
+        for directory in self.library_directories()? {
+            for extension in &["sld", "scm"] {
+                let path = directory
+                    .join(name.deref().path())
+                    .with_extension(extension);
+                if path.exists() {
+                    let char_stream = file_char_stream(&path).map_err(|e| {
+                        let context = format!("cannot read library file {}", path.display());
+                        ErrorData::IO(IoError::new(context, e)).locate(name.location)
+                    })?;
+                    let mut lexer = Lexer::from_char_stream(char_stream);
+                    lexer.set_source(SourceId::new(&path.display().to_string()));
+                    let factory = LibraryFactory::from_parser(name.deref(), self.parser(lexer))?;
+                    return Ok((path, factory));
+                }
+                tried.push(path);
+            }
         }
+        located_error!(
+            LogicError::LibraryNotFound(name.deref().clone(), tried),
+            name.location.clone()
+        )
     }
     fn new_library(&mut self, factory: &LibraryFactory<R>) -> Result<Library<R>> {
         match factory {
@@ -511,18 +1170,28 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
         }
     }
     pub fn get_library(&mut self, name: Located<LibraryName>) -> Result<Library<R>> {
-        let factory = match self.lib_loader.lib_factories.get(&name) {
-            Some(factory) => factory,
+        if let Some(library) = self.libraries.get(name.deref()) {
+            return Ok(library.clone());
+        }
+        let library = match self.lib_loader.lib_factories.get(&name).cloned() {
+            Some(factory) => self.new_library(&factory)?,
             None => {
-                let new_factory = self.file_library_factory(&name)?;
-                self.lib_loader
-                    .lib_factories
-                    .entry(name.deref().clone())
-                    .or_insert(Rc::new(new_factory))
+                let (path, factory) = self.file_library_factory(&name)?;
+                // libraries imported by a library file are searched in the directory it is found in,
+                // e.g. (import (a c)) in dir/a/b.sld searches dir/a/c.sld first
+                let library_directory = path
+                    .ancestors()
+                    .nth(name.deref().path().components().count())
+                    .map(Path::to_owned);
+                let program_directory =
+                    std::mem::replace(&mut self.program_directory, library_directory);
+                let library = self.new_library(&factory);
+                self.program_directory = program_directory;
+                library?
             }
-        }
-        .clone();
-        self.new_library(&factory)
+        };
+        self.libraries.insert(name.deref().clone(), library.clone());
+        Ok(library)
     }
     pub fn eval_import_set(&mut self, import: &ImportSet) -> Result<Vec<(String, Value<R>)>> {
         match &import.data {
@@ -545,17 +1214,19 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
                 }
             }
             ImportSetBody::Only(import_set, identifiers) => {
+                let definitions = self.eval_import_set(import_set.as_ref())?;
+                Self::check_imported(import_set, &definitions, identifiers.iter())?;
                 let id_set = identifiers.into_iter().collect::<HashSet<_>>();
-                Ok(self
-                    .eval_import_set(import_set.as_ref())?
+                Ok(definitions
                     .into_iter()
                     .filter(|(name, _)| id_set.contains(name))
                     .collect())
             }
             ImportSetBody::Except(import_set, identifiers) => {
+                let definitions = self.eval_import_set(import_set.as_ref())?;
+                Self::check_imported(import_set, &definitions, identifiers.iter())?;
                 let id_set = identifiers.into_iter().collect::<HashSet<_>>();
-                Ok(self
-                    .eval_import_set(import_set.as_ref())?
+                Ok(definitions
                     .into_iter()
                     .filter(|(name, _)| !id_set.contains(name))
                     .collect())
@@ -566,36 +1237,82 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
                 .map(|(name, value)| (format!("{}{}", prefix, name), value))
                 .collect()),
             ImportSetBody::Rename(import_set, renames) => {
+                let definitions = self.eval_import_set(import_set.as_ref())?;
+                Self::check_imported(
+                    import_set,
+                    &definitions,
+                    renames.iter().map(|(from, _)| from),
+                )?;
                 let id_map = renames
                     .into_iter()
                     .map(|(from, to)| (from, to))
                     .collect::<HashMap<_, _>>();
-                Ok(self
-                    .eval_import_set(import_set.as_ref())?
+                let renamed = definitions
                     .into_iter()
                     .map(|(name, value)| match id_map.get(&name) {
                         Some(to) => ((*to).clone(), value),
                         None => (name, value),
                     })
-                    .collect())
+                    .collect::<Vec<_>>();
+                let mut names = HashSet::new();
+                for (name, _) in &renamed {
+                    if !names.insert(name) {
+                        return located_error!(
+                            LogicError::DuplicateImport(name.clone()),
+                            import.location
+                        );
+                    }
+                }
+                Ok(renamed)
             }
         }
     }
 
+    // identifiers listed by only, except and rename must be provided by the inner import set
+    fn check_imported<'b>(
+        import_set: &ImportSet,
+        definitions: &[(String, Value<R>)],
+        identifiers: impl Iterator<Item = &'b String>,
+    ) -> Result<()> {
+        for identifier in identifiers {
+            if !definitions.iter().any(|(name, _)| name == identifier) {
+                return located_error!(
+                    LogicError::NotExported(import_set.library_name().clone(), identifier.clone()),
+                    import_set.location
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub fn eval_expression_or_definition(
         &mut self,
         statement: &Statement,
         env: Rc<Environment<R>>,
+    ) -> Result<Option<Value<R>>> {
+        match statement {
+            Statement::Expression(_) | Statement::Definition(_) => {
+                let mut compiled = compile_statement(statement)?;
+                if self.optimize {
+                    optimize_statement(&mut compiled, &env);
+                }
+                if self.dump_ir {
+                    eprintln!("{}", compiled);
+                }
+                Self::eval_compiled_statement(&compiled, &env)
+            }
+            _ => Self::eval_statement(statement, &env),
+        }
+    }
+
+    // expression, definition or syntax definition, which do not need interpreter states like imports
+    pub fn eval_statement(
+        statement: &Statement,
+        env: &Rc<Environment<R>>,
     ) -> Result<Option<Value<R>>> {
         Ok(match statement {
-            Statement::Expression(expr) => Some(Self::eval_expression(&expr, &env)?),
-            Statement::Definition(Definition {
-                data: DefinitionBody(name, expr),
-                ..
-            }) => {
-                let value = Self::eval_expression(&expr, &env)?;
-                env.define(name.clone(), value);
-                None
+            Statement::Expression(_) | Statement::Definition(_) => {
+                Self::eval_compiled_statement(&compile_statement(statement)?, env)?
             }
             Statement::SyntaxDefinition(syntax) => {
                 let SyntaxDefBody(name, value) = &syntax.data;
@@ -612,19 +1329,54 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
         })
     }
 
+    fn eval_compiled_statement(
+        statement: &CompiledStatement<R>,
+        env: &Rc<Environment<R>>,
+    ) -> Result<Option<Value<R>>> {
+        Ok(match statement {
+            CompiledStatement::Expression(node) => Some(Self::eval_node(node, env)?),
+            CompiledStatement::Definition(name, node) => {
+                let value = Self::eval_node(node, env)?;
+                trace::<R>(|| TraceEvent::DefineBinding {
+                    name: name.to_string(),
+                });
+                add_cycle_candidate(env.redefine(name.clone(), value));
+                None
+            }
+        })
+    }
+
     pub fn eval_ast(
         &mut self,
         ast: &Statement,
         env: Rc<Environment<R>>,
     ) -> Result<Option<Value<R>>> {
         let ast_location = ast.location();
-        match self.eval_ast_error_no_location(ast, env) {
-            Ok(value) => Ok(value),
-            Err(Located { data, location }) => Err(data.locate(
-                // prevent loss accurate location
-                location.or(ast_location),
-            )),
-        }
+        // an interrupt requested while idle is not for this evaluation
+        self.interrupt.store(false, Ordering::Relaxed);
+        // an evaluation may be nested in another one, by a builtin evaluating on another
+        // interpreter, whose limits are restored when this one ends
+        let outer_fuel = FUEL.with(|fuel| fuel.replace(self.fuel));
+        let outer_max_depth = MAX_DEPTH.with(|max_depth| max_depth.replace(self.max_depth));
+        let outer_depth_left = DEPTH_LEFT.with(|left| left.replace(self.max_depth));
+        let outer_interrupt =
+            INTERRUPT.with(|interrupt| interrupt.replace(Some(self.interrupt.clone())));
+        let tracer = trace::install(self.tracer.take());
+        let debugger = debug::install(self.debugger.take());
+        let result = self.eval_ast_error_no_location(ast, env);
+        self.debugger = debugger.uninstall();
+        self.tracer = tracer.uninstall();
+        self.fuel = FUEL.with(|fuel| fuel.replace(outer_fuel));
+        MAX_DEPTH.with(|max_depth| max_depth.set(outer_max_depth));
+        DEPTH_LEFT.with(|left| left.set(outer_depth_left));
+        INTERRUPT.with(|interrupt| interrupt.replace(outer_interrupt));
+        // prevent loss accurate location
+        result.map_err(|mut e| {
+            e.backtrace = e
+                .backtrace
+                .map(|backtrace| Backtrace::new(backtrace.frames, self.backtrace_depth));
+            e.or_locate(ast_location)
+        })
     }
 
     pub fn eval_ast_error_no_location(
@@ -658,7 +1410,9 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
     }
 
     pub fn eval_root_ast(&mut self, ast: &Statement) -> Result<Option<Value<R>>> {
-        self.eval_ast(ast, self.env.clone())
+        let result = self.eval_ast(ast, self.env.clone());
+        self.collect_cycles();
+        result
     }
 
     pub fn eval_library_definition<'b>(
@@ -669,6 +1423,7 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
         let mut definitions = HashMap::new();
         let mut final_exports = Vec::new();
         let lib_env = Rc::new(Environment::new());
+        self.define_intrinsics(lib_env.clone());
         for declaration in &library_definition.1 {
             match &declaration.data {
                 LibraryDeclaration::ImportDeclaration(imports) => {
@@ -691,30 +1446,509 @@ impl<'a, R: RealNumberInternalTrait> Interpreter<'a, R> {
                 Some(value) => {
                     definitions.insert(to.clone(), value.clone());
                 }
-                None => located_error!(LogicError::UnboundedSymbol(from.clone()), export.location)?,
+                None => located_error!(
+                    LogicError::UnboundedSymbol(from.clone(), lib_env.similar_names(from)),
+                    export.location
+                )?,
+            }
+        }
+        Ok(Library::new(name, definitions))
+    }
+    pub fn eval_program<'b>(
+        &mut self,
+        asts: impl IntoIterator<Item = &'b Statement>,
+    ) -> Result<Option<Value<R>>> {
+        asts.into_iter()
+            .try_fold(None, |_, ast| self.eval_root_ast(&ast))
+    }
+
+    pub fn eval(&mut self, char_stream: impl Iterator<Item = char>) -> Result<Option<Value<R>>> {
+        self.eval_source(char_stream, SourceId::string())
+    }
+    /// like `eval`, locations of the program refer to `source`
+    /// a parser of programs evaluated by the interpreter, whose `cond-expand` forms are matched
+    /// against the features of the interpreter and the libraries it can import
+    pub fn parser<T: Iterator<Item = Result<Token>>>(&self, lexer: T) -> Parser<T> {
+        *self.available.libraries.borrow_mut() =
+            self.lib_loader.lib_factories.keys().cloned().collect();
+        *self.available.directories.borrow_mut() = self
+            .library_directories()
+            .unwrap_or_else(|_| self.library_paths.clone());
+        let mut parser = Parser::from_lexer(lexer);
+        parser.set_features(self.available.clone());
+        parser
+    }
+
+    pub fn eval_source(
+        &mut self,
+        char_stream: impl Iterator<Item = char>,
+        source: SourceId,
+    ) -> Result<Option<Value<R>>> {
+        {
+            let mut lexer = Lexer::from_char_stream(char_stream);
+            lexer.set_source(source);
+            let mut parser = self.parser(lexer);
+            parser.try_fold(None, |_, statement| {
+                let mut statement = statement?;
+                resolve_statement(&mut statement);
+                self.eval_root_ast(&statement)
+            })
+        }
+    }
+    /// find problems of a program without evaluating it, see `analyze_program`,
+    /// definitions are checked against the procedures of (scheme base)
+    pub fn analyze(
+        &mut self,
+        char_stream: impl Iterator<Item = char>,
+        source: SourceId,
+    ) -> Vec<Diagnostic> {
+        let builtins = self.base_procedures();
+        let mut lexer = Lexer::from_char_stream(char_stream);
+        lexer.set_source(source);
+        analyze_program(&mut self.parser(lexer), &builtins)
+    }
+    /// like `analyze`, with the errors found by `check_program` as well, sorted by location
+    ///
+    /// Variables are bound by the environment of the interpreter and the imports of the program.
+    /// Imported standard libraries are loaded, while importing other libraries, which may run
+    /// code when loaded, disables the checks of unbound variables.
+    pub fn check(
+        &mut self,
+        char_stream: impl Iterator<Item = char>,
+        source: SourceId,
+    ) -> Vec<Diagnostic> {
+        let builtins = self.base_procedures();
+        let mut lexer = Lexer::from_char_stream(char_stream);
+        lexer.set_source(source);
+        let (mut diagnostics, statements) = analyze_statements(&mut self.parser(lexer), &builtins);
+        let mut globals = Some(
+            self.env
+                .iter_local_definitions()
+                .by_ref()
+                .map(|(name, _)| name.to_string())
+                .collect::<HashSet<_>>(),
+        );
+        for statement in &statements {
+            if let Statement::ImportDeclaration(declaration) = statement {
+                for import_set in &declaration.0 {
+                    let name = import_set.library_name();
+                    if !self.libraries.contains_key(name)
+                        && !self.lib_loader.lib_factories.contains_key(name)
+                    {
+                        globals = None;
+                        continue;
+                    }
+                    match self.eval_import_set(import_set) {
+                        Ok(definitions) => {
+                            if let Some(globals) = &mut globals {
+                                globals.extend(definitions.into_iter().map(|(name, _)| name));
+                            }
+                        }
+                        Err(error) => diagnostics.push(error.into()),
+                    }
+                }
+            }
+        }
+        diagnostics.extend(check_program(&statements, globals.as_ref()));
+        diagnostics.sort_by_key(|diagnostic| {
+            diagnostic
+                .location
+                .map(|location| (location.line, location.column))
+        });
+        diagnostics
+    }
+    // names of the procedures of (scheme base), which definitions should not shadow
+    fn base_procedures(&mut self) -> HashSet<String> {
+        match self.get_library(library_name!("scheme", "base").into()) {
+            Ok(library) => library
+                .iter_definitions()
+                .filter(|(_, value)| matches!(value, Value::Procedure(_)))
+                .map(|(name, _)| name.clone())
+                .collect(),
+            Err(_) => HashSet::new(),
+        }
+    }
+    /// evaluate a program, return the value of its last statement, which is None for definitions
+    ///
+    /// ```
+    /// use ruschm::{interpreter::DefaultInterpreter, values::{Number, Value}};
+    /// let mut it = DefaultInterpreter::new_with_stdlib();
+    /// assert_eq!(it.eval_str("(+ 1 2)")?, Some(Value::Number(Number::Integer(3))));
+    /// # Ok::<(), ruschm::error::SchemeError>(())
+    /// ```
+    pub fn eval_str(&mut self, source: &str) -> Result<Option<Value<R>>> {
+        self.eval(source.chars())
+    }
+    /// like `eval_str`, errors are prefixed by the file name,
+    /// libraries imported by the file are searched from its directory
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<Option<Value<R>>> {
+        let path = path.as_ref();
+        self.program_directory = path.parent().map(Path::to_owned);
+        let source = SourceId::new(&path.display().to_string());
+        let result = match file_char_stream(path) {
+            Ok(char_stream) => self.eval_source(char_stream, source),
+            Err(e) => Err(e.into()),
+        };
+        result.map_err(|e| match e.data.is_catchable() {
+            // requests to stop evaluating are not errors of the file
+            false => e,
+            // the backtrace of the error is the one of the file
+            true => SchemeError {
+                backtrace: e.backtrace.clone(),
+                ..ErrorData::Logic(LogicError::LoadError(
+                    path.display().to_string(),
+                    Box::new(e),
+                ))
+                .no_locate()
+            },
+        })
+    }
+    /// top-level environment, where definitions of evaluated programs are bound
+    pub fn environment(&self) -> &Rc<Environment<R>> {
+        &self.env
+    }
+
+    /// the bindings of the top-level environment, sorted by name
+    pub fn global_bindings(&self) -> Vec<(String, Value<R>)> {
+        self.env.bindings()
+    }
+
+    /// the top-level bindings of data, like numbers, strings, lists and vectors, to be restored
+    /// later by `restore`, see `Snapshot`. Procedures of the interpreter and of libraries are
+    /// left out, other bindings which are not data, like procedures defined by the program or
+    /// ports, fail with `LogicError::NotSnapshotData` naming them.
+    pub fn snapshot(&self) -> Result<Snapshot<R>> {
+        let mut bindings = vec![];
+        let mut not_data = vec![];
+        for (name, value) in self.global_bindings() {
+            if is_data(&value, &mut vec![]) {
+                bindings.push((name, copy_data(&value)));
+            } else if !self.is_provided(&value) {
+                not_data.push(name);
+            }
+        }
+        match not_data.is_empty() {
+            true => Ok(Snapshot { bindings }),
+            false => error!(LogicError::NotSnapshotData(not_data)),
+        }
+    }
+
+    // whether `value` is a procedure of the interpreter or of a library
+    fn is_provided(&self, value: &Value<R>) -> bool {
+        match value {
+            Value::Procedure(Procedure::Builtin(_)) | Value::Transformer(_) => true,
+            Value::Procedure(Procedure::User(_, closure)) => {
+                let mut top_level = closure;
+                while let Some(parent) = top_level.parent() {
+                    top_level = parent;
+                }
+                !Rc::ptr_eq(top_level, &self.env)
+            }
+            Value::Procedure(procedure) => self.libraries.values().any(|library| {
+                library.iter_definitions().any(|(_, definition)| {
+                    matches!(definition, Value::Procedure(provided) if provided.ptr_eq(procedure))
+                })
+            }),
+            _ => false,
+        }
+    }
+
+    /// define the bindings of `snapshot` at the top level, replacing the bindings of the same
+    /// names. Each restore gets its own copy of the vectors of the snapshot.
+    pub fn restore(&mut self, snapshot: &Snapshot<R>) {
+        for (name, value) in &snapshot.bindings {
+            add_cycle_candidate(self.env.redefine(name.as_str(), copy_data(value)));
+        }
+    }
+}
+
+#[test]
+fn register_fn() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let total = Rc::new(Cell::new(0));
+    {
+        let total = total.clone();
+        interpreter.register_fn("add-to-total!", 1, move |arguments| {
+            total.set(total.get() + arguments[0].clone().expect_integer()?);
+            Ok(Value::Number(Number::Integer(total.get())))
+        });
+    }
+    assert_eq!(
+        interpreter
+            .eval_str("(add-to-total! 1) (apply add-to-total! '(2)) (map add-to-total! '(3 4))")?,
+        Some(Value::Pair(Box::new(list![
+            Value::Number(Number::Integer(6)),
+            Value::Number(Number::Integer(10))
+        ])))
+    );
+    assert_eq!(total.get(), 10);
+    assert_eq!(
+        interpreter.eval_str("add-to-total!")?.unwrap().to_string(),
+        "<build-in procedure (add-to-total!)>"
+    );
+    assert_eq!(
+        interpreter
+            .eval_str("(add-to-total! 1 2)")
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(wrong_arity("(add-to-total! arg1)", (1, false), 2))
+    );
+    assert_eq!(
+        interpreter.eval_str("(add-to-total! 'a)").unwrap_err().data,
+        ErrorData::Logic(wrong_type(
+            "add-to-total!",
+            Type::Number,
+            &Value::<f32>::Symbol("a".into())
+        ))
+    );
+    Ok(())
+}
+
+#[test]
+fn interpreter_per_thread() {
+    use std::convert::TryInto;
+    let source = "(define (square x) (* x x)) (square 12)".to_string();
+    let handle = std::thread::spawn(move || -> Result<i32> {
+        let mut interpreter = Interpreter::<f64>::new_with_stdlib();
+        interpreter.eval_str(&source)?.unwrap().try_into()
+    });
+    assert_eq!(handle.join().unwrap(), Ok(144));
+}
+
+#[test]
+fn set_output() -> Result<()> {
+    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.set_output(output.clone());
+    interpreter.eval_str(
+        "(display \"hello\") (newline) (display 42) (display 1.5) (newline)
+         (display '(1 (\"a\" #\\b) #(2) (3 . 4)))",
+    )?;
+    assert_eq!(
+        String::from_utf8(output.borrow().clone()).unwrap(),
+        "hello\n421.5\n(1 (a b) #(2) (3 . 4))"
+    );
+    Ok(())
+}
+
+// top-level and library closures usually refer to the environment defining them,
+// environments no longer referred from outside of the interpreter are freed with it
+impl<'a, R: RealNumberInternalTrait> Drop for Interpreter<'a, R> {
+    fn drop(&mut self) {
+        let mut candidates = vec![std::mem::replace(
+            &mut self.env,
+            Rc::new(Environment::new()),
+        )];
+        let mut libraries = std::mem::take(&mut self.libraries);
+        for (_, library) in libraries.drain() {
+            for (_, value) in library.iter_definitions() {
+                referred_environments(value, &mut candidates);
             }
         }
-        Ok(Library::new(name, definitions))
-    }
-    pub fn eval_program<'b>(
-        &mut self,
-        asts: impl IntoIterator<Item = &'b Statement>,
-    ) -> Result<Option<Value<R>>> {
-        asts.into_iter()
-            .try_fold(None, |_, ast| self.eval_root_ast(&ast))
+        collect_cycles(candidates, &self.env);
     }
+}
 
-    pub fn eval(&mut self, char_stream: impl Iterator<Item = char>) -> Result<Option<Value<R>>> {
-        {
-            let lexer = Lexer::from_char_stream(char_stream);
-            let mut parser = Parser::from_lexer(lexer);
-            parser.try_fold(None, |_, statement| self.eval_root_ast(&statement?))
+#[test]
+fn reference_cycles() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval_str("(define f ((lambda () (define (g) g) g)))")?;
+    let closure_env = match interpreter.eval_str("f")? {
+        Some(Value::Procedure(Procedure::User(_, env))) => Rc::downgrade(&env),
+        _ => unreachable!(),
+    };
+    interpreter.eval_str("(define kept f) (set! kept 1)")?;
+    assert!(closure_env.upgrade().is_some());
+    interpreter.eval_str("(define f 1)")?;
+    assert!(closure_env.upgrade().is_none());
+
+    interpreter.eval_str("(define (h) h)")?;
+    let root = Rc::downgrade(&interpreter.env);
+    drop(interpreter);
+    assert!(root.upgrade().is_none());
+
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let held = interpreter.eval_str("((lambda () (define (g) g) g))")?;
+    let held_env = match &held {
+        Some(Value::Procedure(Procedure::User(_, env))) => Rc::downgrade(env),
+        _ => unreachable!(),
+    };
+    drop(interpreter);
+    // values held by the host stay intact
+    assert!(held_env.upgrade().is_some());
+    Ok(())
+}
+
+#[test]
+fn eval_file() -> Result<()> {
+    let path = std::env::temp_dir().join("ruschm_eval_file_test.scm");
+    std::fs::write(&path, "(define a 1)\n(+ a 1)\n").unwrap();
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        interpreter.eval_file(&path)?,
+        Some(Value::Number(Number::Integer(2)))
+    );
+    assert_eq!(
+        interpreter.environment().get("a").as_deref(),
+        Some(&Value::Number(Number::Integer(1)))
+    );
+    std::fs::write(&path, "(define a 1)\n(car a)\n").unwrap();
+    let message = Interpreter::<f32>::new_with_stdlib()
+        .eval_file(&path)
+        .unwrap_err()
+        .to_string();
+    assert!(message.starts_with(&format!("{}:2:", path.display())));
+    // procedures defined elsewhere report their own source
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval_str("(define (first x) (car x))")?;
+    std::fs::write(&path, "(first 1)\n").unwrap();
+    let message = interpreter.eval_file(&path).unwrap_err().to_string();
+    assert!(message.starts_with(&format!("{}: <string>:1:", path.display())));
+    // requests to stop evaluating are not wrapped
+    std::fs::write(&path, "(define (f) (f))\n(f)\n").unwrap();
+    interpreter.set_fuel(100);
+    assert_eq!(
+        interpreter.eval_file(&path).unwrap_err().data,
+        ErrorData::FuelExhausted
+    );
+    std::fs::remove_file(path).unwrap();
+    Ok(())
+}
+
+#[test]
+fn trace_events() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let events = Rc::new(RefCell::new(vec![]));
+    let traced = events.clone();
+    interpreter.set_tracer(Box::new(move |event| {
+        let event = match event {
+            TraceEvent::EnterCall {
+                name,
+                arguments,
+                location,
+                depth,
+            } => format!(
+                "{} enter ({} {}) at {}",
+                depth,
+                name,
+                arguments
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                location.unwrap()
+            ),
+            TraceEvent::TailCall { name, depth, .. } => format!("{} tail {}", depth, name),
+            TraceEvent::ExitCall {
+                name,
+                result,
+                depth,
+            } => format!("{} exit {} {}", depth, name, result.unwrap()),
+            TraceEvent::DefineBinding { name } => format!("define {}", name),
+            TraceEvent::EvalExpression { .. } => return,
+        };
+        traced.borrow_mut().push(event);
+    }));
+    interpreter.eval_source(
+        "(define (f x) (+ x 1))\n(define (g x) (* 2 (f x)))\n(g 3)".chars(),
+        SourceId::new("traced.scm"),
+    )?;
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            "define f",
+            "define g",
+            "1 enter (g 3) at traced.scm:3:3",
+            "2 enter (f 3) at traced.scm:2:22",
+            "2 tail +",
+            "2 exit + 4",
+            "1 tail *",
+            "1 exit * 8",
+        ]
+    );
+    // the tracer is kept between evaluations until it is cleared
+    interpreter.eval_str("(g 1)")?;
+    assert_eq!(events.borrow().len(), 14);
+    assert!(interpreter.clear_tracer().is_some());
+    interpreter.eval_str("(g 1)")?;
+    assert_eq!(events.borrow().len(), 14);
+    Ok(())
+}
+
+#[test]
+fn nested_tracer() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.env.define(
+        "nested",
+        Value::Procedure(Procedure::new_builtin_closure(
+            "nested".to_string(),
+            param_fixed![],
+            |_| {
+                Interpreter::<f32>::new_with_stdlib().eval_str("(+ 1 2)")?;
+                Ok(Value::Void)
+            },
+        )),
+    );
+    let calls = Rc::new(RefCell::new(vec![]));
+    let traced = calls.clone();
+    interpreter.set_tracer(Box::new(move |event| {
+        if let TraceEvent::EnterCall { name, .. } = event {
+            traced.borrow_mut().push(name);
         }
-    }
-    pub fn eval_file(&mut self, path: PathBuf) -> Result<Option<Value<R>>> {
-        self.program_directory = path.clone().parent().map(Path::to_owned);
-        self.eval(file_char_stream(&path)?)
-    }
+    }));
+    interpreter.eval_str("(define (f) 1) (define x (list (nested) (f)))")?;
+    // the tracer still receives the events after the nested evaluation
+    assert_eq!(*calls.borrow(), vec!["nested", "f", "list"]);
+    assert!(interpreter.clear_tracer().is_some());
+    Ok(())
+}
+
+#[test]
+fn analyze() {
+    let mut interpreter = Interpreter::<f32>::new();
+    let diagnostics = interpreter.analyze(
+        "(define (list x) 1)\n(define y 2)".chars(),
+        SourceId::new("analyzed.scm"),
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        vec![
+            "analyzed.scm:1:2: warning: definition of list shadows the procedure of the base library",
+            "analyzed.scm:1:16: warning: parameter x is never used"
+        ]
+    );
+    // nothing is evaluated
+    assert!(interpreter.environment().get("y").is_none());
+}
+
+#[test]
+fn check() {
+    let mut interpreter = Interpreter::<f32>::new();
+    let diagnostics = interpreter.check(
+        "(import (scheme base))\n(define (f a b) (+ a b))\n(f 1 2 3)\n(define (loop) (loop))\n(loop)\n(display 1)".chars(),
+        SourceId::new("checked.scm"),
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        vec![
+            "checked.scm:3:2: error: procedure f expects 2 arguments, got 3",
+            "checked.scm:6:9: error: unbound symbol display"
+        ]
+    );
+    // unknown libraries may bind anything
+    assert!(interpreter
+        .check(
+            "(import (unknown library)) (display 1)".chars(),
+            SourceId::string()
+        )
+        .is_empty());
+    assert!(interpreter.environment().get("f").is_none());
 }
 
 #[test]
@@ -745,7 +1979,7 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
             vec![
                 ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                 ExpressionBody::Primitive(Primitive::Integer(2)).into()
@@ -756,7 +1990,7 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
             vec![
                 ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                 ExpressionBody::Primitive(Primitive::Rational(1, 2)).into()
@@ -767,7 +2001,7 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("*".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("*".into()))),
             vec![
                 ExpressionBody::Primitive(Primitive::Rational(1, 2)).into(),
                 ExpressionBody::Primitive(Primitive::Real("2.0".to_string()).into()).into(),
@@ -778,7 +2012,7 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("/".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("/".into()))),
             vec![
                 ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                 ExpressionBody::Primitive(Primitive::Integer(0)).into()
@@ -789,7 +2023,7 @@ fn arithmetic() -> Result<()> {
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("max".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("max".into()))),
             vec![
                 ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                 ExpressionBody::Primitive(Primitive::Real("1.3".to_string()).into()).into(),
@@ -799,7 +2033,7 @@ fn arithmetic() -> Result<()> {
     );
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("min".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("min".into()))),
             vec![
                 ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                 ExpressionBody::Primitive(Primitive::Real("1.3".to_string()).into()).into(),
@@ -809,30 +2043,34 @@ fn arithmetic() -> Result<()> {
     );
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("min".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("min".into()))),
             vec![ExpressionBody::Primitive(Primitive::String("a".to_string()).into()).into()]
         ))),
-        Err(ErrorData::Logic(LogicError::TypeMisMatch("a".to_string(), Type::Number)).no_locate()),
+        Err(
+            ErrorData::Logic(wrong_type("min", Type::Number, &Value::<f32>::from("a"))).no_locate()
+        ),
     );
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("max".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("max".into()))),
             vec![ExpressionBody::Primitive(Primitive::String("a".to_string()).into()).into()]
         ))),
-        Err(ErrorData::Logic(LogicError::TypeMisMatch("a".to_string(), Type::Number)).no_locate()),
+        Err(
+            ErrorData::Logic(wrong_type("max", Type::Number, &Value::<f32>::from("a"))).no_locate()
+        ),
     );
 
     assert_eq!(
         interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("sqrt".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("sqrt".into()))),
             vec![ExpressionBody::Primitive(Primitive::Integer(4)).into()]
         )))?,
         Value::Number(Number::Real(2.0)),
     );
 
     match interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-        Box::new(Expression::from(ExpressionBody::Symbol("sqrt".to_string()))),
+        Box::new(Expression::from(ExpressionBody::Symbol("sqrt".into()))),
         vec![ExpressionBody::Primitive(Primitive::Integer(-4)).into()],
     )))? {
         Value::Number(Number::Real(should_be_nan)) => {
@@ -847,7 +2085,7 @@ fn arithmetic() -> Result<()> {
     {
         assert_eq!(
             interpreter.eval_root_expression(Expression::from(ExpressionBody::ProcedureCall(
-                Box::new(Expression::from(ExpressionBody::Symbol(cmp.to_string()))),
+                Box::new(Expression::from(ExpressionBody::Symbol((*cmp).into()))),
                 vec![
                     ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                     ExpressionBody::Primitive(Primitive::Rational(1, 1)).into(),
@@ -861,14 +2099,82 @@ fn arithmetic() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn exactness() -> Result<()> {
+    let mut interpreter = Interpreter::<f64>::new_with_stdlib();
+    for (a, b) in &[
+        ("(exact 0.5)", "#e0.5"),
+        ("(inexact 1/3)", "#i1/3"),
+        ("(exact 125.0)", "#e1.25e2"),
+        ("(inexact 3)", "#i3"),
+        ("(exact #i1/4)", "1/4"),
+    ] {
+        assert_eq!(
+            interpreter.eval_str(&format!("(eqv? {} {})", a, b))?,
+            Some(Value::Boolean(true)),
+            "(eqv? {} {})",
+            a,
+            b
+        );
+    }
+    // rounding keeps the exactness of the argument
+    assert_eq!(
+        interpreter.eval_str(
+            "(equal? (list (floor 2.7) (ceiling -2.7) (round 2.5) (truncate -2.7) (round 7/2)) \
+                     (list 2.0 -2.0 2.0 -2.0 4))"
+        )?,
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval_str("(exact (/ 1. 0))").unwrap_err().data,
+        ErrorData::Logic(LogicError::InExactConversion("inf".to_string()))
+    );
+    Ok(())
+}
+
 #[test]
 fn undefined() -> Result<()> {
     let interpreter = Interpreter::<f32>::new_with_stdlib();
 
+    assert_eq!(
+        interpreter.eval_root_expression(Expression::from(ExpressionBody::Symbol("foo".into()))),
+        Err(ErrorData::Logic(LogicError::UnboundedSymbol("foo".to_string(), vec![])).no_locate())
+    );
+    Ok(())
+}
+
+#[test]
+fn unbound_suggestion() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        interpreter
+            .eval("(dispaly 1)".chars())
+            .unwrap_err()
+            .to_string(),
+        "unbound symbol dispaly; did you mean `display`?"
+    );
+    interpreter.eval("(define my-counter 0)".chars())?;
+    assert_eq!(
+        interpreter.eval("(+ my-countr 1)".chars()),
+        Err(ErrorData::Logic(LogicError::UnboundedSymbol(
+            "my-countr".to_string(),
+            vec!["my-counter".to_string()]
+        ))
+        .no_locate())
+    );
     assert_eq!(
         interpreter
-            .eval_root_expression(Expression::from(ExpressionBody::Symbol("foo".to_string()))),
-        Err(ErrorData::Logic(LogicError::UnboundedSymbol("foo".to_string())).no_locate())
+            .eval("(set! my-countr 1)".chars())
+            .unwrap_err()
+            .to_string(),
+        "unbound symbol my-countr; did you mean `my-counter`?"
+    );
+    assert_eq!(
+        interpreter
+            .eval("qwertyuiop".chars())
+            .unwrap_err()
+            .to_string(),
+        "unbound symbol qwertyuiop"
     );
     Ok(())
 }
@@ -879,14 +2185,14 @@ fn variable_definition() -> Result<()> {
 
     let program = vec![
         Statement::Definition(Definition::from(DefinitionBody(
-            "a".to_string(),
+            "a".into(),
             ExpressionBody::Primitive(Primitive::Integer(1)).into(),
         ))),
         Statement::Definition(Definition::from(DefinitionBody(
-            "b".to_string(),
-            Expression::from(ExpressionBody::Symbol("a".to_string())),
+            "b".into(),
+            Expression::from(ExpressionBody::Symbol("a".into())),
         ))),
-        Statement::Expression(Expression::from(ExpressionBody::Symbol("b".to_string()))),
+        Statement::Expression(Expression::from(ExpressionBody::Symbol("b".into()))),
     ];
     assert_eq!(
         interpreter.eval_program(program.iter())?,
@@ -901,14 +2207,14 @@ fn variable_assignment() -> Result<()> {
 
     let program = vec![
         Statement::Definition(Definition::from(DefinitionBody(
-            "a".to_string(),
+            "a".into(),
             ExpressionBody::Primitive(Primitive::Integer(1)).into(),
         ))),
         Statement::Expression(Expression::from(ExpressionBody::Assignment(
-            "a".to_string(),
+            "a".into(),
             Box::new(ExpressionBody::Primitive(Primitive::Integer(2)).into()),
         ))),
-        Statement::Expression(Expression::from(ExpressionBody::Symbol("a".to_string()))),
+        Statement::Expression(Expression::from(ExpressionBody::Symbol("a".into()))),
     ];
     assert_eq!(
         interpreter.eval_program(program.iter())?,
@@ -923,17 +2229,15 @@ fn builtin_procedural() -> Result<()> {
 
     let program = vec![
         Statement::Definition(Definition::from(DefinitionBody(
-            "get-add".to_string(),
+            "get-add".into(),
             simple_procedure(
                 param_fixed![],
-                Expression::from(ExpressionBody::Symbol("+".to_string())),
+                Expression::from(ExpressionBody::Symbol("+".into())),
             ),
         ))),
         Statement::Expression(Expression::from(ExpressionBody::ProcedureCall(
             Box::new(Expression::from(ExpressionBody::ProcedureCall(
-                Box::new(Expression::from(ExpressionBody::Symbol(
-                    "get-add".to_string(),
-                ))),
+                Box::new(Expression::from(ExpressionBody::Symbol("get-add".into()))),
                 vec![],
             ))),
             vec![
@@ -955,20 +2259,20 @@ fn procedure_definition() -> Result<()> {
 
     let program = vec![
         Statement::Definition(Definition::from(DefinitionBody(
-            "add".to_string(),
+            "add".into(),
             simple_procedure(
                 param_fixed!["x", "y"],
                 Expression::from(ExpressionBody::ProcedureCall(
-                    Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
+                    Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
                     vec![
-                        Expression::from(ExpressionBody::Symbol("x".to_string())),
-                        Expression::from(ExpressionBody::Symbol("y".to_string())),
+                        Expression::from(ExpressionBody::Symbol("x".into())),
+                        Expression::from(ExpressionBody::Symbol("y".into())),
                     ],
                 )),
             ),
         ))),
         Statement::Expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("add".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("add".into()))),
             vec![
                 ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                 ExpressionBody::Primitive(Primitive::Integer(2)).into(),
@@ -989,10 +2293,10 @@ fn procedure_debug() -> Result<()> {
     let program = vec![Statement::Expression(simple_procedure(
         param_fixed!["x".to_string(), "y".to_string()],
         Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
             vec![
-                Expression::from(ExpressionBody::Symbol("x".to_string())),
-                Expression::from(ExpressionBody::Symbol("y".to_string())),
+                Expression::from(ExpressionBody::Symbol("x".into())),
+                Expression::from(ExpressionBody::Symbol("y".into())),
             ],
         )),
     ))];
@@ -1010,10 +2314,10 @@ fn lambda_call() -> Result<()> {
             Box::new(simple_procedure(
                 append_variadic_param!(param_fixed!["x", "y"], "z"),
                 Expression::from(ExpressionBody::ProcedureCall(
-                    Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
+                    Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
                     vec![
-                        Expression::from(ExpressionBody::Symbol("x".to_string())),
-                        Expression::from(ExpressionBody::Symbol("y".to_string())),
+                        Expression::from(ExpressionBody::Symbol("x".into())),
+                        Expression::from(ExpressionBody::Symbol("y".into())),
                     ],
                 )),
             )),
@@ -1038,11 +2342,11 @@ fn closure() -> Result<()> {
 
     let program = vec![
         Statement::Definition(Definition::from(DefinitionBody(
-            "counter-creator".to_string(),
+            "counter-creator".into(),
             Expression::from(ExpressionBody::Procedure(SchemeProcedure(
                 param_fixed![],
                 vec![Definition::from(DefinitionBody(
-                    "current".to_string(),
+                    "current".into(),
                     ExpressionBody::Primitive(Primitive::Integer(0)).into(),
                 ))],
                 vec![Expression::from(ExpressionBody::Procedure(
@@ -1051,44 +2355,38 @@ fn closure() -> Result<()> {
                         vec![],
                         vec![
                             Expression::from(ExpressionBody::Assignment(
-                                "current".to_string(),
+                                "current".into(),
                                 Box::new(Expression::from(ExpressionBody::ProcedureCall(
-                                    Box::new(Expression::from(ExpressionBody::Symbol(
-                                        "+".to_string(),
-                                    ))),
+                                    Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
                                     vec![
-                                        Expression::from(ExpressionBody::Symbol(
-                                            "current".to_string(),
-                                        )),
+                                        Expression::from(ExpressionBody::Symbol("current".into())),
                                         ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                                     ],
                                 ))),
                             )),
-                            Expression::from(ExpressionBody::Symbol("current".to_string())),
+                            Expression::from(ExpressionBody::Symbol("current".into())),
                         ],
+                        None,
                     ),
                 ))],
+                None,
             ))),
         ))),
         Statement::Definition(Definition::from(DefinitionBody(
-            "counter".to_string(),
+            "counter".into(),
             Expression::from(ExpressionBody::ProcedureCall(
                 Box::new(Expression::from(ExpressionBody::Symbol(
-                    "counter-creator".to_string(),
+                    "counter-creator".into(),
                 ))),
                 vec![],
             )),
         ))),
         Statement::Expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol(
-                "counter".to_string(),
-            ))),
+            Box::new(Expression::from(ExpressionBody::Symbol("counter".into()))),
             vec![],
         ))),
         Statement::Expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol(
-                "counter".to_string(),
-            ))),
+            Box::new(Expression::from(ExpressionBody::Symbol("counter".into()))),
             vec![],
         ))),
     ];
@@ -1137,24 +2435,24 @@ fn local_environment() -> Result<()> {
 
     let program = vec![
         Statement::Definition(Definition::from(DefinitionBody(
-            "adda".to_string(),
+            "adda".into(),
             simple_procedure(
-                param_fixed![ParameterFormalsBody::Name("x".to_string())],
+                param_fixed!["x"],
                 Expression::from(ExpressionBody::ProcedureCall(
-                    Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
+                    Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
                     vec![
-                        Expression::from(ExpressionBody::Symbol("x".to_string())),
-                        Expression::from(ExpressionBody::Symbol("a".to_string())),
+                        Expression::from(ExpressionBody::Symbol("x".into())),
+                        Expression::from(ExpressionBody::Symbol("a".into())),
                     ],
                 )),
             ),
         ))),
         Statement::Definition(Definition::from(DefinitionBody(
-            "a".to_string(),
+            "a".into(),
             ExpressionBody::Primitive(Primitive::Integer(1)).into(),
         ))),
         Statement::Expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("adda".to_string()))),
+            Box::new(Expression::from(ExpressionBody::Symbol("adda".into()))),
             vec![ExpressionBody::Primitive(Primitive::Integer(2)).into()],
         ))),
     ];
@@ -1165,43 +2463,480 @@ fn local_environment() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn internal_definitions() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        interpreter.eval(
+            "((lambda ()
+                (define (f) (g))
+                (define (g) 1)
+                (f)))"
+                .chars()
+        )?,
+        Some(Value::Number(Number::Integer(1)))
+    );
+    assert_eq!(
+        interpreter.eval(
+            "((lambda ()
+                (define a b)
+                (define b 1)
+                a))"
+            .chars()
+        ),
+        Err(ErrorData::Logic(LogicError::UninitializedVariable("b".to_string())).no_locate())
+    );
+    Ok(())
+}
+
+#[test]
+fn lexical_addressing() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let mut eval = |source: &str| interpreter.eval(source.chars());
+    // closures capture the frames of every enclosing procedure
+    eval(
+        "(define (make-adder a)
+            (lambda (b) (lambda (c) (+ a b c))))",
+    )?;
+    assert_eq!(
+        eval("(((make-adder 1) 10) 100)")?,
+        Some(Value::Number(Number::Integer(111)))
+    );
+    // inner bindings shadow outer ones, a later binding in a frame shadows the parameter
+    assert_eq!(
+        eval(
+            "((lambda (x y)
+                ((lambda (x) (define y (* x 2)) (list x y)) (+ x 1)))
+              1 5)"
+        )?,
+        Some(Value::Pair(Box::new(list!(
+            Value::Number(Number::Integer(2)),
+            Value::Number(Number::Integer(4))
+        ))))
+    );
+    // set! through a resolved address is seen by every closure sharing the frame
+    eval(
+        "(define (make-counter)
+            (define count 0)
+            (cons (lambda () (set! count (+ count 1)) count)
+                  (lambda () count)))
+         (define counter (make-counter))",
+    )?;
+    eval("((car counter)) ((car counter))")?;
+    assert_eq!(
+        eval("((cdr counter))")?,
+        Some(Value::Number(Number::Integer(2)))
+    );
+    // parameters shadow globals, which are still looked up by name
+    eval("(define x 'global) (define (f x) (set! x 'local) x)")?;
+    assert_eq!(eval("(f 1)")?, Some(Value::Symbol("local".into())));
+    assert_eq!(eval("x")?, Some(Value::Symbol("global".into())));
+    // variadic parameter and recursion through the global name
+    eval("(define (count . rest) (if (null? rest) 0 (+ 1 (apply count (cdr rest)))))")?;
+    assert_eq!(
+        eval("(count 'a 'b 'c)")?,
+        Some(Value::Number(Number::Integer(3)))
+    );
+    Ok(())
+}
+
+#[test]
+fn shared_procedure_body() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval_str(
+        "
+        (define (make-adder n) (lambda (x) (+ x n)))
+        (define add1 (make-adder 1))
+        (define add2 (make-adder 2))",
+    )?;
+    let lambda = |name: &str| match &*interpreter.env.get(name).unwrap() {
+        Value::Procedure(Procedure::User(lambda, _)) => lambda.clone(),
+        other => panic!("unexpected value {}", other),
+    };
+    // closures of one lambda expression share its body, and differ in their environments
+    assert!(Rc::ptr_eq(&lambda("add1"), &lambda("add2")));
+    assert_eq!(
+        interpreter.eval_str("(list (add1 1) (add2 1))")?,
+        Some(Value::Pair(Box::new(list!(2.into(), 3.into()))))
+    );
+    // recursive and mutually recursive closures
+    assert_eq!(
+        interpreter.eval_str(
+            "
+            (define (make-parity)
+                (define (even? n) (if (= n 0) #t (odd? (- n 1))))
+                (define (odd? n) (if (= n 0) #f (even? (- n 1))))
+                (lambda (n) (list (even? n) (odd? n))))
+            ((make-parity) 7)"
+        )?,
+        Some(Value::Pair(Box::new(list!(false.into(), true.into()))))
+    );
+    assert_eq!(
+        interpreter.eval_str(
+            "
+            (define (make-factorial)
+                (lambda (n) (if (= n 0) 1 (* n ((make-factorial) (- n 1))))))
+            ((make-factorial) 5)"
+        )?,
+        Some(Value::Number(Number::Integer(120)))
+    );
+    Ok(())
+}
+
+#[test]
+fn optimize() -> Result<()> {
+    let programs = [
+        "(+ 1 2 (* 3 4) (- 10))",
+        "(if (< 1 2) (* 1.5 2) 'no)",
+        "(define (f x) (if #t (+ x (* 2 3)) (car '()))) (f 1)",
+        "(define (g x) 1 \"ignored\" (if #f x)) (g 1)",
+        "(define x 10) (begin 1 (set! x (+ x 1)) x)",
+        "(define (+ a b) (* a b)) (+ 2 3)",
+        // builtin procedures redefined after calls to them are folded
+        "(define (f) (+ 1 (* 2 3))) (define (* a b) (- a b)) (f)",
+        "(define (g) (- 5 1)) (set! - +) (g)",
+        "(define (h) (if (+ 1 1) 'yes 'no)) (define (+ a b) #f) (h)",
+        "(/ 1 0)",
+        "(- 1 \"a\")",
+        "(begin (if #f (error \"unreachable\")) (vector 1 2))",
+    ];
+    for program in programs.iter() {
+        let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+        let mut optimized = Interpreter::<f32>::new_with_stdlib();
+        optimized.set_optimize(true);
+        assert_eq!(
+            optimized.eval_str(program),
+            interpreter.eval_str(program),
+            "{}",
+            program
+        );
+    }
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.set_optimize(true);
+    assert_eq!(
+        interpreter.eval_str("(+ 1 (/ 1 0))"),
+        Err(ErrorData::Logic(LogicError::DivisionByZero).locate(Some([1, 6].into())))
+    );
+    Ok(())
+}
+
+#[test]
+fn truthiness() -> Result<()> {
+    // every value but #f is true
+    let values = [
+        ("#f", false),
+        ("#t", true),
+        ("0", true),
+        ("0.0", true),
+        ("1/2", true),
+        ("\"\"", true),
+        ("#\\a", true),
+        ("'a", true),
+        ("'()", true),
+        ("'(#f)", true),
+        ("(vector)", true),
+        ("car", true),
+        ("(lambda () #f)", true),
+        ("(if #f #f)", true),
+        ("(current-output-port)", true),
+    ];
+    for optimize in [false, true].iter() {
+        let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+        interpreter.set_optimize(*optimize);
+        for (value, truth) in values.iter() {
+            let expected = match truth {
+                true => "'(yes #f yes yes #t yes)",
+                false => "'(no #t no #f #f no)",
+            };
+            let tests = format!(
+                "(list (if {0} 'yes 'no) (not {0}) (cond ({0} 'yes) (else 'no)) (and {0} 'yes) \
+                 (if (or {0} #f) #t #f) (let ((r 'no)) (when {0} (set! r 'yes)) r))",
+                value
+            );
+            assert_eq!(
+                interpreter.eval_str(&tests)?,
+                interpreter.eval_str(expected)?,
+                "{} optimized: {}",
+                value,
+                optimize
+            );
+            assert_eq!(
+                interpreter.eval_str(&format!("(unless {} 'run)", value))?,
+                match truth {
+                    true => Some(Value::Void),
+                    false => Some(Value::Symbol("run".into())),
+                },
+                "{}",
+                value
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn procedure_frames() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    // set! on parameters, seen by closures capturing them
+    interpreter.eval_str(
+        "
+        (define (make-account balance)
+            (define (deposit amount) (set! balance (+ balance amount)) balance)
+            (lambda (amount) (if (> amount 0) (deposit amount) balance)))
+        (define account (make-account 10))
+        (define other (make-account 100))",
+    )?;
+    assert_eq!(
+        interpreter.eval_str("(account 5) (other 1) (account 2)")?,
+        Some(Value::Number(Number::Integer(17)))
+    );
+    assert_eq!(
+        interpreter.eval_str("(other 0)")?,
+        Some(Value::Number(Number::Integer(101)))
+    );
+    // a parameter assigned after the closure is created
+    assert_eq!(
+        interpreter
+            .eval_str("((lambda (x) (define (get) x) (set! x 'changed) (get)) 'original)")?,
+        Some(Value::Symbol("changed".into()))
+    );
+    // every call has its own frame
+    assert_eq!(
+        interpreter.eval_str(
+            "
+            (define (make-pair a b) (lambda (first?) (if first? a b)))
+            (define p (make-pair 1 2))
+            (define q (make-pair 3 4))
+            (list (p #t) (p #f) (q #t) (q #f))"
+        )?,
+        Some(Value::Pair(Box::new(list!(
+            1.into(),
+            2.into(),
+            3.into(),
+            4.into()
+        ))))
+    );
+    Ok(())
+}
+
+#[test]
+fn arity() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let error = interpreter
+        .eval("((lambda (x y) x) 1)".chars())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "procedure (lambda (x y)) expects 2 arguments, got 1"
+    );
+    assert_eq!(error.location, Some(Location::from([1, 3]).with_length(1)));
+    assert_eq!(
+        interpreter
+            .eval("((lambda (x y) x) 1 2 3)".chars())
+            .unwrap_err()
+            .to_string(),
+        "procedure (lambda (x y)) expects 2 arguments, got 3"
+    );
+
+    interpreter.eval("(define (f x . rest) x)".chars())?;
+    assert_eq!(
+        interpreter.eval("(f 1 2 3)".chars())?,
+        Some(Value::Number(Number::Integer(1)))
+    );
+    assert_eq!(
+        interpreter.eval("(f)".chars()).unwrap_err().to_string(),
+        "procedure (f x . rest) expects at least 1 argument, got 0"
+    );
+    // tail call
+    interpreter.eval("(define (g) (f))".chars())?;
+    let error = interpreter.eval("(g)".chars()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "procedure (f x . rest) expects at least 1 argument, got 0"
+    );
+    assert_eq!(error.location, Some(Location::from([1, 15]).with_length(1)));
+
+    assert_eq!(
+        interpreter
+            .eval("(car 1 2)".chars())
+            .unwrap_err()
+            .to_string(),
+        "procedure (car pair) expects 1 argument, got 2"
+    );
+    Ok(())
+}
+
+#[test]
+fn variadic_formals() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval_str(
+        "(define (list-all . xs) xs) (define list-lambda (lambda xs xs)) \
+         (define (first-and-rest x . rest) (list x rest))",
+    )?;
+    let cases = [
+        ("(list-all)", "'()"),
+        ("(list-all 1 2 3)", "'(1 2 3)"),
+        ("(list-lambda)", "'()"),
+        ("(list-lambda 1 2 3)", "'(1 2 3)"),
+        ("(apply list-all '(1 2))", "'(1 2)"),
+        ("(first-and-rest 1)", "'(1 ())"),
+        ("(first-and-rest 1 2 3)", "'(1 (2 3))"),
+    ];
+    for (expression, expected) in cases.iter() {
+        assert_eq!(
+            interpreter.eval_str(expression)?,
+            interpreter.eval_str(expected)?,
+            "{}",
+            expression
+        );
+    }
+    assert_eq!(
+        interpreter.eval_str("list-all")?.unwrap().to_string(),
+        "#<procedure list-all xs>"
+    );
+    Ok(())
+}
+
+#[test]
+fn procedure_names() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval_str(
+        "(define (safe-div a b) (/ a b)) (define cube (lambda (x) (* x x x))) \
+         (define (g . args) args) (define h safe-div)",
+    )?;
+    let printed = [
+        ("safe-div", "#<procedure safe-div (a b)>"),
+        ("cube", "#<procedure cube (x)>"),
+        ("g", "#<procedure g args>"),
+        // the name of a procedure is the one it is defined with
+        ("h", "#<procedure safe-div (a b)>"),
+        ("(lambda (x . rest) x)", "#<procedure (x . rest)>"),
+    ];
+    for (expression, expected) in printed.iter() {
+        assert_eq!(
+            interpreter.eval_str(expression)?.unwrap().to_string(),
+            *expected
+        );
+    }
+    for (expression, message) in [
+        (
+            "(safe-div 1)",
+            "procedure (safe-div a b) expects 2 arguments, got 1",
+        ),
+        (
+            "(h 1)",
+            "procedure (safe-div a b) expects 2 arguments, got 1",
+        ),
+        (
+            "((lambda (x) x))",
+            "procedure (lambda (x)) expects 1 argument, got 0",
+        ),
+        (
+            "(car cube)",
+            "car: expect a pair, got #<procedure cube (x)>",
+        ),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval_str(expression).unwrap_err().to_string(),
+            *message
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn mixed_bodies() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let source = "
+        (define (f n)
+          (define log '())
+          (set! log (cons n log))
+          (define (even? n) (if (= n 0) #t (odd? (- n 1))))
+          (set! log (cons 'between log))
+          (define (odd? n) (if (= n 0) #f (even? (- n 1))))
+          (list (odd? n) log))
+        (f 3)";
+    let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+    parser.set_mixed_bodies(true);
+    let mut result = None;
+    for statement in parser {
+        let mut statement = statement?;
+        resolve_statement(&mut statement);
+        result = interpreter.eval_root_ast(&statement)?;
+    }
+    // expressions run in order, definitions are in the scope of the whole body
+    assert_eq!(result, interpreter.eval_str("'(#t (between 3))")?);
+    Ok(())
+}
+
+#[test]
+fn keyword_shadowing() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let cases = [
+        (
+            "(define (apply-f if x) (if x)) (apply-f (lambda (x) (* x 2)) 21)",
+            "42",
+        ),
+        ("((lambda (define) (define 1 2)) list)", "'(1 2)"),
+        ("((lambda lambda lambda) 1 2)", "'(1 2)"),
+        (
+            "(define (f) (define define list) (define 1 2)) (f)",
+            "'(1 2)",
+        ),
+        ("(define (g let) (let 1)) (g -)", "-1"),
+        // not shadowed outside of the procedures
+        ("(if #f 1 2)", "2"),
+        ("(define x 1) (set! x 2) x", "2"),
+        ("(let ((y 1)) y)", "1"),
+    ];
+    for (expression, expected) in cases.iter() {
+        assert_eq!(
+            interpreter.eval_str(expression)?,
+            interpreter.eval_str(expected)?,
+            "{}",
+            expression
+        );
+    }
+    Ok(())
+}
+
 #[test]
 fn procedure_as_data() -> Result<()> {
     let mut interpreter = Interpreter::<f32>::new_with_stdlib();
 
     let program = vec![
         Statement::Definition(Definition::from(DefinitionBody(
-            "add".to_string(),
+            "add".into(),
             simple_procedure(
                 param_fixed!["x", "y"],
                 Expression::from(ExpressionBody::ProcedureCall(
-                    Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
+                    Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
                     vec![
-                        Expression::from(ExpressionBody::Symbol("x".to_string())),
-                        Expression::from(ExpressionBody::Symbol("y".to_string())),
+                        Expression::from(ExpressionBody::Symbol("x".into())),
+                        Expression::from(ExpressionBody::Symbol("y".into())),
                     ],
                 )),
             ),
         ))),
         Statement::Definition(Definition::from(DefinitionBody(
-            "apply-op".to_string(),
+            "apply-op".into(),
             simple_procedure(
                 param_fixed!["op", "x", "y"],
                 Expression::from(ExpressionBody::ProcedureCall(
-                    Box::new(Expression::from(ExpressionBody::Symbol("op".to_string()))),
+                    Box::new(Expression::from(ExpressionBody::Symbol("op".into()))),
                     vec![
-                        Expression::from(ExpressionBody::Symbol("x".to_string())),
-                        Expression::from(ExpressionBody::Symbol("y".to_string())),
+                        Expression::from(ExpressionBody::Symbol("x".into())),
+                        Expression::from(ExpressionBody::Symbol("y".into())),
                     ],
                 )),
             ),
         ))),
         Statement::Expression(Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol(
-                "apply-op".to_string(),
-            ))),
+            Box::new(Expression::from(ExpressionBody::Symbol("apply-op".into()))),
             vec![
-                Expression::from(ExpressionBody::Symbol("add".to_string())),
+                Expression::from(ExpressionBody::Symbol("add".into())),
                 ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                 ExpressionBody::Primitive(Primitive::Integer(2)).into(),
             ],
@@ -1215,84 +2950,74 @@ fn procedure_as_data() -> Result<()> {
 }
 
 #[test]
-fn eval_tail_expression() -> Result<()> {
-    let expect_result = convert_located(vec![
+fn eval_tail_node() -> Result<()> {
+    let arguments = convert_located(vec![
         ExpressionBody::Primitive(Primitive::Integer(2)),
         ExpressionBody::Primitive(Primitive::Integer(5)),
     ]);
     let interpreter = Interpreter::<f32>::new_with_stdlib();
+    let eval_tail = |expression: Expression| -> Result<TailExpressionResult<f32>> {
+        Interpreter::eval_tail_node(&compile_expression(&expression)?, &interpreter.env)
+    };
+    let add = interpreter
+        .env
+        .get("+")
+        .unwrap()
+        .clone()
+        .expect_procedure()?;
+    let add_call = || {
+        TailExpressionResult::TailCall(
+            add.clone(),
+            smallvec![2.into(), 5.into()],
+            None,
+            interpreter.env.clone(),
+        )
+    };
 
-    {
-        let expression = ExpressionBody::Primitive(Primitive::Integer(3)).into();
-        assert_eq!(
-            Interpreter::eval_tail_expression(&expression, interpreter.env.clone())?,
-            TailExpressionResult::Value(Value::Number(Number::Integer(3)))
-        );
-    }
-    {
-        let expression = Expression::from(ExpressionBody::ProcedureCall(
-            Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
-            expect_result.clone(),
-        ));
-        assert_eq!(
-            Interpreter::eval_tail_expression(&expression, interpreter.env.clone())?,
-            TailExpressionResult::TailCall(TailCall::Ref(
-                &Expression::from(ExpressionBody::Symbol("+".to_string())),
-                &expect_result,
-                interpreter.env.clone()
-            ))
-        );
-    }
-    {
-        let expression = Expression::from(ExpressionBody::Conditional(Box::new((
+    assert_eq!(
+        eval_tail(ExpressionBody::Primitive(Primitive::Integer(3)).into())?,
+        TailExpressionResult::Value(Value::Number(Number::Integer(3)))
+    );
+    assert_eq!(
+        eval_tail(Expression::from(ExpressionBody::ProcedureCall(
+            Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
+            arguments.clone(),
+        )))?,
+        add_call()
+    );
+    assert_eq!(
+        eval_tail(Expression::from(ExpressionBody::Conditional(Box::new((
             ExpressionBody::Primitive(Primitive::Boolean(true)).into(),
             Expression::from(ExpressionBody::ProcedureCall(
-                Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
-                expect_result.clone(),
+                Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
+                arguments.clone(),
             )),
             None,
-        ))));
-        assert_eq!(
-            Interpreter::eval_tail_expression(&expression, interpreter.env.clone())?,
-            TailExpressionResult::TailCall(TailCall::Ref(
-                &Expression::from(ExpressionBody::Symbol("+".to_string())),
-                &expect_result,
-                interpreter.env.clone()
-            ))
-        );
-    }
-    {
-        let expression = Expression::from(ExpressionBody::Conditional(Box::new((
+        )))))?,
+        add_call()
+    );
+    assert_eq!(
+        eval_tail(Expression::from(ExpressionBody::Conditional(Box::new((
             ExpressionBody::Primitive(Primitive::Boolean(false)).into(),
             Expression::from(ExpressionBody::ProcedureCall(
-                Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
-                expect_result.clone(),
+                Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
+                arguments.clone(),
             )),
             Some(ExpressionBody::Primitive(Primitive::Integer(4)).into()),
-        ))));
-        assert_eq!(
-            Interpreter::eval_tail_expression(&expression, interpreter.env.clone())?,
-            TailExpressionResult::Value(Value::Number(Number::Integer(4)))
-        );
-    }
-    {
-        let expression = Expression::from(ExpressionBody::Conditional(Box::new((
+        )))))?,
+        TailExpressionResult::Value(Value::Number(Number::Integer(4)))
+    );
+    assert_eq!(
+        eval_tail(Expression::from(ExpressionBody::Conditional(Box::new((
             ExpressionBody::Primitive(Primitive::Boolean(false)).into(),
             ExpressionBody::Primitive(Primitive::Integer(4)).into(),
             Some(Expression::from(ExpressionBody::ProcedureCall(
-                Box::new(Expression::from(ExpressionBody::Symbol("+".to_string()))),
-                expect_result.clone(),
+                Box::new(Expression::from(ExpressionBody::Symbol("+".into()))),
+                arguments.clone(),
             ))),
-        ))));
-        assert_eq!(
-            Interpreter::eval_tail_expression(&expression, interpreter.env.clone())?,
-            TailExpressionResult::TailCall(TailCall::Ref(
-                &Expression::from(ExpressionBody::Symbol("+".to_string())),
-                &expect_result,
-                interpreter.env.clone()
-            ))
-        );
-    }
+        )))))?,
+        add_call()
+    );
     Ok(())
 }
 
@@ -1310,10 +3035,10 @@ fn datum_literal() -> Result<()> {
     );
     assert_eq!(
         Interpreter::eval_expression(
-            &ExpressionBody::Quote(Box::new(DatumBody::Symbol("a".to_string()).into())).into(),
+            &ExpressionBody::Quote(Box::new(DatumBody::Symbol("a".into()).into())).into(),
             &interpreter.env,
         )?,
-        Value::Symbol("a".to_string())
+        Value::Symbol("a".into())
     );
     assert_eq!(
         Interpreter::eval_expression(
@@ -1339,7 +3064,7 @@ fn datum_literal() -> Result<()> {
         Interpreter::eval_expression(
             &ExpressionBody::Quote(
                 Box::<Datum>::new(
-                    DatumBody::Vector(vec![DatumBody::Symbol("a".to_string()).into()]).into()
+                    DatumBody::Vector(vec![DatumBody::Symbol("a".into()).into()]).into()
                 )
                 .into()
             )
@@ -1347,12 +3072,62 @@ fn datum_literal() -> Result<()> {
             &interpreter.env,
         )?,
         Value::Vector(ValueReference::new_immutable(vec![Value::Symbol(
-            "a".to_string()
+            "a".into()
         )]))
     );
     Ok(())
 }
 
+#[test]
+fn self_evaluating_vectors() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        interpreter.eval_str("#(a 1 (+ 1 2) #(b))")?,
+        interpreter.eval_str("'#(a 1 (+ 1 2) #(b))")?
+    );
+    assert_eq!(
+        interpreter.eval_str("(vector-ref #(1 (+ 1 2)) 1)")?,
+        interpreter.eval_str("'(+ 1 2)")?
+    );
+    assert_eq!(
+        interpreter.eval_str("(define a 1) (vector-ref (vector a (+ 1 2)) 1)")?,
+        Some(Value::Number(Number::Integer(3)))
+    );
+    Ok(())
+}
+
+#[test]
+fn literal_mutation() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    // the literal is the same vector each time the body is evaluated, a counter if it could be
+    // mutated
+    interpreter.eval_str(
+        "(define (next) \n\
+           (define counter '#(0)) \n\
+           (vector-set! counter 0 (+ (vector-ref counter 0) 1)) \n\
+           (vector-ref counter 0))",
+    )?;
+    let error = interpreter.eval_str("(next)").unwrap_err();
+    assert_eq!(
+        error.data,
+        ErrorData::Logic(LogicError::LiteralMutation("#(0)".to_string()))
+    );
+    assert_eq!(error.data.to_string(), "attempt to modify a literal #(0)");
+    assert_eq!(
+        error.location,
+        Some(Location::from([3, 13]).with_length(11))
+    );
+    assert!(interpreter.eval_str("(vector-set! #(1 2) 0 3)").is_err());
+    assert_eq!(
+        interpreter.eval_str(
+            "(define v (vector 1 2)) (define w (make-vector 2 0)) \
+             (vector-set! v 0 3) (vector-set! w 0 3) (list v w)"
+        )?,
+        interpreter.eval_str("(list (vector 3 2) (vector 3 0))")?
+    );
+    Ok(())
+}
+
 #[test]
 fn search_library() -> Result<()> {
     let mut interpreter = Interpreter::<f32>::new();
@@ -1368,9 +3143,16 @@ fn search_library() -> Result<()> {
         let library = interpreter.get_library(library_name!("lib", "not", "exist").into());
         assert_eq!(
             library,
-            error!(LogicError::LibraryNotFound(library_name!(
-                "lib", "not", "exist"
-            )))
+            error!(LogicError::LibraryNotFound(
+                library_name!("lib", "not", "exist"),
+                ["sld", "scm"]
+                    .iter()
+                    .map(|extension| std::env::current_dir()
+                        .unwrap()
+                        .join("lib/not/exist")
+                        .with_extension(extension))
+                    .collect()
+            ))
         );
     }
     let lib_not_exist_factory = LibraryFactory::Native(
@@ -1398,8 +3180,8 @@ fn import_set() -> Result<()> {
         library_name!("foo", "bar"),
         Box::new(|| {
             vec![
-                ("a".to_string(), Value::String("father".to_string())),
-                ("b".to_string(), Value::String("bob".to_string())),
+                ("a".to_string(), Value::String("father".into())),
+                ("b".to_string(), Value::String("bob".into())),
             ]
         }),
     ));
@@ -1407,28 +3189,28 @@ fn import_set() -> Result<()> {
     {
         let definitions = interpreter.eval_import_set(&direct.clone().into())?;
         assert!(definitions.len() == 2);
-        assert!(definitions.contains(&("a".to_string(), Value::String("father".to_string()))));
-        assert!(definitions.contains(&("b".to_string(), Value::String("bob".to_string()))));
+        assert!(definitions.contains(&("a".to_string(), Value::String("father".into()))));
+        assert!(definitions.contains(&("b".to_string(), Value::String("bob".into()))));
     }
     {
         let only = ImportSetBody::Only(Box::new(direct.clone().into()), vec!["b".to_string()]);
         let definitions = interpreter.eval_import_set(&only.into())?;
         assert!(definitions.len() == 1);
-        assert!(definitions.contains(&("b".to_string(), Value::String("bob".to_string()))));
+        assert!(definitions.contains(&("b".to_string(), Value::String("bob".into()))));
     }
     let prefix = ImportSetBody::Prefix(Box::new(direct.clone().into()), "god-".to_string());
     {
         let definitions = interpreter.eval_import_set(&prefix.clone().into())?;
         assert!(definitions.len() == 2);
-        assert!(definitions.contains(&("god-a".to_string(), Value::String("father".to_string()))));
-        assert!(definitions.contains(&("god-b".to_string(), Value::String("bob".to_string()))));
+        assert!(definitions.contains(&("god-a".to_string(), Value::String("father".into()))));
+        assert!(definitions.contains(&("god-b".to_string(), Value::String("bob".into()))));
     }
     {
         let except =
             ImportSetBody::Except(Box::new(prefix.clone().into()), vec!["god-b".to_string()]);
         let definitions = interpreter.eval_import_set(&except.into())?;
         assert!(definitions.len() == 1);
-        assert!(definitions.contains(&("god-a".to_string(), Value::String("father".to_string()))));
+        assert!(definitions.contains(&("god-a".to_string(), Value::String("father".into()))));
     }
     {
         let rename = ImportSetBody::Rename(
@@ -1437,11 +3219,203 @@ fn import_set() -> Result<()> {
         );
         let definitions = interpreter.eval_import_set(&rename.into())?;
         assert!(definitions.len() == 2);
-        assert!(definitions.contains(&("god-a".to_string(), Value::String("father".to_string()))));
-        assert!(definitions.contains(&("human-a".to_string(), Value::String("bob".to_string()))));
+        assert!(definitions.contains(&("god-a".to_string(), Value::String("father".into()))));
+        assert!(definitions.contains(&("human-a".to_string(), Value::String("bob".into()))));
+    }
+    Ok(())
+}
+#[test]
+fn import_set_modifiers() -> Result<()> {
+    // imports are only allowed at the beginning of a program
+    let interpreter = || {
+        let mut interpreter = Interpreter::<f32>::new();
+        interpreter.register_library_factory(LibraryFactory::Native(
+            library_name!("foo", "bar"),
+            Box::new(|| {
+                vec![
+                    ("a".to_string(), Value::String("father".into())),
+                    ("b".to_string(), Value::String("bob".into())),
+                ]
+            }),
+        ));
+        interpreter
+    };
+    assert_eq!(
+        interpreter().eval("(import (prefix (only (foo bar) a) my-)) my-a".chars())?,
+        Some(Value::String("father".into()))
+    );
+    assert_eq!(
+        interpreter()
+            .eval("(import (prefix (only (foo bar) a) other-)) other-b".chars())
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::UnboundedSymbol(
+            "other-b".to_string(),
+            vec!["other-a".to_string()]
+        ))
+    );
+    assert_eq!(
+        interpreter().eval("(import (only (foo bar) c))".chars()),
+        Err(ErrorData::Logic(LogicError::NotExported(
+            library_name!("foo", "bar"),
+            "c".to_string()
+        ))
+        .locate(Some([1, 15].into())))
+    );
+    assert_eq!(
+        interpreter()
+            .eval("(import (except (prefix (foo bar) p-) a))".chars())
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::NotExported(
+            library_name!("foo", "bar"),
+            "a".to_string()
+        ))
+    );
+    assert_eq!(
+        interpreter()
+            .eval("(import (rename (foo bar) (c d)))".chars())
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::NotExported(
+            library_name!("foo", "bar"),
+            "c".to_string()
+        ))
+    );
+    assert_eq!(
+        interpreter()
+            .eval("(import (rename (foo bar) (a b)))".chars())
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::DuplicateImport("b".to_string()))
+    );
+    Ok(())
+}
+
+#[test]
+fn library_path() -> Result<()> {
+    let root = std::env::temp_dir().join("ruschm_library_path_test");
+    let first = root.join("first");
+    let second = root.join("second");
+    for directory in [&first, &second].iter() {
+        std::fs::create_dir_all(directory.join("my")).unwrap();
+    }
+    std::fs::write(
+        first.join("my/utils.scm"),
+        "(define-library (my utils) (export origin) (import (scheme base) (test counter)) \
+         (begin (load!) (define origin 'first)))",
+    )
+    .unwrap();
+    std::fs::write(
+        second.join("my/utils.sld"),
+        "(define-library (my utils) (export origin) (import (scheme base)) \
+         (begin (define origin 'second)))",
+    )
+    .unwrap();
+    for (directory, origin) in [(&first, "first"), (&second, "second")].iter() {
+        std::fs::write(
+            directory.join("my/local.sld"),
+            format!(
+                "(define-library (my local) (export local-origin) (import (scheme base)) \
+                 (begin (define local-origin '{})))",
+                origin
+            ),
+        )
+        .unwrap();
+    }
+    // a library file imports libraries next to it before searching library paths
+    std::fs::write(
+        second.join("my/app.sld"),
+        "(define-library (my app) (export app-origin) (import (my local)) \
+         (begin (define app-origin local-origin)))",
+    )
+    .unwrap();
+
+    let loaded = Rc::new(Cell::new(0));
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    {
+        let loaded = loaded.clone();
+        interpreter.register_library_factory(LibraryFactory::Native(
+            library_name!("test", "counter"),
+            Box::new(move || {
+                let loaded = loaded.clone();
+                vec![(
+                    "load!".to_string(),
+                    Value::Procedure(Procedure::new_builtin_impure(
+                        "load!".to_string(),
+                        param_fixed![],
+                        move |_, _| {
+                            loaded.set(loaded.get() + 1);
+                            Ok(Value::Void)
+                        },
+                    )),
+                )]
+            }),
+        ));
+    }
+    interpreter.add_library_path(&first);
+    interpreter.add_library_path(&second);
+    assert_eq!(
+        interpreter.library_paths(),
+        &[first.clone(), second.clone()]
+    );
+    interpreter.eval("(import (my utils) (prefix (my utils) my-) (my app))".chars())?;
+    assert_eq!(
+        interpreter.eval("origin".chars())?,
+        Some(Value::Symbol("first".into()))
+    );
+    assert_eq!(
+        interpreter.eval("app-origin".chars())?,
+        Some(Value::Symbol("second".into()))
+    );
+    // the library body is evaluated only once
+    assert_eq!(loaded.get(), 1);
+
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.add_library_path(&first);
+    let error = interpreter
+        .eval("(import (my missing))".chars())
+        .unwrap_err();
+    let message = error.to_string();
+    assert!(message.starts_with("library (my missing) not found, tried "));
+    assert!(message.contains(first.join("my/missing.sld").to_str().unwrap()));
+    assert!(message.contains(first.join("my/missing.scm").to_str().unwrap()));
+
+    std::fs::remove_dir_all(root).unwrap();
+    Ok(())
+}
+
+#[test]
+fn library_cache() -> Result<()> {
+    let loaded = Rc::new(Cell::new(0));
+    let mut interpreter = Interpreter::<f32>::new();
+    {
+        let loaded = loaded.clone();
+        interpreter.register_library_factory(LibraryFactory::Native(
+            library_name!("foo"),
+            Box::new(move || {
+                loaded.set(loaded.get() + 1);
+                vec![("a".to_string(), Value::Boolean(true))]
+            }),
+        ));
     }
+    interpreter.eval("(import (foo) (prefix (foo) foo-))".chars())?;
+    assert_eq!(loaded.get(), 1);
+    // registering a factory again replaces the evaluated library
+    interpreter.register_library_factory(LibraryFactory::Native(
+        library_name!("foo"),
+        Box::new(|| vec![("a".to_string(), Value::Boolean(false))]),
+    ));
+    assert_eq!(
+        interpreter.get_library(library_name!("foo").into())?,
+        Library::new(
+            library_name!("foo"),
+            vec![("a".to_string(), Value::Boolean(false))]
+        )
+    );
     Ok(())
 }
+
 #[test]
 fn import() -> Result<()> {
     let mut interpreter = Interpreter::<f32>::new();
@@ -1449,8 +3423,8 @@ fn import() -> Result<()> {
         library_name!("foo", "bar"),
         Box::new(|| {
             vec![
-                ("a".to_string(), Value::String("father".to_string())),
-                ("b".to_string(), Value::String("bob".to_string())),
+                ("a".to_string(), Value::String("father".into())),
+                ("b".to_string(), Value::String("bob".into())),
             ]
         }),
     ));
@@ -1470,12 +3444,81 @@ fn import() -> Result<()> {
     interpreter.eval_import(&import_declaration, interpreter.env.clone())?;
     {
         let value = interpreter.env.get("b").unwrap();
-        assert_eq!(value.deref(), &Value::String("bob".to_string()));
+        assert_eq!(value.deref(), &Value::String("bob".into()));
     }
     {
         let value = interpreter.env.get("c").unwrap();
-        assert_eq!(value.deref(), &Value::String("father".to_string()));
+        assert_eq!(value.deref(), &Value::String("father".into()));
+    }
+    Ok(())
+}
+
+#[test]
+fn library_isolation() -> Result<()> {
+    let interpreter = || -> Result<Interpreter<f32>> {
+        let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+        interpreter.register_library_factory(LibraryFactory::from_char_stream(
+            &library_name!("iso", "lib"),
+            "(define-library (iso lib)
+               (import (scheme base))
+               (export (rename helper-result result))
+               (begin (define (helper) 1) (define helper-result (helper))))"
+                .chars(),
+        )?);
+        interpreter.register_library_factory(LibraryFactory::from_char_stream(
+            &library_name!("iso", "leak"),
+            "(define-library (iso leak)
+               (import (scheme base))
+               (export leaked)
+               (begin (define leaked outside)))"
+                .chars(),
+        )?);
+        interpreter.register_library_factory(LibraryFactory::Native(
+            library_name!("iso", "other"),
+            Box::new(|| vec![("result".to_string(), Value::Boolean(false))]),
+        ));
+        interpreter
+            .env
+            .define("outside".to_string(), Value::Boolean(true));
+        Ok(interpreter)
+    };
+    {
+        let mut interpreter = interpreter()?;
+        assert_eq!(
+            interpreter.eval("(import (iso lib) (iso lib)) result".chars())?,
+            Some(Value::Number(Number::Integer(1)))
+        );
+        // library definitions do not leak into the importer
+        assert_eq!(
+            interpreter.eval("helper".chars()).unwrap_err().data,
+            ErrorData::Logic(LogicError::UnboundedSymbol("helper".to_string(), vec![]))
+        );
     }
+    assert_eq!(
+        interpreter()?
+            .eval("(import (only (iso lib) helper))".chars())
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::NotExported(
+            library_name!("iso", "lib"),
+            "helper".to_string()
+        ))
+    );
+    // library bodies do not see the top-level environment
+    assert_eq!(
+        interpreter()?
+            .eval("(import (iso leak))".chars())
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::UnboundedSymbol("outside".to_string(), vec![]))
+    );
+    assert_eq!(
+        interpreter()?
+            .eval("(import (iso lib) (iso other))".chars())
+            .unwrap_err(),
+        ErrorData::Logic(LogicError::DuplicateImport("result".to_string()))
+            .locate(Some([1, 18].into()))
+    );
     Ok(())
 }
 
@@ -1486,8 +3529,8 @@ fn library_definition() -> Result<()> {
         library_name!("foo", "bar"),
         Box::new(|| {
             vec![
-                ("a".to_string(), Value::String("father".to_string())),
-                ("b".to_string(), Value::String("bob".to_string())),
+                ("a".to_string(), Value::String("father".into())),
+                ("b".to_string(), Value::String("bob".into())),
             ]
         }),
     ));
@@ -1521,7 +3564,7 @@ fn library_definition() -> Result<()> {
             library,
             Library::new(
                 library_name!("foo", "foo-bar").into(),
-                vec![("a".to_string(), Value::String("father".to_string()))]
+                vec![("a".to_string(), Value::String("father".into()))]
             )
         );
     }
@@ -1553,8 +3596,8 @@ fn library_definition() -> Result<()> {
             Library::new(
                 library_name!("foo", "foo-bar").into(),
                 vec![
-                    ("a".to_string(), Value::String("father".to_string())),
-                    ("c".to_string(), Value::String("bob".to_string()))
+                    ("a".to_string(), Value::String("father".into())),
+                    ("c".to_string(), Value::String("bob".into()))
                 ]
             )
         );
@@ -1575,7 +3618,7 @@ fn library_definition() -> Result<()> {
                 .into(),
                 LibraryDeclaration::Begin(vec![Statement::Definition(
                     DefinitionBody(
-                        "a".to_string(),
+                        "a".into(),
                         ExpressionBody::Primitive(Primitive::Integer(5)).into(),
                     )
                     .into(),
@@ -1621,7 +3664,7 @@ fn transformer() -> Result<()> {
         "foo".to_string(),
         Value::Transformer(Transformer::Native(|datum| {
             Ok(DatumBody::Pair(Box::new(GenericPair::cons(
-                DatumBody::Symbol("+".to_string()).into(),
+                DatumBody::Symbol("+".into()).into(),
                 datum.into(),
             )))
             .no_locate())
@@ -1631,7 +3674,7 @@ fn transformer() -> Result<()> {
     assert_eq!(
         Interpreter::eval_expression(
             &ExpressionBody::ProcedureCall(
-                Box::new(ExpressionBody::Symbol("foo".to_string()).into()),
+                Box::new(ExpressionBody::Symbol("foo".into()).into()),
                 vec![
                     ExpressionBody::Primitive(Primitive::Integer(1)).into(),
                     ExpressionBody::Primitive(Primitive::Integer(2)).into(),
@@ -1644,30 +3687,259 @@ fn transformer() -> Result<()> {
         Ok(Value::Number(Number::Integer(6)))
     );
     assert_eq!(
-        Interpreter::eval_tail_expression(
-            &ExpressionBody::ProcedureCall(
-                Box::new(ExpressionBody::Symbol("foo".to_string()).into()),
-                vec![
-                    ExpressionBody::Primitive(Primitive::Integer(1)).into(),
-                    ExpressionBody::Primitive(Primitive::Integer(2)).into(),
-                    ExpressionBody::Primitive(Primitive::Integer(3)).into(),
-                ],
-            )
-            .into(),
-            env.clone(),
+        Interpreter::eval_tail_node(
+            &compile_expression(
+                &ExpressionBody::ProcedureCall(
+                    Box::new(ExpressionBody::Symbol("foo".into()).into()),
+                    vec![
+                        ExpressionBody::Primitive(Primitive::Integer(1)).into(),
+                        ExpressionBody::Primitive(Primitive::Integer(2)).into(),
+                        ExpressionBody::Primitive(Primitive::Integer(3)).into(),
+                    ],
+                )
+                .into()
+            )?,
+            &env,
         ),
-        Ok(TailExpressionResult::TailCall(TailCall::Owned(
-            ExpressionBody::Symbol("+".to_string()).into(),
-            vec![
-                ExpressionBody::Primitive(Primitive::Integer(1)).into(),
-                ExpressionBody::Primitive(Primitive::Integer(2)).into(),
-                ExpressionBody::Primitive(Primitive::Integer(3)).into(),
-            ],
-            env,
-            PhantomData
+        Ok(TailExpressionResult::TailCall(
+            env.get("+").unwrap().clone().expect_procedure()?,
+            smallvec![1.into(), 2.into(), 3.into()],
+            None,
+            env.clone()
+        ))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn runtime_error_location() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    let error = interpreter
+        .eval(
+            "(define (f x)
+  (+ x
+     undefined-variable))
+(f 1)"
+                .chars(),
+        )
+        .unwrap_err();
+    assert_eq!(
+        error,
+        ErrorData::Logic(LogicError::UnboundedSymbol(
+            "undefined-variable".to_string(),
+            vec![]
+        ))
+        .no_locate()
+    );
+    assert_eq!(error.location.map(|location| location.line), Some(3));
+
+    // errors raised by builtins point at the call
+    let error = interpreter
+        .eval("(define v (vector 1))\n\n(vector-ref v 'a)".chars())
+        .unwrap_err();
+    assert_eq!(error.location.map(|location| location.line), Some(3));
+    Ok(())
+}
+
+#[test]
+fn backtrace() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval(
+        "(define (a) (+ 1 (b)))
+         (define (b) (+ 1 (c)))
+         (define (c) (+ 1 (car 1)))"
+            .chars(),
+    )?;
+    let backtrace = |interpreter: &mut Interpreter<f32>, source: &str| {
+        interpreter.eval(source.chars()).unwrap_err().backtrace
+    };
+    let names = |backtrace: Option<Backtrace>| {
+        backtrace
+            .unwrap()
+            .frames
+            .iter()
+            .map(|frame| frame.procedure.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(backtrace(&mut interpreter, "(a)")),
+        vec!["car", "c", "b", "a"]
+    );
+
+    interpreter.set_backtrace_depth(2);
+    let truncated = backtrace(&mut interpreter, "(a)");
+    assert_eq!(truncated.as_ref().unwrap().omitted, 2);
+    assert_eq!(names(truncated), vec!["car", "c"]);
+
+    // tail calls replace their caller's frame
+    interpreter.eval("(define (d) (c))".chars())?;
+    let frames = backtrace(&mut interpreter, "(d)").unwrap().frames;
+    assert_eq!(frames[1].procedure, "c");
+    assert_eq!(frames[1].tail_calls, 1);
+
+    // errors raised outside of procedures
+    assert_eq!(backtrace(&mut interpreter, "undefined-variable"), None);
+    Ok(())
+}
+
+#[test]
+fn deep_recursion() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    // nested expressions and non-tail calls deeper than the stack of a test thread
+    let nested = format!("{}0{}", "(+ 1 ".repeat(3000), ")".repeat(3000));
+    assert_eq!(
+        interpreter.eval_str(&nested)?,
+        Some(Value::Number(Number::Integer(3000)))
+    );
+    interpreter.eval_str("(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1)))))")?;
+    assert_eq!(
+        interpreter.eval_str("(count 10000)")?,
+        Some(Value::Number(Number::Integer(10000)))
+    );
+    let nested = format!("{}0{}", "(+ 1 ".repeat(100_000), ")".repeat(100_000));
+    assert_eq!(
+        interpreter.eval_str(&nested).map_err(|e| e.data),
+        Err(ErrorData::Syntax(SyntaxError::TooDeeplyNested(
+            DEFAULT_MAX_NESTING
         )))
     );
+    interpreter.set_max_depth(1000);
+    assert_eq!(
+        interpreter.eval_str("(count 10000)"),
+        Err(ErrorData::RecursionTooDeep(1000).locate(Some([1, 52].into())))
+    );
+    // the limit is not consumed by finished evaluations
+    assert_eq!(
+        interpreter.eval_str("(count 100)")?,
+        Some(Value::Number(Number::Integer(100)))
+    );
+    Ok(())
+}
+
+#[test]
+fn deep_data() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    // lists built as deep as the nesting of the program, which are dropped with the frames
+    // evaluating them
+    interpreter.eval_str("(define (mk x) (list x))")?;
+    for procedure in &["mk", "list"] {
+        let nested = format!(
+            "(define v {}0{}) 'ok",
+            format!("({} ", procedure).repeat(3000),
+            ")".repeat(3000)
+        );
+        assert_eq!(
+            interpreter.eval_str(&nested)?,
+            Some(Value::Symbol("ok".into()))
+        );
+    }
+    interpreter.eval_str("(define (f n) (if (= n 0) 0 (+ 1 (f (- n 1)))))")?;
+    assert_eq!(
+        interpreter.eval_str("(f 5000)")?,
+        Some(Value::Number(Number::Integer(5000)))
+    );
+    // quoted data as deep as the parser allows are compared, written and displayed, and walked
+    // when the interpreter binding them is dropped
+    let nested = format!("{}{}", "(".repeat(9000), ")".repeat(9000));
+    let value = interpreter
+        .eval_str(&format!("(define deep '{}) deep", nested))?
+        .unwrap();
+    assert!(value == interpreter.eval_str(&format!("'{}", nested))?.unwrap());
+    assert_eq!(value.written().to_string(), nested);
+    assert_eq!(value.to_string(), nested);
+    let output = interpreter.eval_str(&format!(
+        "(define port (open-output-string)) (write '{} port) (get-output-string port)",
+        nested
+    ))?;
+    assert_eq!(output, Some(Value::String(nested.as_str().into())));
+    Ok(())
+}
+
+#[test]
+fn fuel() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(interpreter.fuel(), None);
+    interpreter.eval("(define (f) (f))".chars())?;
+    interpreter.set_fuel(1000);
+    assert_eq!(
+        interpreter.eval("(f)".chars()),
+        Err(ErrorData::FuelExhausted.no_locate())
+    );
+    assert_eq!(interpreter.fuel(), Some(0));
+
+    interpreter.set_fuel(1000);
+    assert_eq!(
+        interpreter.eval("(+ 1 (* 2 3))".chars())?,
+        Some(Value::Number(Number::Integer(7)))
+    );
+    assert_eq!(interpreter.fuel(), Some(998));
+
+    interpreter.clear_fuel();
+    assert_eq!(
+        interpreter.eval("(+ 1 2)".chars())?,
+        Some(Value::Number(Number::Integer(3)))
+    );
+    assert_eq!(interpreter.fuel(), None);
+    Ok(())
+}
+
+#[test]
+fn nested_fuel() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    // a host procedure evaluating on an interpreter without fuel
+    interpreter.env.define(
+        "nested",
+        Value::Procedure(Procedure::new_builtin_closure(
+            "nested".to_string(),
+            param_fixed![],
+            |_| {
+                Interpreter::<f32>::new_with_stdlib().eval_str("(+ 1 2)")?;
+                Ok(Value::Void)
+            },
+        )),
+    );
+    interpreter.eval_str("(define (f) (nested) (f))")?;
+    interpreter.set_fuel(1000);
+    assert_eq!(
+        interpreter.eval_str("(f)"),
+        Err(ErrorData::FuelExhausted.no_locate())
+    );
+    assert_eq!(interpreter.fuel(), Some(0));
+    Ok(())
+}
 
+#[test]
+fn interrupt() {
+    use std::{sync::mpsc, thread, time::Duration};
+    let (sender, receiver) = mpsc::channel();
+    let evaluation = thread::spawn(move || {
+        let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+        interpreter.eval("(define (f) (f))".chars()).unwrap();
+        sender.send(interpreter.interrupt_handle()).unwrap();
+        let interrupted =
+            interpreter.eval("(f)".chars()) == Err(ErrorData::Interrupted.no_locate());
+        // the environment is intact and the flag is reset
+        let after =
+            interpreter.eval("(+ 1 2)".chars()) == Ok(Some(Value::Number(Number::Integer(3))));
+        (interrupted, after)
+    });
+    let interrupt = receiver.recv().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    interrupt.store(true, Ordering::Relaxed);
+    assert_eq!(evaluation.join().unwrap(), (true, true));
+}
+
+#[test]
+fn interrupt_while_idle() -> Result<()> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter
+        .interrupt_handle()
+        .store(true, Ordering::Relaxed);
+    assert_eq!(
+        interpreter.eval_str("(+ 1 2)")?,
+        Some(Value::Number(Number::Integer(3)))
+    );
     Ok(())
 }
 
@@ -1677,14 +3949,14 @@ fn ast_location() -> Result<()> {
     assert_eq!(
         interpreter
             .eval_ast(
-                &ExpressionBody::Symbol("foo".to_string())
-                    .locate(Some([4, 6]))
+                &ExpressionBody::Symbol("foo".into())
+                    .locate(Some([4, 6].into()))
                     .into(),
                 Rc::new(Environment::new())
             )
             .unwrap_err()
             .location,
-        Some([4, 6])
+        Some([4, 6].into())
     );
     Ok(())
 }
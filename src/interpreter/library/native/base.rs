@@ -1,9 +1,13 @@
+use crate::interpreter::compiler::datum_value;
+use crate::interpreter::error::{index_out_of_range, wrong_type};
 use crate::parser::pair::GenericPair;
 use crate::parser::*;
+use crate::port::*;
+use crate::stack;
 use crate::values::*;
 use crate::{environment::*, interpreter::*};
-use crate::{error::ErrorData, error::ToLocated};
-use std::rc::Rc;
+use crate::{error::ErrorData, error::SchemeError};
+use std::{cell::RefCell, collections::HashSet, convert::TryFrom, rc::Rc};
 
 fn apply<R: RealNumberInternalTrait>(
     arguments: impl IntoIterator<Item = Value<R>>,
@@ -13,24 +17,210 @@ fn apply<R: RealNumberInternalTrait>(
     let proc = iter.next().unwrap().expect_procedure()?;
     let mut args = iter.collect::<ArgVec<R>>();
     if !args.is_empty() {
-        let extended = args.pop().unwrap();
-
-        let extended = match extended {
-            Value::Pair(p) => p.into_iter().collect::<ArgVec<R>>(),
-            other => return error!(LogicError::TypeMisMatch(other.to_string(), Type::Pair))?,
-        };
+        let extended = args.pop().unwrap().expect_proper_list("apply")?;
         args.extend(extended);
     }
     Interpreter::apply_procedure(&proc, args, &env)
 }
 
+// no continuations in ruschm, so that before and after thunks are only run when body is entered
+// and left, including leaving by error. `emergency-exit` is the only exception.
+fn dynamic_wind<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let before = iter.next().unwrap().expect_procedure()?;
+    let thunk = iter.next().unwrap().expect_procedure()?;
+    let after = iter.next().unwrap().expect_procedure()?;
+    Interpreter::apply_procedure(&before, ArgVec::new(), &env)?;
+    let result = Interpreter::apply_procedure(&thunk, ArgVec::new(), &env);
+    match &result {
+        Err(SchemeError {
+            data: ErrorData::Exit {
+                emergency: true, ..
+            },
+            ..
+        }) => (),
+        _ => {
+            Interpreter::apply_procedure(&after, ArgVec::new(), &env)?;
+        }
+    }
+    result
+}
+
+// (error message irritant ...), irritants are kept written, errors do not hold values
+fn error<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let message = iter.next().unwrap().expect_string()?;
+    error!(LogicError::Raised(
+        message.to_string(),
+        iter.map(|irritant| irritant.written().to_string())
+            .collect()
+    ))
+}
+
+// whether `obj` is an error object whose error matches `kind`
+fn error_object_test<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    kind: impl Fn(&ErrorData) -> bool,
+) -> Result<Value<R>> {
+    Ok(Value::Boolean(
+        match arguments.into_iter().next().unwrap() {
+            Value::ErrorObject(error) => kind(&error.data),
+            _ => false,
+        },
+    ))
+}
+
+// the message given to `error`, the description of other errors
+fn error_object_message<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let error = arguments
+        .into_iter()
+        .next()
+        .unwrap()
+        .expect_error_object()?;
+    Ok(Value::String(
+        match &error.data {
+            ErrorData::Logic(LogicError::Raised(message, _)) => message.clone(),
+            other => other.to_string(),
+        }
+        .into(),
+    ))
+}
+
+// the irritants given to `error` read back, those without written form stay strings
+fn error_object_irritants<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let error = arguments
+        .into_iter()
+        .next()
+        .unwrap()
+        .expect_error_object()?;
+    let irritants = match &error.data {
+        ErrorData::Logic(LogicError::Raised(_, irritants)) => irritants.as_slice(),
+        _ => &[],
+    };
+    let list = irritants
+        .iter()
+        .map(|irritant| {
+            let mut parser = Parser::from_lexer(Lexer::from_char_stream(irritant.chars()));
+            match (parser.parse_datum(), parser.parse_datum()) {
+                (Ok(Some(datum)), Ok(None)) => datum_value(&datum),
+                _ => Ok(Value::String(irritant.as_str().into())),
+            }
+        })
+        .collect::<Result<Pair<R>>>()?;
+    Ok(Value::Pair(Box::new(list)))
+}
+
+#[test]
+fn guard_form() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        it.eval_str(
+            r#"(guard (e ((file-error? e) (list (error-object? e) (read-error? e))))
+                 (open-input-file "/no/such/directory/file"))"#
+        )?
+        .unwrap()
+        .to_string(),
+        "(#t #f)"
+    );
+    assert_eq!(
+        it.eval_str(
+            r#"(guard (e ((file-error? e) 'file) ((read-error? e) 'read))
+                 (read (open-input-string "(1 . )")))"#
+        )?,
+        Some(Value::Symbol("read".into()))
+    );
+    assert_eq!(
+        it.eval_str(
+            r#"(guard (e ((string? e) 'string)
+                         ((error-object? e)
+                          (cons (error-object-message e) (error-object-irritants e))))
+                 (error "out of range" 5 'index "text" '(1 2) car))"#
+        )?
+        .unwrap()
+        .written()
+        .to_string(),
+        r#"("out of range" 5 index "text" (1 2) "<build-in procedure (car)>")"#
+    );
+    assert_eq!(
+        it.eval_str("(guard (e (#t 'caught)) (+ 1 2))")?,
+        Some(Value::Number(Number::Integer(3)))
+    );
+    assert_eq!(
+        it.eval_str("(guard (e ((symbol? e) 'symbol) (else (error-object-message e))) (car 1))")?,
+        Some(Value::String("car: expect a pair, got 1".into()))
+    );
+    // without a matching clause, the error is raised again
+    assert_eq!(
+        it.eval_str("(guard (e ((file-error? e) 'file)) (error \"failed\" 1))")
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::Raised(
+            "failed".to_string(),
+            vec!["1".to_string()]
+        ))
+    );
+    assert_eq!(
+        it.eval_str("(guard (e (#t 'caught)) (exit 3))")
+            .unwrap_err()
+            .data,
+        ErrorData::Exit {
+            code: 3,
+            emergency: false
+        }
+    );
+    Ok(())
+}
+
+fn make_parameter<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let value = iter.next().unwrap();
+    let converter = match iter.next() {
+        Some(converter) => Some(converter.expect_procedure()?),
+        None => None,
+    };
+    let value = match &converter {
+        Some(converter) => {
+            Interpreter::apply_procedure(converter, std::iter::once(value).collect(), &env)?
+        }
+        None => value,
+    };
+    Ok(Value::Procedure(Procedure::Parameter(Rc::new(
+        Parameter::new(value, converter),
+    ))))
+}
+
+fn features<R: RealNumberInternalTrait>(features: &RefCell<Vec<String>>) -> Value<R> {
+    let list = features
+        .borrow()
+        .iter()
+        .map(|feature| Value::Symbol(feature.into()))
+        .collect::<Pair<R>>();
+    Value::Pair(Box::new(list))
+}
+
 fn car<R: RealNumberInternalTrait>(
     arguments: impl IntoIterator<Item = Value<R>>,
 ) -> Result<Value<R>> {
     let mut iter = arguments.into_iter();
-    match iter.next().unwrap().expect_list()? {
-        Pair::Some(car, _) => Ok(car),
-        empty => return error!(LogicError::TypeMisMatch(empty.to_string(), Type::Pair)),
+    match iter.next().unwrap().expect_list()?.into_parts() {
+        Some((car, _)) => Ok(car),
+        None => error!(wrong_type(
+            "car",
+            Type::Pair,
+            &Value::<R>::Pair(Box::new(Pair::Empty))
+        )),
     }
 }
 
@@ -38,9 +228,13 @@ fn cdr<R: RealNumberInternalTrait>(
     arguments: impl IntoIterator<Item = Value<R>>,
 ) -> Result<Value<R>> {
     let mut iter = arguments.into_iter();
-    match iter.next().unwrap().expect_list()? {
-        Pair::Some(_, cdr) => Ok(cdr),
-        empty => return error!(LogicError::TypeMisMatch(empty.to_string(), Type::Pair)),
+    match iter.next().unwrap().expect_list()?.into_parts() {
+        Some((_, cdr)) => Ok(cdr),
+        None => error!(wrong_type(
+            "cdr",
+            Type::Pair,
+            &Value::<R>::Pair(Box::new(Pair::Empty))
+        )),
     }
 }
 
@@ -75,23 +269,89 @@ fn is_pair<R: RealNumberInternalTrait>(
     }
 }
 
-fn eqv<R: RealNumberInternalTrait>(
+fn eqv_values<R: RealNumberInternalTrait>(a: &Value<R>, b: &Value<R>) -> bool {
+    match (a, b) {
+        (Value::Vector(a), Value::Vector(b)) => a.ptr_eq(b),
+        (Value::Pair(a), Value::Pair(b)) => match (a.as_ref(), b.as_ref()) {
+            (GenericPair::Empty, GenericPair::Empty) => true,
+            _ => a.as_ref() as *const Pair<R> == b.as_ref() as *const Pair<R>,
+        },
+        (Value::Number(a), Value::Number(b)) => a.exact_eqv(b),
+        (Value::Procedure(a), Value::Procedure(b)) => a.ptr_eq(b),
+        _ => a == b,
+    }
+}
+
+pub(crate) fn eqv<R: RealNumberInternalTrait>(
     arguments: impl IntoIterator<Item = Value<R>>,
 ) -> Result<Value<R>> {
     let mut iter = arguments.into_iter();
     let a = iter.next().unwrap();
     let b = iter.next().unwrap();
-    match (&a, &b) {
-        (Value::Vector(a), Value::Vector(b)) => Ok(Value::Boolean(a.ptr_eq(b))),
-        (Value::Pair(a), Value::Pair(b)) => Ok(Value::Boolean(match (a.as_ref(), b.as_ref()) {
-            (GenericPair::Empty, GenericPair::Empty) => true,
-            _ => a.as_ref() as *const Pair<R> == b.as_ref() as *const Pair<R>,
-        })),
-        (Value::Number(a), Value::Number(b)) => Ok(Value::Boolean(a.exact_eqv(b))),
-        _ => Ok(Value::Boolean(a == b)),
+    Ok(Value::Boolean(eqv_values(&a, &b)))
+}
+
+/// structural equality of `equal?`: pairs and vectors are equal when their elements are,
+/// strings when their characters are whatever their mutability, and other values, numbers and
+/// procedures included, when they are `eqv?`. So `(equal? 1 1.0)` is `#f`, and a procedure is
+/// only equal to itself, not to another closure of the same lambda expression.
+///
+/// Pairs of vectors being compared are assumed equal when met again, so that vectors shared
+/// in both values are compared once and vectors containing themselves are compared in bounded
+/// time.
+pub(crate) fn equal<R: RealNumberInternalTrait>(a: &Value<R>, b: &Value<R>) -> bool {
+    equal_visiting(a, b, &mut HashSet::new())
+}
+
+fn equal_visiting<R: RealNumberInternalTrait>(
+    mut a: &Value<R>,
+    mut b: &Value<R>,
+    visiting: &mut HashSet<(*const (), *const ())>,
+) -> bool {
+    // cdrs are walked in a loop, so that long lists do not recurse
+    loop {
+        match (a, b) {
+            (Value::Pair(pair_a), Value::Pair(pair_b)) => {
+                match (pair_a.as_ref(), pair_b.as_ref()) {
+                    (GenericPair::Some(car_a, cdr_a), GenericPair::Some(car_b, cdr_b)) => {
+                        if !stack::guard(|| equal_visiting(car_a, car_b, visiting)) {
+                            return false;
+                        }
+                        a = cdr_a;
+                        b = cdr_b;
+                    }
+                    (GenericPair::Empty, GenericPair::Empty) => return true,
+                    _ => return false,
+                }
+            }
+            (Value::Vector(vector_a), Value::Vector(vector_b)) => {
+                if vector_a.ptr_eq(vector_b)
+                    || !visiting.insert((vector_a.as_ptr(), vector_b.as_ptr()))
+                {
+                    return true;
+                }
+                let (vector_a, vector_b) = (vector_a.as_ref(), vector_b.as_ref());
+                return vector_a.len() == vector_b.len()
+                    && vector_a
+                        .iter()
+                        .zip(vector_b.iter())
+                        .all(|(a, b)| stack::guard(|| equal_visiting(a, b, visiting)));
+            }
+            (Value::String(a), Value::String(b)) => return a == b,
+            _ => return eqv_values(a, b),
+        }
     }
 }
 
+fn equal_predicate<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let a = iter.next().unwrap();
+    let b = iter.next().unwrap();
+    Ok(Value::Boolean(equal(&a, &b)))
+}
+
 #[test]
 fn equivalance_predicate() {
     {
@@ -143,13 +403,84 @@ fn equivalance_predicate() {
     }
 }
 
+#[test]
+fn equal_matrix() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str("(define (id x) x) (define p (make-parameter 1))")?;
+    // values of the same class are equal?, values of different classes are not
+    let samples = [
+        ("1", 0),
+        ("(- 2 1)", 0),
+        ("1.0", 1),
+        ("(/ 1 2)", 2),
+        ("(/ 2 4)", 2),
+        (r"#\a", 3),
+        (r#""ab""#, 4),
+        (r#""ab""#, 4),
+        (r#""a""#, 5),
+        ("'ab", 6),
+        ("'()", 7),
+        ("(list)", 7),
+        ("'(1 (2 #(3)))", 8),
+        ("(list 1 (list 2 (vector 3)))", 8),
+        ("'(1 (2 #(4)))", 9),
+        ("'(1 . 2)", 10),
+        (r#"#(1 "a" (b))"#, 11),
+        (r#"(vector 1 "a" '(b))"#, 11),
+        ("#()", 12),
+        ("#t", 13),
+        ("#f", 14),
+        ("car", 15),
+        ("car", 15),
+        ("cdr", 16),
+        ("id", 17),
+        ("(lambda (x) x)", 18),
+        ("(lambda (x) x)", 19),
+        ("p", 20),
+        ("(make-parameter 1)", 21),
+        ("(if #f #f)", 22),
+        ("(eof-object)", 23),
+    ];
+    for (index, (expression, _)) in samples.iter().enumerate() {
+        it.eval_str(&format!("(define v{} {})", index, expression))?;
+    }
+    for (i, (a, class_a)) in samples.iter().enumerate() {
+        for (j, (b, class_b)) in samples.iter().enumerate() {
+            assert_eq!(
+                it.eval_str(&format!("(equal? v{} v{})", i, j))?,
+                Some(Value::Boolean(class_a == class_b)),
+                "(equal? {} {})",
+                a,
+                b
+            );
+        }
+    }
+    // vectors containing themselves
+    assert_eq!(
+        it.eval_str(
+            "(define a (vector 1 2)) (vector-set! a 1 a) \
+             (define b (vector 1 2)) (vector-set! b 1 b) \
+             (list (equal? a b) (equal? a (vector 1 a)) (equal? a (vector 2 a)))"
+        )?,
+        it.eval_str("'(#t #t #f)")?
+    );
+    // shared vectors are compared once instead of 2^40 times
+    assert_eq!(
+        it.eval_str(
+            "(define (tower n) (if (= n 0) (vector 0) (let ((v (tower (- n 1)))) (vector v v)))) \
+             (equal? (tower 40) (tower 40))"
+        )?,
+        Some(Value::Boolean(true))
+    );
+    Ok(())
+}
+
 fn not<R: RealNumberInternalTrait>(
     arguments: impl IntoIterator<Item = Value<R>>,
 ) -> Result<Value<R>> {
-    match arguments.into_iter().next().unwrap() {
-        Value::Boolean(false) => Ok(Value::Boolean(true)),
-        _ => Ok(Value::Boolean(false)),
-    }
+    Ok(Value::Boolean(
+        !arguments.into_iter().next().unwrap().as_boolean(),
+    ))
 }
 
 fn add<R: RealNumberInternalTrait>(
@@ -328,7 +659,12 @@ numeric_one_argument!(acos);
 numeric_one_argument!(atan);
 numeric_one_argument!(floor);
 numeric_one_argument!(ceiling);
+numeric_one_argument!(round);
+numeric_one_argument!(truncate);
 numeric_one_argument!(exact, ?);
+numeric_one_argument!(numerator, ?);
+numeric_one_argument!(denominator, ?);
+numeric_one_argument!(inexact);
 
 #[test]
 fn builtin_numeric_one() {
@@ -353,6 +689,9 @@ macro_rules! numeric_two_arguments {
 
 numeric_two_arguments!(floor_quotient, ?);
 numeric_two_arguments!(floor_remainder, ?);
+numeric_two_arguments!(truncate_quotient, ?);
+numeric_two_arguments!(truncate_remainder, ?);
+numeric_two_arguments!(rationalize, ?);
 numeric_two_arguments!(log);
 numeric_two_arguments!(atan2);
 
@@ -433,7 +772,7 @@ fn builtin_vector_length() {
     {
         let vector: Value<f32> = Value::Vector(ValueReference::new_immutable(vec![
             Value::Number(Number::Integer(5)),
-            Value::String("foo".to_string()),
+            Value::String("foo".into()),
             Value::Number(Number::Rational(5, 3)),
         ]));
         let arguments = vec![vector.clone()];
@@ -452,24 +791,94 @@ fn builtin_vector_length() {
     }
 }
 
+fn list_to_vector<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let list = arguments.into_iter().next().unwrap();
+    let vector = list
+        .expect_proper_list("list->vector")?
+        .into_iter()
+        .collect();
+    Ok(Value::Vector(ValueReference::new_mutable(vector)))
+}
+
+fn length<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let list = arguments.into_iter().next().unwrap();
+    let length = list.expect_proper_list("length")?.iter().count();
+    Ok(Value::Number(Number::Integer(length as i32)))
+}
+
+#[test]
+fn improper_lists() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str("(import (scheme list))")?;
+    assert_eq!(
+        it.eval_str("(list (length '(1 2 3)) (list->vector '(1 2)) (append '(1) '(2) 3))")?,
+        it.eval_str("(list 3 (vector 1 2) '(1 2 . 3))")?
+    );
+    // cycles cannot be built, a pair owns its cdr
+    for expression in &[
+        "(length '(1 . 2))",
+        "(append '(1 . 2) '(3))",
+        "(map car '(1 . 2))",
+        "(for-each car '(1 . 2))",
+        "(fold-left + 0 '(1 . 2))",
+        "(fold-right + 0 '(1 . 2))",
+        "(memq 'a '(1 . 2))",
+        "(memv 'a '(1 . 2))",
+        "(apply + '(1 . 2))",
+        "(list->vector '(1 . 2))",
+        "(assq 'a '(1 . 2))",
+        "(filter car '(1 . 2))",
+        "(delete-duplicates '(1 . 2))",
+    ] {
+        let procedure = &expression[1..expression.find(' ').unwrap()];
+        assert_eq!(
+            it.eval_str(expression).unwrap_err().data.to_string(),
+            format!("{}: expect a list, got (1 . 2)", procedure)
+        );
+    }
+    assert_eq!(
+        it.eval_str("(length 1)").unwrap_err().data.to_string(),
+        "length: expect a list, got 1"
+    );
+    Ok(())
+}
+
+// (%proper-list 'procedure list) is list, checked for the list procedures of (scheme base)
+// written in scheme before they walk it
+fn proper_list<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let procedure = iter.next().unwrap().expect_symbol()?;
+    let list = iter
+        .next()
+        .unwrap()
+        .expect_proper_list(&procedure.to_string())?;
+    Ok(Value::Pair(Box::new(list)))
+}
+
 fn vector_ref<R: RealNumberInternalTrait>(
     arguments: impl IntoIterator<Item = Value<R>>,
 ) -> Result<Value<R>> {
     let mut iter = arguments.into_iter();
     let vector = iter.next().unwrap().expect_vector()?;
     let k = iter.next().unwrap().expect_integer()?;
-    let r = match vector.as_ref().get(k as usize) {
+    let vector = vector.as_ref();
+    match usize::try_from(k).ok().and_then(|index| vector.get(index)) {
         Some(value) => Ok(value.clone()),
-        None => return error!(LogicError::VectorIndexOutOfBounds),
-    };
-    r
+        None => error!(index_out_of_range("vector-ref", k, vector.len())),
+    }
 }
 
 #[test]
 fn builtin_vector_ref() {
     let vector: Value<f32> = Value::Vector(ValueReference::new_immutable(vec![
         Value::Number(Number::Integer(5)),
-        Value::String("foo".to_string()),
+        Value::String("foo".into()),
         Value::Number(Number::Rational(5, 3)),
     ]));
     {
@@ -478,7 +887,7 @@ fn builtin_vector_ref() {
     }
     {
         let arguments = vec![vector.clone(), Value::Number(Number::Integer(1))];
-        assert_eq!(vector_ref(arguments), Ok(Value::String("foo".to_string())));
+        assert_eq!(vector_ref(arguments), Ok(Value::String("foo".into())));
     }
     {
         let arguments = vec![vector.clone(), Value::Number(Number::Integer(2))];
@@ -491,7 +900,14 @@ fn builtin_vector_ref() {
         let arguments = vec![vector.clone(), Value::Number(Number::Integer(3))];
         assert_eq!(
             vector_ref(arguments),
-            Err(ErrorData::Logic(LogicError::VectorIndexOutOfBounds).no_locate())
+            Err(ErrorData::Logic(index_out_of_range("vector-ref", 3, 3)).no_locate())
+        );
+    }
+    {
+        let arguments = vec![vector.clone(), Value::Number(Number::Integer(-1))];
+        assert_eq!(
+            vector_ref(arguments),
+            error!(index_out_of_range("vector-ref", -1, 3))
         );
     }
 }
@@ -503,8 +919,13 @@ fn vector_set<R: RealNumberInternalTrait>(
     let vector = iter.next().unwrap().expect_vector()?;
     let k = iter.next().unwrap().expect_integer()?;
     let obj = iter.next().unwrap();
-    match vector.as_mut()?.get_mut(k as usize) {
-        None => return Err(ErrorData::Logic(LogicError::VectorIndexOutOfBounds).no_locate()),
+    let mut vector = vector.as_mut()?;
+    let length = vector.len();
+    match usize::try_from(k)
+        .ok()
+        .and_then(|index| vector.get_mut(index))
+    {
+        None => return error!(index_out_of_range("vector-set!", k, length)),
         Some(value) => {
             *value = obj;
         }
@@ -516,7 +937,7 @@ fn vector_set<R: RealNumberInternalTrait>(
 fn builtin_vector_set() -> Result<()> {
     let vector: Value<f32> = Value::Vector(ValueReference::new_mutable(vec![
         Value::Number(Number::Integer(5)),
-        Value::String("foo".to_string()),
+        Value::String("foo".into()),
         Value::Number(Number::Rational(5, 3)),
     ]));
     {
@@ -530,7 +951,7 @@ fn builtin_vector_set() -> Result<()> {
             vector,
             Value::Vector(ValueReference::new_mutable(vec![
                 Value::Number(Number::Real(3.14)),
-                Value::String("foo".to_string()),
+                Value::String("foo".into()),
                 Value::Number(Number::Rational(5, 3)),
             ]))
         );
@@ -555,7 +976,7 @@ fn builtin_vector_set() -> Result<()> {
         let arguments = vec![
             vector.clone(),
             Value::Number(Number::Integer(2)),
-            Value::String("bar".to_string()),
+            Value::String("bar".into()),
         ];
         assert_eq!(vector_set(arguments), Ok(Value::Void));
         assert_eq!(
@@ -563,7 +984,7 @@ fn builtin_vector_set() -> Result<()> {
             Value::Vector(ValueReference::new_mutable(vec![
                 Value::Number(Number::Real(3.14)),
                 Value::Number(Number::Integer(5)),
-                Value::String("bar".to_string()),
+                Value::String("bar".into()),
             ]))
         );
     }
@@ -575,17 +996,12 @@ fn builtin_vector_set() -> Result<()> {
         ];
         assert_eq!(
             vector_set(arguments),
-            error!(LogicError::VectorIndexOutOfBounds)
+            error!(index_out_of_range("vector-set!", 3, 3))
         );
     }
     Ok(())
 }
 
-fn newline<R: RealNumberInternalTrait>(_: impl IntoIterator<Item = Value<R>>) -> Result<Value<R>> {
-    println!("");
-    Ok(Value::Void)
-}
-
 macro_rules! typed_comparision {
     ($name:tt, $operator:tt, $expect_type: tt) => {
         fn $name<R: RealNumberInternalTrait>(
@@ -718,17 +1134,116 @@ fn builtin_min() {
     }
 }
 
-pub fn library_map<R: RealNumberInternalTrait>() -> Vec<(String, Value<R>)> {
-    library_map_result().unwrap()
+fn open_input_string<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let string = arguments.into_iter().next().unwrap().expect_string()?;
+    Ok(Value::Port(Port::new_input(InputPort::from_string(
+        &string,
+    ))))
+}
+
+fn eof_object<R: RealNumberInternalTrait>(
+    _: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    Ok(Value::Eof)
+}
+
+fn input_port_open<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let port = arguments.into_iter().next().unwrap().expect_input_port()?;
+    let open = port.borrow().is_open();
+    Ok(Value::Boolean(open))
+}
+
+fn output_port_open<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let port = arguments.into_iter().next().unwrap().expect_output_port()?;
+    let open = port.borrow().is_open();
+    Ok(Value::Boolean(open))
+}
+
+fn close_port<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    arguments
+        .into_iter()
+        .next()
+        .unwrap()
+        .expect_port()?
+        .close()?;
+    Ok(Value::Void)
 }
 
-fn library_map_result<R: RealNumberInternalTrait>() -> Result<Vec<(String, Value<R>)>> {
+fn close_input_port<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let port = arguments.into_iter().next().unwrap().expect_input_port()?;
+    port.borrow_mut().close();
+    Ok(Value::Void)
+}
+
+fn close_output_port<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let port = arguments.into_iter().next().unwrap().expect_output_port()?;
+    port.borrow_mut().close()?;
+    Ok(Value::Void)
+}
+
+fn open_output_string<R: RealNumberInternalTrait>(
+    _: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    Ok(Value::Port(Port::new_output(OutputPort::new_string())))
+}
+
+fn get_output_string<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let value = arguments.into_iter().next().unwrap();
+    let port = value.clone().expect_output_port()?;
+    let string = port.borrow().get_string().map(|s| s.to_string());
+    match string {
+        Some(string) => Ok(Value::String(string.into())),
+        None => error!(wrong_type("get-output-string", Type::String, &value)),
+    }
+}
+
+pub fn library_map<R: RealNumberInternalTrait>(
+    features: Rc<RefCell<Vec<String>>>,
+) -> Vec<(String, Value<R>)> {
+    library_map_result(features).unwrap()
+}
+
+fn library_map_result<R: RealNumberInternalTrait>(
+    feature_registry: Rc<RefCell<Vec<String>>>,
+) -> Result<Vec<(String, Value<R>)>> {
     Ok(vec![
+        function_mapping!("features", param_fixed![], move |_, _| Ok(features(
+            &feature_registry
+        ))),
         function_mapping!(
             "apply",
             append_variadic_param!(param_fixed!["proc"], "args"),
             apply
         ),
+        function_mapping!(
+            "dynamic-wind",
+            param_fixed!["before", "thunk", "after"],
+            dynamic_wind
+        ),
+        function_mapping!(
+            "make-parameter",
+            append_variadic_param!(param_fixed!["value"], "converter"),
+            make_parameter
+        ),
+        pure_function_mapping!(
+            "%proper-list",
+            param_fixed!["procedure", "list"],
+            proper_list
+        ),
         pure_function_mapping!("car", param_fixed!["pair"], car),
         pure_function_mapping!("cdr", param_fixed!["pair"], cdr),
         pure_function_mapping!("eqv?", param_fixed!["obj1", "obj2"], eqv),
@@ -737,6 +1252,7 @@ fn library_map_result<R: RealNumberInternalTrait>() -> Result<Vec<(String, Value
             param_fixed!["obj1", "obj2"],
             eqv
         ),
+        pure_function_mapping!("equal?", param_fixed!["obj1", "obj2"], equal_predicate),
         pure_function_mapping!("cons", param_fixed!["car", "cdr"], cons),
         pure_function_mapping!(
             "boolean?",
@@ -813,17 +1329,92 @@ fn library_map_result<R: RealNumberInternalTrait>() -> Result<Vec<(String, Value
         pure_function_mapping!("atan2", param_fixed!["y", "x"], atan2),
         pure_function_mapping!("floor", param_fixed!["x"], floor),
         pure_function_mapping!("ceiling", param_fixed!["x"], ceiling),
+        pure_function_mapping!("round", param_fixed!["x"], round),
+        pure_function_mapping!("truncate", param_fixed!["x"], truncate),
         pure_function_mapping!("exact", param_fixed!["x"], exact),
+        pure_function_mapping!("inexact", param_fixed!["z"], inexact),
+        pure_function_mapping!("numerator", param_fixed!["q"], numerator),
+        pure_function_mapping!("denominator", param_fixed!["q"], denominator),
+        pure_function_mapping!("rationalize", param_fixed!["x", "y"], rationalize),
         pure_function_mapping!("floor-quotient", param_fixed!["n1", "n2"], floor_quotient),
         pure_function_mapping!("floor-remainder", param_fixed!["n1", "n2"], floor_remainder),
-        pure_function_mapping!("newline", param_fixed![], newline),
+        pure_function_mapping!(
+            "truncate-quotient",
+            param_fixed!["n1", "n2"],
+            truncate_quotient
+        ),
+        pure_function_mapping!(
+            "truncate-remainder",
+            param_fixed!["n1", "n2"],
+            truncate_remainder
+        ),
         pure_function_mapping!(
             "vector",
             append_variadic_param!(param_fixed![], "obj"),
             vector
         ),
+        pure_function_mapping!("port?", param_fixed!["obj"], value_test!(Value::Port(_))),
+        pure_function_mapping!(
+            "input-port?",
+            param_fixed!["obj"],
+            value_test!(Value::Port(Port::Input(_)))
+        ),
+        pure_function_mapping!(
+            "output-port?",
+            param_fixed!["obj"],
+            value_test!(Value::Port(Port::Output(_)))
+        ),
+        pure_function_mapping!("input-port-open?", param_fixed!["port"], input_port_open),
+        pure_function_mapping!("output-port-open?", param_fixed!["port"], output_port_open),
+        pure_function_mapping!("eof-object", param_fixed![], eof_object),
+        pure_function_mapping!("eof-object?", param_fixed!["obj"], value_test!(Value::Eof)),
+        pure_function_mapping!(
+            "error",
+            append_variadic_param!(param_fixed!["message"], "irritants"),
+            error
+        ),
+        pure_function_mapping!(
+            "error-object?",
+            param_fixed!["obj"],
+            value_test!(Value::ErrorObject(_))
+        ),
+        pure_function_mapping!(
+            "error-object-message",
+            param_fixed!["error-object"],
+            error_object_message
+        ),
+        pure_function_mapping!(
+            "error-object-irritants",
+            param_fixed!["error-object"],
+            error_object_irritants
+        ),
+        pure_function_mapping!("read-error?", param_fixed!["obj"], |arguments| {
+            error_object_test(arguments, |error| {
+                matches!(
+                    error,
+                    ErrorData::Syntax(_) | ErrorData::Logic(LogicError::MetaCircularSyntax(_))
+                )
+            })
+        }),
+        pure_function_mapping!("file-error?", param_fixed!["obj"], |arguments| {
+            error_object_test(arguments, |error| {
+                matches!(error, ErrorData::Logic(LogicError::FileError(..)))
+            })
+        }),
+        pure_function_mapping!("close-port", param_fixed!["port"], close_port),
+        pure_function_mapping!("close-input-port", param_fixed!["port"], close_input_port),
+        pure_function_mapping!("close-output-port", param_fixed!["port"], close_output_port),
+        pure_function_mapping!(
+            "open-input-string",
+            param_fixed!["string"],
+            open_input_string
+        ),
+        pure_function_mapping!("open-output-string", param_fixed![], open_output_string),
+        pure_function_mapping!("get-output-string", param_fixed!["port"], get_output_string),
         pure_function_mapping!("make-vector", param_fixed!["k", "obj"], make_vector),
         pure_function_mapping!("vector-length", param_fixed!["vector"], vector_length),
+        pure_function_mapping!("list->vector", param_fixed!["list"], list_to_vector),
+        pure_function_mapping!("length", param_fixed!["list"], length),
         pure_function_mapping!("vector-ref", param_fixed!["vector", "k"], vector_ref),
         pure_function_mapping!(
             "vector-set!",
@@ -835,21 +1426,14 @@ fn library_map_result<R: RealNumberInternalTrait>() -> Result<Vec<(String, Value
 
 #[test]
 fn builtin_parameters_length() -> Result<()> {
-    let sqrt = library_map::<f32>()
+    let sqrt = library_map::<f32>(Rc::new(RefCell::new(vec![])))
         .into_iter()
         .find(|(name, _)| name.as_str() == "sqrt")
         .unwrap()
         .1
         .expect_procedure()?;
-    let newline = library_map::<f32>()
-        .into_iter()
-        .find(|(name, _)| name.as_str() == "newline")
-        .unwrap()
-        .1
-        .expect_procedure()?;
 
     println!("{}", sqrt.get_parameters());
     assert_eq!(sqrt.get_parameters().len(), (1, false));
-    assert_eq!(newline.get_parameters().len(), (0, false));
     Ok(())
 }
@@ -0,0 +1,87 @@
+use crate::environment::*;
+use crate::interpreter::*;
+use crate::parser::pair::GenericPair;
+use crate::parser::*;
+use crate::values::*;
+use std::rc::Rc;
+
+// pause in the debugger set by `Interpreter::set_debugger`, whose session sees the local
+// bindings of the caller. Without a debugger, like when running a script, evaluation goes on.
+fn debug<R: RealNumberInternalTrait>(
+    _: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    match debug_breakpoint(env) {
+        Some(result) => result?,
+        None => eprintln!("debug: no debugger in non-interactive mode, continuing"),
+    }
+    Ok(Value::Void)
+}
+
+pub fn library_map<R: RealNumberInternalTrait>() -> Vec<(String, Value<R>)> {
+    vec![function_mapping!("debug", param_fixed![], debug)]
+}
+
+#[test]
+fn debug_breakpoints() -> Result<()> {
+    use crate::error::ErrorData;
+    use std::cell::RefCell;
+
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    // without a debugger
+    assert_eq!(
+        it.eval_str("(define (f x) (debug) x) (f 1)")?,
+        Some(Value::Number(Number::Integer(1)))
+    );
+    let breakpoints = Rc::new(RefCell::new(vec![]));
+    let seen = breakpoints.clone();
+    it.set_debugger(Box::new(move |breakpoint: Breakpoint<f32>| {
+        let x = breakpoint.env.get("x").map(|x| x.clone());
+        seen.borrow_mut()
+            .push((x, breakpoint.backtrace.frames[0].procedure.clone()));
+        match breakpoint.env.get("abort") {
+            Some(_) => error!(ErrorData::DebugAborted),
+            None => Ok(()),
+        }
+    }));
+    // in tail position as well, in the frame of the procedure
+    it.eval_str("(define (g x) (debug)) (g 2) (f 3)")?;
+    assert_eq!(
+        *breakpoints.borrow(),
+        [
+            (Some(Value::Number(Number::Integer(2))), "debug".to_string()),
+            (Some(Value::Number(Number::Integer(3))), "debug".to_string())
+        ]
+    );
+    let error = it
+        .eval_str("(define (h abort) (+ 1 (debug))) (h #t)")
+        .unwrap_err();
+    assert_eq!(error.data, ErrorData::DebugAborted);
+    let frames = error.backtrace.unwrap().frames;
+    assert_eq!(
+        frames
+            .iter()
+            .map(|frame| frame.procedure.as_str())
+            .collect::<Vec<_>>(),
+        ["debug", "h"]
+    );
+    // the debugger is kept by a nested evaluation on another interpreter
+    it.env.define(
+        "nested",
+        Value::Procedure(Procedure::new_builtin_closure(
+            "nested".to_string(),
+            param_fixed![],
+            |_| {
+                Interpreter::<f32>::new_with_stdlib().eval_str("(+ 1 2)")?;
+                Ok(Value::Void)
+            },
+        )),
+    );
+    it.eval_str("(define (k x) (nested) (debug)) (k 4)")?;
+    assert_eq!(
+        breakpoints.borrow().last(),
+        Some(&(Some(Value::Number(Number::Integer(4))), "debug".to_string()))
+    );
+    assert!(it.clear_debugger().is_some());
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use crate::error::ErrorData;
+use crate::port::*;
+use crate::values::*;
+use crate::{environment::*, interpreter::*};
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    rc::Rc,
+};
+
+use crate::interpreter::error::LogicError;
+
+fn file_error<T>(path: &str, io_error: io::Error) -> Result<T> {
+    error!(LogicError::FileError(path.to_string(), io_error.into()))
+}
+
+fn open_input_file<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let path = String::try_from(arguments.into_iter().next().unwrap())?;
+    match File::open(&path) {
+        Ok(file) => Ok(Value::Port(Port::new_input(InputPort::from_reader(
+            path,
+            BufReader::new(file),
+        )))),
+        Err(e) => file_error(&path, e),
+    }
+}
+
+// the port is flushed with the other output ports of the interpreter
+fn open_output_file<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    output_ports: &OutputPorts,
+) -> Result<Value<R>> {
+    let path = String::try_from(arguments.into_iter().next().unwrap())?;
+    match File::create(&path) {
+        Ok(file) => Ok(Value::Port(
+            output_ports.open(OutputPort::from_writer(path, BufWriter::new(file))),
+        )),
+        Err(e) => file_error(&path, e),
+    }
+}
+
+// port is closed whether or not proc returns normally
+fn call_with_port<R: RealNumberInternalTrait>(
+    port: Value<R>,
+    proc: Procedure<R>,
+    env: &Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut args = ArgVec::new();
+    args.push(port.clone());
+    let result = Interpreter::apply_procedure(&proc, args, env);
+    port.expect_port()?.close()?;
+    result
+}
+
+fn call_with_input_file<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let port = open_input_file(iter.next())?;
+    let proc = iter.next().unwrap().expect_procedure()?;
+    call_with_port(port, proc, &env)
+}
+
+fn call_with_output_file<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+    output_ports: &OutputPorts,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let port = open_output_file(iter.next(), output_ports)?;
+    let proc = iter.next().unwrap().expect_procedure()?;
+    call_with_port(port, proc, &env)
+}
+
+pub fn library_map<R: RealNumberInternalTrait>(
+    output_ports: Rc<OutputPorts>,
+) -> Vec<(String, Value<R>)> {
+    library_map_result(output_ports).unwrap()
+}
+
+fn library_map_result<R: RealNumberInternalTrait>(
+    output_ports: Rc<OutputPorts>,
+) -> Result<Vec<(String, Value<R>)>> {
+    let call_output_ports = output_ports.clone();
+    Ok(vec![
+        pure_function_mapping!("open-input-file", param_fixed!["filename"], open_input_file),
+        function_mapping!(
+            "open-output-file",
+            param_fixed!["filename"],
+            move |arguments, _| open_output_file(arguments, &output_ports)
+        ),
+        function_mapping!(
+            "call-with-input-file",
+            param_fixed!["filename", "proc"],
+            call_with_input_file
+        ),
+        function_mapping!(
+            "call-with-output-file",
+            param_fixed!["filename", "proc"],
+            move |arguments, env| call_with_output_file(arguments, env, &call_output_ports)
+        ),
+    ])
+}
+
+#[test]
+fn output_ports_of_interpreters() -> Result<()> {
+    let file = std::env::temp_dir().join(format!("ruschm-output-ports-{}", std::process::id()));
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    let other = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str(&format!(
+        "(define port (open-output-file {:?})) (write-string \"buffered\" port)",
+        file.display().to_string()
+    ))?;
+    // only the interpreter opening a port flushes it
+    other.flush_output_ports()?;
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "");
+    it.flush_output_ports()?;
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "buffered");
+    std::fs::remove_file(&file).unwrap();
+    Ok(())
+}
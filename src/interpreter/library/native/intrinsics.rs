@@ -0,0 +1,265 @@
+// Procedures the derived forms of grammar.sld expand to. Macros are not hygienic, so that the
+// expansions refer to these names in the environment of the use, where the interpreter binds
+// them, see `Interpreter::define_intrinsics`. They are not exported by (scheme base).
+use crate::environment::*;
+use crate::error::ErrorData;
+use crate::interpreter::error::wrong_type;
+use crate::interpreter::*;
+use crate::parser::pair::GenericPair;
+use crate::parser::*;
+use crate::values::*;
+use std::rc::Rc;
+
+// expansion target of `guard`: (%guard thunk handler), handler is applied to the error object
+// and a procedure raising the error again when no clause of the guard matches
+fn guard<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let thunk = iter.next().unwrap().expect_procedure()?;
+    let handler = iter.next().unwrap().expect_procedure()?;
+    match Interpreter::apply_procedure(&thunk, ArgVec::new(), &env) {
+        Err(error) if error.is_catchable() => {
+            let error = Rc::new(error);
+            let raised = error.clone();
+            let reraise =
+                Procedure::new_builtin_impure("raise".to_string(), param_fixed![], move |_, _| {
+                    Err(raised.as_ref().clone())
+                });
+            Interpreter::apply_procedure(
+                &handler,
+                [Value::ErrorObject(error), Value::Procedure(reraise)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                &env,
+            )
+        }
+        result => result,
+    }
+}
+
+// expansion target of `parameterize`: (%parameterize (list param ...) (list value ...) thunk)
+// parameters are restored after thunk returns, whether normally or by error
+fn parameterize<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let parameters = iter.next().unwrap().expect_list()?;
+    let values = iter.next().unwrap().expect_list()?;
+    let thunk = iter.next().unwrap().expect_procedure()?;
+    let mut bindings = vec![];
+    for (parameter, value) in parameters.into_iter().zip(values.into_iter()) {
+        let parameter = match parameter {
+            Value::Procedure(Procedure::Parameter(parameter)) => parameter,
+            other => return error!(wrong_type("parameterize", Type::Parameter, &other)),
+        };
+        let value = match parameter.converter() {
+            Some(converter) => {
+                Interpreter::apply_procedure(converter, std::iter::once(value).collect(), &env)?
+            }
+            None => value,
+        };
+        bindings.push((parameter, value));
+    }
+    let old_values = bindings
+        .iter()
+        .map(|(parameter, value)| parameter.replace(value.clone()))
+        .collect::<Vec<_>>();
+    let result = Interpreter::apply_procedure(&thunk, ArgVec::new(), &env);
+    for ((parameter, _), old_value) in bindings.iter().zip(old_values.into_iter()) {
+        parameter.replace(old_value);
+    }
+    result
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn now() -> Option<std::time::Instant> {
+    Some(std::time::Instant::now())
+}
+
+// no clock on wasm32-unknown-unknown, only procedure applications are reported there
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn now() -> Option<std::time::Instant> {
+    None
+}
+
+// expansion target of `time`: (%time thunk), the elapsed time and the procedure applications
+// of thunk are written to the current error port, even if it raises an error
+fn time<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+    current_error_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let thunk = arguments.into_iter().next().unwrap().expect_procedure()?;
+    let start = now();
+    let applications = crate::interpreter::applications();
+    let result = Interpreter::apply_procedure(&thunk, ArgVec::new(), &env);
+    // the application of thunk itself is not counted
+    let applications = crate::interpreter::applications() - applications - 1;
+    let report = match start {
+        Some(start) => format!(
+            "time: {:.3}ms, {} procedure applications\n",
+            start.elapsed().as_secs_f64() * 1000.0,
+            applications
+        ),
+        None => format!("time: {} procedure applications\n", applications),
+    };
+    let port = current_error_port.get().expect_output_port()?;
+    port.borrow_mut().write_str(&report)?;
+    result
+}
+
+#[test]
+fn time_form() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str(
+        "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))) \
+         (define errors (open-output-string))",
+    )?;
+    assert_eq!(
+        it.eval_str("(parameterize ((current-error-port errors)) (time (+ (time (fib 10)) 1)))")?,
+        Some(Value::Number(Number::Integer(56)))
+    );
+    let report = match it.eval_str("(get-output-string errors)")? {
+        Some(Value::String(report)) => report.to_string(),
+        other => panic!("unexpected {:?}", other),
+    };
+    let lines = report.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{}", report);
+    for line in &lines {
+        assert!(
+            line.starts_with("time: ") && line.contains("ms, "),
+            "{}",
+            line
+        );
+    }
+    // fib is applied 177 times by (fib 10), the outer time also counts +, %time and the inner
+    // thunk
+    let applications = |line: &str| -> u64 {
+        line.rsplit(", ")
+            .next()
+            .unwrap()
+            .split(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    assert!(applications(lines[0]) > 177);
+    assert_eq!(applications(lines[1]), applications(lines[0]) + 3);
+    assert!(Interpreter::<f32>::new_with_stdlib()
+        .eval_str("(import (only (scheme base) %time))")
+        .is_err());
+    Ok(())
+}
+
+// the parser transforms `assert` to (%assert value 'expression "location" [message]), value is
+// returned unless it is #f
+fn assert<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let value = iter.next().unwrap();
+    let expression = iter.next().unwrap();
+    let location = iter.next().unwrap();
+    match value.as_boolean() {
+        true => Ok(value),
+        false => error!(LogicError::AssertionFailed(
+            expression.to_string(),
+            match location {
+                Value::String(location) => Some(location.to_string()),
+                _ => None,
+            },
+            iter.next().map(|message| message.to_string())
+        )),
+    }
+}
+
+#[test]
+fn assert_form() -> Result<()> {
+    use crate::parser::error::SyntaxError;
+
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str("(define x 1)")?;
+    assert_eq!(
+        it.eval_str("(assert (+ x 1))")?,
+        Some(Value::Number(Number::Integer(2)))
+    );
+    let error = it.eval_str("(define x -1)\n(assert (> x 0))").unwrap_err();
+    assert_eq!(
+        error.data.to_string(),
+        "assertion failed: (> x 0) at <string>:2:2"
+    );
+    assert_eq!(
+        error.location,
+        Some(crate::error::Location::from([2, 2]).with_length(1))
+    );
+    assert_eq!(
+        it.eval_str("(assert (> x 0) \"x must be positive\")")
+            .unwrap_err()
+            .data,
+        ErrorData::Logic(LogicError::AssertionFailed(
+            "(> x 0)".to_string(),
+            Some("<string>:1:2".to_string()),
+            Some("x must be positive".to_string())
+        ))
+    );
+    assert_eq!(
+        it.eval_str("(assert (> x 0) \"x must be positive\")")
+            .unwrap_err()
+            .data
+            .to_string(),
+        "assertion failed: (> x 0) at <string>:1:2, x must be positive"
+    );
+    // failed assertions are caught by guard
+    assert_eq!(
+        it.eval_str(
+            "(guard (e ((error-object? e) (error-object-message e)))
+               (assert (> x 0))
+               'unreachable)"
+        )?,
+        Some(Value::String(
+            "assertion failed: (> x 0) at <string>:2:17".into()
+        ))
+    );
+    assert_eq!(
+        it.eval_str("(assert)").unwrap_err().data,
+        ErrorData::Syntax(SyntaxError::UnexpectedEnd)
+    );
+    assert!(Interpreter::<f32>::new_with_stdlib()
+        .eval_str("(import (only (scheme base) %assert))")
+        .is_err());
+    Ok(())
+}
+
+pub fn library_map<R: RealNumberInternalTrait>(
+    current_error_port: Rc<Parameter<R>>,
+) -> Vec<(String, Value<R>)> {
+    library_map_result(current_error_port).unwrap()
+}
+
+fn library_map_result<R: RealNumberInternalTrait>(
+    current_error_port: Rc<Parameter<R>>,
+) -> Result<Vec<(String, Value<R>)>> {
+    Ok(vec![
+        function_mapping!("%guard", param_fixed!["thunk", "handler"], guard),
+        pure_function_mapping!(
+            "%assert",
+            append_variadic_param!(param_fixed!["value", "expression", "location"], "message"),
+            assert
+        ),
+        function_mapping!(
+            "%parameterize",
+            param_fixed!["parameters", "values", "thunk"],
+            parameterize
+        ),
+        function_mapping!("%time", param_fixed!["thunk"], move |arguments, env| time(
+            arguments,
+            env,
+            &current_error_port
+        )),
+    ])
+}
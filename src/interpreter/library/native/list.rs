@@ -0,0 +1,551 @@
+use super::base::{equal, eqv};
+use crate::environment::*;
+use crate::error::ErrorData;
+use crate::interpreter::error::{index_out_of_range, wrong_arity, wrong_type};
+use crate::interpreter::*;
+use crate::parser::pair::GenericPair;
+use crate::values::*;
+use std::rc::Rc;
+
+// elements of a proper list given to `procedure`
+fn elements<R: RealNumberInternalTrait>(procedure: &str, value: Value<R>) -> Result<Vec<Value<R>>> {
+    Ok(value.expect_proper_list(procedure)?.into_iter().collect())
+}
+
+fn list<R: RealNumberInternalTrait>(values: impl IntoIterator<Item = Value<R>>) -> Value<R> {
+    Value::Pair(Box::new(values.into_iter().collect::<Pair<R>>()))
+}
+
+fn call<R: RealNumberInternalTrait>(
+    procedure: &Procedure<R>,
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: &Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    Interpreter::apply_procedure(procedure, arguments.into_iter().collect(), env)
+}
+
+// optional arguments of a procedure with `signature`, taking `fixed` arguments before them
+fn optional<R: RealNumberInternalTrait>(
+    signature: &str,
+    fixed: usize,
+    optional: usize,
+    rest: Vec<Value<R>>,
+) -> Result<Vec<Value<R>>> {
+    match rest.len() > optional {
+        true => error!(wrong_arity(
+            signature,
+            (fixed + optional, false),
+            fixed + rest.len()
+        )),
+        false => Ok(rest),
+    }
+}
+
+// an equivalence given as an optional procedure argument, or `equal?`
+fn equivalence<R: RealNumberInternalTrait>(
+    procedure: Option<Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<impl Fn(&Value<R>, &Value<R>) -> Result<bool>> {
+    let procedure = procedure.map(Value::expect_procedure).transpose()?;
+    Ok(move |a: &Value<R>, b: &Value<R>| match &procedure {
+        Some(procedure) => Ok(call(procedure, vec![a.clone(), b.clone()], &env)?.as_boolean()),
+        None => Ok(equal(a, b)),
+    })
+}
+
+// elements kept by a predicate, and the other ones
+type Split<R> = (Vec<Value<R>>, Vec<Value<R>>);
+
+// the elements of `list` for which `pred` is true, and the other ones
+fn split<R: RealNumberInternalTrait>(
+    procedure: &str,
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: &Rc<Environment<R>>,
+) -> Result<Split<R>> {
+    let mut iter = arguments.into_iter();
+    let pred = iter.next().unwrap().expect_procedure()?;
+    let (mut kept, mut removed) = (vec![], vec![]);
+    for element in elements(procedure, iter.next().unwrap())? {
+        match call(&pred, vec![element.clone()], env)?.as_boolean() {
+            true => kept.push(element),
+            false => removed.push(element),
+        }
+    }
+    Ok((kept, removed))
+}
+
+fn filter<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    Ok(list(split("filter", arguments, &env)?.0))
+}
+
+fn remove<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    Ok(list(split("remove", arguments, &env)?.1))
+}
+
+// no multiple values in ruschm, so that both lists are returned in a list
+fn partition<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let (kept, removed) = split("partition", arguments, &env)?;
+    Ok(list(vec![list(kept), list(removed)]))
+}
+
+fn delete_duplicates<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let list_elements = elements("delete-duplicates", iter.next().unwrap())?;
+    let mut rest = optional("(delete-duplicates list [=])", 1, 1, iter.collect())?;
+    let equivalent = equivalence(rest.pop(), env)?;
+    let mut kept: Vec<Value<R>> = vec![];
+    for element in list_elements {
+        let mut duplicated = false;
+        for previous in &kept {
+            if equivalent(previous, &element)? {
+                duplicated = true;
+                break;
+            }
+        }
+        if !duplicated {
+            kept.push(element);
+        }
+    }
+    Ok(list(kept))
+}
+
+fn iota<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let count = iter.next().unwrap().expect_integer()?;
+    if count < 0 {
+        return error!(LogicError::NegativeCount("iota".to_string(), count));
+    }
+    let mut rest = optional("(iota count [start step])", 1, 2, iter.collect())?.into_iter();
+    let start = rest
+        .next()
+        .map_or(Ok(Number::Integer(0)), Value::expect_number)?;
+    let step = rest
+        .next()
+        .map_or(Ok(Number::Integer(1)), Value::expect_number)?;
+    Ok(list(
+        (0..count).map(|i| Value::Number(start + step * Number::Integer(i))),
+    ))
+}
+
+fn last<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    match elements("last", arguments.into_iter().next().unwrap())?.pop() {
+        Some(last) => Ok(last),
+        None => error!(wrong_type(
+            "last",
+            Type::Pair,
+            &Value::<R>::Pair(Box::new(GenericPair::Empty))
+        )),
+    }
+}
+
+// the list given to `procedure` and the number of its elements to take or drop
+fn list_index<R: RealNumberInternalTrait>(
+    procedure: &str,
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<(Vec<Value<R>>, usize)> {
+    let mut iter = arguments.into_iter();
+    let list_elements = elements(procedure, iter.next().unwrap())?;
+    let k = iter.next().unwrap().expect_integer()?;
+    match k >= 0 && k as usize <= list_elements.len() {
+        true => Ok((list_elements, k as usize)),
+        false => error!(index_out_of_range(procedure, k, list_elements.len())),
+    }
+}
+
+fn take<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let (mut list_elements, k) = list_index("take", arguments)?;
+    list_elements.truncate(k);
+    Ok(list(list_elements))
+}
+
+fn drop<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let (list_elements, k) = list_index("drop", arguments)?;
+    Ok(list(list_elements.into_iter().skip(k)))
+}
+
+// a procedure and the arguments of each of its applications
+type Applications<R> = (Procedure<R>, Vec<Vec<Value<R>>>);
+
+// the procedure and the arguments of its applications to the elements of the lists at the same
+// position, as many as the elements of the shortest list
+fn zip_lists<R: RealNumberInternalTrait>(
+    procedure: &str,
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Applications<R>> {
+    let mut iter = arguments.into_iter();
+    let proc = iter.next().unwrap().expect_procedure()?;
+    let lists = iter
+        .map(|list| elements(procedure, list))
+        .collect::<Result<Vec<_>>>()?;
+    let length = lists.iter().map(Vec::len).min().unwrap_or(0);
+    let applications = (0..length)
+        .map(|i| lists.iter().map(|list| list[i].clone()).collect())
+        .collect();
+    Ok((proc, applications))
+}
+
+fn count<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let (pred, applications) = zip_lists("count", arguments)?;
+    let mut count = 0;
+    for application in applications {
+        if call(&pred, application, &env)?.as_boolean() {
+            count += 1;
+        }
+    }
+    Ok(Value::Number(Number::Integer(count)))
+}
+
+fn find<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let pred = iter.next().unwrap().expect_procedure()?;
+    for element in elements("find", iter.next().unwrap())? {
+        if call(&pred, vec![element.clone()], &env)?.as_boolean() {
+            return Ok(element);
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+// the first true value returned by pred, #f if none
+fn any<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let (pred, applications) = zip_lists("any", arguments)?;
+    for application in applications {
+        let value = call(&pred, application, &env)?;
+        if value.as_boolean() {
+            return Ok(value);
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+// #f if pred returns #f for some elements, the value returned for the last ones otherwise
+fn every<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let (pred, applications) = zip_lists("every", arguments)?;
+    let mut last = Value::Boolean(true);
+    for application in applications {
+        last = call(&pred, application, &env)?;
+        if !last.as_boolean() {
+            break;
+        }
+    }
+    Ok(last)
+}
+
+fn append_map<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let (proc, applications) = zip_lists("append-map", arguments)?;
+    let mut appended = vec![];
+    for application in applications {
+        appended.extend(elements("append-map", call(&proc, application, &env)?)?);
+    }
+    Ok(list(appended))
+}
+
+// the first pair of `alist` whose car is equivalent to `key`
+fn associate<R: RealNumberInternalTrait>(
+    procedure: &str,
+    key: &Value<R>,
+    alist: Value<R>,
+    equivalent: impl Fn(&Value<R>, &Value<R>) -> Result<bool>,
+) -> Result<Value<R>> {
+    for entry in elements(procedure, alist)? {
+        match &entry {
+            Value::Pair(pair) => match pair.as_ref() {
+                GenericPair::Some(car, _) => {
+                    if equivalent(key, car)? {
+                        return Ok(entry);
+                    }
+                }
+                GenericPair::Empty => return error!(wrong_type(procedure, Type::Pair, &entry)),
+            },
+            _ => return error!(wrong_type(procedure, Type::Pair, &entry)),
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn eqv_equivalence<R: RealNumberInternalTrait>(a: &Value<R>, b: &Value<R>) -> Result<bool> {
+    Ok(eqv(vec![a.clone(), b.clone()])?.as_boolean())
+}
+
+fn assq<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let key = iter.next().unwrap();
+    associate("assq", &key, iter.next().unwrap(), eqv_equivalence)
+}
+
+fn assv<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let key = iter.next().unwrap();
+    associate("assv", &key, iter.next().unwrap(), eqv_equivalence)
+}
+
+fn assoc<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let key = iter.next().unwrap();
+    let alist = iter.next().unwrap();
+    let mut rest = optional("(assoc key alist [=])", 2, 1, iter.collect())?;
+    associate("assoc", &key, alist, equivalence(rest.pop(), env)?)
+}
+
+fn alist_cons<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let key = iter.next().unwrap();
+    let datum = iter.next().unwrap();
+    let entry = Value::Pair(Box::new(Pair::cons(key, datum)));
+    Ok(Value::Pair(Box::new(Pair::cons(
+        entry,
+        iter.next().unwrap(),
+    ))))
+}
+
+fn alist_copy<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let alist = elements("alist-copy", arguments.into_iter().next().unwrap())?;
+    for entry in &alist {
+        if !matches!(entry, Value::Pair(pair) if matches!(pair.as_ref(), GenericPair::Some(_, _))) {
+            return error!(wrong_type("alist-copy", Type::Pair, &entry));
+        }
+    }
+    Ok(list(alist))
+}
+
+fn alist_delete<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let key = iter.next().unwrap();
+    let alist = elements("alist-delete", iter.next().unwrap())?;
+    let mut rest = optional("(alist-delete key alist [=])", 2, 1, iter.collect())?;
+    let equivalent = equivalence(rest.pop(), env)?;
+    let mut kept = vec![];
+    for entry in alist {
+        match &entry {
+            Value::Pair(pair) => match pair.as_ref() {
+                GenericPair::Some(car, _) => {
+                    if !equivalent(&key, car)? {
+                        kept.push(entry);
+                    }
+                }
+                GenericPair::Empty => {
+                    return error!(wrong_type("alist-delete", Type::Pair, &entry))
+                }
+            },
+            _ => return error!(wrong_type("alist-delete", Type::Pair, &entry)),
+        }
+    }
+    Ok(list(kept))
+}
+
+fn library_map_result<R: RealNumberInternalTrait>() -> Result<Vec<(String, Value<R>)>> {
+    Ok(vec![
+        function_mapping!("filter", param_fixed!["pred", "list"], filter),
+        function_mapping!("remove", param_fixed!["pred", "list"], remove),
+        function_mapping!("partition", param_fixed!["pred", "list"], partition),
+        function_mapping!(
+            "delete-duplicates",
+            append_variadic_param!(param_fixed!["list"], "="),
+            delete_duplicates
+        ),
+        pure_function_mapping!(
+            "iota",
+            append_variadic_param!(param_fixed!["count"], "start-step"),
+            iota
+        ),
+        pure_function_mapping!("last", param_fixed!["list"], last),
+        pure_function_mapping!("take", param_fixed!["list", "k"], take),
+        pure_function_mapping!("drop", param_fixed!["list", "k"], drop),
+        function_mapping!(
+            "count",
+            append_variadic_param!(param_fixed!["pred", "list"], "lists"),
+            count
+        ),
+        function_mapping!("find", param_fixed!["pred", "list"], find),
+        function_mapping!(
+            "any",
+            append_variadic_param!(param_fixed!["pred", "list"], "lists"),
+            any
+        ),
+        function_mapping!(
+            "every",
+            append_variadic_param!(param_fixed!["pred", "list"], "lists"),
+            every
+        ),
+        function_mapping!(
+            "append-map",
+            append_variadic_param!(param_fixed!["proc", "list"], "lists"),
+            append_map
+        ),
+        pure_function_mapping!("assq", param_fixed!["key", "alist"], assq),
+        pure_function_mapping!("assv", param_fixed!["key", "alist"], assv),
+        function_mapping!(
+            "assoc",
+            append_variadic_param!(param_fixed!["key", "alist"], "="),
+            assoc
+        ),
+        pure_function_mapping!(
+            "alist-cons",
+            param_fixed!["key", "datum", "alist"],
+            alist_cons
+        ),
+        pure_function_mapping!("alist-copy", param_fixed!["alist"], alist_copy),
+        function_mapping!(
+            "alist-delete",
+            append_variadic_param!(param_fixed!["key", "alist"], "="),
+            alist_delete
+        ),
+    ])
+}
+
+pub fn library_map<R: RealNumberInternalTrait>() -> Vec<(String, Value<R>)> {
+    library_map_result().unwrap()
+}
+
+#[test]
+fn list_library() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str(
+        "(import (srfi 1)) \
+         (define (odd? x) (= (floor-remainder x 2) 1)) (define (even? x) (not (odd? x)))",
+    )?;
+    let cases = [
+        ("(filter odd? '(1 2 3 4 5))", "'(1 3 5)"),
+        ("(remove odd? '(1 2 3 4 5))", "'(2 4)"),
+        ("(partition odd? '(1 2 3 4 5))", "'((1 3 5) (2 4))"),
+        ("(delete-duplicates '(a b a (c) b (c)))", "'(a b (c))"),
+        (
+            "(delete-duplicates '(1 2 3 4) (lambda (a b) (= (+ a b) 5)))",
+            "'(1 2)",
+        ),
+        ("(iota 5)", "'(0 1 2 3 4)"),
+        ("(iota 3 1)", "'(1 2 3)"),
+        ("(iota 3 0 2)", "'(0 2 4)"),
+        ("(iota 0)", "'()"),
+        ("(last '(1 2 3))", "3"),
+        ("(last-pair '(1 2 3))", "'(3)"),
+        ("(take '(a b c d) 2)", "'(a b)"),
+        ("(drop '(a b c d) 2)", "'(c d)"),
+        ("(take '(a b) 2)", "'(a b)"),
+        ("(count odd? '(1 2 3))", "2"),
+        ("(count < '(1 5 3) '(2 4 6 8))", "2"),
+        ("(find even? '(1 2 3 4))", "2"),
+        ("(find even? '(1 3))", "#f"),
+        (
+            "(any (lambda (x) (and (even? x) (* x x))) '(1 3 4 6))",
+            "16",
+        ),
+        ("(any odd? '())", "#f"),
+        ("(every odd? '(1 3 5))", "#t"),
+        ("(every (lambda (x) (and (odd? x) x)) '(1 3 5))", "5"),
+        ("(every odd? '(1 2 3))", "#f"),
+        ("(append-map (lambda (x) (list x x)) '(1 2))", "'(1 1 2 2)"),
+        ("(assq 'b '((a 1) (b 2)))", "'(b 2)"),
+        ("(assv 2 '((1 one) (2 two)))", "'(2 two)"),
+        ("(assoc '(b) '(((a) 1) ((b) 2)))", "'((b) 2)"),
+        (
+            "(assoc 5 '((2 two) (4 four)) (lambda (a b) (= a (+ b 1))))",
+            "'(4 four)",
+        ),
+        ("(assq 'c '((a 1)))", "#f"),
+        ("(alist-cons 'a 1 '((b . 2)))", "'((a . 1) (b . 2))"),
+        ("(alist-copy '((a . 1)))", "'((a . 1))"),
+        ("(alist-delete 'a '((a . 1) (b . 2) (a . 3)))", "'((b . 2))"),
+    ];
+    for (expression, expected) in cases.iter() {
+        assert_eq!(
+            it.eval_str(expression)?,
+            it.eval_str(expected)?,
+            "{}",
+            expression
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn list_library_errors() {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str("(import (scheme list)) (define (odd? x) (= (floor-remainder x 2) 1))")
+        .unwrap();
+    let errors = [
+        ("(iota -1)", "iota: expect a non-negative count, got -1"),
+        (
+            "(take '(a b) 3)",
+            "take: index 3 is out of range for length 2",
+        ),
+        (
+            "(drop '(a b) -1)",
+            "drop: index -1 is out of range for length 2",
+        ),
+        ("(last '())", "last: expect a pair, got ()"),
+        ("(filter odd? 1)", "filter: expect a list, got 1"),
+        ("(filter 1 '(1))", "filter: expect a procedure, got 1"),
+        ("(find odd? '(1 . 2))", "find: expect a list, got (1 . 2)"),
+        ("(assq 'a '(1))", "assq: expect a pair, got 1"),
+        (
+            "(filter odd?)",
+            "procedure (filter pred list) expects 2 arguments, got 1",
+        ),
+        (
+            "(iota 1 2 3 4)",
+            "procedure (iota count [start step]) expects 3 arguments, got 4",
+        ),
+    ];
+    for (expression, message) in errors.iter() {
+        assert_eq!(
+            it.eval_str(expression).unwrap_err().data.to_string(),
+            *message,
+            "{}",
+            expression
+        );
+    }
+    // not part of the base library
+    assert!(Interpreter::<f32>::new_with_stdlib()
+        .eval_str("(filter car '((1)))")
+        .is_err());
+}
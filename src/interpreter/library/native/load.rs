@@ -0,0 +1,73 @@
+use crate::interpreter::error::LogicError;
+use crate::io::file_char_stream;
+use crate::parser::*;
+use crate::values::*;
+use crate::{environment::*, interpreter::*};
+use crate::{error::ErrorData, error::SchemeError, error::SourceId};
+use std::{cell::RefCell, convert::TryFrom, path::PathBuf, rc::Rc};
+
+fn load_file<R: RealNumberInternalTrait>(
+    path: &PathBuf,
+    filename: &str,
+    env: &Rc<Environment<R>>,
+    features: &Rc<dyn Features>,
+) -> Result<Value<R>> {
+    let mut lexer = Lexer::from_char_stream(file_char_stream(path)?);
+    lexer.set_source(SourceId::new(filename));
+    let mut parser = Parser::from_lexer(lexer);
+    parser.set_features(features.clone());
+    for statement in parser {
+        let mut statement = statement?;
+        resolve_statement(&mut statement);
+        if let Err(error) = Interpreter::eval_statement(&statement, env) {
+            return Err(error.or_locate(statement.location()));
+        }
+    }
+    Ok(Value::Void)
+}
+
+// relative path is resolved against current working directory,
+// statements are evaluated in the environment where load is called.
+fn load<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+    loading_files: &RefCell<Vec<PathBuf>>,
+    features: &Rc<dyn Features>,
+) -> Result<Value<R>> {
+    let filename = String::try_from(arguments.into_iter().next().unwrap())?;
+    let path = match PathBuf::from(&filename).canonicalize() {
+        Ok(path) => path,
+        Err(e) => return error!(LogicError::FileError(filename, e.into())),
+    };
+    if loading_files.borrow().contains(&path) {
+        return error!(LogicError::LoadCyclic(filename));
+    }
+    loading_files.borrow_mut().push(path.clone());
+    let result = load_file(&path, &filename, &env, features);
+    loading_files.borrow_mut().pop();
+    result.map_err(|e| match e.data.is_catchable() {
+        false => e,
+        true => SchemeError {
+            backtrace: e.backtrace.clone(),
+            ..ErrorData::Logic(LogicError::LoadError(filename, Box::new(e))).no_locate()
+        },
+    })
+}
+
+// `cond-expand` in loaded files is matched against `features`
+pub fn library_map<R: RealNumberInternalTrait>(
+    features: Rc<dyn Features>,
+) -> Vec<(String, Value<R>)> {
+    library_map_result(features).unwrap()
+}
+
+fn library_map_result<R: RealNumberInternalTrait>(
+    features: Rc<dyn Features>,
+) -> Result<Vec<(String, Value<R>)>> {
+    let loading_files = RefCell::new(vec![]);
+    Ok(vec![function_mapping!(
+        "load",
+        param_fixed!["filename"],
+        move |arguments, env| load(arguments, env, &loading_files, &features)
+    )])
+}
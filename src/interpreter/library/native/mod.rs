@@ -1,4 +1,14 @@
 #[macro_use]
 mod macros;
 pub mod base;
+pub mod debug;
+pub mod file;
+pub mod intrinsics;
+pub mod list;
+pub mod load;
+pub mod process_context;
+pub mod random;
+pub mod read;
+pub mod reflection;
+pub mod string;
 pub mod write;
@@ -0,0 +1,108 @@
+use crate::error::ErrorData;
+use crate::interpreter::*;
+use crate::parser::pair::GenericPair;
+use crate::parser::*;
+use crate::values::*;
+use std::{cell::RefCell, convert::TryInto, env, rc::Rc};
+
+// r7rs 6.14. (exit obj): #t or absent means normal exit, #f means abnormal exit
+fn exit_code<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<i32> {
+    match arguments.into_iter().next() {
+        None | Some(Value::Boolean(true)) => Ok(0),
+        Some(Value::Boolean(false)) => Ok(1),
+        Some(Value::Number(Number::Integer(code))) => Ok(code),
+        Some(other) => error!(LogicError::TypeMisMatch(
+            other.written().to_string(),
+            Type::Integer
+        )),
+    }
+}
+
+fn exit<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let code = exit_code(arguments)?;
+    error!(ErrorData::Exit {
+        code,
+        emergency: false
+    })
+}
+
+fn emergency_exit<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let code = exit_code(arguments)?;
+    error!(ErrorData::Exit {
+        code,
+        emergency: true
+    })
+}
+
+fn command_line<R: RealNumberInternalTrait>(command_line: &RefCell<Vec<String>>) -> Value<R> {
+    let list = command_line
+        .borrow()
+        .iter()
+        .map(|argument| Value::String(argument.as_str().into()))
+        .collect::<Pair<R>>();
+    Value::Pair(Box::new(list))
+}
+
+// environment names and values which are not valid unicode are converted lossily,
+// invalid sequences become U+FFFD
+fn get_environment_variable<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let name: String = arguments.into_iter().next().unwrap().try_into()?;
+    Ok(match env::var_os(name) {
+        Some(value) => value.to_string_lossy().into_owned().into(),
+        None => false.into(),
+    })
+}
+
+fn get_environment_variables<R: RealNumberInternalTrait>(
+    _: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let alist = env::vars_os()
+        .map(|(name, value)| {
+            Value::Pair(Box::new(Pair::cons(
+                Value::from(name.to_string_lossy().into_owned()),
+                Value::from(value.to_string_lossy().into_owned()),
+            )))
+        })
+        .collect::<Pair<R>>();
+    Ok(Value::Pair(Box::new(alist)))
+}
+
+pub fn library_map<R: RealNumberInternalTrait>(
+    command_line: Rc<RefCell<Vec<String>>>,
+) -> Vec<(String, Value<R>)> {
+    library_map_result(command_line).unwrap()
+}
+
+fn library_map_result<R: RealNumberInternalTrait>(
+    command_line_arguments: Rc<RefCell<Vec<String>>>,
+) -> Result<Vec<(String, Value<R>)>> {
+    Ok(vec![
+        function_mapping!("command-line", param_fixed![], move |_, _| Ok(
+            command_line(&command_line_arguments)
+        )),
+        pure_function_mapping!(
+            "get-environment-variable",
+            param_fixed!["name"],
+            get_environment_variable
+        ),
+        pure_function_mapping!(
+            "get-environment-variables",
+            param_fixed![],
+            get_environment_variables
+        ),
+        pure_function_mapping!("exit", append_variadic_param!(param_fixed![], "obj"), exit),
+        pure_function_mapping!(
+            "emergency-exit",
+            append_variadic_param!(param_fixed![], "obj"),
+            emergency_exit
+        ),
+    ])
+}
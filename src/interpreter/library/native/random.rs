@@ -0,0 +1,170 @@
+use crate::error::ErrorData;
+use crate::interpreter::error::wrong_type;
+use crate::interpreter::*;
+use crate::parser::pair::GenericPair;
+use crate::parser::*;
+use crate::values::*;
+use std::{
+    cell::RefCell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    rc::Rc,
+};
+
+/// xoshiro256** generator behind `random-integer` and `random-real`, each interpreter has its own
+pub struct Xoshiro256 {
+    state: [u64; 4],
+}
+
+impl Xoshiro256 {
+    /// seeded differently for each generator, from the random keys of `RandomState`
+    pub fn new() -> Self {
+        Self::from_seed(RandomState::new().build_hasher().finish())
+    }
+
+    // the state is expanded from the seed by splitmix64, so that it is never all zeros
+    pub fn from_seed(seed: u64) -> Self {
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        Self {
+            state: [next(), next(), next(), next()],
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    // uniform in [0, bound), draws falling in the incomplete last range of bound are rejected
+    pub fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+
+    // uniform in [0, 1) with the 53 bits of precision of f64
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Default for Xoshiro256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn random_integer<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    random: &RefCell<Xoshiro256>,
+) -> Result<Value<R>> {
+    match arguments.into_iter().next().unwrap() {
+        Value::Number(Number::Integer(n)) if n > 0 => Ok(Value::Number(Number::Integer(
+            random.borrow_mut().below(n as u64) as i32,
+        ))),
+        other => error!(wrong_type("random-integer", Type::PositiveInteger, &other)),
+    }
+}
+
+fn random_real<R: RealNumberInternalTrait>(random: &RefCell<Xoshiro256>) -> Result<Value<R>> {
+    let unit = random.borrow_mut().unit();
+    // rounding to a narrower real type may give 1
+    let real = R::from(unit).unwrap();
+    Ok(Value::Number(Number::Real(match real < R::one() {
+        true => real,
+        false => R::zero(),
+    })))
+}
+
+fn random_seed<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    random: &RefCell<Xoshiro256>,
+) -> Result<Value<R>> {
+    let seed = arguments.into_iter().next().unwrap().expect_integer()?;
+    *random.borrow_mut() = Xoshiro256::from_seed(seed as u64);
+    Ok(Value::Void)
+}
+
+pub fn library_map<R: RealNumberInternalTrait>(
+    random: Rc<RefCell<Xoshiro256>>,
+) -> Vec<(String, Value<R>)> {
+    let integer_random = random.clone();
+    let real_random = random.clone();
+    vec![
+        function_mapping!("random-integer", param_fixed!["n"], move |arguments, _| {
+            random_integer(arguments, &integer_random)
+        }),
+        function_mapping!("random-real", param_fixed![], move |_, _| random_real(
+            &real_random
+        )),
+        function_mapping!("random-seed!", param_fixed!["seed"], move |arguments, _| {
+            random_seed(arguments, &random)
+        }),
+    ]
+}
+
+#[test]
+fn random() -> Result<()> {
+    let draws = |it: &mut Interpreter<f64>, seed: i32| -> Result<Option<Value<f64>>> {
+        it.eval_str(&format!(
+            "(random-seed! {}) \
+             (list (random-integer 1000) (random-integer 1000) (random-integer 1000) (random-real))",
+            seed
+        ))
+    };
+    let mut it = Interpreter::<f64>::new_with_stdlib();
+    let first = draws(&mut it, 42)?;
+    assert_eq!(draws(&mut it, 42)?, first);
+    let mut other = Interpreter::<f64>::new_with_stdlib();
+    assert_eq!(draws(&mut other, 42)?, first);
+    assert_ne!(draws(&mut other, 7)?, first);
+
+    it.eval_str("(random-seed! 1)")?;
+    for _ in 0..1000 {
+        match it.eval_str("(random-integer 10)")? {
+            Some(Value::Number(Number::Integer(n))) => assert!((0..10).contains(&n)),
+            other => panic!("unexpected {:?}", other),
+        }
+        match it.eval_str("(random-real)")? {
+            Some(Value::Number(Number::Real(r))) => assert!((0.0..1.0).contains(&r)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert_eq!(
+        it.eval_str("(random-integer 1)")?,
+        Some(Value::Number(Number::Integer(0)))
+    );
+    for expression in &[
+        "(random-integer 0)",
+        "(random-integer -3)",
+        "(random-integer 1.5)",
+    ] {
+        assert_eq!(
+            it.eval_str(expression).unwrap_err().data.to_string(),
+            format!(
+                "random-integer: expect a positive integer, got {}",
+                &expression[16..expression.len() - 1]
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use crate::error::ErrorData;
+use crate::parser::pair::GenericPair;
+use crate::parser::*;
+use crate::port::InputPort;
+use crate::values::*;
+use crate::{environment::*, interpreter::*};
+use std::{cell::RefCell, rc::Rc};
+
+fn read<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+    current_input_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let port = optional_port(arguments.into_iter().next(), current_input_port)?;
+    let datum = port.borrow_mut().read_datum()?;
+    match datum {
+        Some(datum) => Interpreter::read_literal(&datum, &env),
+        None => Ok(Value::Eof),
+    }
+}
+
+fn optional_port<R: RealNumberInternalTrait>(
+    argument: Option<Value<R>>,
+    current_input_port: &Parameter<R>,
+) -> Result<Rc<RefCell<InputPort>>> {
+    match argument {
+        Some(value) => value.expect_input_port(),
+        None => current_input_port.get().expect_input_port(),
+    }
+}
+
+fn eof_or<R: RealNumberInternalTrait, T>(
+    value: Option<T>,
+    convert: impl FnOnce(T) -> Value<R>,
+) -> Value<R> {
+    match value {
+        Some(value) => convert(value),
+        None => Value::Eof,
+    }
+}
+
+fn read_char<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_input_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let port = optional_port(arguments.into_iter().next(), current_input_port)?;
+    let c = port.borrow_mut().read_char()?;
+    Ok(eof_or(c, Value::Character))
+}
+
+fn peek_char<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_input_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let port = optional_port(arguments.into_iter().next(), current_input_port)?;
+    let c = port.borrow_mut().peek_char()?;
+    Ok(eof_or(c, Value::Character))
+}
+
+fn read_line<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_input_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let port = optional_port(arguments.into_iter().next(), current_input_port)?;
+    let line = port.borrow_mut().read_line()?;
+    Ok(eof_or(line, Value::from))
+}
+
+fn read_string<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_input_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let k = iter.next().unwrap().expect_integer()?;
+    if k < 0 {
+        return error!(LogicError::NegativeLength);
+    }
+    let port = optional_port(iter.next(), current_input_port)?;
+    let string = port.borrow_mut().read_string(k as usize)?;
+    Ok(eof_or(string, Value::from))
+}
+
+fn char_ready<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_input_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let port = optional_port(arguments.into_iter().next(), current_input_port)?;
+    let ready = port.borrow_mut().char_ready()?;
+    Ok(Value::Boolean(ready))
+}
+
+pub fn library_map<R: RealNumberInternalTrait>(
+    current_input_port: Rc<Parameter<R>>,
+) -> Vec<(String, Value<R>)> {
+    library_map_result(current_input_port).unwrap()
+}
+
+fn library_map_result<R: RealNumberInternalTrait>(
+    current_input_port: Rc<Parameter<R>>,
+) -> Result<Vec<(String, Value<R>)>> {
+    let mut mappings = vec![(
+        "current-input-port".to_string(),
+        Value::Procedure(Procedure::Parameter(current_input_port.clone())),
+    )];
+    {
+        let current_input_port = current_input_port.clone();
+        mappings.push(function_mapping!(
+            "read",
+            append_variadic_param!(param_fixed![], "port"),
+            move |arguments, env| read(arguments, env, &current_input_port)
+        ));
+    }
+    macro_rules! input_function_mapping {
+        ($ident:tt, $parameter:expr, $function:expr) => {{
+            let current_input_port = current_input_port.clone();
+            mappings.push(function_mapping!(
+                $ident,
+                $parameter,
+                move |arguments, _| $function(arguments, &current_input_port)
+            ));
+        }};
+    }
+    input_function_mapping!(
+        "read-char",
+        append_variadic_param!(param_fixed![], "port"),
+        read_char
+    );
+    input_function_mapping!(
+        "peek-char",
+        append_variadic_param!(param_fixed![], "port"),
+        peek_char
+    );
+    input_function_mapping!(
+        "read-line",
+        append_variadic_param!(param_fixed![], "port"),
+        read_line
+    );
+    input_function_mapping!(
+        "read-string",
+        append_variadic_param!(param_fixed!["k"], "port"),
+        read_string
+    );
+    input_function_mapping!(
+        "char-ready?",
+        append_variadic_param!(param_fixed![], "port"),
+        char_ready
+    );
+    Ok(mappings)
+}
@@ -0,0 +1,103 @@
+use crate::environment::*;
+use crate::error::ErrorData;
+use crate::interpreter::*;
+use crate::parser::pair::GenericPair;
+use crate::parser::*;
+use crate::values::*;
+use std::rc::Rc;
+
+// the top-level environment of the program or library calling a procedure, where reflection
+// looks for bindings, local ones are not seen
+fn top_level<R: RealNumberInternalTrait>(mut env: Rc<Environment<R>>) -> Rc<Environment<R>> {
+    while let Some(parent) = env.parent().cloned() {
+        env = parent;
+    }
+    env
+}
+
+// names bound at the top level, sorted
+fn environment_bindings<R: RealNumberInternalTrait>(
+    _: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    let names = top_level(env)
+        .names()
+        .into_iter()
+        .map(|name| Value::Symbol(name.into()))
+        .collect::<Pair<R>>();
+    Ok(Value::Pair(Box::new(names)))
+}
+
+fn bound<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    env: Rc<Environment<R>>,
+) -> Result<Value<R>> {
+    match arguments.into_iter().next().unwrap() {
+        Value::Symbol(name) => Ok(Value::Boolean(top_level(env).get(name).is_some())),
+        other => error!(LogicError::TypeMisMatch(
+            other.written().to_string(),
+            Type::Symbol
+        )),
+    }
+}
+
+// the name procedures are defined with, #f for anonymous ones
+fn procedure_name<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let procedure = arguments.into_iter().next().unwrap().expect_procedure()?;
+    Ok(match &procedure {
+        Procedure::User(lambda, _) => match &lambda.name {
+            Some(name) => Value::Symbol(name.clone()),
+            None => Value::Boolean(false),
+        },
+        Procedure::Builtin(builtin) => Value::Symbol(builtin.name.as_str().into()),
+        Procedure::Parameter(_) => Value::Boolean(false),
+    })
+}
+
+pub fn library_map<R: RealNumberInternalTrait>() -> Vec<(String, Value<R>)> {
+    vec![
+        function_mapping!("environment-bindings", param_fixed![], environment_bindings),
+        function_mapping!("bound?", param_fixed!["name"], bound),
+        pure_function_mapping!("procedure-name", param_fixed!["procedure"], procedure_name),
+    ]
+}
+
+#[test]
+fn reflection() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    let names = it
+        .eval_str("(environment-bindings)")?
+        .unwrap()
+        .expect_list()?
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(names.iter().any(|name| name == "car"));
+    assert!(names.iter().any(|name| name == "procedure-name"));
+    assert_eq!(
+        it.eval_str("(list (bound? 'car) (bound? 'square))")?,
+        it.eval_str("'(#t #f)")?
+    );
+    it.eval_str("(define (square x) (* x x)) (define cube (lambda (x) (* x x x)))")?;
+    assert_eq!(
+        it.eval_str(
+            "(list (bound? 'square) (procedure-name square) (procedure-name cube) \
+             (procedure-name (lambda (x) x)) (procedure-name car))"
+        )?,
+        it.eval_str("'(#t square cube #f car)")?
+    );
+    assert_eq!(
+        it.eval_str("((lambda (local) (list (bound? 'local) (bound? 'square))) 1)")?,
+        it.eval_str("'(#f #t)")?
+    );
+    let globals = it.global_bindings();
+    assert!(globals.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert!(globals
+        .iter()
+        .any(|(name, value)| name == "square" && matches!(value, Value::Procedure(_))));
+    assert!(!globals.iter().any(|(name, _)| name == "local"));
+    Ok(())
+}
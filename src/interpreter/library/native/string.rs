@@ -0,0 +1,256 @@
+use crate::error::ErrorData;
+use crate::interpreter::error::{wrong_arity, wrong_type};
+use crate::interpreter::*;
+use crate::values::*;
+
+// strings are indexed by characters, like write-string does
+
+fn list<R: RealNumberInternalTrait>(strings: impl IntoIterator<Item = String>) -> Value<R> {
+    Value::Pair(Box::new(
+        strings
+            .into_iter()
+            .map(|string| Value::String(string.into()))
+            .collect::<Pair<R>>(),
+    ))
+}
+
+// a separator or a delimiter given as a string or a character
+fn text<R: RealNumberInternalTrait>(procedure: &str, value: Value<R>) -> Result<String> {
+    match value {
+        Value::String(string) => Ok(string.to_string()),
+        Value::Character(c) => Ok(c.to_string()),
+        other => error!(wrong_type(procedure, Type::String, &other)),
+    }
+}
+
+// (string-split string separator [keep-empty?]), fields between adjacent separators or at the
+// ends of the string are empty strings, kept unless keep-empty? is #f. An empty separator splits
+// the string into its characters.
+fn string_split<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let string = iter.next().unwrap().expect_string()?;
+    let separator = text("string-split", iter.next().unwrap())?;
+    let keep_empty = match iter.next() {
+        Some(keep_empty) => keep_empty.expect_boolean()?,
+        None => true,
+    };
+    if iter.next().is_some() {
+        return error!(wrong_arity(
+            "(string-split string separator [keep-empty?])",
+            (3, false),
+            4 + iter.count()
+        ));
+    }
+    let fields: Vec<String> = match separator.is_empty() {
+        true => string.chars().map(String::from).collect(),
+        false => string.split(separator.as_str()).map(String::from).collect(),
+    };
+    Ok(list(
+        fields
+            .into_iter()
+            .filter(|field| keep_empty || !field.is_empty()),
+    ))
+}
+
+// (string-join list [delimiter]), the delimiter defaults to a space
+fn string_join<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let strings = iter
+        .next()
+        .unwrap()
+        .expect_proper_list("string-join")?
+        .into_iter()
+        .map(|string| match string {
+            Value::String(string) => Ok(string.to_string()),
+            other => error!(wrong_type("string-join", Type::String, &other)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let delimiter = match iter.next() {
+        Some(delimiter) => text("string-join", delimiter)?,
+        None => " ".to_string(),
+    };
+    if iter.next().is_some() {
+        return error!(wrong_arity(
+            "(string-join list [delimiter])",
+            (2, false),
+            3 + iter.count()
+        ));
+    }
+    Ok(Value::String(strings.join(&delimiter).into()))
+}
+
+// the index of the first occurrence of pattern in string, #f if none
+fn string_contains<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let string = iter.next().unwrap().expect_string()?;
+    let pattern = text("string-contains", iter.next().unwrap())?;
+    Ok(match string.find(pattern.as_str()) {
+        Some(byte_index) => {
+            Value::Number(Number::Integer(string[..byte_index].chars().count() as i32))
+        }
+        None => Value::Boolean(false),
+    })
+}
+
+// the string and whether a character is trimmed, by default whitespaces, or the characters of
+// an optional string or a character
+fn trim_arguments<R: RealNumberInternalTrait>(
+    procedure: &str,
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<(String, impl Fn(char) -> bool)> {
+    let mut iter = arguments.into_iter();
+    let string = iter.next().unwrap().expect_string()?;
+    let characters = match iter.next() {
+        Some(characters) => Some(text(procedure, characters)?),
+        None => None,
+    };
+    if iter.next().is_some() {
+        return error!(wrong_arity(
+            &format!("({} string [characters])", procedure),
+            (2, false),
+            3 + iter.count()
+        ));
+    }
+    Ok((string.to_string(), move |c: char| match &characters {
+        Some(characters) => characters.contains(c),
+        None => c.is_whitespace(),
+    }))
+}
+
+fn string_trim<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let (string, trimmed) = trim_arguments("string-trim", arguments)?;
+    Ok(Value::String(string.trim_matches(trimmed).into()))
+}
+
+fn string_trim_left<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let (string, trimmed) = trim_arguments("string-trim-left", arguments)?;
+    Ok(Value::String(string.trim_start_matches(trimmed).into()))
+}
+
+fn string_trim_right<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+) -> Result<Value<R>> {
+    let (string, trimmed) = trim_arguments("string-trim-right", arguments)?;
+    Ok(Value::String(string.trim_end_matches(trimmed).into()))
+}
+
+fn library_map_result<R: RealNumberInternalTrait>() -> Result<Vec<(String, Value<R>)>> {
+    Ok(vec![
+        pure_function_mapping!(
+            "string-split",
+            append_variadic_param!(param_fixed!["string", "separator"], "keep-empty"),
+            string_split
+        ),
+        pure_function_mapping!(
+            "string-join",
+            append_variadic_param!(param_fixed!["list"], "delimiter"),
+            string_join
+        ),
+        pure_function_mapping!(
+            "string-contains",
+            param_fixed!["string", "pattern"],
+            string_contains
+        ),
+        pure_function_mapping!(
+            "string-trim",
+            append_variadic_param!(param_fixed!["string"], "characters"),
+            string_trim
+        ),
+        pure_function_mapping!(
+            "string-trim-left",
+            append_variadic_param!(param_fixed!["string"], "characters"),
+            string_trim_left
+        ),
+        pure_function_mapping!(
+            "string-trim-right",
+            append_variadic_param!(param_fixed!["string"], "characters"),
+            string_trim_right
+        ),
+    ])
+}
+
+pub fn library_map<R: RealNumberInternalTrait>() -> Vec<(String, Value<R>)> {
+    library_map_result().unwrap()
+}
+
+#[test]
+fn string_library() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str("(import (ruschm string))")?;
+    let cases = [
+        (r#"(string-split "a,b,c" ",")"#, r#"'("a" "b" "c")"#),
+        (r#"(string-split "a,b,c" #\,)"#, r#"'("a" "b" "c")"#),
+        (r#"(string-split ",a,,b," ",")"#, r#"'("" "a" "" "b" "")"#),
+        (r#"(string-split ",a,,b," "," #f)"#, r#"'("a" "b")"#),
+        (r#"(string-split "" ",")"#, r#"'("")"#),
+        (r#"(string-split "" "," #f)"#, "'()"),
+        (r#"(string-split "a::b::" "::")"#, r#"'("a" "b" "")"#),
+        (r#"(string-split "λμ" "")"#, r#"'("λ" "μ")"#),
+        (r#"(string-join '("a" "b" "c") ", ")"#, r#""a, b, c""#),
+        (r#"(string-join '("a" "b"))"#, r#""a b""#),
+        (r#"(string-join '("a" "b") #\-)"#, r#""a-b""#),
+        (r#"(string-join '() ",")"#, r#""""#),
+        (r#"(string-join '("") ",")"#, r#""""#),
+        (r#"(string-contains "hello world" "o w")"#, "4"),
+        (r#"(string-contains "λx.x" ".")"#, "2"),
+        (r#"(string-contains "abc" #\c)"#, "2"),
+        (r#"(string-contains "abc" "")"#, "0"),
+        (r#"(string-contains "" "a")"#, "#f"),
+        (r#"(string-trim "  a b \n")"#, r#""a b""#),
+        (r#"(string-trim-left "  a  ")"#, r#""a  ""#),
+        (r#"(string-trim-right "  a  ")"#, r#""  a""#),
+        (r#"(string-trim "xxaxyx" "xy")"#, r#""a""#),
+        (r#"(string-trim "--a--" #\-)"#, r#""a""#),
+        (r#"(string-trim "")"#, r#""""#),
+        (r#"(string-trim "   ")"#, r#""""#),
+    ];
+    for (expression, expected) in cases.iter() {
+        assert_eq!(
+            it.eval_str(expression)?,
+            it.eval_str(expected)?,
+            "{}",
+            expression
+        );
+    }
+    let errors = [
+        (
+            r#"(string-split 1 ",")"#,
+            "string-split: expect a string, got 1",
+        ),
+        (
+            r#"(string-split "a" 1)"#,
+            "string-split: expect a string, got 1",
+        ),
+        (
+            r#"(string-join '("a" 1))"#,
+            "string-join: expect a string, got 1",
+        ),
+        (
+            r#"(string-join "a")"#,
+            r#"string-join: expect a list, got "a""#,
+        ),
+        (
+            r#"(string-trim "a" "b" "c")"#,
+            "procedure (string-trim string [characters]) expects 2 arguments, got 3",
+        ),
+    ];
+    for (expression, message) in errors.iter() {
+        assert_eq!(
+            it.eval_str(expression).unwrap_err().data.to_string(),
+            *message,
+            "{}",
+            expression
+        );
+    }
+    Ok(())
+}
@@ -1,19 +1,376 @@
-use crate::{
-    interpreter::Result,
-    values::{Procedure, RealNumberInternalTrait, Value},
-};
+use crate::error::ErrorData;
+use crate::interpreter::error::{index_out_of_range, wrong_type};
+use crate::interpreter::*;
+use crate::parser::pair::GenericPair;
+use crate::parser::*;
+use crate::port::{OutputPort, Port};
+use crate::values::*;
+use std::{cell::RefCell, rc::Rc};
+
+fn optional_port<R: RealNumberInternalTrait>(
+    argument: Option<Value<R>>,
+    current_output_port: &Parameter<R>,
+) -> Result<Rc<RefCell<OutputPort>>> {
+    match argument {
+        Some(value) => value.expect_output_port(),
+        None => current_output_port.get().expect_output_port(),
+    }
+}
 
 fn display<R: RealNumberInternalTrait>(
     arguments: impl IntoIterator<Item = Value<R>>,
+    current_output_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let value = iter.next().unwrap();
+    let port = optional_port(iter.next(), current_output_port)?;
+    port.borrow_mut().write_str(&value.to_string())?;
+    Ok(Value::Void)
+}
+
+fn write<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_output_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let value = iter.next().unwrap();
+    let port = optional_port(iter.next(), current_output_port)?;
+    port.borrow_mut().write_str(&value.written().to_string())?;
+    Ok(Value::Void)
+}
+
+fn newline<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_output_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let port = optional_port(arguments.into_iter().next(), current_output_port)?;
+    port.borrow_mut().write_str("\n")?;
+    Ok(Value::Void)
+}
+
+fn write_char<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_output_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let c = iter.next().unwrap().expect_character()?;
+    let port = optional_port(iter.next(), current_output_port)?;
+    port.borrow_mut().write_str(c.encode_utf8(&mut [0; 4]))?;
+    Ok(Value::Void)
+}
+
+// (write-string string [port [start [end]]])
+fn write_string<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_output_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let string = iter.next().unwrap().expect_string()?;
+    let port = optional_port(iter.next(), current_output_port)?;
+    let length = string.chars().count();
+    let start = match iter.next() {
+        Some(start) => start.expect_integer()?,
+        None => 0,
+    };
+    let end = match iter.next() {
+        Some(end) => end.expect_integer()?,
+        None => length as i32,
+    };
+    if start < 0 || start as usize > length {
+        return error!(index_out_of_range("write-string", start, length));
+    }
+    if end < start || end as usize > length {
+        return error!(index_out_of_range("write-string", end, length));
+    }
+    let substring = string
+        .chars()
+        .skip(start as usize)
+        .take((end - start) as usize)
+        .collect::<String>();
+    port.borrow_mut().write_str(&substring)?;
+    Ok(Value::Void)
+}
+
+// (format destination format-string arg ...), destination is #f to return the output as a
+// string, #t for the current output port, or a port
+fn format<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_output_port: &Parameter<R>,
+) -> Result<Value<R>> {
+    let mut iter = arguments.into_iter();
+    let destination = iter.next().unwrap();
+    let format_string = iter.next().unwrap().expect_string()?;
+    let arguments = iter.collect::<Vec<_>>();
+    // directives are checked before any output
+    let mut directives = 0;
+    let mut chars = format_string.chars().enumerate();
+    while let Some((index, c)) = chars.next() {
+        if c == '~' {
+            match chars.next() {
+                Some((_, 'a')) | Some((_, 'A')) | Some((_, 's')) | Some((_, 'S'))
+                | Some((_, 'd')) | Some((_, 'D')) => directives += 1,
+                Some((_, '%')) | Some((_, '~')) => (),
+                Some((_, other)) => {
+                    return error!(LogicError::UnknownFormatDirective(
+                        format!("~{}", other),
+                        index
+                    ))
+                }
+                None => return error!(LogicError::UnknownFormatDirective("~".to_string(), index)),
+            }
+        }
+    }
+    if directives != arguments.len() {
+        return error!(LogicError::FormatArguments(directives, arguments.len()));
+    }
+    let mut output = String::new();
+    let mut arguments = arguments.into_iter();
+    let mut chars = format_string.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            output.push(c);
+            continue;
+        }
+        match chars.next().unwrap() {
+            'a' | 'A' => output.push_str(&arguments.next().unwrap().to_string()),
+            's' | 'S' => output.push_str(&arguments.next().unwrap().written().to_string()),
+            'd' | 'D' => match arguments.next().unwrap() {
+                Value::Number(number) => output.push_str(&number.to_string()),
+                other => return error!(wrong_type("format", Type::Number, &other)),
+            },
+            '%' => output.push('\n'),
+            _ => output.push('~'),
+        }
+    }
+    let port = match destination {
+        Value::Boolean(false) => return Ok(Value::String(output.into())),
+        Value::Boolean(true) => current_output_port.get().expect_output_port()?,
+        Value::Port(Port::Output(port)) => port,
+        other => return error!(wrong_type("format", Type::OutputPort, &other)),
+    };
+    port.borrow_mut().write_str(&output)?;
+    Ok(Value::Void)
+}
+
+fn flush_output_port<R: RealNumberInternalTrait>(
+    arguments: impl IntoIterator<Item = Value<R>>,
+    current_output_port: &Parameter<R>,
 ) -> Result<Value<R>> {
-    print!("{}", arguments.into_iter().next().unwrap());
+    let port = optional_port(arguments.into_iter().next(), current_output_port)?;
+    port.borrow_mut().flush()?;
     Ok(Value::Void)
 }
 
-pub fn library_map<'a, R: RealNumberInternalTrait>() -> Vec<(String, Value<R>)> {
-    vec![pure_function_mapping!(
+pub fn library_map<R: RealNumberInternalTrait>(
+    current_output_port: Rc<Parameter<R>>,
+    current_error_port: Rc<Parameter<R>>,
+) -> Vec<(String, Value<R>)> {
+    library_map_result(current_output_port, current_error_port).unwrap()
+}
+
+fn library_map_result<R: RealNumberInternalTrait>(
+    current_output_port: Rc<Parameter<R>>,
+    current_error_port: Rc<Parameter<R>>,
+) -> Result<Vec<(String, Value<R>)>> {
+    let mut mappings = vec![
+        (
+            "current-output-port".to_string(),
+            Value::Procedure(Procedure::Parameter(current_output_port.clone())),
+        ),
+        (
+            "current-error-port".to_string(),
+            Value::Procedure(Procedure::Parameter(current_error_port.clone())),
+        ),
+    ];
+    macro_rules! output_function_mapping {
+        ($ident:tt, $parameter:expr, $function:expr) => {{
+            let current_output_port = current_output_port.clone();
+            mappings.push(function_mapping!(
+                $ident,
+                $parameter,
+                move |arguments, _| $function(arguments, &current_output_port)
+            ));
+        }};
+    }
+    output_function_mapping!(
         "display",
-        param_fixed!["value"],
+        append_variadic_param!(param_fixed!["value"], "port"),
         display
-    )]
+    );
+    output_function_mapping!(
+        "write",
+        append_variadic_param!(param_fixed!["value"], "port"),
+        write
+    );
+    output_function_mapping!(
+        "newline",
+        append_variadic_param!(param_fixed![], "port"),
+        newline
+    );
+    output_function_mapping!(
+        "write-char",
+        append_variadic_param!(param_fixed!["char"], "port"),
+        write_char
+    );
+    output_function_mapping!(
+        "write-string",
+        append_variadic_param!(param_fixed!["string"], "port"),
+        write_string
+    );
+    output_function_mapping!(
+        "flush-output-port",
+        append_variadic_param!(param_fixed![], "port"),
+        flush_output_port
+    );
+    output_function_mapping!(
+        "format",
+        append_variadic_param!(param_fixed!["destination", "format-string"], "args"),
+        format
+    );
+    Ok(mappings)
+}
+
+#[test]
+fn newline_parameters() -> Result<()> {
+    let port = || {
+        Rc::new(Parameter::new(
+            Value::Port(Port::new_output(OutputPort::new_string())),
+            None,
+        ))
+    };
+    let newline = library_map::<f32>(port(), port())
+        .into_iter()
+        .find(|(name, _)| name.as_str() == "newline")
+        .unwrap()
+        .1
+        .expect_procedure()?;
+    assert_eq!(newline.get_parameters().len(), (0, true));
+    Ok(())
+}
+
+#[test]
+fn display_and_write() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str(
+        r#"(define value (list "hi\n" #\a 'sym '|two words| (vector "s" #\b) 1.5 '(1 . "x")))
+           (define (output procedure value)
+             (define port (open-output-string))
+             (procedure value port)
+             (get-output-string port))"#,
+    )?;
+    let output = |it: &mut Interpreter<f32>, expression: &str| -> Result<String> {
+        Ok(it
+            .eval_str(expression)?
+            .unwrap()
+            .expect_string()?
+            .to_string())
+    };
+    assert_eq!(
+        output(&mut it, "(output display value)")?,
+        "(hi\n a sym two words #(s b) 1.5 (1 . x))"
+    );
+    assert_eq!(
+        output(&mut it, "(output write value)")?,
+        r#"("hi\n" #\a sym |two words| #("s" #\b) 1.5 (1 . "x"))"#
+    );
+    assert_eq!(output(&mut it, r"(output display #\a)")?, "a");
+    assert_eq!(output(&mut it, r"(output write #\a)")?, r"#\a");
+    assert_eq!(output(&mut it, r#"(output display "hi\n")"#)?, "hi\n");
+    assert_eq!(output(&mut it, r#"(output write "hi\n")"#)?, r#""hi\n""#);
+    // the repl echoes values like write
+    assert_eq!(
+        it.eval_str("value")?.unwrap().written().to_string(),
+        output(&mut it, "(output write value)")?
+    );
+    Ok(())
+}
+
+#[test]
+fn write_characters() -> Result<()> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    let cases = [
+        (r"#\space", r"#\space", " "),
+        (r"#\newline", r"#\newline", "\n"),
+        (r"#\x1b", r"#\escape", "\u{1b}"),
+        (r"#\x1", r"#\x0001", "\u{1}"),
+        (r"#\xa0", r"#\x00a0", "\u{a0}"),
+        (r"#\x301", r"#\x0301", "\u{301}"),
+        (r"#\😀", r"#\😀", "😀"),
+        (r"#\(", r"#\(", "("),
+    ];
+    for (literal, written, displayed) in cases.iter() {
+        let value = it.eval_str(literal)?.unwrap();
+        assert_eq!(value.written().to_string(), *written, "{}", literal);
+        assert_eq!(value.to_string(), *displayed, "{}", literal);
+        // written characters are read back as themselves
+        assert_eq!(it.eval_str(written)?.unwrap(), value, "{}", literal);
+    }
+    Ok(())
+}
+
+#[test]
+fn format_directives() -> Result<()> {
+    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.set_output(output.clone());
+    assert_eq!(
+        it.eval_str(r#"(format #f "~a + ~a = ~s~%" 1 2 "three")"#)?,
+        Some(Value::String("1 + 2 = \"three\"\n".into()))
+    );
+    assert_eq!(
+        it.eval_str(r#"(format #f "~a ~s ~d ~~ ~A" "a\"b" #\c 42 '(x "y"))"#)?,
+        Some(Value::String("a\"b #\\c 42 ~ (x y)".into()))
+    );
+    assert_eq!(
+        it.eval_str(r#"(format #t "~a~%" 'hello)"#)?,
+        Some(Value::Void)
+    );
+    assert_eq!(
+        it.eval_str(
+            r#"(define port (open-output-string))
+               (format port "~s=~d" 'x 1)
+               (get-output-string port)"#
+        )?,
+        Some(Value::String("x=1".into()))
+    );
+    assert_eq!(
+        String::from_utf8(output.borrow().clone()).unwrap(),
+        "hello\n"
+    );
+    let errors = [
+        (
+            r#"(format #f "~a ~a" 1)"#,
+            "format: the format string has 2 directives taking arguments, got 1 argument",
+        ),
+        (
+            r#"(format #f "~a" 1 2)"#,
+            "format: the format string has 1 directive taking arguments, got 2 arguments",
+        ),
+        (
+            r#"(format #f "~%" 1)"#,
+            "format: the format string has 0 directives taking arguments, got 1 argument",
+        ),
+        (
+            r#"(format #f "ab~x" 1)"#,
+            "format: unknown directive ~x at index 2",
+        ),
+        (
+            r#"(format #f "~")"#,
+            "format: unknown directive ~ at index 0",
+        ),
+        (
+            r#"(format #f "~d" "1")"#,
+            r#"format: expect a number, got "1""#,
+        ),
+        (r#"(format 1 "")"#, "format: expect an output port, got 1"),
+    ];
+    for (expression, message) in errors.iter() {
+        assert_eq!(
+            it.eval_str(expression).unwrap_err().data.to_string(),
+            *message,
+            "{}",
+            expression
+        );
+    }
+    Ok(())
 }
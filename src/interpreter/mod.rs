@@ -1,7 +1,23 @@
 #[macro_use]
 pub mod library;
 type Result<T> = std::result::Result<T, SchemeError>;
+mod analyzer;
+mod compiler;
+mod debug;
 mod interpreter;
+mod optimizer;
+mod resolver;
+mod snapshot;
+mod trace;
+pub use analyzer::{analyze_program, analyze_statements, check_program};
+pub use compiler::{
+    compile_expression, compile_statement, CompiledStatement, Folded, Lambda, Node, NodeBody,
+};
+pub use debug::{Breakpoint, Debugger};
+pub use optimizer::{optimize_node, optimize_statement};
+pub use resolver::{resolve_library_definition, resolve_statement};
+pub use snapshot::{Snapshot, SNAPSHOT_VERSION};
+pub use trace::{TraceEvent, Tracer};
 
 use error::LogicError;
 pub use interpreter::*;
@@ -0,0 +1,252 @@
+use super::{CompiledStatement, Folded, Lambda, Node, NodeBody};
+use crate::{environment::*, stack, symbol::Symbol, values::*};
+use std::{mem, rc::Rc};
+
+/// Simplify a compiled statement without changing its result:
+/// - calls of `+`, `-` and `*` on number constants are folded, unless exact arithmetic overflows
+/// - conditionals with a constant test are replaced by the branch taken
+/// - constants and lambda expressions whose values are discarded in procedure bodies are dropped
+///
+/// Arithmetic is only folded while the name is bound to the builtin procedure in `env`, and the
+/// folded value is only used while it still is, so that redefining `+` after the code is
+/// optimized calls the new procedure. Calls which could fail, like `(/ 1 0)`, or identities like
+/// `(* x 1)`, which would hide type errors, are left as they are.
+pub fn optimize_statement<R: RealNumberInternalTrait>(
+    statement: &mut CompiledStatement<R>,
+    env: &Rc<Environment<R>>,
+) {
+    match statement {
+        CompiledStatement::Expression(node) | CompiledStatement::Definition(_, node) => {
+            optimize_node(node, env)
+        }
+    }
+}
+
+pub fn optimize_node<R: RealNumberInternalTrait>(node: &mut Node<R>, env: &Rc<Environment<R>>) {
+    stack::guard(|| {
+        match &mut node.data {
+            NodeBody::SetGlobal(_, value) | NodeBody::SetLocal(_, _, value) => {
+                optimize_node(value, env)
+            }
+            NodeBody::Lambda(lambda) => optimize_lambda(Rc::make_mut(lambda), env),
+            NodeBody::Call(procedure, arguments) => {
+                optimize_node(procedure, env);
+                for argument in arguments.iter_mut() {
+                    optimize_node(argument, env);
+                }
+                if let Some((value, builtins)) = fold_call(procedure, arguments, env) {
+                    let call = mem::replace(&mut node.data, NodeBody::Constant(Value::Void));
+                    node.data = NodeBody::Folded(Box::new(Folded {
+                        value,
+                        builtins,
+                        call: Node {
+                            data: call,
+                            location: node.location,
+                        },
+                    }));
+                }
+            }
+            NodeBody::If(branches) => {
+                let (test, consequent, alternative) = branches.as_mut();
+                optimize_node(test, env);
+                optimize_node(consequent, env);
+                if let Some(alternative) = alternative {
+                    optimize_node(alternative, env);
+                }
+                if let NodeBody::Constant(test) = &test.data {
+                    let taken = match (test.as_boolean(), alternative) {
+                        (true, _) => {
+                            mem::replace(consequent, NodeBody::Constant(Value::Void).into())
+                        }
+                        (false, Some(alternative)) => {
+                            mem::replace(alternative, NodeBody::Constant(Value::Void).into())
+                        }
+                        (false, None) => NodeBody::Constant(Value::Void).into(),
+                    };
+                    // the location of the branch is kept for its errors
+                    *node = Node {
+                        location: taken.location.or(node.location),
+                        data: taken.data,
+                    };
+                }
+            }
+            NodeBody::Constant(_)
+            | NodeBody::Global(_)
+            | NodeBody::Local(..)
+            | NodeBody::Folded(_) => (),
+        }
+    })
+}
+
+fn optimize_lambda<R: RealNumberInternalTrait>(lambda: &mut Lambda<R>, env: &Rc<Environment<R>>) {
+    for definition in lambda.definitions.iter_mut() {
+        optimize_node(definition, env);
+    }
+    for node in lambda.body.iter_mut() {
+        optimize_node(node, env);
+    }
+    if let Some(last) = lambda.body.pop() {
+        lambda
+            .body
+            .retain(|node| !matches!(node.data, NodeBody::Constant(_) | NodeBody::Lambda(_)));
+        lambda.body.push(last);
+    }
+}
+
+// whether `name` is bound to the builtin procedure of the same name in `env`
+pub(crate) fn is_builtin<R: RealNumberInternalTrait>(name: &str, env: &Rc<Environment<R>>) -> bool {
+    matches!(
+        env.get(name).as_deref(),
+        Some(Value::Procedure(Procedure::Builtin(builtin))) if builtin.name == name
+    )
+}
+
+// exact rational arithmetic is not checked for overflow, so only integers and reals are folded
+fn fold_number<R: RealNumberInternalTrait>(
+    a: Number<R>,
+    b: Number<R>,
+    integer: fn(i32, i32) -> Option<i32>,
+    inexact: fn(Number<R>, Number<R>) -> Number<R>,
+) -> Option<Number<R>> {
+    match (a, b) {
+        (Number::Integer(a), Number::Integer(b)) => integer(a, b).map(Number::Integer),
+        (Number::Rational(..), _) | (_, Number::Rational(..)) => None,
+        (a, b) => Some(inexact(a, b)),
+    }
+}
+
+// the value of the call and the builtin procedures it is computed with
+fn fold_call<R: RealNumberInternalTrait>(
+    procedure: &Node<R>,
+    arguments: &[Node<R>],
+    env: &Rc<Environment<R>>,
+) -> Option<(Value<R>, Vec<Symbol>)> {
+    let name = match &procedure.data {
+        NodeBody::Global(name) => name,
+        _ => return None,
+    };
+    let mut builtins = vec![name.clone()];
+    let numbers = arguments
+        .iter()
+        .map(|argument| match &argument.data {
+            NodeBody::Constant(Value::Number(number)) => Some(*number),
+            NodeBody::Folded(folded) => match folded.value {
+                Value::Number(number) => {
+                    builtins.extend(folded.builtins.iter().cloned());
+                    Some(number)
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if !is_builtin(name, env) {
+        return None;
+    }
+    let add = |a, b| fold_number(a, b, i32::checked_add, |a, b| a + b);
+    let sub = |a, b| fold_number(a, b, i32::checked_sub, |a, b| a - b);
+    let mul = |a, b| fold_number(a, b, i32::checked_mul, |a, b| a * b);
+    let value = match (name.as_str(), numbers.as_slice()) {
+        ("+", numbers) => numbers
+            .iter()
+            .try_fold(Number::Integer(0), |a, b| add(a, *b)),
+        ("*", numbers) => numbers
+            .iter()
+            .try_fold(Number::Integer(1), |a, b| mul(a, *b)),
+        ("-", [first]) => sub(Number::Integer(0), *first),
+        ("-", [first, rest @ ..]) => rest.iter().try_fold(*first, |a, b| sub(a, *b)),
+        _ => None,
+    }?;
+    builtins.sort();
+    builtins.dedup();
+    Some((Value::Number(value), builtins))
+}
+
+#[cfg(test)]
+fn optimized(source: &str) -> super::Result<String> {
+    use crate::parser::*;
+    let interpreter = super::Interpreter::<f32>::new_with_stdlib();
+    let env = interpreter.env.clone();
+    let mut statement = Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+        .next()
+        .unwrap()?;
+    super::resolve_statement(&mut statement);
+    let mut compiled = super::compile_statement(&statement)?;
+    optimize_statement(&mut compiled, &env);
+    Ok(compiled.to_string())
+}
+
+#[test]
+fn constant_folding() -> super::Result<()> {
+    assert_eq!(
+        optimized("(+ 1 2)")?,
+        "(folded 3 (call 2 (global +) (const 1) (const 2)))"
+    );
+    assert_eq!(
+        optimized("(* 2 (- 10 (+ 1 2)))")?,
+        "(folded 14 (call 2 (global *) (const 2) (folded 7 (call 2 (global -) (const 10) \
+         (folded 3 (call 2 (global +) (const 1) (const 2)))))))"
+    );
+    assert_eq!(
+        optimized("(- 5)")?,
+        "(folded -5 (call 1 (global -) (const 5)))"
+    );
+    assert_eq!(
+        optimized("(+ 1 2.5)")?,
+        "(folded 3.5 (call 2 (global +) (const 1) (const 2.5)))"
+    );
+    assert_eq!(
+        optimized("(+ x 1)")?,
+        "(call 2 (global +) (global x) (const 1))"
+    );
+    assert_eq!(
+        optimized("(* x 1)")?,
+        "(call 2 (global *) (global x) (const 1))"
+    );
+    // errors and overflows are left for the evaluation
+    assert_eq!(
+        optimized("(/ 1 0)")?,
+        "(call 2 (global /) (const 1) (const 0))"
+    );
+    assert_eq!(
+        optimized("(- 1 \"a\")")?,
+        "(call 2 (global -) (const 1) (const \"a\"))"
+    );
+    assert_eq!(
+        optimized("(* 65536 65536)")?,
+        "(call 2 (global *) (const 65536) (const 65536))"
+    );
+    assert_eq!(
+        optimized("(+ 1/2 1)")?,
+        "(call 2 (global +) (const 1/2) (const 1))"
+    );
+    // parameters shadowing builtin procedures
+    assert_eq!(
+        optimized("(lambda (+) (+ 1 2))")?,
+        "(lambda (+) (frame +) (call 2 (local + 0 0) (const 1) (const 2)))"
+    );
+    Ok(())
+}
+
+#[test]
+fn dead_code() -> super::Result<()> {
+    assert_eq!(optimized("(if #t (f) (g))")?, "(call 0 (global f))");
+    assert_eq!(
+        optimized("(if (< 2 1) (f) (g))")?,
+        "(if (call 2 (global <) (const 2) (const 1)) (call 0 (global f)) (call 0 (global g)))"
+    );
+    assert_eq!(optimized("(if #f (f) (g))")?, "(call 0 (global g))");
+    assert_eq!(optimized("(if #f (f))")?, "(const Void)");
+    assert_eq!(optimized("(if 1 'yes 'no)")?, "(const yes)");
+    // folded calls are evaluated if the procedures are redefined, whose result may be #f
+    assert_eq!(
+        optimized("(if (+ 1 1) 'yes 'no)")?,
+        "(if (folded 2 (call 2 (global +) (const 1) (const 1))) (const yes) (const no))"
+    );
+    assert_eq!(
+        optimized("(begin 1 (f) \"unused\" (lambda () 2) (+ 1 2))")?,
+        "(call 0 (lambda () (frame) (call 0 (global f)) \
+         (folded 3 (call 2 (global +) (const 1) (const 2)))))"
+    );
+    Ok(())
+}
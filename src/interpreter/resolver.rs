@@ -0,0 +1,130 @@
+use crate::{
+    error::{Located, ToLocated},
+    parser::{visit::*, *},
+    symbol::Symbol,
+};
+use std::mem;
+
+// names bound by the frames of enclosing procedures, innermost last
+type Scopes = Vec<Vec<Symbol>>;
+
+/// Annotate references to variables bound by enclosing procedures with their lexical address,
+/// so that the evaluator indexes procedure frames instead of looking names up.
+/// Top level and free references are left as they are.
+pub fn resolve_statement(statement: &mut Statement) {
+    let unresolved = mem::replace(statement, ExpressionBody::Period.no_locate().into());
+    *statement = Resolver {
+        scopes: Scopes::new(),
+    }
+    .fold_statement(unresolved);
+}
+
+pub fn resolve_library_definition(library: &mut LibraryDefinition) {
+    for declaration in library.1.iter_mut() {
+        if let LibraryDeclaration::Begin(statements) = &mut declaration.data {
+            statements.iter_mut().for_each(resolve_statement);
+        }
+    }
+}
+
+fn lookup(scopes: &Scopes, name: &Symbol) -> Option<LexicalAddress> {
+    scopes.iter().rev().enumerate().find_map(|(depth, frame)| {
+        // a later binding of the same name in a frame shadows the earlier one
+        frame
+            .iter()
+            .rposition(|bound| bound == name)
+            .map(|slot| LexicalAddress { depth, slot })
+    })
+}
+
+// the layout of the frame created when the procedure is applied
+fn frame_names(procedure: &SchemeProcedure) -> Vec<Symbol> {
+    let SchemeProcedure(formals, definitions, ..) = procedure;
+    let mut names = vec![];
+    if let Some(variadic) = formals.iter_to_last(|fixed| names.push(fixed.as_name())) {
+        names.push(variadic.as_name());
+    }
+    names.extend(
+        definitions
+            .iter()
+            .map(|definition| definition.data.0.clone()),
+    );
+    names
+}
+
+struct Resolver {
+    scopes: Scopes,
+}
+
+impl Folder for Resolver {
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        let Located { data, location } = rebuild_expression(self, expression);
+        let data = match data {
+            ExpressionBody::Symbol(name) => match lookup(&self.scopes, &name) {
+                Some(address) => ExpressionBody::LocalVariable(name, address),
+                None => ExpressionBody::Symbol(name),
+            },
+            ExpressionBody::Assignment(name, value) => match lookup(&self.scopes, &name) {
+                Some(address) => ExpressionBody::LocalAssignment(name, address, value),
+                None => ExpressionBody::Assignment(name, value),
+            },
+            data => data,
+        };
+        Located { data, location }
+    }
+
+    fn fold_procedure(&mut self, procedure: SchemeProcedure) -> SchemeProcedure {
+        self.scopes.push(frame_names(&procedure));
+        let procedure = rebuild_procedure(self, procedure);
+        self.scopes.pop();
+        procedure
+    }
+}
+
+#[test]
+fn lexical_addresses() -> super::Result<()> {
+    let mut statement = Parser::from_lexer(Lexer::from_char_stream(
+        "(lambda (x . rest) (define y x) (lambda (x) (set! y x) (+ x y rest z)))".chars(),
+    ))
+    .next()
+    .unwrap()?;
+    resolve_statement(&mut statement);
+    let address = |depth, slot| LexicalAddress { depth, slot };
+    let outer = match statement {
+        Statement::Expression(Expression {
+            data: ExpressionBody::Procedure(outer),
+            ..
+        }) => outer,
+        other => panic!("unexpected statement {:?}", other),
+    };
+    assert_eq!(
+        outer.1[0].data.1.data,
+        ExpressionBody::LocalVariable("x".into(), address(0, 0))
+    );
+    let inner = match &outer.2[0].data {
+        ExpressionBody::Procedure(inner) => inner,
+        other => panic!("unexpected expression {:?}", other),
+    };
+    assert!(matches!(
+        &inner.2[0].data,
+        ExpressionBody::LocalAssignment(name, at, value)
+            if name == "y" && *at == address(1, 2)
+            && value.data == ExpressionBody::LocalVariable("x".into(), address(0, 0))
+    ));
+    match &inner.2[1].data {
+        ExpressionBody::ProcedureCall(_, arguments) => assert_eq!(
+            arguments
+                .iter()
+                .map(|argument| argument.data.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                ExpressionBody::LocalVariable("x".into(), address(0, 0)),
+                ExpressionBody::LocalVariable("y".into(), address(1, 2)),
+                ExpressionBody::LocalVariable("rest".into(), address(1, 1)),
+                ExpressionBody::Symbol("z".into()),
+            ]
+        ),
+        other => panic!("unexpected expression {:?}", other),
+    }
+    Ok(())
+}
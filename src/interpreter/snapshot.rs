@@ -0,0 +1,249 @@
+//! Checkpoints of the data bound at the top level of an interpreter, see
+//! `Interpreter::snapshot`.
+//!
+//! A snapshot is saved as text, readable by the Scheme reader: a header `(ruschm-snapshot 1)`,
+//! where 1 is `SNAPSHOT_VERSION`, followed by a `(name value)` list for each binding, in the
+//! order of the names, with the value written like `write` does. Files of other versions are
+//! rejected. Vectors are restored as mutable vectors, sharing between values is not kept.
+use super::{error::LogicError, Result};
+use crate::error::ErrorData;
+use crate::parser::{pair::GenericPair, written_symbol, Lexer, Parser};
+use crate::stack;
+use crate::values::{Number, RealNumberInternalTrait, Value, ValueReference};
+use std::{fmt, fs, path::Path};
+
+pub const SNAPSHOT_VERSION: i32 = 1;
+
+const HEADER: &str = "ruschm-snapshot";
+
+/// top-level bindings of data taken by `Interpreter::snapshot`, sorted by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot<R: RealNumberInternalTrait> {
+    pub bindings: Vec<(String, Value<R>)>,
+}
+
+// whether `value` is made of numbers which can be read back, booleans, characters, strings,
+// symbols, pairs and vectors. `vectors` are the vectors being checked, which contain `value`.
+pub(crate) fn is_data<R: RealNumberInternalTrait>(
+    value: &Value<R>,
+    vectors: &mut Vec<*const ()>,
+) -> bool {
+    // recurses once per level of nesting of the cars and vectors
+    stack::guard(|| match value {
+        // infinities and NaN have no written form
+        Value::Number(Number::Real(real)) => real.abs() <= R::max_value(),
+        Value::Number(_)
+        | Value::Boolean(_)
+        | Value::Character(_)
+        | Value::String(_)
+        | Value::Symbol(_) => true,
+        Value::Pair(pair) => match pair.as_ref() {
+            GenericPair::Some(car, cdr) => is_data(car, vectors) && is_data(cdr, vectors),
+            GenericPair::Empty => true,
+        },
+        Value::Vector(vector) => {
+            if vectors.contains(&vector.as_ptr()) {
+                return false;
+            }
+            vectors.push(vector.as_ptr());
+            let data = vector
+                .as_ref()
+                .iter()
+                .all(|element| is_data(element, vectors));
+            vectors.pop();
+            data
+        }
+        _ => false,
+    })
+}
+
+// a copy of `value` not sharing its vectors, which are mutable
+pub(crate) fn copy_data<R: RealNumberInternalTrait>(value: &Value<R>) -> Value<R> {
+    stack::guard(|| match value {
+        Value::Pair(pair) => Value::Pair(Box::new(match pair.as_ref() {
+            GenericPair::Some(car, cdr) => GenericPair::Some(copy_data(car), copy_data(cdr)),
+            GenericPair::Empty => GenericPair::Empty,
+        })),
+        Value::Vector(vector) => Value::Vector(ValueReference::new_mutable(
+            vector.as_ref().iter().map(copy_data).collect(),
+        )),
+        other => other.clone(),
+    })
+}
+
+fn invalid<T>(message: impl Into<String>) -> Result<T> {
+    error!(LogicError::InvalidSnapshot(message.into()))
+}
+
+// the elements of a proper list
+fn elements<R: RealNumberInternalTrait>(value: &Value<R>) -> Option<Vec<Value<R>>> {
+    let list = value.clone().expect_list().ok()?;
+    Some(list.into_iter().collect())
+}
+
+impl<R: RealNumberInternalTrait> Snapshot<R> {
+    /// read a snapshot written by its `Display`
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+        let mut data = std::iter::from_fn(|| parser.parse_datum().transpose())
+            .map(|datum| super::compiler::datum_value::<R>(&datum?));
+        let header = match data.next() {
+            Some(header) => elements(&header?),
+            None => None,
+        };
+        match header.as_deref() {
+            Some([Value::Symbol(name), Value::Number(Number::Integer(version))])
+                if name.as_str() == HEADER =>
+            {
+                if *version != SNAPSHOT_VERSION {
+                    return invalid(format!(
+                        "version {} is not supported, expect version {}",
+                        version, SNAPSHOT_VERSION
+                    ));
+                }
+            }
+            _ => return invalid("missing header"),
+        }
+        let bindings = data
+            .map(|binding| {
+                let binding = binding?;
+                match elements(&binding).as_deref() {
+                    Some([Value::Symbol(name), value]) => Ok((name.to_string(), value.clone())),
+                    _ => invalid(format!("expect (name value), got {}", binding.written())),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { bindings })
+    }
+
+    /// write the snapshot to `path`, replacing the file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string()).map_err(|e| {
+            ErrorData::Logic(LogicError::FileError(path.display().to_string(), e.into()))
+                .no_locate()
+        })
+    }
+
+    /// read a snapshot saved by `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(source) => Self::parse(&source),
+            Err(e) => error!(LogicError::FileError(path.display().to_string(), e.into())),
+        }
+    }
+}
+
+impl<R: RealNumberInternalTrait> fmt::Display for Snapshot<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "({} {})", HEADER, SNAPSHOT_VERSION)?;
+        for (name, value) in &self.bindings {
+            writeln!(f, "({} {})", written_symbol(name), value.written())?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn snapshots() -> Result<()> {
+    use super::Interpreter;
+
+    let mut it = Interpreter::<f64>::new_with_stdlib();
+    it.eval_str(
+        r#"(define numbers (list 1 -2/3 0.5 (vector 1 #(2.25 "three") '())))
+           (define text "tab\t\"quoted\" λ")
+           (define |odd name| '(#\a #\space #\x7 . end))
+           (define flags (cons #t #f))
+           (define first car)"#,
+    )?;
+    let snapshot = it.snapshot()?;
+    assert_eq!(
+        snapshot.to_string(),
+        r#"(ruschm-snapshot 1)
+(flags (#t . #f))
+(numbers (1 -2/3 0.5 #(1 #(2.25 "three") ())))
+(|odd name| (#\a #\space #\alarm . end))
+(text "tab\t\"quoted\" λ")
+"#
+    );
+    let restored = Snapshot::<f64>::parse(&snapshot.to_string())?;
+    assert_eq!(restored.to_string(), snapshot.to_string());
+
+    // existing bindings are replaced
+    let mut fresh = Interpreter::<f64>::new_with_stdlib();
+    fresh.eval_str("(define text 'old) (define kept 1)")?;
+    fresh.restore(&restored);
+    for name in &["numbers", "text", "|odd name|", "flags"] {
+        let written = |it: &mut Interpreter<f64>| -> Result<String> {
+            Ok(it.eval_str(name)?.unwrap().written().to_string())
+        };
+        assert_eq!(written(&mut fresh)?, written(&mut it)?);
+    }
+    assert_eq!(
+        fresh.eval_str("kept")?,
+        Some(Value::Number(Number::Integer(1)))
+    );
+    // vectors can be modified and are not shared with the snapshot
+    fresh.eval_str("(vector-set! (list-ref numbers 3) 0 'x)")?;
+    fresh.restore(&restored);
+    assert_eq!(
+        fresh.eval_str("(vector-ref (list-ref numbers 3) 0)")?,
+        Some(Value::Number(Number::Integer(1)))
+    );
+
+    let file = std::env::temp_dir().join(format!("ruschm-snapshot-{}", std::process::id()));
+    snapshot.save(&file)?;
+    assert_eq!(Snapshot::<f64>::load(&file)?, restored);
+    fs::remove_file(&file).unwrap();
+
+    it.eval_str(
+        "(define (square x) (* x x)) (define port (open-input-string \"\")) \
+         (define infinite (/ 1.0 0))",
+    )?;
+    assert_eq!(
+        it.snapshot().unwrap_err().data.to_string(),
+        "cannot snapshot infinite, port, square, which are not data"
+    );
+    for (source, message) in &[
+        ("", "invalid snapshot: missing header"),
+        ("(x 1)", "invalid snapshot: missing header"),
+        (
+            "(ruschm-snapshot 2)",
+            "invalid snapshot: version 2 is not supported, expect version 1",
+        ),
+        (
+            "(ruschm-snapshot 1) (x 1 2)",
+            "invalid snapshot: expect (name value), got (x 1 2)",
+        ),
+    ] {
+        assert_eq!(
+            Snapshot::<f64>::parse(source).unwrap_err().data.to_string(),
+            *message
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn deep_snapshots() -> Result<()> {
+    use super::Interpreter;
+
+    // data as deep as the parser allows are checked, copied and written
+    let nested = format!("{}{}", "(".repeat(9000), ")".repeat(9000));
+    let mut it = Interpreter::<f64>::new_with_stdlib();
+    it.eval_str(&format!(
+        "(define deep '{}) (define wide (vector deep deep))",
+        nested
+    ))?;
+    let snapshot = it.snapshot()?;
+    let mut fresh = Interpreter::<f64>::new_with_stdlib();
+    fresh.restore(&snapshot);
+    assert_eq!(
+        fresh.eval_str("deep")?.unwrap().written().to_string(),
+        nested
+    );
+    let restored = Snapshot::<f64>::parse(&snapshot.to_string())?;
+    assert!(Snapshot::<f64>::parse(&restored.to_string())? == restored);
+    Ok(())
+}
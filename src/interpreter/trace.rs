@@ -0,0 +1,115 @@
+use super::Result;
+use crate::{
+    error::Location,
+    values::{RealNumberInternalTrait, Value},
+};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+};
+
+/// what the interpreter does, reported to the tracer set by `Interpreter::set_tracer`
+///
+/// Applications nest: each `EnterCall` is followed by the events of the application, then by
+/// the `ExitCall` of the same `depth`. A call in tail position replaces the procedure being
+/// applied instead of nesting, it is reported by `TailCall` and the `ExitCall` of the
+/// application is the one of the last procedure.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent<R: RealNumberInternalTrait> {
+    /// `name` is applied to `arguments` by the call at `location`, `depth` is the number of
+    /// applications in progress including this one
+    EnterCall {
+        name: String,
+        arguments: Vec<Value<R>>,
+        location: Option<Location>,
+        depth: usize,
+    },
+    /// the procedure applied at `depth` calls `name` in tail position
+    TailCall {
+        name: String,
+        arguments: Vec<Value<R>>,
+        location: Option<Location>,
+        depth: usize,
+    },
+    /// the application at `depth`, whose last procedure is `name`, returns `result`
+    ExitCall {
+        name: String,
+        result: Result<Value<R>>,
+        depth: usize,
+    },
+    /// an expression located at `location` is about to be evaluated
+    EvalExpression { location: Option<Location> },
+    /// a top-level or library definition binds `name`
+    DefineBinding { name: String },
+}
+
+pub type Tracer<R> = Box<dyn FnMut(TraceEvent<R>)>;
+
+thread_local! {
+    // whether TRACER is set, checked before any event is built
+    static TRACING: Cell<bool> = const { Cell::new(false) };
+    // the tracer of the interpreter evaluating on this thread, a Tracer<R>
+    static TRACER: RefCell<Option<Box<dyn Any>>> = RefCell::new(None);
+}
+
+pub(crate) fn tracing() -> bool {
+    TRACING.with(Cell::get)
+}
+
+// report the event built by `event` if tracing, the tracer is not reentered by events raised
+// while it runs
+pub(crate) fn trace<R: RealNumberInternalTrait>(event: impl FnOnce() -> TraceEvent<R>) {
+    if !tracing() {
+        return;
+    }
+    TRACER.with(|tracer| {
+        if let Ok(mut tracer) = tracer.try_borrow_mut() {
+            if let Some(tracer) = tracer
+                .as_mut()
+                .and_then(|tracer| tracer.downcast_mut::<Tracer<R>>())
+            {
+                tracer(event())
+            }
+        }
+    })
+}
+
+// make `tracer` receive the events of this thread until the returned guard is uninstalled or
+// dropped, which reinstalls the tracer it replaced, the one of an evaluation this one is nested in
+pub(crate) fn install<R: RealNumberInternalTrait>(tracer: Option<Tracer<R>>) -> Installed<R> {
+    Installed {
+        outer: Some(reinstall(
+            tracer.map(|tracer| Box::new(tracer) as Box<dyn Any>),
+        )),
+        tracer: PhantomData,
+    }
+}
+
+fn reinstall(tracer: Option<Box<dyn Any>>) -> Option<Box<dyn Any>> {
+    TRACING.with(|tracing| tracing.set(tracer.is_some()));
+    TRACER.with(|installed| installed.replace(tracer))
+}
+
+pub(crate) struct Installed<R: RealNumberInternalTrait> {
+    // the tracer replaced by `install`, None once it is reinstalled
+    outer: Option<Option<Box<dyn Any>>>,
+    tracer: PhantomData<Tracer<R>>,
+}
+
+impl<R: RealNumberInternalTrait> Installed<R> {
+    // the tracer installed by `install`, which no longer receives events
+    pub(crate) fn uninstall(mut self) -> Option<Tracer<R>> {
+        reinstall(self.outer.take().unwrap())
+            .and_then(|tracer| tracer.downcast::<Tracer<R>>().ok())
+            .map(|tracer| *tracer)
+    }
+}
+
+impl<R: RealNumberInternalTrait> Drop for Installed<R> {
+    fn drop(&mut self) {
+        if let Some(outer) = self.outer.take() {
+            reinstall(outer);
+        }
+    }
+}
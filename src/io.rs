@@ -1,16 +1,41 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::Path,
-};
+use std::{fs, path::Path};
 
+// the whole file is read at once, so that reading and decoding errors are reported before lexing
 pub fn file_char_stream(path: &Path) -> Result<impl Iterator<Item = char>, std::io::Error> {
-    let f = BufReader::new(File::open(path)?);
-    Ok(f.lines().flat_map(|line| {
-        line.unwrap()
-            .chars()
-            .chain(std::iter::once('\n'))
-            .collect::<Vec<_>>()
-            .into_iter()
-    }))
+    Ok(skip_shebang(&fs::read_to_string(path)?)
+        .chars()
+        .collect::<Vec<_>>()
+        .into_iter())
+}
+
+/// the interpreter directive starting a script, like `#!/usr/bin/env ruschm`, and the rest of the
+/// source from the line break ending it, so that the following lines keep their numbers. The
+/// `#!fold-case` and `#!no-fold-case` directives are not interpreter directives.
+pub fn split_shebang(source: &str) -> (&str, &str) {
+    let first_line = source.split('\n').next().unwrap_or_default();
+    let directive = first_line.trim_end_matches('\r');
+    match source.starts_with("#!") && directive != "#!fold-case" && directive != "#!no-fold-case" {
+        true => source.split_at(first_line.len()),
+        false => ("", source),
+    }
+}
+
+/// the source without its interpreter directive, see `split_shebang`
+pub fn skip_shebang(source: &str) -> &str {
+    split_shebang(source).1
+}
+
+#[test]
+fn shebang() {
+    assert_eq!(
+        split_shebang("#!/usr/bin/env ruschm\n(display 1)\n"),
+        ("#!/usr/bin/env ruschm", "\n(display 1)\n")
+    );
+    assert_eq!(split_shebang("#! ruschm"), ("#! ruschm", ""));
+    assert_eq!(
+        split_shebang("(display 1)\n#!x\n"),
+        ("", "(display 1)\n#!x\n")
+    );
+    assert_eq!(split_shebang(" #!x\n"), ("", " #!x\n"));
+    assert_eq!(split_shebang("#!fold-case\r\n"), ("", "#!fold-case\r\n"));
 }
@@ -5,8 +5,17 @@ pub mod error;
 pub mod parser;
 #[macro_use]
 pub mod interpreter;
+pub mod completion;
 pub mod environment;
+pub mod formatter;
+pub mod fuzz;
 pub mod io;
 pub mod library_factory;
+pub mod port;
+#[cfg(feature = "native")]
 pub mod repl;
+mod stack;
+pub mod symbol;
 pub mod values;
+
+pub use formatter::format_source;
@@ -1,7 +1,7 @@
 use crate::{
-    error::{ErrorData, Located, SchemeError, ToLocated},
-    interpreter::error::LogicError,
-    parser::{Lexer, LibraryDefinition, LibraryName, Parser, Statement},
+    error::{ErrorData, Located, SchemeError, SourceId},
+    interpreter::{error::LogicError, resolve_library_definition},
+    parser::{Lexer, LibraryDefinition, LibraryName, Parser, Statement, Token},
 };
 
 pub enum GenericLibraryFactory<'a, V> {
@@ -20,15 +20,34 @@ impl<'a, V> GenericLibraryFactory<'a, V> {
         expect_library_name: &LibraryName,
         char_stream: impl Iterator<Item = char>,
     ) -> Result<Self, SchemeError> {
-        let lexer = Lexer::from_char_stream(char_stream);
-        let parser = Parser::from_lexer(lexer);
+        Self::from_source(expect_library_name, char_stream, SourceId::string())
+    }
+    /// like `from_char_stream`, locations of the library refer to `source`
+    pub fn from_source(
+        expect_library_name: &LibraryName,
+        char_stream: impl Iterator<Item = char>,
+        source: SourceId,
+    ) -> Result<Self, SchemeError> {
+        let mut lexer = Lexer::from_char_stream(char_stream);
+        lexer.set_source(source);
+        Self::from_parser(expect_library_name, Parser::from_lexer(lexer))
+    }
+    /// like `from_char_stream`, the library is parsed by `parser`
+    pub fn from_parser(
+        expect_library_name: &LibraryName,
+        parser: Parser<impl Iterator<Item = Result<Token, SchemeError>>>,
+    ) -> Result<Self, SchemeError> {
         for statement in parser {
-            if let Statement::LibraryDefinition(library_definition) = statement? {
+            if let Statement::LibraryDefinition(mut library_definition) = statement? {
                 if &library_definition.0 == expect_library_name {
+                    resolve_library_definition(&mut library_definition.data);
                     return Ok(Self::AST(library_definition));
                 }
             }
         }
-        error!(LogicError::LibraryNotFound(expect_library_name.clone()))
+        error!(LogicError::LibraryNotFound(
+            expect_library_name.clone(),
+            vec![]
+        ))
     }
 }
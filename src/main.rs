@@ -1,30 +1,615 @@
-use ruschm::{error, interpreter::Interpreter, repl};
+use ruschm::{
+    error,
+    error::Severity,
+    error::SourceId,
+    format_source,
+    interpreter::error::LogicError,
+    interpreter::{Interpreter, TraceEvent},
+    io::{file_char_stream, skip_shebang},
+    parser::{dump::dump_statements, expand_program},
+    repl,
+    values::Value,
+};
 
-use std::{env, process::exit};
-use std::{io::Write, path::PathBuf};
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use std::{env, fs, iter, process::exit, sync::atomic::Ordering};
+use std::{
+    io,
+    io::IsTerminal,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+// exit statuses, in the spirit of sysexits.h, besides the code given to (exit)
+const EXIT_USAGE: i32 = 64;
+const EXIT_SYNTAX: i32 = 65;
+const EXIT_NO_INPUT: i32 = 66;
+const EXIT_RUNTIME: i32 = 70;
+const EXIT_IO: i32 = 74;
+const EXIT_INTERRUPTED: i32 = 130;
+
+// errors of the script itself are unwrapped from the error of loading it, which is located by
+// the script
+fn reported(error: &error::SchemeError) -> &error::SchemeError {
+    match &error.data {
+        error::ErrorData::Logic(LogicError::LoadError(_, inner)) if inner.location.is_some() => {
+            inner.as_ref()
+        }
+        _ => error,
+    }
+}
+
+// the line an error is located at is read from the file named by its source, or from `program`
+// for the source of a program read from stdin
+fn render(error: &error::SchemeError, program: Option<(SourceId, &str)>) -> String {
+    let error = reported(error);
+    let source = error
+        .location
+        .and_then(|location| match program {
+            Some((source, program)) if source == location.source => Some(program.to_string()),
+            _ => fs::read_to_string(location.source.name()).ok(),
+        })
+        .unwrap_or_default();
+    match io::stderr().is_terminal() {
+        true => error.render_colored(&source),
+        false => error.render(&source),
+    }
+}
+
+// the files named, and the *.scm and *.sld files under the directories named, sorted
+fn source_files(paths: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        let path = PathBuf::from(path);
+        match path.is_dir() {
+            true => {
+                let mut pending = vec![path];
+                while let Some(directory) = pending.pop() {
+                    for entry in fs::read_dir(directory)? {
+                        let path = entry?.path();
+                        if path.is_dir() {
+                            pending.push(path);
+                        } else if matches!(
+                            path.extension().and_then(|extension| extension.to_str()),
+                            Some("scm") | Some("sld")
+                        ) {
+                            files.push(path);
+                        }
+                    }
+                }
+            }
+            false => files.push(path),
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// write through a temporary file renamed over the file, so that it is never left half written
+fn replace_file(file: &Path, contents: &str) -> io::Result<()> {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = file.with_file_name(format!(".{}.ruschm-fmt", name));
+    fs::write(&temporary, contents)
+        .and_then(|_| fs::rename(&temporary, file))
+        .map_err(|error| {
+            let _ = fs::remove_file(&temporary);
+            error
+        })
+}
+
+// the file formatted, files with syntax errors are reported and left as they are
+fn formatted(file: &str, source: &str) -> Option<String> {
+    match format_source(source) {
+        Ok(formatted) => Some(formatted),
+        Err(mut error) => {
+            // located in the file, so that the line is rendered
+            if let Some(location) = error.location.as_mut() {
+                location.source = SourceId::new(file);
+            }
+            eprint!("{}", render(&error, Some((SourceId::new(file), source))));
+            None
+        }
+    }
+}
+
+// rewrite the files formatted, or with `check` list the files formatting would change and exit
+// with 1 if there are any, `-` formats stdin to stdout. The exit status is not zero if a file
+// cannot be read, written or parsed, the other files are still formatted.
+fn format_files(paths: &[String], check: bool) {
+    // the status of the last file which could not be formatted
+    let mut failed = None;
+    let mut changed = false;
+    if paths.iter().any(|path| path == "-") {
+        let source = read_stdin();
+        match formatted("<stdin>", &source) {
+            Some(formatted) if check => changed |= formatted != source,
+            Some(formatted) => print!("{}", formatted),
+            None => failed = Some(EXIT_SYNTAX),
+        }
+    }
+    let files = paths
+        .iter()
+        .filter(|path| *path != "-")
+        .cloned()
+        .collect::<Vec<_>>();
+    let files = match source_files(&files) {
+        Ok(files) => files,
+        Err(error) => {
+            eprintln!("cannot list files: {}", error);
+            exit(EXIT_NO_INPUT);
+        }
+    };
+    for file in files {
+        let name = file.display().to_string();
+        let source = match fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("cannot read {}: {}", name, error);
+                failed = Some(EXIT_NO_INPUT);
+                continue;
+            }
+        };
+        match formatted(&name, &source) {
+            Some(formatted) if formatted == source => (),
+            Some(_) if check => {
+                println!("{}", name);
+                changed = true;
+            }
+            Some(formatted) => {
+                if let Err(error) = replace_file(&file, &formatted) {
+                    eprintln!("cannot write {}: {}", name, error);
+                    failed = Some(EXIT_IO);
+                }
+            }
+            None => failed = Some(EXIT_SYNTAX),
+        }
+    }
+    match (failed, changed) {
+        (Some(status), _) => exit(status),
+        (None, true) => exit(1),
+        (None, false) => (),
+    }
+}
+
+// print the program after its macros and derived forms are expanded, without evaluating it
+fn expand_file(file: &str) {
+    let char_stream = match file_char_stream(file.as_ref()) {
+        Ok(char_stream) => char_stream,
+        Err(error) => {
+            eprintln!("cannot read {}: {}", file, error);
+            exit(EXIT_NO_INPUT);
+        }
+    };
+    match expand_program(char_stream, SourceId::new(file)) {
+        Ok(statements) => statements
+            .iter()
+            .for_each(|statement| println!("{}", statement)),
+        Err(error) => {
+            eprint!("{}", render(&error, None));
+            exit(EXIT_SYNTAX);
+        }
+    }
+}
+
+// print the statements of the program as parsed, as a tree or with `json` serialized, without
+// evaluating it
+fn ast_file(file: &str, json: bool) {
+    let char_stream = match file_char_stream(file.as_ref()) {
+        Ok(char_stream) => char_stream,
+        Err(error) => {
+            eprintln!("cannot read {}: {}", file, error);
+            exit(EXIT_NO_INPUT);
+        }
+    };
+    let statements = match expand_program(char_stream, SourceId::new(file)) {
+        Ok(statements) => statements,
+        Err(error) => {
+            eprint!("{}", render(&error, None));
+            exit(EXIT_SYNTAX);
+        }
+    };
+    match json {
+        #[cfg(feature = "serde")]
+        true => println!("{}", serde_json::to_string_pretty(&statements).unwrap()),
+        #[cfg(not(feature = "serde"))]
+        true => {
+            eprintln!("--ast=json requires the serde feature");
+            exit(EXIT_USAGE);
+        }
+        false => print!("{}", dump_statements(&statements)),
+    }
+}
+
+// report the problems found without evaluating the program, exit with 1 if there are errors
+fn check_file(file: &str, library_paths: Vec<PathBuf>, warnings: bool, json: bool) {
+    let program = match fs::read_to_string(file) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("cannot read {}: {}", file, error);
+            exit(EXIT_NO_INPUT);
+        }
+    };
+    let mut it = Interpreter::<f32>::new();
+    for directory in library_paths {
+        it.add_library_path(directory);
+    }
+    let mut failed = false;
+    for diagnostic in it.check(skip_shebang(&program).chars(), SourceId::new(file)) {
+        failed |= diagnostic.severity == Severity::Error;
+        match (diagnostic.severity, json) {
+            (Severity::Warning, _) if !warnings => (),
+            (_, false) => eprintln!("{}", diagnostic),
+            (_, true) => eprintln!("{}", diagnostic.to_json()),
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
+
+// print the applications of procedures to stderr, indented by their depth
+fn trace_calls(it: &mut Interpreter<f32>) {
+    fn call(name: &str, arguments: &[Value<f32>], location: Option<error::Location>) -> String {
+        let mut call = format!("({}", name);
+        for argument in arguments {
+            call.push_str(&format!(" {}", argument.written()));
+        }
+        call.push(')');
+        if let Some(location) = location {
+            call.push_str(&format!(" at {}", location));
+        }
+        call
+    }
+    let indent = |depth: usize| "  ".repeat(depth.saturating_sub(1));
+    it.set_tracer(Box::new(move |event| match event {
+        TraceEvent::EnterCall {
+            name,
+            arguments,
+            location,
+            depth,
+        } => eprintln!("{}-> {}", indent(depth), call(&name, &arguments, location)),
+        TraceEvent::TailCall {
+            name,
+            arguments,
+            location,
+            depth,
+        } => eprintln!("{}=> {}", indent(depth), call(&name, &arguments, location)),
+        TraceEvent::ExitCall {
+            result: Ok(value),
+            depth,
+            ..
+        } => eprintln!("{}<- {}", indent(depth), value.written()),
+        TraceEvent::ExitCall {
+            result: Err(error),
+            depth,
+            ..
+        } => eprintln!("{}<- error: {}", indent(depth), error),
+        _ => (),
+    }));
+}
+
+// buffered output ports are flushed whether or not evaluation succeeds
+fn flushed(
+    it: &Interpreter<f32>,
+    result: Result<Option<Value<f32>>, error::SchemeError>,
+) -> Result<Option<Value<f32>>, error::SchemeError> {
+    let flush = it.flush_output_ports();
+    result.and_then(|value| flush.map(|_| value))
+}
+
+// the status of a program stopped by `error`: the code given to (exit), EXIT_SYNTAX for errors
+// reading or expanding the program, EXIT_NO_INPUT if its file cannot be read, EXIT_INTERRUPTED
+// for ctrl-c, EXIT_RUNTIME for the others
+fn exit_status(error: &error::SchemeError) -> i32 {
+    match &error.data {
+        error::ErrorData::Logic(LogicError::LoadError(_, inner)) => exit_status(inner),
+        error::ErrorData::Exit { code, .. } => *code,
+        error::ErrorData::Syntax(_) => EXIT_SYNTAX,
+        error::ErrorData::Interrupted => EXIT_INTERRUPTED,
+        error::ErrorData::IO(_) if error.location.is_none() => EXIT_NO_INPUT,
+        _ => EXIT_RUNTIME,
+    }
+}
+
+// ctrl-c stops the evaluation, so that output ports are flushed and the status is
+// EXIT_INTERRUPTED
+fn interrupt_on_ctrl_c(it: &Interpreter<f32>) {
+    let interrupt = it.interrupt_handle();
+    if let Err(e) = ctrlc::set_handler(move || interrupt.store(true, Ordering::Relaxed)) {
+        eprintln!("cannot install ctrl-c handler: {}", e);
+    }
+}
+
+// the value of a program evaluated successfully, otherwise the error is reported and the process
+// exits with the status of the error
+fn finish(
+    result: Result<Option<Value<f32>>, error::SchemeError>,
+    json: bool,
+    program: Option<(SourceId, &str)>,
+) -> Option<Value<f32>> {
+    match result {
+        Ok(value) => value,
+        Err(error::SchemeError {
+            data: error::ErrorData::Exit { code, .. },
+            ..
+        }) => exit(code),
+        Err(e) if json => {
+            eprintln!("{}", reported(&e).to_json());
+            exit(exit_status(&e));
+        }
+        Err(e) => {
+            eprint!("{}", render(&e, program));
+            if let Some(backtrace) = &e.backtrace {
+                eprintln!("{}", backtrace);
+            }
+            exit(exit_status(&e));
+        }
+    }
+}
+
+// options of the interpreters evaluating -e expressions and programs
+struct RunOptions {
+    library_paths: Vec<PathBuf>,
+    dump_ir: bool,
+    trace: bool,
+}
+
+// an interpreter set up by the command line, with (scheme base) imported if `stdlib`, whose
+// evaluation is interrupted by ctrl-c
+fn make_interpreter(options: &RunOptions, stdlib: bool) -> Interpreter<f32> {
+    let it = new_interpreter(options, stdlib);
+    interrupt_on_ctrl_c(&it);
+    it
+}
+
+// like make_interpreter, for the repl, which handles ctrl-c itself
+fn new_interpreter(options: &RunOptions, stdlib: bool) -> Interpreter<f32> {
+    let mut it = match stdlib {
+        true => Interpreter::<f32>::new_with_stdlib(),
+        false => Interpreter::<f32>::new(),
+    };
+    for directory in &options.library_paths {
+        it.add_library_path(directory.clone());
+    }
+    it.set_dump_ir(options.dump_ir);
+    if options.trace {
+        trace_calls(&mut it);
+    }
+    it
+}
+
+// evaluate the script `file`, or the program read from stdin without one, after reporting the
+// diagnostics of its analysis, the process exits with the status of the error if it fails.
+// (command-line) is the script name followed by the script's arguments.
+fn run_program(
+    mut it: Interpreter<f32>,
+    file: Option<&str>,
+    arguments: Vec<String>,
+    warnings: bool,
+    json: bool,
+) {
+    let source = SourceId::new(file.unwrap_or("<stdin>"));
+    // a program read from stdin is read before it is analyzed and evaluated, errors reading a
+    // script are left to the evaluation, which reports them
+    let program = match file {
+        Some(file) => fs::read_to_string(file),
+        None => Ok(read_stdin()),
+    };
+    // syntax errors are reported by the evaluation too, unless they are reported as json, where
+    // all of them are reported at once
+    if let (true, Ok(program)) = (warnings || json, &program) {
+        let diagnostics = it.analyze(skip_shebang(&program).chars(), source);
+        let mut failed = false;
+        for diagnostic in diagnostics {
+            match (diagnostic.severity, json) {
+                (Severity::Error, false) => (),
+                (Severity::Error, true) => {
+                    failed = true;
+                    eprintln!("{}", diagnostic.to_json());
+                }
+                (Severity::Warning, _) if !warnings => (),
+                (Severity::Warning, false) => eprintln!("{}", diagnostic),
+                (Severity::Warning, true) => eprintln!("{}", diagnostic.to_json()),
+            }
+        }
+        if failed {
+            exit(EXIT_SYNTAX);
+        }
+    }
+    it.set_command_line(arguments);
+    let result = match file {
+        Some(file) => it.eval_file(PathBuf::from(file)),
+        None => it.eval_source(
+            skip_shebang(program.as_deref().unwrap_or_default()).chars(),
+            source,
+        ),
+    };
+    // the lines of errors in a script are read from its file
+    let stdin_program = match file {
+        Some(_) => None,
+        None => program.as_deref().ok(),
+    };
+    finish(
+        flushed(&it, result),
+        json,
+        stdin_program.map(|program| (source, program)),
+    );
+}
+
+fn read_stdin() -> String {
+    let mut program = String::new();
+    if let Err(error) = io::stdin().read_to_string(&mut program) {
+        eprintln!("cannot read stdin: {}", error);
+        exit(EXIT_NO_INPUT);
+    }
+    program
+}
 
 fn main() -> Result<(), error::SchemeError> {
-    Ok(match env::args().skip(1).next() {
-        Some(file) => {
-            let mut it = Interpreter::<f32>::new();
-            let result = it.eval_file(PathBuf::from(file.clone()));
-            match result {
-                Ok(_) => (),
-                Err(e) => {
-                    let mut stderr = StandardStream::stderr(ColorChoice::Always);
-                    stderr
-                        .set_color(&ColorSpec::new().set_fg(Some(Color::Red)))
-                        .unwrap();
-                    write!(&mut stderr, "{}", file).unwrap();
-                    if let Some(location) = e.location {
-                        write!(&mut stderr, ":{}:{} ", location[0], location[1]).unwrap();
-                    };
-                    writeln!(&mut stderr, " {}", e).unwrap();
-                    exit(-1);
-                }
-            }
-        }
-        None => repl::run(),
+    let mut library_paths = vec![];
+    let mut dump_ir = false;
+    let mut trace = false;
+    let mut warnings = true;
+    let mut json = false;
+    let mut format = false;
+    let mut check = false;
+    let mut expand = false;
+    let mut ast = None;
+    let mut interactive = None;
+    let mut history = repl::HistoryConfig::default();
+    let mut expressions = vec![];
+    let mut arguments = vec![];
+    let mut options = env::args().skip(1).peekable();
+    // `ruschm fmt file ...` is `ruschm --format file ...`
+    if options.peek().map(String::as_str) == Some("fmt") {
+        options.next();
+        format = true;
+    }
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "-L" | "--library-path" => match options.next() {
+                Some(directory) => library_paths.push(PathBuf::from(directory)),
+                None => {
+                    eprintln!("expect a directory after {}", option);
+                    exit(EXIT_USAGE);
+                }
+            },
+            "-e" | "--eval" => match options.next() {
+                Some(expression) => expressions.push(expression),
+                None => {
+                    eprintln!("expect an expression after {}", option);
+                    exit(EXIT_USAGE);
+                }
+            },
+            "--version" => {
+                println!("ruschm {}", env!("CARGO_PKG_VERSION"));
+                return Ok(());
+            }
+            // the script name, or the arguments of -e expressions, even if they look like options
+            "--" => arguments.extend(options.by_ref()),
+            "--dump-ir" => dump_ir = true,
+            "--trace-calls" => trace = true,
+            "--no-warnings" => warnings = false,
+            "--format" => format = true,
+            "--check" => check = true,
+            "--expand" => expand = true,
+            "--ast" => ast = Some(false),
+            "--ast=json" => ast = Some(true),
+            "--history-file" => match options.next() {
+                Some(file) => history.file = Some(PathBuf::from(file)),
+                None => {
+                    eprintln!("expect a file after {}", option);
+                    exit(EXIT_USAGE);
+                }
+            },
+            "--history-size" => match options.next().and_then(|size| size.parse().ok()) {
+                Some(size) => history.size = size,
+                None => {
+                    eprintln!("expect a number of entries after {}", option);
+                    exit(EXIT_USAGE);
+                }
+            },
+            "--no-history" => history.file = None,
+            "--interactive" => interactive = Some(true),
+            "--no-interactive" => interactive = Some(false),
+            "--diagnostics-format" => match options.next().as_deref() {
+                Some("human") => json = false,
+                Some("json") => json = true,
+                _ => {
+                    eprintln!("expect human or json after {}", option);
+                    exit(EXIT_USAGE);
+                }
+            },
+            _ => {
+                // the script name and the script's own arguments
+                arguments.push(option);
+                arguments.extend(options.by_ref());
+            }
+        }
+    }
+    if expand {
+        match arguments.first() {
+            Some(file) => expand_file(file),
+            None => {
+                eprintln!("expect a file to expand");
+                exit(EXIT_USAGE);
+            }
+        }
+        return Ok(());
+    }
+    if let Some(json) = ast {
+        match arguments.first() {
+            Some(file) => ast_file(file, json),
+            None => {
+                eprintln!("expect a file to print the syntax tree of");
+                exit(EXIT_USAGE);
+            }
+        }
+        return Ok(());
+    }
+    if format {
+        if arguments.is_empty() {
+            eprintln!("expect a file to format");
+            exit(EXIT_USAGE);
+        }
+        format_files(&arguments, check);
+        return Ok(());
+    }
+    // without --format, --check checks the program instead of its formatting
+    if check {
+        match arguments.first() {
+            Some(file) => check_file(file, library_paths, warnings, json),
+            None => {
+                eprintln!("expect a file to check");
+                exit(EXIT_USAGE);
+            }
+        }
+        return Ok(());
+    }
+    let options = RunOptions {
+        library_paths,
+        dump_ir,
+        trace,
+    };
+    if !expressions.is_empty() {
+        let mut it = make_interpreter(&options, true);
+        it.set_command_line(iter::once("-e".to_string()).chain(arguments));
+        // the expressions share one environment, the value of the last one is printed
+        let source = SourceId::new("<command line>");
+        let result = expressions.iter().try_fold(None, |_, expression| {
+            it.eval_source(expression.chars(), source)
+        });
+        match finish(flushed(&it, result), json, None) {
+            None | Some(Value::Void) => (),
+            Some(value) => println!("{}", value.written()),
+        }
+        return Ok(());
+    }
+    Ok(match arguments.first().cloned() {
+        // a program read from stdin can use (scheme base) without importing it, like -e
+        // expressions, scripts import the libraries they use
+        Some(file) if file == "-" => run_program(
+            make_interpreter(&options, true),
+            None,
+            arguments,
+            warnings,
+            json,
+        ),
+        Some(file) => run_program(
+            make_interpreter(&options, false),
+            Some(&file),
+            arguments,
+            warnings,
+            json,
+        ),
+        // the repl is only run on terminals, a program piped to stdin is evaluated like `-`,
+        // printing nothing but its own output
+        None if !interactive.unwrap_or_else(|| io::stdin().is_terminal()) => run_program(
+            make_interpreter(&options, true),
+            None,
+            arguments,
+            warnings,
+            json,
+        ),
+        None => repl::run_with_history(new_interpreter(&options, true), history),
     })
 }
@@ -1,4 +1,6 @@
 use crate::error::*;
+use crate::stack;
+use crate::symbol::Symbol;
 use either::Either;
 use itertools::Itertools;
 use std::fmt::Display;
@@ -9,15 +11,141 @@ use super::{
 };
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Primitive {
     String(String),
     Character(char),
     Boolean(bool),
     Integer(i32),
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_rational"))]
     Rational(i32, u32),
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_real"))]
     Real(String),
 }
 
+// the lexer only produces rationals with a denominator and reals which are numbers
+#[cfg(feature = "serde")]
+fn deserialize_rational<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<(i32, u32), D::Error> {
+    let (numerator, denominator) = serde::Deserialize::deserialize(deserializer)?;
+    match denominator {
+        0 => Err(serde::de::Error::custom(format!(
+            "invalid rational {}/0",
+            numerator
+        ))),
+        _ => Ok((numerator, denominator)),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn deserialize_real<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let literal: String = serde::Deserialize::deserialize(deserializer)?;
+    match literal.parse::<f64>() {
+        Ok(_) => Ok(literal),
+        Err(_) => Err(serde::de::Error::custom(format!(
+            "invalid real {}",
+            literal
+        ))),
+    }
+}
+
+impl Primitive {
+    /// the external representation, which is read back as the same primitive, unlike `Display`
+    /// which writes strings and characters as they are
+    pub fn written(&self) -> String {
+        match self {
+            Primitive::String(string) => {
+                let mut written = String::from("\"");
+                for c in string.chars() {
+                    match c {
+                        '"' => written.push_str("\\\""),
+                        '\\' => written.push_str("\\\\"),
+                        '\n' => written.push_str("\\n"),
+                        '\t' => written.push_str("\\t"),
+                        '\r' => written.push_str("\\r"),
+                        c => written.push(c),
+                    }
+                }
+                written.push('"');
+                written
+            }
+            Primitive::Character(c) => written_character(*c),
+            other => other.to_string(),
+        }
+    }
+}
+
+/// the names of characters written as `#\name`, which are read back by the lexer
+pub const CHARACTER_NAMES: [(&str, char); 9] = [
+    ("alarm", '\u{7}'),
+    ("backspace", '\u{8}'),
+    ("delete", '\u{7f}'),
+    ("escape", '\u{1b}'),
+    ("newline", '\n'),
+    ("null", '\0'),
+    ("return", '\r'),
+    ("space", ' '),
+    ("tab", '\t'),
+];
+
+// combining marks, which would be drawn over the backslash when written raw
+fn is_combining(c: char) -> bool {
+    matches!(c, '\u{300}'..='\u{36f}'
+        | '\u{1ab0}'..='\u{1aff}'
+        | '\u{1dc0}'..='\u{1dff}'
+        | '\u{20d0}'..='\u{20ff}'
+        | '\u{fe20}'..='\u{fe2f}')
+}
+
+/// the external representation of a character, by its name, as `#\xNNNN` if it is not printed
+/// visibly, or the character itself
+pub fn written_character(c: char) -> String {
+    match CHARACTER_NAMES.iter().find(|(_, named)| *named == c) {
+        Some((name, _)) => format!("#\\{}", name),
+        None if c.is_control() || c.is_whitespace() || is_combining(c) => {
+            format!("#\\x{:04x}", c as u32)
+        }
+        None => format!("#\\{}", c),
+    }
+}
+
+/// the external representation of a symbol, between `|` if it is not an identifier
+pub fn written_symbol(symbol: &str) -> String {
+    match symbol.is_empty()
+        || symbol
+            .chars()
+            .any(|c| c.is_whitespace() || "()\"';`,|".contains(c))
+    {
+        true => format!("|{}|", symbol.replace('\\', "\\\\").replace('|', "\\|")),
+        false => symbol.to_string(),
+    }
+}
+
+/// write the rational `numerator/denominator` reduced, with its sign on the numerator and as an
+/// integer when the denominator is 1, whatever the representation it was built with
+pub fn fmt_rational(
+    f: &mut std::fmt::Formatter<'_>,
+    numerator: i64,
+    denominator: i64,
+) -> std::fmt::Result {
+    if denominator == 0 {
+        return write!(f, "{}/{}", numerator, denominator);
+    }
+    let (mut a, mut b) = (numerator.abs(), denominator.abs());
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+    let sign = numerator.signum() * denominator.signum();
+    let (numerator, denominator) = (sign * numerator.abs() / a, denominator.abs() / a);
+    match denominator {
+        1 => write!(f, "{}", numerator),
+        _ => write!(f, "{}/{}", numerator, denominator),
+    }
+}
+
 impl Display for Primitive {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -25,7 +153,7 @@ impl Display for Primitive {
             Primitive::Character(inner) => write!(f, "{}", inner),
             Primitive::Boolean(inner) => write!(f, "{}", if *inner { "#t" } else { "#f" }),
             Primitive::Integer(inner) => write!(f, "{}", inner),
-            Primitive::Rational(a, b) => write!(f, "{}/{}", a, b),
+            Primitive::Rational(a, b) => fmt_rational(f, *a as i64, *b as i64),
             Primitive::Real(inner) => write!(f, "{}", inner),
         }
     }
@@ -34,9 +162,10 @@ impl Display for Primitive {
 pub type DatumList = GenericPair<Datum>;
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DatumBody {
     Primitive(Primitive),
-    Symbol(String),
+    Symbol(Symbol),
     Pair(Box<DatumList>),
     Vector(Vec<Datum>),
 }
@@ -58,6 +187,46 @@ impl Display for DatumBody {
     }
 }
 
+/// The external representation of a datum, see `DatumBody::written`.
+pub struct WrittenDatum<'a>(&'a DatumBody);
+
+// writing recurses once per level of nesting of the cars and vectors
+impl Display for WrittenDatum<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        stack::guard(|| match self.0 {
+            DatumBody::Primitive(primitive) => write!(f, "{}", primitive.written()),
+            DatumBody::Symbol(symbol) => write!(f, "{}", written_symbol(symbol)),
+            DatumBody::Pair(pair) => {
+                write!(f, "(")?;
+                let mut items = pair.iter().peekable();
+                while let Some(item) = items.next() {
+                    write!(f, "{}", item.written())?;
+                    if items.peek().is_some() {
+                        write!(f, " ")?;
+                    }
+                }
+                if let Some(last) = pair.last_cdr() {
+                    write!(f, " . {}", last.written())?;
+                }
+                write!(f, ")")
+            }
+            DatumBody::Vector(vector) => write!(
+                f,
+                "#({})",
+                vector.iter().map(|datum| datum.written()).join(" ")
+            ),
+        })
+    }
+}
+
+impl DatumBody {
+    /// the external representation, which is read back as the same datum, unlike `Display`
+    /// which writes strings and characters as they are
+    pub fn written(&self) -> WrittenDatum<'_> {
+        WrittenDatum(self)
+    }
+}
+
 impl Datum {
     pub fn expect_list(self) -> Result<DatumList, SchemeError> {
         match self.data {
@@ -71,7 +240,7 @@ impl Datum {
         }
     }
 
-    pub fn expect_symbol(&self) -> Result<String, SchemeError> {
+    pub fn expect_symbol(&self) -> Result<Symbol, SchemeError> {
         match &self.data {
             DatumBody::Symbol(symbol) => Ok(symbol.clone()),
             _ => {
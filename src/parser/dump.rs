@@ -0,0 +1,202 @@
+//! A readable tree of parsed statements, printed by `ruschm --ast`.
+//!
+//! Each node is a line indented by its depth, naming the kind of the node, what it binds or
+//! holds, and where it starts, like `ProcedureCall @3:1`. Quoted data, literals and macro
+//! definitions are leaves written on the line of their node.
+use super::{visit::*, *};
+use crate::error::*;
+use itertools::Itertools;
+use std::fmt::Write;
+
+/// the tree of `statements`, in order
+pub fn dump_statements(statements: &[Statement]) -> String {
+    let mut printer = TreePrinter {
+        output: String::new(),
+        depth: 0,
+    };
+    statements
+        .iter()
+        .for_each(|statement| printer.visit_statement(statement));
+    printer.output
+}
+
+struct TreePrinter {
+    output: String,
+    depth: usize,
+}
+
+impl TreePrinter {
+    fn line(&mut self, node: impl std::fmt::Display, location: Option<Location>) {
+        let _ = write!(self.output, "{}{}", "  ".repeat(self.depth), node);
+        if let Some(location) = location {
+            let _ = write!(self.output, " @{}:{}", location.line, location.column);
+        }
+        self.output.push('\n');
+    }
+
+    // a node whose children are printed by `children`, one level deeper
+    fn node(
+        &mut self,
+        node: impl std::fmt::Display,
+        location: Option<Location>,
+        children: impl FnOnce(&mut Self),
+    ) {
+        self.line(node, location);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+}
+
+fn export_spec(spec: &ExportSpec) -> String {
+    match spec {
+        ExportSpec::Direct(name) => name.clone(),
+        ExportSpec::Rename(from, to) => format!("(rename {} {})", from, to),
+    }
+}
+
+impl Visitor for TreePrinter {
+    fn visit_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::ImportDeclaration(declaration) => {
+                self.node("Import", declaration.location, |printer| {
+                    walk_statement(printer, statement)
+                })
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_definition(&mut self, definition: &Definition) {
+        self.node(
+            format!("Definition {}", definition.0),
+            definition.location,
+            |printer| walk_definition(printer, definition),
+        )
+    }
+
+    fn visit_syntax_definition(&mut self, definition: &SyntaxDef) {
+        self.line(
+            format!("SyntaxDefinition {}", definition.0),
+            definition.location,
+        )
+    }
+
+    fn visit_library_definition(&mut self, library: &Located<LibraryDefinition>) {
+        self.node(
+            format!("LibraryDefinition {}", library.0),
+            library.location,
+            |printer| {
+                for declaration in library.1.iter() {
+                    match &declaration.data {
+                        LibraryDeclaration::ImportDeclaration(imports) => {
+                            printer.node("Import", declaration.location, |printer| {
+                                imports
+                                    .0
+                                    .iter()
+                                    .for_each(|import_set| printer.visit_import_set(import_set))
+                            })
+                        }
+                        LibraryDeclaration::Export(specs) => printer.line(
+                            format!(
+                                "Export {}",
+                                specs.iter().map(|spec| export_spec(spec)).join(" ")
+                            ),
+                            declaration.location,
+                        ),
+                        LibraryDeclaration::Begin(statements) => {
+                            printer.node("Begin", declaration.location, |printer| {
+                                statements
+                                    .iter()
+                                    .for_each(|statement| printer.visit_statement(statement))
+                            })
+                        }
+                    }
+                }
+            },
+        )
+    }
+
+    fn visit_import_set(&mut self, import_set: &ImportSet) {
+        let node = match &import_set.data {
+            ImportSetBody::Direct(name) => format!("Library {}", name.data),
+            ImportSetBody::Only(_, names) => format!("Only {}", names.join(" ")),
+            ImportSetBody::Except(_, names) => format!("Except {}", names.join(" ")),
+            ImportSetBody::Prefix(_, prefix) => format!("Prefix {}", prefix),
+            ImportSetBody::Rename(_, renames) => format!(
+                "Rename {}",
+                renames
+                    .iter()
+                    .map(|(from, to)| format!("({} {})", from, to))
+                    .join(" ")
+            ),
+        };
+        self.node(node, import_set.location, |printer| {
+            walk_import_set(printer, import_set)
+        })
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        let node = match &expression.data {
+            ExpressionBody::Symbol(symbol) => format!("Symbol {}", symbol),
+            ExpressionBody::LocalVariable(symbol, address) => format!(
+                "LocalVariable {} {}:{}",
+                symbol, address.depth, address.slot
+            ),
+            ExpressionBody::Primitive(primitive) => format!("Primitive {}", primitive.written()),
+            ExpressionBody::Period => "Period".to_string(),
+            ExpressionBody::Assignment(symbol, _) => format!("Assignment {}", symbol),
+            ExpressionBody::LocalAssignment(symbol, address, _) => format!(
+                "LocalAssignment {} {}:{}",
+                symbol, address.depth, address.slot
+            ),
+            ExpressionBody::Procedure(SchemeProcedure(_, _, _, Some(name))) => {
+                format!("Procedure {}", name)
+            }
+            ExpressionBody::Procedure(_) => "Procedure".to_string(),
+            ExpressionBody::ProcedureCall(..) => "ProcedureCall".to_string(),
+            ExpressionBody::Conditional(_) => "Conditional".to_string(),
+            ExpressionBody::Quote(datum) => format!("Quote {}", datum.written()),
+            ExpressionBody::Datum(datum) => format!("Datum {}", datum.written()),
+        };
+        self.node(node, expression.location, |printer| {
+            walk_expression(printer, expression)
+        })
+    }
+
+    fn visit_parameter(&mut self, parameter: &ParameterFormals) {
+        self.line(format!("Parameter {}", parameter.data), parameter.location)
+    }
+}
+
+#[test]
+fn dump() -> Result<()> {
+    let program = "(import (only (scheme base) car))
+(define (f x . rest)
+  (if (null? rest) 'none \"some\"))
+(define-syntax id (syntax-rules () ((id x) x)))
+(set! f (lambda () #\\a))";
+    let statements = expand_program(program.chars(), SourceId::string())?;
+    assert_eq!(
+        dump_statements(&statements),
+        "Import @1:2
+  Only car @1:14
+    Library (scheme base) @1:22
+Definition f @2:2
+  Procedure f @2:11
+    Parameter x @2:13
+    Parameter rest @2:20
+    Conditional @3:4
+      ProcedureCall @3:8
+        Symbol null? @3:13
+        Symbol rest @3:18
+      Quote none @3:25
+      Primitive \"some\" @3:32
+SyntaxDefinition id @4:2
+Assignment f @5:2
+  Procedure @5:10
+    Primitive #\\a @5:23
+"
+    );
+    Ok(())
+}
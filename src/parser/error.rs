@@ -1,4 +1,5 @@
-use super::{Datum, DefinitionBody, ParameterFormals, SyntaxPattern, SyntaxTemplate, TokenData};
+use super::{Datum, ParameterFormals, SyntaxPattern, SyntaxTemplate, TokenData};
+use crate::{error::Location, symbol::Symbol};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq, Clone)]
@@ -21,10 +22,26 @@ pub enum SyntaxError {
     UnrecognizedToken,
     #[error("unknown escape character")]
     UnknownEscape(char),
+    #[error("unknown character name #\\{0}")]
+    UnknownCharacterName(String),
     #[error("unmactched parentheses!")]
     UnmatchedParentheses,
     #[error("try to define non-symbol {0}")]
     DefineNonSymbol(Datum),
+    #[error("a period in a list must be between its elements and its last cdr, like (a b . c), and (lambda args ...) takes any number of arguments")]
+    MisplacedPeriod,
+    #[error(
+        "duplicate definition of {}{}",
+        .0,
+        match .1 {Some(location) => format!(", first defined at {}:{}", location.line, location.column), None => String::new()}
+    )]
+    DuplicateDefinition(Symbol, Option<Location>),
+    #[error("definitions are not allowed in expression context, use let to bind {0} here")]
+    DefinitionInExpressionContext(Symbol),
+    #[error("{0} is not allowed in expression context")]
+    NotAnExpression(String),
+    #[error("duplicate parameter {0}")]
+    DuplicateParameter(Symbol),
     #[error("illegal parameter {0}")]
     IllegalParameter(ParameterFormals),
     #[error("invalid definition {0}")]
@@ -39,16 +56,26 @@ pub enum SyntaxError {
     InvalidIdentifier(String),
     #[error("imcomplete quoted identifier {0}")]
     ImcompleteQuotedIdent(String),
+    #[error("invalid number {0}")]
+    InvalidNumber(String),
+    #[error("{0} cannot be converted to an exact number")]
+    NoExactNumber(String),
     #[error("rational denominator should not be 0!")]
     RationalDivideByZero,
     #[error("empty procedure call")]
     EmptyCall,
+    #[error("nesting deeper than {0} levels")]
+    TooDeeplyNested(usize),
     #[error("illegal pattern")]
     IllegalPattern,
     #[error("illegal definition")]
     IllegalDefinition,
-    #[error("invalid context for definition {0:?}")]
-    InvalidDefinitionContext(DefinitionBody),
+    #[error(
+        "internal definitions must precede expressions in a body, the definition of {} follows the expression{}",
+        .0,
+        match .1 {Some(location) => format!(" at {}:{}", location.line, location.column), None => String::new()}
+    )]
+    DefinitionAfterExpression(Symbol, Option<Location>),
     #[error("{1} is not matched to any pattern of macro {0}")]
     MacroMissMatch(String, Datum),
     #[error("keyword should be {0} instead of {1}")]
@@ -1,16 +1,19 @@
 #![allow(dead_code)]
 use crate::error::*;
+use crate::symbol::Symbol;
+use std::convert::TryFrom;
 use std::fmt;
 use std::iter::Iterator;
 use std::iter::Peekable;
+use std::mem;
 
-use super::{error::SyntaxError, Primitive, Result};
+use super::{error::SyntaxError, Primitive, Result, CHARACTER_NAMES};
 
 pub type Token = Located<TokenData>;
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum TokenData {
-    Identifier(String),
+    Identifier(Symbol),
     Primitive(Primitive),
     LeftParen,
     RightParen,
@@ -21,6 +24,7 @@ pub enum TokenData {
     Unquote,          // ,
     UnquoteSplicing,  // ,@
     Period,           // .
+    Comment(String),  // ; and the rest of the line, only kept by lexers keeping comments
 }
 
 impl fmt::Display for TokenData {
@@ -34,7 +38,14 @@ impl ToLocated for TokenData {}
 pub struct Lexer<CharIter: Iterator<Item = char>> {
     pub current: Option<char>,
     pub peekable_char_stream: Peekable<CharIter>,
-    location: [u32; 2],
+    source: SourceId,
+    // line and column of the last character read
+    position: [u32; 2],
+    // position of the first character of the last token read
+    start: [u32; 2],
+    // reused for identifiers, which are interned instead of kept
+    buffer: String,
+    keep_comments: bool,
 }
 
 impl<CharIter: Iterator<Item = char>> Iterator for Lexer<CharIter> {
@@ -42,7 +53,7 @@ impl<CharIter: Iterator<Item = char>> Iterator for Lexer<CharIter> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.try_next() {
             Ok(None) => None,
-            Ok(Some(data)) => Some(Ok(data.locate(Some(self.location)))),
+            Ok(Some(data)) => Some(Ok(data.locate(self.token_location()))),
             Err(e) => Some(Err(e)),
         }
     }
@@ -76,16 +87,47 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
         Self {
             current: None,
             peekable_char_stream: char_stream.peekable(),
-            location: [1, 1],
+            source: SourceId::string(),
+            position: [1, 1],
+            start: [1, 1],
+            buffer: String::new(),
+            keep_comments: false,
         }
     }
 
-    pub fn set_last_location(&mut self, location: [u32; 2]) {
-        self.location = location;
+    /// name the source of the tokens, like the path of the file read
+    pub fn set_source(&mut self, source: SourceId) {
+        self.source = source;
+    }
+
+    /// emit comments as `TokenData::Comment` instead of skipping them, for tools reproducing the
+    /// source like the formatter, parsers do not accept them
+    pub fn set_keep_comments(&mut self, keep_comments: bool) {
+        self.keep_comments = keep_comments;
+    }
+
+    pub fn set_last_location(&mut self, position: [u32; 2]) {
+        self.position = position;
+    }
+
+    fn location(&self) -> Option<Location> {
+        Some(Location::new(self.source, self.position))
+    }
+
+    // the location of the last token read, spanning its characters on its last line
+    fn token_location(&self) -> Option<Location> {
+        let [line, column] = self.position;
+        let length = match self.start {
+            [start_line, start_column] if start_line == line => column - start_column + 1,
+            _ => column - 1,
+        };
+        Some(Location::new(self.source, self.position).with_length(length))
     }
 
     fn try_next(&mut self) -> Result<Option<TokenData>> {
-        match self.advance(1) {
+        let current = *self.advance(1);
+        self.start = self.position;
+        match current {
             Some(c) => match c {
                 ' ' | '\t' | '\n' | '\r' => self.atmosphere(),
                 ';' => self.comment(),
@@ -94,17 +136,11 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                 '#' => match self.advance(1) {
                     Some(cn) => match cn {
                         '(' => Ok(Some(TokenData::VecConsIntro)),
+                        'e' => self.prefixed_number(true),
+                        'i' => self.prefixed_number(false),
                         't' => Ok(Some(TokenData::Primitive(Primitive::Boolean(true)))),
                         'f' => Ok(Some(TokenData::Primitive(Primitive::Boolean(false)))),
-                        '\\' => match self.advance(1).take() {
-                            Some(cnn) => Ok(Some(TokenData::Primitive(Primitive::Character(cnn)))),
-                            None => {
-                                return located_error!(
-                                    SyntaxError::UnexpectedEnd,
-                                    Some(self.location)
-                                )
-                            }
-                        },
+                        '\\' => self.character(),
                         'u' => {
                             if Some('8') == self.advance(1).take()
                                 && Some('(') == self.advance(1).take()
@@ -113,18 +149,15 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                             } else {
                                 return located_error!(
                                     SyntaxError::UnrecognizedToken,
-                                    Some(self.location)
+                                    self.location()
                                 );
                             }
                         }
                         _ => {
-                            return located_error!(
-                                SyntaxError::UnrecognizedToken,
-                                Some(self.location)
-                            )
+                            return located_error!(SyntaxError::UnrecognizedToken, self.location())
                         }
                     },
-                    None => return located_error!(SyntaxError::UnexpectedEnd, Some(self.location)),
+                    None => return located_error!(SyntaxError::UnexpectedEnd, self.location()),
                 },
                 '\'' => Ok(Some(TokenData::Quote)),
                 '`' => Ok(Some(TokenData::Quasiquote)),
@@ -166,18 +199,18 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
             self.current = self.peekable_char_stream.next();
             match self.current {
                 Some('\n') => {
-                    self.location[0] += 1;
-                    self.location[1] = 1;
+                    self.position[0] += 1;
+                    self.position[1] = 1;
                 }
-                Some(_) => self.location[1] += 1,
+                Some(_) => self.position[1] += 1,
                 None => (),
             }
         }
-        // println!("{:?} location {:?} ", self.current.unwrap(), self.location);
+        // println!("{:?} location {:?} ", self.current.unwrap(), self.position);
         &mut self.current
     }
 
-    fn test_delimiter(location: Option<[u32; 2]>, c: char) -> Result<()> {
+    fn test_delimiter(c: char, location: Option<Location>) -> Result<()> {
         match c {
             ' ' | '\t' | '\n' | '\r' | '(' | ')' | '"' | ';' | '|' => Ok(()),
             other => {
@@ -202,21 +235,29 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
     }
 
     fn comment(&mut self) -> Result<Option<TokenData>> {
+        let mut comment = String::from(";");
         while let Some(c) = self.peekable_char_stream.peek() {
             match c {
                 '\n' | '\r' => break,
                 _ => {
+                    if self.keep_comments {
+                        comment.push(*c);
+                    }
                     self.advance(1);
                 }
             }
         }
-        self.try_next()
+        match self.keep_comments {
+            true => Ok(Some(TokenData::Comment(comment))),
+            false => self.try_next(),
+        }
     }
 
     fn normal_identifier(&mut self) -> Result<Option<TokenData>> {
         match self.current {
             Some(c) => {
-                let mut identifier_str = String::new();
+                let mut identifier_str = mem::take(&mut self.buffer);
+                identifier_str.clear();
                 identifier_str.push(c);
                 loop {
                     if let Some(nc) = self.peekable_char_stream.peek() {
@@ -224,7 +265,7 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                             _ if is_identifier_initial(*nc) => identifier_str.push(*nc),
                             '0'..='9' | '+' | '-' | '.' | '@' => identifier_str.push(*nc),
                             _ => {
-                                Self::test_delimiter(Some(self.location), *nc)?;
+                                Self::test_delimiter(*nc, self.location())?;
                                 break;
                             }
                         }
@@ -233,7 +274,9 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                         break;
                     }
                 }
-                Ok(Some(TokenData::Identifier(identifier_str)))
+                let symbol = Symbol::new(&identifier_str);
+                self.buffer = identifier_str;
+                Ok(Some(TokenData::Identifier(symbol)))
             }
             None => Ok(None),
         }
@@ -256,20 +299,20 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                                 identifier_str.push(self.advance(1).unwrap());
                             }
                             _ => {
-                                Self::test_delimiter(Some(self.location), *nc)?;
+                                Self::test_delimiter(*nc, self.location())?;
                                 break;
                             }
                         },
                         None => {
                             return located_error!(
                                 SyntaxError::InvalidIdentifier(identifier_str.clone()),
-                                Some(self.location)
+                                self.location()
                             );
                         }
                     }
                 },
                 false => {
-                    Self::test_delimiter(Some(self.location), *c)?;
+                    Self::test_delimiter(*c, self.location())?;
                 }
             }
         }
@@ -300,7 +343,7 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                     '.' => self.dot_subsequent(&mut identifier_str)?,
                     _ => (),
                 }
-                Ok(Some(TokenData::Identifier(identifier_str)))
+                Ok(Some(TokenData::Identifier(identifier_str.into())))
             }
             None => Ok(None),
         }
@@ -313,10 +356,10 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                 None => {
                     return located_error!(
                         SyntaxError::ImcompleteQuotedIdent(identifier_str),
-                        Some(self.location)
+                        self.location()
                     );
                 }
-                Some('|') => break Ok(Some(TokenData::Identifier(identifier_str))),
+                Some('|') => break Ok(Some(TokenData::Identifier(identifier_str.into()))),
                 Some(nc) => identifier_str.push(*nc),
             }
         }
@@ -351,7 +394,7 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                                             other => {
                                                 return located_error!(
                                                     SyntaxError::UnknownEscape(*other),
-                                                    Some(self.location)
+                                                    self.location()
                                                 )
                                             }
                                         }
@@ -359,7 +402,7 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                                     None => {
                                         return located_error!(
                                             SyntaxError::UnexpectedEnd,
-                                            Some(self.location)
+                                            self.location()
                                         )
                                     }
                                 }
@@ -367,7 +410,7 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                             _ => string_literal.push(c),
                         }
                     } else {
-                        return located_error!(SyntaxError::UnexpectedEnd, Some(self.location));
+                        return located_error!(SyntaxError::UnexpectedEnd, self.location());
                     }
                 }
             }
@@ -375,6 +418,39 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
         }
     }
 
+    // a character after #\, or the character named by the letters and digits following it, like
+    // #\space or #\x3bb
+    fn character(&mut self) -> Result<Option<TokenData>> {
+        let first = match self.advance(1).take() {
+            Some(c) => c,
+            None => return located_error!(SyntaxError::UnexpectedEnd, self.location()),
+        };
+        let mut name = first.to_string();
+        if first.is_alphabetic() {
+            while let Some(nc) = self.peekable_char_stream.peek() {
+                if !nc.is_alphanumeric() {
+                    break;
+                }
+                name.push(*nc);
+                self.advance(1);
+            }
+        }
+        let character = match name.chars().count() {
+            1 => Some(first),
+            _ => match CHARACTER_NAMES.iter().find(|(named, _)| *named == name) {
+                Some((_, c)) => Some(*c),
+                None => name
+                    .strip_prefix('x')
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(std::char::from_u32),
+            },
+        };
+        match character {
+            Some(c) => Ok(Some(TokenData::Primitive(Primitive::Character(c)))),
+            None => located_error!(SyntaxError::UnknownCharacterName(name), self.location()),
+        }
+    }
+
     fn digital10(&mut self, number_literal: &mut String) -> Result<()> {
         loop {
             match self.peekable_char_stream.peek() {
@@ -412,12 +488,12 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                     self.digital10(number_literal)?;
                     match self.peekable_char_stream.peek() {
                         Some('e') => self.number_suffix(number_literal),
-                        Some(nnc) => Self::test_delimiter(Some(self.location), *nnc),
+                        Some(nnc) => Self::test_delimiter(*nnc, self.location()),
                         None => Ok(()),
                     }
                 }
                 _ => {
-                    Self::test_delimiter(Some(self.location), *nc)?;
+                    Self::test_delimiter(*nc, self.location())?;
                     Ok(())
                 }
             },
@@ -437,43 +513,55 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
                             '0'..='9' => self.digital10(&mut number_literal)?,
                             'e' => {
                                 self.number_suffix(&mut number_literal)?;
-                                break Ok(Some(TokenData::Primitive(Primitive::Real(
-                                    number_literal,
-                                ))));
+                                break self.real_token(number_literal).map(Some);
                             }
                             '.' => {
                                 self.real(&mut number_literal)?;
-                                break Ok(Some(TokenData::Primitive(Primitive::Real(
-                                    number_literal,
-                                ))));
+                                break self.real_token(number_literal).map(Some);
                             }
                             '/' => {
                                 let mut denominator = String::new();
                                 self.advance(1);
                                 self.digital10(&mut denominator)?;
-                                break Ok(Some(TokenData::Primitive(Primitive::Rational(
-                                    number_literal.parse::<i32>().unwrap(),
-                                    match denominator.parse::<u32>().unwrap() {
-                                        0 => {
+                                let (numerator, denominator) =
+                                    match (number_literal.parse(), denominator.parse()) {
+                                        (Ok(numerator), Ok(denominator)) => {
+                                            (numerator, denominator)
+                                        }
+                                        _ => {
                                             return located_error!(
-                                                SyntaxError::RationalDivideByZero,
-                                                Some(self.location)
+                                                SyntaxError::InvalidNumber(format!(
+                                                    "{}/{}",
+                                                    number_literal, denominator
+                                                )),
+                                                self.location()
                                             )
                                         }
-                                        other => other,
-                                    },
+                                    };
+                                let denominator: u32 = match denominator {
+                                    0 => {
+                                        return located_error!(
+                                            SyntaxError::RationalDivideByZero,
+                                            self.location()
+                                        )
+                                    }
+                                    other => other,
+                                };
+                                break Ok(Some(TokenData::Primitive(Primitive::Rational(
+                                    numerator,
+                                    denominator,
                                 ))));
                             }
                             _ => {
-                                Self::test_delimiter(Some(self.location), *nc)?;
+                                Self::test_delimiter(*nc, self.location())?;
                                 break Ok(Some(TokenData::Primitive(Primitive::Integer(
-                                    number_literal.parse::<i32>().unwrap(),
+                                    self.parse_number(&number_literal)?,
                                 ))));
                             }
                         },
                         None => {
                             break Ok(Some(TokenData::Primitive(Primitive::Integer(
-                                number_literal.parse::<i32>().unwrap(),
+                                self.parse_number(&number_literal)?,
                             ))))
                         }
                     }
@@ -482,6 +570,117 @@ impl<CharIter: Iterator<Item = char>> Lexer<CharIter> {
             None => Ok(None),
         }
     }
+
+    // literals like `1e` or integers overflowing the exact representation are rejected here,
+    // so that primitives of the tokens are always valid
+    fn parse_number<T: std::str::FromStr>(&self, number_literal: &str) -> Result<T> {
+        number_literal.parse::<T>().or_else(|_| {
+            located_error!(
+                SyntaxError::InvalidNumber(number_literal.to_string()),
+                self.location()
+            )
+        })
+    }
+
+    // a number after an exactness prefix, `#e1.5` is read as the exact 3/2 and `#i1/3` as the
+    // real closest to 1/3
+    fn prefixed_number(&mut self, exact: bool) -> Result<Option<TokenData>> {
+        let prefix = if exact { "#e" } else { "#i" };
+        let current = *self.advance(1);
+        let is_number = match (current, self.peekable_char_stream.peek()) {
+            (Some('0'..='9'), _) => true,
+            (Some('+') | Some('-'), Some('0'..='9') | Some('.')) => true,
+            _ => false,
+        };
+        if !is_number {
+            let mut literal = prefix.to_string();
+            literal.extend(current);
+            while let Some(nc) = self.peekable_char_stream.peek() {
+                if Self::test_delimiter(*nc, None).is_ok() {
+                    break;
+                }
+                literal.push(*nc);
+                self.advance(1);
+            }
+            return match literal.as_str() {
+                "#e+inf.0" | "#e-inf.0" | "#e+nan.0" | "#e-nan.0" => {
+                    located_error!(SyntaxError::NoExactNumber(literal), self.location())
+                }
+                _ => located_error!(SyntaxError::InvalidNumber(literal), self.location()),
+            };
+        }
+        let primitive = match self.number()? {
+            Some(TokenData::Primitive(primitive)) => primitive,
+            _ => unreachable!("numbers are lexed as primitives"),
+        };
+        Ok(Some(TokenData::Primitive(match (exact, primitive) {
+            (true, Primitive::Real(number_literal)) => match exact_decimal(&number_literal) {
+                Some(exact) => exact,
+                None => {
+                    return located_error!(
+                        SyntaxError::NoExactNumber(format!("{}{}", prefix, number_literal)),
+                        self.location()
+                    )
+                }
+            },
+            (false, Primitive::Integer(n)) => Primitive::Real(format!("{:?}", n as f64)),
+            (false, Primitive::Rational(numerator, denominator)) => {
+                Primitive::Real(format!("{:?}", numerator as f64 / denominator as f64))
+            }
+            (_, primitive) => primitive,
+        })))
+    }
+
+    fn real_token(&self, number_literal: String) -> Result<TokenData> {
+        self.parse_number::<f64>(&number_literal)?;
+        Ok(TokenData::Primitive(Primitive::Real(number_literal)))
+    }
+}
+
+// the exact number written by a decimal literal like `-1.25e2`, an integer or a reduced
+// rational, if it fits in the exact representation
+fn exact_decimal(number_literal: &str) -> Option<Primitive> {
+    let (mantissa, exponent) = match number_literal.find('e') {
+        Some(index) => (
+            &number_literal[..index],
+            number_literal[index + 1..].parse::<i32>().ok()?,
+        ),
+        None => (number_literal, 0),
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => (-1, mantissa),
+        None => (1, mantissa.trim_start_matches('+')),
+    };
+    let (integral, fraction) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+        None => (mantissa, ""),
+    };
+    let digits = format!("{}{}", integral, fraction);
+    let digits = digits.trim_start_matches('0');
+    // the value is digits * 10^scale
+    let scale = exponent.checked_sub(fraction.len() as i32)?;
+    if digits.is_empty() {
+        return Some(Primitive::Integer(0));
+    }
+    let power = |exponent: i32| 10i128.checked_pow(exponent.unsigned_abs());
+    let digits = digits.parse::<i128>().ok()?;
+    let (mut numerator, mut denominator) = match scale >= 0 {
+        true => (digits.checked_mul(power(scale)?)?, 1),
+        false => (digits, power(scale)?),
+    };
+    let (mut a, mut b) = (numerator, denominator);
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+    numerator /= a;
+    denominator /= a;
+    let numerator = i32::try_from(sign * numerator).ok()?;
+    Some(match denominator {
+        1 => Primitive::Integer(numerator),
+        denominator => Primitive::Rational(numerator, i32::try_from(denominator).ok()? as u32),
+    })
 }
 
 fn tokenize(text: &str) -> Result<Vec<TokenData>> {
@@ -493,6 +692,25 @@ fn tokenize(text: &str) -> Result<Vec<TokenData>> {
         .collect())
 }
 
+#[test]
+fn token_spans() -> Result<()> {
+    let spans = |text: &str| -> Result<Vec<(u32, u32)>> {
+        Ok(Lexer::from_char_stream(text.chars())
+            .map(|token| token.map(|token| token.location.unwrap()))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .map(|location| (location.start_column(), location.length))
+            .collect())
+    };
+    assert_eq!(
+        spans("(define xyz  \"a b\" #\\space)")?,
+        [(1, 1), (2, 6), (9, 3), (14, 5), (20, 7), (27, 1)]
+    );
+    // tokens spanning lines are located on their last line
+    assert_eq!(spans("\"a\nbc\" 1")?, [(1, 3), (5, 1)]);
+    Ok(())
+}
+
 #[test]
 fn simple_tokens() -> Result<()> {
     assert_eq!(
@@ -529,18 +747,18 @@ fn identifier() -> Result<()> {
         |two words| |two; words|"
         )?,
         vec![
-            TokenData::Identifier(String::from("...")),
-            TokenData::Identifier(String::from("+")),
-            TokenData::Identifier(String::from("+soup+")),
-            TokenData::Identifier(String::from("<=?")),
-            TokenData::Identifier(String::from("->string")),
-            TokenData::Identifier(String::from("a34kTMNs")),
-            TokenData::Identifier(String::from("lambda")),
-            TokenData::Identifier(String::from("list->vector")),
-            TokenData::Identifier(String::from("q")),
-            TokenData::Identifier(String::from("V17a")),
-            TokenData::Identifier(String::from("two words")),
-            TokenData::Identifier(String::from("two; words"))
+            TokenData::Identifier("...".into()),
+            TokenData::Identifier("+".into()),
+            TokenData::Identifier("+soup+".into()),
+            TokenData::Identifier("<=?".into()),
+            TokenData::Identifier("->string".into()),
+            TokenData::Identifier("a34kTMNs".into()),
+            TokenData::Identifier("lambda".into()),
+            TokenData::Identifier("list->vector".into()),
+            TokenData::Identifier("q".into()),
+            TokenData::Identifier("V17a".into()),
+            TokenData::Identifier("two words".into()),
+            TokenData::Identifier("two; words".into())
         ]
     );
 
@@ -565,6 +783,28 @@ fn character() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn named_character() -> Result<()> {
+    assert_eq!(
+        tokenize("#\\space #\\newline #\\x7 #\\x1F600 #\\x(#\\a)")?,
+        vec![
+            TokenData::Primitive(Primitive::Character(' ')),
+            TokenData::Primitive(Primitive::Character('\n')),
+            TokenData::Primitive(Primitive::Character('\u{7}')),
+            TokenData::Primitive(Primitive::Character('😀')),
+            TokenData::Primitive(Primitive::Character('x')),
+            TokenData::LeftParen,
+            TokenData::Primitive(Primitive::Character('a')),
+            TokenData::RightParen,
+        ]
+    );
+    assert_eq!(
+        tokenize("#\\spaces").unwrap_err().data,
+        ErrorData::Syntax(SyntaxError::UnknownCharacterName("spaces".to_string()))
+    );
+    Ok(())
+}
+
 #[test]
 fn string() -> Result<()> {
     assert_eq!(
@@ -615,6 +855,44 @@ fn number() -> Result<()> {
         tokenize("1/00"),
         located_error!(SyntaxError::RationalDivideByZero, None)
     );
+    for invalid in &["1e", "-2.e+", "99999999999", "1/", "1/99999999999"] {
+        assert_eq!(
+            tokenize(invalid),
+            located_error!(SyntaxError::InvalidNumber(invalid.to_string()), None)
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn exactness_prefix() -> Result<()> {
+    assert_eq!(
+        tokenize("#e0.5 #e1.25e2 #e-1.5 #e+.0 #e1e-3 #e3 #e1/2 #i1/3 #i3 #i-2.5")?,
+        vec![
+            TokenData::Primitive(Primitive::Rational(1, 2)),
+            TokenData::Primitive(Primitive::Integer(125)),
+            TokenData::Primitive(Primitive::Rational(-3, 2)),
+            TokenData::Primitive(Primitive::Integer(0)),
+            TokenData::Primitive(Primitive::Rational(1, 1000)),
+            TokenData::Primitive(Primitive::Integer(3)),
+            TokenData::Primitive(Primitive::Rational(1, 2)),
+            TokenData::Primitive(Primitive::Real("0.3333333333333333".to_string())),
+            TokenData::Primitive(Primitive::Real("3.0".to_string())),
+            TokenData::Primitive(Primitive::Real("-2.5".to_string())),
+        ]
+    );
+    for inexact in &["#e+inf.0", "#e-nan.0", "#e1e20", "#e0.1e-20"] {
+        assert_eq!(
+            tokenize(inexact),
+            located_error!(SyntaxError::NoExactNumber(inexact.to_string()), None)
+        );
+    }
+    for invalid in &["#i+inf.0", "#ex", "#e"] {
+        assert_eq!(
+            tokenize(invalid),
+            located_error!(SyntaxError::InvalidNumber(invalid.to_string()), None)
+        );
+    }
     Ok(())
 }
 
@@ -625,15 +903,15 @@ fn delimiter() -> Result<()> {
         tokenize("\t(- \n4\r(+ 1 2)) ...)")?,
         vec![
             TokenData::LeftParen,
-            TokenData::Identifier(String::from("-")),
+            TokenData::Identifier("-".into()),
             TokenData::Primitive(Primitive::Integer(4)),
             TokenData::LeftParen,
-            TokenData::Identifier(String::from("+")),
+            TokenData::Identifier("+".into()),
             TokenData::Primitive(Primitive::Integer(1)),
             TokenData::Primitive(Primitive::Integer(2)),
             TokenData::RightParen,
             TokenData::RightParen,
-            TokenData::Identifier(String::from("...")),
+            TokenData::Identifier("...".into()),
             TokenData::RightParen,
         ]
     );
@@ -645,8 +923,22 @@ fn comment() -> Result<()> {
     assert_eq!(
         tokenize("abcd;+-12\n 12;dew\r34")?,
         vec![
-            TokenData::Identifier(String::from("abcd")),
+            TokenData::Identifier("abcd".into()),
+            TokenData::Primitive(Primitive::Integer(12)),
+            TokenData::Primitive(Primitive::Integer(34))
+        ]
+    );
+    let mut lexer = Lexer::from_char_stream("abcd;+-12\n 12;dew\r34".chars());
+    lexer.set_keep_comments(true);
+    assert_eq!(
+        lexer
+            .map(|token| token.map(|token| token.data))
+            .collect::<Result<Vec<_>>>()?,
+        vec![
+            TokenData::Identifier("abcd".into()),
+            TokenData::Comment(";+-12".to_string()),
             TokenData::Primitive(Primitive::Integer(12)),
+            TokenData::Comment(";dew".to_string()),
             TokenData::Primitive(Primitive::Integer(34))
         ]
     );
@@ -664,25 +956,25 @@ fn temp() -> Result<()> {
         )?,
         vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-syntax".to_string()),
-            TokenData::Identifier("begin".to_string()),
+            TokenData::Identifier("define-syntax".into()),
+            TokenData::Identifier("begin".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("syntax-rules".to_string()),
+            TokenData::Identifier("syntax-rules".into()),
             TokenData::LeftParen,
             TokenData::RightParen,
             TokenData::LeftParen,
             TokenData::LeftParen,
-            TokenData::Identifier("begin".to_string()),
-            TokenData::Identifier("exp".to_string()),
-            TokenData::Identifier("...".to_string()),
+            TokenData::Identifier("begin".into()),
+            TokenData::Identifier("exp".into()),
+            TokenData::Identifier("...".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
             TokenData::LeftParen,
-            TokenData::Identifier("lambda".to_string()),
+            TokenData::Identifier("lambda".into()),
             TokenData::LeftParen,
             TokenData::RightParen,
-            TokenData::Identifier("exp".to_string()),
-            TokenData::Identifier("...".to_string()),
+            TokenData::Identifier("exp".into()),
+            TokenData::Identifier("...".into()),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::RightParen,
@@ -4,15 +4,17 @@ use std::{
 };
 
 use super::error::SyntaxError;
-use super::{pair::*, Datum, DatumBody, Primitive};
+use super::{pair::*, written_symbol, Datum, DatumBody, Primitive};
 use crate::error::*;
+use crate::symbol::Symbol;
 use either::Either;
 use itertools::Itertools;
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserDefinedTransformer {
-    pub ellipsis: Option<String>,
-    pub literals: HashSet<String>,
+    pub ellipsis: Option<Symbol>,
+    pub literals: HashSet<Symbol>,
     pub rules: Vec<(SyntaxPattern, SyntaxTemplate)>,
 }
 
@@ -36,12 +38,13 @@ impl UserDefinedTransformer {
 }
 
 #[derive(PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SyntaxPatternBody {
     Underscore,
     Ellipsis,
     Pair(Box<GenericPair<SyntaxPattern>>),
     Vector(Vec<SyntaxPattern>),
-    Identifier(String),
+    Identifier(Symbol),
     Primitive(Primitive),
 }
 
@@ -54,8 +57,8 @@ impl Display for SyntaxPatternBody {
             SyntaxPatternBody::Ellipsis => write!(f, "..."),
             SyntaxPatternBody::Pair(p) => write!(f, "{}", p),
             SyntaxPatternBody::Vector(v) => write!(f, "#({})", v.iter().join(" ")),
-            SyntaxPatternBody::Identifier(i) => write!(f, "{}", i),
-            SyntaxPatternBody::Primitive(p) => write!(f, "{}", p),
+            SyntaxPatternBody::Identifier(i) => write!(f, "{}", written_symbol(i)),
+            SyntaxPatternBody::Primitive(p) => write!(f, "{}", p.written()),
         }
     }
 }
@@ -72,11 +75,11 @@ impl SyntaxPattern {
         pattern_index: usize,
         datum_index: usize,
         depth: usize,
-        patterns: &Vec<SyntaxPattern>,
-        datums: &Vec<Datum>,
-        pattern_literals: &HashSet<String>,
-        substitutions: &mut HashMap<String, (Datum, Vec<Datum>)>,
-        multi_matches: Option<SyntaxPattern>,
+        patterns: &[&SyntaxPattern],
+        datums: &[&Datum],
+        pattern_literals: &HashSet<Symbol>,
+        substitutions: &mut HashMap<Symbol, (Datum, Vec<Datum>)>,
+        multi_matches: Option<&SyntaxPattern>,
     ) -> Result<bool, SchemeError> {
         Ok(
             match (patterns.get(pattern_index), datums.get(datum_index)) {
@@ -127,7 +130,7 @@ impl SyntaxPattern {
                                     datums,
                                     pattern_literals,
                                     substitutions,
-                                    Some(multi_match_pattern.clone()),
+                                    Some(multi_match_pattern),
                                 )? {
                                     true
                                 } else {
@@ -144,7 +147,7 @@ impl SyntaxPattern {
                                 }
                             } else {
                                 return located_error!(
-                                    SyntaxError::UnexpectedPattern(sub_pattern.clone()),
+                                    SyntaxError::UnexpectedPattern((*sub_pattern).clone()),
                                     sub_pattern.location
                                 );
                             }
@@ -169,7 +172,7 @@ impl SyntaxPattern {
                             datums,
                             pattern_literals,
                             substitutions,
-                            Some(sub_pattern.clone()),
+                            Some(sub_pattern),
                         )?,
                     }
                 }
@@ -182,8 +185,8 @@ impl SyntaxPattern {
         &self,
         datum: &Datum,
         depth: usize,
-        pattern_literals: &HashSet<String>,
-        substitutions: &mut HashMap<String, (Datum, Vec<Datum>)>,
+        pattern_literals: &HashSet<Symbol>,
+        substitutions: &mut HashMap<Symbol, (Datum, Vec<Datum>)>,
     ) -> Result<bool, SchemeError> {
         // println!(
         //     "{:indent$}matching '{}' with {}",
@@ -200,8 +203,8 @@ impl SyntaxPattern {
                     0,
                     0,
                     depth + 1,
-                    &pattern_pair.iter().cloned().collect(),
-                    &datum_pair.iter().cloned().collect(),
+                    &pattern_pair.iter().collect::<Vec<_>>(),
+                    &datum_pair.iter().collect::<Vec<_>>(),
                     pattern_literals,
                     substitutions,
                     None,
@@ -226,8 +229,8 @@ impl SyntaxPattern {
                     0,
                     0,
                     depth + 1,
-                    &sub_patterns,
-                    &sub_data,
+                    &sub_patterns.iter().collect::<Vec<_>>(),
+                    &sub_data.iter().collect::<Vec<_>>(),
                     pattern_literals,
                     substitutions,
                     None,
@@ -274,19 +277,24 @@ impl From<GenericPair<SyntaxPattern>> for SyntaxPattern {
 pub type SyntaxPattern = Located<SyntaxPatternBody>;
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SyntaxTemplateElement(pub SyntaxTemplate, pub bool);
 
 impl Display for SyntaxTemplateElement {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{} {}", self.0, if self.1 { "..." } else { "" })
+        match self.1 {
+            true => write!(f, "{} ...", self.0),
+            false => write!(f, "{}", self.0),
+        }
     }
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SyntaxTemplateBody {
     Pair(Box<GenericPair<SyntaxTemplateElement>>),
     Vector(Vec<SyntaxTemplateElement>),
-    Identifier(String),
+    Identifier(Symbol),
     Primitive(Primitive),
     Ellipsis,
 }
@@ -297,8 +305,8 @@ impl Display for SyntaxTemplateBody {
             SyntaxTemplateBody::Ellipsis => write!(f, "..."),
             SyntaxTemplateBody::Pair(p) => write!(f, "{}", p),
             SyntaxTemplateBody::Vector(v) => write!(f, "#({})", v.iter().join(" ")),
-            SyntaxTemplateBody::Identifier(i) => write!(f, "{}", i),
-            SyntaxTemplateBody::Primitive(p) => write!(f, "{}", p),
+            SyntaxTemplateBody::Identifier(i) => write!(f, "{}", written_symbol(i)),
+            SyntaxTemplateBody::Primitive(p) => write!(f, "{}", p.written()),
         }
     }
 }
@@ -308,29 +316,28 @@ pub type SyntaxTemplate = Located<SyntaxTemplateBody>;
 impl SyntaxTemplate {
     pub fn substitude<'a>(
         &self,
-        substitutions: &HashMap<String, (Datum, Vec<Datum>)>,
+        substitutions: &HashMap<Symbol, (Datum, Vec<Datum>)>,
     ) -> Result<Vec<Datum>, SchemeError> {
         let location = self.location;
         match &self.data {
             SyntaxTemplateBody::Pair(list) => {
                 let mut substituted_pair_items = vec![];
-                for pair_item in list.clone().into_pair_iter() {
-                    match pair_item {
-                        PairIterItem::Proper(template_element) => substituted_pair_items.extend(
-                            SyntaxTemplate::substitute_template_element(
-                                &template_element,
-                                substitutions,
-                            )?,
-                        ),
-                        PairIterItem::Improper(SyntaxTemplateElement(last, false)) => {
-                            substituted_pair_items.extend(last.substitude(substitutions)?)
-                        }
-                        _ => {
-                            return error!(SyntaxError::UnexpectedDatum(
-                                DatumBody::Symbol("...".to_string()).locate(self.location)
-                            ))
-                        }
+                for template_element in list.iter() {
+                    substituted_pair_items.extend(SyntaxTemplate::substitute_template_element(
+                        template_element,
+                        substitutions,
+                    )?)
+                }
+                match list.last_cdr() {
+                    Some(SyntaxTemplateElement(last, false)) => {
+                        substituted_pair_items.extend(last.substitude(substitutions)?)
+                    }
+                    Some(_) => {
+                        return error!(SyntaxError::UnexpectedDatum(
+                            DatumBody::Symbol("...".into()).locate(self.location)
+                        ))
                     }
+                    None => (),
                 }
                 let substituded_list = substituted_pair_items.into_iter().collect();
 
@@ -363,18 +370,16 @@ impl SyntaxTemplate {
 
     fn substitude_ellipsis_item(
         template: &SyntaxTemplate,
-        substitutions: &HashMap<String, (Datum, Vec<Datum>)>,
+        substitutions: &HashMap<Symbol, (Datum, Vec<Datum>)>,
         item_index: usize,
     ) -> Result<Option<Datum>, SchemeError> {
         Ok(match &template.data {
             SyntaxTemplateBody::Pair(list) => {
                 let mut new_list_elements = Vec::new();
-                for pair_item in list.clone().into_pair_iter() {
-                    match Self::substitude_ellipsis_item(
-                        &pair_item.get_inside().0,
-                        substitutions,
-                        item_index,
-                    )? {
+                let improper = list.last_cdr().map(PairIterItem::Improper);
+                for pair_item in list.iter().map(PairIterItem::Proper).chain(improper) {
+                    let SyntaxTemplateElement(sub_template, _) = pair_item.get_inside();
+                    match Self::substitude_ellipsis_item(sub_template, substitutions, item_index)? {
                         Some(sub_datum) => {
                             new_list_elements.push(pair_item.replace_inside(sub_datum))
                         }
@@ -422,7 +427,7 @@ impl SyntaxTemplate {
 
     fn substitute_template_element<'a>(
         template_element: &SyntaxTemplateElement,
-        substitutions: &'a HashMap<String, (Datum, Vec<Datum>)>,
+        substitutions: &'a HashMap<Symbol, (Datum, Vec<Datum>)>,
     ) -> Result<Vec<Datum>, SchemeError> {
         match template_element {
             SyntaxTemplateElement(sub_template, true) => {
@@ -504,6 +509,16 @@ impl Display for Transformer {
     }
 }
 
+/// what an identifier denotes where a form is transformed: a keyword of a macro, or a variable
+/// bound by a procedure, which shadows the keywords of special forms and macros of outer scopes
+///
+/// Expansions are not hygienic, the keywords they introduce are shadowed at the use of the macro.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxBinding {
+    Keyword(Transformer),
+    Variable,
+}
+
 impl Transformer {
     pub fn transform(&self, keyword: &str, datum: Datum) -> Result<Datum, SchemeError> {
         match self {
@@ -12,6 +12,8 @@ mod parser;
 pub use parser::*;
 mod datum;
 pub use datum::*;
+pub mod dump;
+pub mod visit;
 
 use crate::error::SchemeError;
 pub mod error;
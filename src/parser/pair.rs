@@ -1,24 +1,71 @@
-use crate::error::*;
+use super::error::SyntaxError;
+use crate::{error::*, stack};
 use either::Either;
-use std::{fmt::Display, iter::FromIterator, mem};
+use std::{fmt::Display, iter::FromIterator, mem, ptr};
 // r7rs 6.4. Pairs and lists
 
 // Some(T, T) for pair
 // Some(T, T <another pair> )) for proper list
 // Empty for empty list
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GenericPair<T> {
     Some(T, T),
     Empty,
 }
 
+// cloning recurses once per level of nesting of lists, as deep as the parser allows
+impl<T: Clone> Clone for GenericPair<T> {
+    fn clone(&self) -> Self {
+        stack::guard(|| match self {
+            GenericPair::Some(car, cdr) => GenericPair::Some(car.clone(), cdr.clone()),
+            GenericPair::Empty => GenericPair::Empty,
+        })
+    }
+}
+
+// comparing recurses once per level of nesting as well
+impl<T: PartialEq> PartialEq for GenericPair<T> {
+    fn eq(&self, other: &Self) -> bool {
+        stack::guard(|| match (self, other) {
+            (GenericPair::Some(car, cdr), GenericPair::Some(other_car, other_cdr)) => {
+                car == other_car && cdr == other_cdr
+            }
+            (GenericPair::Empty, GenericPair::Empty) => true,
+            _ => false,
+        })
+    }
+}
+
 impl<T> Default for GenericPair<T> {
     fn default() -> Self {
         GenericPair::Empty
     }
 }
 
+// dropping recurses once per level of nesting of lists as well, which can be as deep as the
+// program builds them
+impl<T> Drop for GenericPair<T> {
+    fn drop(&mut self) {
+        if let Some(parts) = mem::take(self).into_parts() {
+            stack::guard(|| drop(parts))
+        }
+    }
+}
+
+impl<T> GenericPair<T> {
+    /// the car and the cdr, which patterns cannot move out of a pair as it implements `Drop`
+    pub fn into_parts(self) -> Option<(T, T)> {
+        let pair = mem::ManuallyDrop::new(self);
+        match &*pair {
+            // the pair is forgotten, so that car and cdr are only dropped by the new owner
+            GenericPair::Some(car, cdr) => Some(unsafe { (ptr::read(car), ptr::read(cdr)) }),
+            GenericPair::Empty => None,
+        }
+    }
+}
+
 pub trait Pairable: From<GenericPair<Self>> {
     fn either_pair_mut(&mut self) -> Either<&mut GenericPair<Self>, &mut Self>;
     fn either_pair_ref(&self) -> Either<&GenericPair<Self>, &Self>;
@@ -140,15 +187,13 @@ pub enum PairPopItem<T> {
 
 impl<T: Pairable> GenericPair<T> {
     pub fn pop(&mut self) -> Option<PairPopItem<T>> {
-        match mem::take(self) {
-            GenericPair::Some(car, cdr) => match cdr.into_pair() {
-                Either::Left(mut pair) => {
-                    mem::swap(self, &mut pair);
-                    Some(PairPopItem::Proper(car))
-                }
-                Either::Right(cdr) => Some(PairPopItem::Improper(car, cdr)),
-            },
-            GenericPair::Empty => None,
+        let (car, cdr) = mem::take(self).into_parts()?;
+        match cdr.into_pair() {
+            Either::Left(mut pair) => {
+                mem::swap(self, &mut pair);
+                Some(PairPopItem::Proper(car))
+            }
+            Either::Right(cdr) => Some(PairPopItem::Improper(car, cdr)),
         }
     }
 
@@ -156,7 +201,10 @@ impl<T: Pairable> GenericPair<T> {
         self.pop()
             .map(|item| match item {
                 PairPopItem::Proper(t) => Ok(t),
-                PairPopItem::Improper(_, _) => todo!(),
+                PairPopItem::Improper(_, _) => error!(SyntaxError::ExpectSomething(
+                    "list".to_string(),
+                    "improper list".to_string()
+                )),
             })
             .transpose()
     }
@@ -181,7 +229,10 @@ impl<T: Pairable> GenericPair<T> {
     ) -> Result<Self, SchemeError> {
         match T::from_pair_iter(iter.into_iter()).into_pair() {
             Either::Left(pair) => Ok(pair),
-            Either::Right(_) => todo!(),
+            Either::Right(_) => error!(SyntaxError::ExpectSomething(
+                "list".to_string(),
+                "non-list".to_string()
+            )),
         }
     }
 
@@ -205,18 +256,18 @@ impl<T: Pairable> GenericPair<T> {
         self,
         f: &mut impl FnMut(T) -> Result<Target, SchemeError>,
     ) -> Result<GenericPair<Target>, SchemeError> {
-        Ok(match self {
-            GenericPair::Some(car, cdr) => GenericPair::Some(
+        Ok(match self.into_parts() {
+            Some((car, cdr)) => GenericPair::Some(
                 match car.into_pair() {
-                    Either::Left(pair) => Target::from(pair.map_ok(f)?),
+                    Either::Left(pair) => Target::from(stack::guard(|| pair.map_ok(f))?),
                     Either::Right(value) => f(value)?,
                 },
                 match cdr.into_pair() {
-                    Either::Left(pair) => Target::from(pair.map_ok(f)?),
+                    Either::Left(pair) => Target::from(stack::guard(|| pair.map_ok(f))?),
                     Either::Right(value) => f(value)?,
                 },
             ),
-            GenericPair::Empty => GenericPair::Empty,
+            None => GenericPair::Empty,
         })
     }
 
@@ -227,11 +278,11 @@ impl<T: Pairable> GenericPair<T> {
         Ok(match self {
             GenericPair::Some(car, cdr) => GenericPair::Some(
                 match car.either_pair_ref() {
-                    Either::Left(pair) => Target::from(pair.map_ok_ref(f)?),
+                    Either::Left(pair) => Target::from(stack::guard(|| pair.map_ok_ref(f))?),
                     Either::Right(value) => f(value)?,
                 },
                 match cdr.either_pair_ref() {
-                    Either::Left(pair) => Target::from(pair.map_ok_ref(f)?),
+                    Either::Left(pair) => Target::from(stack::guard(|| pair.map_ok_ref(f))?),
                     Either::Right(value) => f(value)?,
                 },
             ),
@@ -287,34 +338,37 @@ impl<'a, T: Pairable> Iterator for IterMut<'a, T> {
     }
 }
 
+// formatting recurses once per level of nesting of the cars
 impl<T: Display + Pairable> Display for GenericPair<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "(")?;
-        let mut current_value = self;
-        loop {
-            match current_value {
-                GenericPair::Some(car, cdr) => {
-                    write!(f, "{}", car)?;
-                    match cdr.either_pair_ref() {
-                        Either::Left(pair) => {
-                            match pair {
-                                GenericPair::Some(_, _) => {
-                                    write!(f, " ")?;
+        stack::guard(|| {
+            write!(f, "(")?;
+            let mut current_value = self;
+            loop {
+                match current_value {
+                    GenericPair::Some(car, cdr) => {
+                        write!(f, "{}", car)?;
+                        match cdr.either_pair_ref() {
+                            Either::Left(pair) => {
+                                match pair {
+                                    GenericPair::Some(_, _) => {
+                                        write!(f, " ")?;
+                                    }
+                                    GenericPair::Empty => (),
                                 }
-                                GenericPair::Empty => (),
+                                current_value = pair;
+                            }
+                            Either::Right(value) => {
+                                write!(f, " . {}", value)?;
+                                break;
                             }
-                            current_value = pair;
-                        }
-                        Either::Right(value) => {
-                            write!(f, " . {}", value)?;
-                            break;
                         }
                     }
-                }
-                GenericPair::Empty => break,
-            };
-        }
-        write!(f, ")")
+                    GenericPair::Empty => break,
+                };
+            }
+            write!(f, ")")
+        })
     }
 }
 
@@ -3,14 +3,20 @@ use super::{
     lexer::Lexer,
     pair::GenericPair,
     pair::{PairIterItem, Pairable},
-    Datum, DatumBody, DatumList, Result, SyntaxTemplateElement, Transformer,
+    written_symbol, Datum, DatumBody, DatumList, Result, SyntaxBinding, SyntaxTemplateElement,
+    Transformer,
 };
 use crate::error::ToLocated;
 use crate::{environment::LexicalScope, error::*, parser::lexer::Token};
 use crate::{interpreter::error::LogicError, parser::lexer::TokenData};
+use crate::{stack, symbol::Symbol};
 use fmt::Display;
 use itertools::Itertools;
-use std::{collections::HashSet, fmt, mem, rc::Rc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, mem,
+    rc::Rc,
+};
 use std::{
     iter::{repeat, FromIterator, Iterator, Peekable},
     path::PathBuf,
@@ -23,12 +29,14 @@ use super::{
     SyntaxTemplateBody, UserDefinedTransformer,
 };
 
-pub type ParseResult = Result<Option<(Statement, Option<[u32; 2]>)>>;
+pub type ParseResult = Result<Option<(Statement, Option<Location>)>>;
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LibraryDefinition(pub LibraryName, pub Vec<Located<LibraryDeclaration>>);
 impl ToLocated for LibraryDefinition {}
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LibraryDeclaration {
     ImportDeclaration(Located<ImportDeclaration>),
     Export(Vec<Located<ExportSpec>>),
@@ -40,6 +48,7 @@ impl ToLocated for LibraryDeclaration {}
 // ⟨library name⟩ is a list whose members are identifiers and
 // exact non-negative integers.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LibraryNameElement {
     Identifier(String),
     Integer(u32),
@@ -69,6 +78,7 @@ impl From<u32> for LibraryNameElement {
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LibraryName(pub Vec<LibraryNameElement>);
 
 #[macro_export]
@@ -139,17 +149,20 @@ impl LibraryName {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExportSpec {
     Direct(String),
     Rename(String, String),
 }
 impl ToLocated for ExportSpec {}
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImportDeclaration(pub Vec<ImportSet>);
 
 impl ToLocated for ImportDeclaration {}
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statement {
     ImportDeclaration(Located<ImportDeclaration>),
     Definition(Definition),
@@ -159,7 +172,7 @@ pub enum Statement {
 }
 
 impl Statement {
-    pub fn location(&self) -> Option<[u32; 2]> {
+    pub fn location(&self) -> Option<Location> {
         match self {
             Statement::ImportDeclaration(located) => located.location,
             Statement::Definition(located) => located.location,
@@ -168,15 +181,22 @@ impl Statement {
             Statement::LibraryDefinition(located) => located.location,
         }
     }
+    /// the expression, or an error located at the definition or declaration given instead
     pub fn expect_expression(self) -> Result<Expression> {
         let location = self.location();
-        match self {
-            Self::Expression(expression) => Ok(expression),
-            _ => located_error!(
-                SyntaxError::ExpectSomething("expression".to_string(), "other".to_string()),
-                location
-            ),
-        }
+        let form = match self {
+            Self::Expression(expression) => return Ok(expression),
+            Self::Definition(definition) => {
+                return located_error!(
+                    SyntaxError::DefinitionInExpressionContext(definition.data.0),
+                    location
+                )
+            }
+            Self::ImportDeclaration(_) => "import",
+            Self::SyntaxDefinition(_) => "define-syntax",
+            Self::LibraryDefinition(_) => "define-library",
+        };
+        located_error!(SyntaxError::NotAnExpression(form.to_string()), location)
     }
 }
 
@@ -211,12 +231,14 @@ impl Into<Statement> for Located<LibraryDefinition> {
 }
 
 #[derive(PartialEq, Debug, Clone)]
-pub struct DefinitionBody(pub String, pub Expression);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DefinitionBody(pub Symbol, pub Expression);
 
 impl ToLocated for DefinitionBody {}
 
 #[derive(PartialEq, Debug, Clone)]
-pub struct SyntaxDefBody(pub String, pub UserDefinedTransformer);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyntaxDefBody(pub Symbol, pub UserDefinedTransformer);
 
 impl ToLocated for SyntaxDefBody {}
 
@@ -225,6 +247,7 @@ pub type SyntaxDef = Located<SyntaxDefBody>;
 pub type ImportSet = Located<ImportSetBody>;
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImportSetBody {
     Direct(Located<LibraryName>),
     Only(Box<ImportSet>, Vec<String>),
@@ -235,13 +258,38 @@ pub enum ImportSetBody {
 
 impl ToLocated for ImportSetBody {}
 
+impl ImportSetBody {
+    // the library which an import set is eventually based on
+    pub fn library_name(&self) -> &LibraryName {
+        match self {
+            ImportSetBody::Direct(name) => name,
+            ImportSetBody::Only(inner, _)
+            | ImportSetBody::Except(inner, _)
+            | ImportSetBody::Prefix(inner, _)
+            | ImportSetBody::Rename(inner, _) => inner.library_name(),
+        }
+    }
+}
+
+// position of a variable bound by an enclosing procedure: the number of frames to walk up from the
+// current one, and the index of the binding in that frame
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LexicalAddress {
+    pub depth: usize,
+    pub slot: usize,
+}
+
 pub type Expression = Located<ExpressionBody>;
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExpressionBody {
-    Symbol(String),
+    Symbol(Symbol),
+    LocalVariable(Symbol, LexicalAddress), // resolved by interpreter::resolve_statement
     Primitive(Primitive),
     Period,
-    Assignment(String, Box<Expression>),
+    Assignment(Symbol, Box<Expression>),
+    LocalAssignment(Symbol, LexicalAddress, Box<Expression>),
     Procedure(SchemeProcedure),
     ProcedureCall(Box<Expression>, Vec<Expression>),
     Conditional(Box<(Expression, Expression, Option<Expression>)>),
@@ -270,8 +318,9 @@ impl From<Primitive> for Expression {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParameterFormalsBody {
-    Name(String),                             // (lambda x ...) or (define (f . x) ...)
+    Name(Symbol),                             // (lambda x ...) or (define (f . x) ...)
     Pair(Box<GenericPair<ParameterFormals>>), // (lambda (...) ...) or (define (f ...) ...)
 }
 
@@ -280,7 +329,7 @@ pub type ParameterFormals = Located<ParameterFormalsBody>;
 impl ToLocated for ParameterFormalsBody {}
 
 impl ParameterFormals {
-    pub fn new_non_located(parameters: impl Iterator<Item = String>, last: Option<String>) -> Self {
+    pub fn new_non_located(parameters: impl Iterator<Item = Symbol>, last: Option<Symbol>) -> Self {
         ParameterFormals::from_pair_iter(
             parameters
                 .map(|s| PairIterItem::Proper(ParameterFormalsBody::Name(s).no_locate()))
@@ -290,7 +339,7 @@ impl ParameterFormals {
         )
     }
 
-    pub fn split(self) -> Result<(Vec<String>, Option<String>)> {
+    pub fn split(self) -> Result<(Vec<Symbol>, Option<Symbol>)> {
         Ok(match self.data {
             ParameterFormalsBody::Name(name) => (Vec::new(), Some(name)),
             ParameterFormalsBody::Pair(pair) => {
@@ -325,7 +374,7 @@ impl ParameterFormals {
         (fixed, variadic)
     }
 
-    pub fn as_name(&self) -> String {
+    pub fn as_name(&self) -> Symbol {
         match &self.data {
             ParameterFormalsBody::Name(name) => name.clone(),
             ParameterFormalsBody::Pair(_) => {
@@ -382,7 +431,7 @@ macro_rules! param_fixed {
         use $crate::parser::ParameterFormals;
         use $crate::parser::pair::GenericPair;
         use $crate::list;
-        ParameterFormals::from(list![$(ParameterFormalsBody::Name($x.to_string()).no_locate()),*])
+        ParameterFormals::from(list![$(ParameterFormalsBody::Name($crate::symbol::Symbol::from($x)).no_locate()),*])
 }};
     () => {
         ParameterFormals::from(list![])
@@ -395,7 +444,7 @@ macro_rules! append_variadic_param {
         let mut pair = $fixed;
         use $crate::error::ToLocated;
         use $crate::parser::ParameterFormalsBody;
-        pair.append(ParameterFormalsBody::Name($append.to_string()).no_locate())?;
+        pair.append(ParameterFormalsBody::Name($crate::symbol::Symbol::from($append)).no_locate())?;
         pair
     }};
 }
@@ -417,16 +466,13 @@ impl Display for ParameterFormalsBody {
 
 #[test]
 fn test_parameter_formals() -> Result<()> {
-    let test_cases = vec![
-        (
-            vec!["x1".to_string(), "x2".to_string(), "x3".to_string()],
-            Some("x".to_string()),
-        ),
+    let test_cases: Vec<(Vec<Symbol>, Option<Symbol>)> = vec![
         (
-            vec!["x1".to_string(), "x2".to_string(), "x3".to_string()],
-            None,
+            vec!["x1".into(), "x2".into(), "x3".into()],
+            Some("x".into()),
         ),
-        (vec![], Some("x".to_string())),
+        (vec!["x1".into(), "x2".into(), "x3".into()], None),
+        (vec![], Some("x".into())),
         (vec![], None),
     ];
 
@@ -440,15 +486,17 @@ fn test_parameter_formals() -> Result<()> {
 }
 
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SchemeProcedure(
     pub ParameterFormals,
     pub Vec<Definition>,
     pub Vec<Expression>,
+    pub Option<Symbol>, // name, for procedures defined by (define (name ...) ...)
 );
 
 impl SchemeProcedure {
-    pub fn get_body_location(&self) -> Option<[u32; 2]> {
-        let SchemeProcedure(_, defs, exprs) = self;
+    pub fn get_body_location(&self) -> Option<Location> {
+        let SchemeProcedure(_, defs, exprs, _) = self;
         defs.first()
             .and_then(|d| d.location)
             .or(exprs.first().and_then(|e| e.location))
@@ -462,11 +510,249 @@ impl fmt::Display for SchemeProcedure {
     }
 }
 
+// `(head items...)` from the list `(items...)`
+fn with_head(head: &str, list: String) -> String {
+    match list.as_str() {
+        "()" => format!("({})", head),
+        _ => format!("({} {}", head, &list[1..]),
+    }
+}
+
+fn write_body(
+    f: &mut fmt::Formatter,
+    definitions: &[Definition],
+    expressions: &[Expression],
+) -> fmt::Result {
+    for definition in definitions {
+        write!(f, " {}", definition)?;
+    }
+    for expression in expressions {
+        write!(f, " {}", expression)?;
+    }
+    Ok(())
+}
+
+// the external representations of the syntax tree are programs which are read back as the same
+// tree, like the expansion printed by `--expand`
+impl Display for ExpressionBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpressionBody::Symbol(name) | ExpressionBody::LocalVariable(name, _) => {
+                write!(f, "{}", written_symbol(name))
+            }
+            ExpressionBody::Primitive(primitive) => write!(f, "{}", primitive.written()),
+            ExpressionBody::Period => write!(f, "."),
+            ExpressionBody::Assignment(name, value)
+            | ExpressionBody::LocalAssignment(name, _, value) => {
+                write!(f, "(set! {} {})", written_symbol(name), value)
+            }
+            ExpressionBody::Procedure(SchemeProcedure(formals, definitions, expressions, _)) => {
+                write!(f, "(lambda {}", formals)?;
+                write_body(f, definitions, expressions)?;
+                write!(f, ")")
+            }
+            ExpressionBody::ProcedureCall(procedure, arguments) => {
+                write!(f, "({}", procedure)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+            ExpressionBody::Conditional(conditional) => match conditional.as_ref() {
+                (test, consequent, None) => write!(f, "(if {} {})", test, consequent),
+                (test, consequent, Some(alternative)) => {
+                    write!(f, "(if {} {} {})", test, consequent, alternative)
+                }
+            },
+            ExpressionBody::Quote(datum) => write!(f, "'{}", datum.written()),
+            ExpressionBody::Datum(datum) => write!(f, "{}", datum.written()),
+        }
+    }
+}
+
+impl Display for DefinitionBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DefinitionBody(name, value) = self;
+        let name = written_symbol(name);
+        match &value.data {
+            // procedures keep their names with the (define (name ...) ...) form
+            ExpressionBody::Procedure(SchemeProcedure(
+                formals,
+                definitions,
+                expressions,
+                Some(procedure_name),
+            )) if written_symbol(procedure_name) == name => {
+                let header = match &formals.data {
+                    ParameterFormalsBody::Name(variadic) => format!("({} . {})", name, variadic),
+                    ParameterFormalsBody::Pair(_) => with_head(&name, formals.to_string()),
+                };
+                write!(f, "(define {}", header)?;
+                write_body(f, definitions, expressions)?;
+                write!(f, ")")
+            }
+            _ => write!(f, "(define {} {})", name, value),
+        }
+    }
+}
+
+impl Display for SyntaxDefBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let SyntaxDefBody(keyword, transformer) = self;
+        let keyword = written_symbol(keyword);
+        write!(f, "(define-syntax {} (syntax-rules", keyword)?;
+        if let Some(ellipsis) = &transformer.ellipsis {
+            write!(f, " {}", written_symbol(ellipsis))?;
+        }
+        write!(
+            f,
+            " ({})",
+            transformer
+                .literals
+                .iter()
+                .sorted()
+                .map(|literal| written_symbol(literal))
+                .join(" ")
+        )?;
+        for (pattern, template) in &transformer.rules {
+            write!(
+                f,
+                " ({} {})",
+                with_head(&keyword, pattern.to_string()),
+                template
+            )?;
+        }
+        write!(f, "))")
+    }
+}
+
+impl Display for ImportSetBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportSetBody::Direct(name) => write!(f, "{}", name),
+            ImportSetBody::Only(inner, names) => write!(f, "(only {} {})", inner, names.join(" ")),
+            ImportSetBody::Except(inner, names) => {
+                write!(f, "(except {} {})", inner, names.join(" "))
+            }
+            ImportSetBody::Prefix(inner, prefix) => write!(f, "(prefix {} {})", inner, prefix),
+            ImportSetBody::Rename(inner, renames) => write!(
+                f,
+                "(rename {} {})",
+                inner,
+                renames
+                    .iter()
+                    .map(|(from, to)| format!("({} {})", from, to))
+                    .join(" ")
+            ),
+        }
+    }
+}
+
+impl Display for ImportDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(import {})", self.0.iter().join(" "))
+    }
+}
+
+impl Display for ExportSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportSpec::Direct(name) => write!(f, "{}", name),
+            ExportSpec::Rename(from, to) => write!(f, "(rename {} {})", from, to),
+        }
+    }
+}
+
+impl Display for LibraryDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LibraryDeclaration::ImportDeclaration(declaration) => write!(f, "{}", declaration),
+            LibraryDeclaration::Export(specs) => write!(f, "(export {})", specs.iter().join(" ")),
+            LibraryDeclaration::Begin(statements) => {
+                write!(f, "(begin {})", statements.iter().join(" "))
+            }
+        }
+    }
+}
+
+impl Display for LibraryDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let LibraryDefinition(name, declarations) = self;
+        write!(f, "(define-library {}", name)?;
+        for declaration in declarations {
+            write!(f, " {}", declaration)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::ImportDeclaration(declaration) => write!(f, "{}", declaration),
+            Statement::Definition(definition) => write!(f, "{}", definition),
+            Statement::SyntaxDefinition(definition) => write!(f, "{}", definition),
+            Statement::Expression(expression) => write!(f, "{}", expression),
+            Statement::LibraryDefinition(library) => write!(f, "{}", library),
+        }
+    }
+}
+
+/// Parse a program, expanding its macros and derived forms like `let` and `cond` into the core
+/// forms which are evaluated. The statements keep the locations of the source, and printed they
+/// form a program which is read back as the same statements.
+pub fn expand_program(
+    char_stream: impl Iterator<Item = char>,
+    source: SourceId,
+) -> Result<Vec<Statement>> {
+    let mut lexer = Lexer::from_char_stream(char_stream);
+    lexer.set_source(source);
+    Parser::from_lexer(lexer).collect()
+}
+
+/// default limit of nested lists and vectors in a datum
+#[cfg(feature = "native")]
+pub const DEFAULT_MAX_NESTING: usize = 10_000;
+
+/// default limit of nested lists and vectors in a datum, lower without the `native` feature as
+/// the stack does not grow then
+#[cfg(not(feature = "native"))]
+pub const DEFAULT_MAX_NESTING: usize = 200;
+
 pub struct Parser<TokenIter: Iterator<Item = Result<Token>>> {
     pub current: Option<Token>,
     pub lexer: Peekable<TokenIter>,
-    pub syntax_env: Rc<LexicalScope<Transformer>>,
-    location: Option<[u32; 2]>,
+    pub syntax_env: Rc<LexicalScope<SyntaxBinding>>,
+    location: Option<Location>,
+    // lists and vectors being parsed
+    nesting: usize,
+    max_nesting: usize,
+    // lists and vectors left open by the last error, which are skipped when recovering from it
+    unclosed: Option<usize>,
+    mixed_bodies: bool,
+    features: Option<Rc<dyn Features>>,
+    // forms spliced by a top-level `cond-expand`, parsed before the rest of the input
+    pending: VecDeque<Datum>,
+}
+
+/// What the requirements of `cond-expand` are matched against, see `Parser::set_features`.
+pub trait Features {
+    /// whether `feature` is a feature identifier of the implementation
+    fn has_feature(&self, feature: &str) -> bool;
+    /// whether the library `name` can be imported
+    fn has_library(&self, name: &LibraryName) -> bool;
+}
+
+/// the feature identifiers of ruschm, returned by `(features)` unless the embedder adds others
+pub fn default_features() -> Vec<String> {
+    vec![
+        "r7rs".to_string(),
+        "exact-closed".to_string(),
+        "ruschm".to_string(),
+        format!("ruschm-{}", env!("CARGO_PKG_VERSION")),
+        std::env::consts::OS.to_string(),
+        std::env::consts::FAMILY.to_string(),
+        std::env::consts::ARCH.to_string(),
+    ]
 }
 
 impl<TokenIter: Iterator<Item = Result<Token>>> Iterator for Parser<TokenIter> {
@@ -480,8 +766,43 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Iterator for Parser<TokenIter> {
     }
 }
 
-fn create_syntax_binding() -> Rc<LexicalScope<Transformer>> {
-    thread_local! {static BINDINGS: Rc<LexicalScope<Transformer>> = {
+// keywords of the forms transformed by the parser itself
+const SPECIAL_FORMS: &[&str] = &[
+    "assert",
+    "cond-expand",
+    "define",
+    "define-library",
+    "define-syntax",
+    "if",
+    "import",
+    "lambda",
+    "quote",
+    "set!",
+];
+
+/// keywords of the special forms and of the derived forms like `let`, sorted
+pub fn syntactic_keywords() -> Vec<String> {
+    let bindings = create_syntax_binding();
+    let mut definitions = bindings.iter_local_definitions();
+    let mut keywords = SPECIAL_FORMS
+        .iter()
+        .map(|keyword| keyword.to_string())
+        .chain((&mut *definitions).map(|(keyword, _)| keyword.to_string()))
+        .collect::<Vec<_>>();
+    keywords.sort();
+    keywords
+}
+
+#[test]
+fn keywords() {
+    let keywords = syntactic_keywords();
+    assert!(keywords.iter().any(|keyword| keyword == "lambda"));
+    assert!(keywords.iter().any(|keyword| keyword == "let*"));
+    assert!(keywords.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+fn create_syntax_binding() -> Rc<LexicalScope<SyntaxBinding>> {
+    thread_local! {static BINDINGS: Rc<LexicalScope<SyntaxBinding>> = {
             let mut parser = Parser::from_lexer_primary_syntax(Lexer::from_char_stream(
                 include_str!("grammar.sld").chars(),
             ));
@@ -499,6 +820,12 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
             lexer: lexer.peekable(),
             syntax_env: Rc::new(LexicalScope::new()),
             location: None,
+            nesting: 0,
+            max_nesting: DEFAULT_MAX_NESTING,
+            unclosed: None,
+            mixed_bodies: false,
+            features: None,
+            pending: VecDeque::new(),
         }
     }
 
@@ -508,93 +835,165 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
             lexer: lexer.peekable(),
             syntax_env: create_syntax_binding(),
             location: None,
+            nesting: 0,
+            max_nesting: DEFAULT_MAX_NESTING,
+            unclosed: None,
+            mixed_bodies: false,
+            features: None,
+            pending: VecDeque::new(),
         }
     }
 
+    /// deeper nested data fail with `SyntaxError::TooDeeplyNested` instead of exhausting memory
+    pub fn set_max_nesting(&mut self, max_nesting: usize) {
+        self.max_nesting = max_nesting;
+    }
+
+    /// accept bodies where definitions follow expressions, as several Schemes do: the expressions
+    /// before a definition are evaluated in a `begin` with its initializer, so that the body keeps
+    /// the order of evaluation and the `letrec*` scope of internal definitions
+    pub fn set_mixed_bodies(&mut self, mixed_bodies: bool) {
+        self.mixed_bodies = mixed_bodies;
+    }
+
+    /// match the requirements of `cond-expand` against `features`, instead of the default
+    /// features without any library
+    pub fn set_features(&mut self, features: Rc<dyn Features>) {
+        self.features = Some(features);
+    }
+
     pub fn parse_current(
         &mut self,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<Option<Statement>> {
         Ok(match self.current_datum()? {
-            Some(datum) => Some(Self::transform_to_statement(datum, syntax_env)?),
+            Some(datum) => Some(self.transform_to_statement(datum, syntax_env)?),
             None => None,
         })
     }
 
     pub fn transform_to_statement(
+        &self,
         datum: Datum,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
+    ) -> Result<Statement> {
+        let location = datum.location;
+        // errors of the parts of a form missing or not located by themselves point at the form
+        stack::guard(|| self.transform_datum(datum, syntax_env))
+            .map_err(|error| error.or_locate(location))
+    }
+
+    fn transform_datum(
+        &self,
+        datum: Datum,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<Statement> {
         let location = datum.location;
         Ok(match datum.data {
             DatumBody::Primitive(p) => ExpressionBody::Primitive(p).locate(location).into(),
             DatumBody::Symbol(s) => ExpressionBody::Symbol(s).locate(location).into(),
             DatumBody::Pair(mut pair) => {
-                let first = pair.pop_proper()?;
+                let first = pair
+                    .pop_proper()
+                    .map_err(|error| error.or_locate(location))?;
                 match first {
                     None => return error!(SyntaxError::EmptyCall),
                     Some(first) => {
                         match &first.data {
-                            DatumBody::Symbol(keyword) => match keyword.as_str() {
-                                "define" => {
-                                    Self::transform_definition(pair.into_iter(), syntax_env)?
+                            // the keywords of special forms and macros are shadowed by variables
+                            DatumBody::Symbol(symbol)
+                                if !matches!(
+                                    syntax_env.get(symbol).as_deref(),
+                                    Some(SyntaxBinding::Variable)
+                                ) =>
+                            {
+                                match symbol.as_str() {
+                                    "define" => self
+                                        .transform_definition(pair.into_iter(), syntax_env)?
                                         .locate(datum.location)
-                                        .into()
-                                }
-                                "define-library" => {
-                                    Self::transform_library(pair.into_iter(), syntax_env)?
+                                        .into(),
+                                    "define-library" => self
+                                        .transform_library(pair.into_iter(), syntax_env)?
                                         .locate(datum.location)
-                                        .into()
-                                }
-                                "lambda" => Self::transform_lambda(pair.into_iter(), syntax_env)?
-                                    .locate(datum.location)
-                                    .into(),
-                                "if" => Self::transform_condition(pair.into_iter(), syntax_env)?
-                                    .locate(datum.location)
-                                    .into(),
-                                "import" => Self::transform_import_decl(pair.into_iter())?
-                                    .locate(datum.location)
-                                    .into(),
-                                "quote" => Self::transform_quote(pair.into_iter())?
-                                    .locate(datum.location)
-                                    .into(),
-                                "set!" => Self::transform_assignment(pair.into_iter(), syntax_env)?
-                                    .locate(datum.location)
-                                    .into(),
-                                "define-syntax" => {
-                                    Self::transform_syntax_definition(pair.into_iter(), syntax_env)?
+                                        .into(),
+                                    "lambda" => self
+                                        .transform_lambda(pair.into_iter(), syntax_env)?
                                         .locate(datum.location)
-                                        .into()
-                                }
-                                keyword => {
-                                    if let Some(transformer) =
-                                        syntax_env.get(&first.expect_symbol()?)
-                                    {
-                                        let remained = DatumBody::Pair(pair).locate(location);
-                                        let expanded_datum =
-                                            transformer.transform(keyword, remained)?;
-                                        Self::transform_to_statement(expanded_datum, syntax_env)?
-                                    } else {
-                                        Self::transform_procedure_call(
-                                            first,
-                                            pair.into_iter(),
-                                            syntax_env,
-                                        )?
+                                        .into(),
+                                    "if" => self
+                                        .transform_condition(pair.into_iter(), syntax_env)?
+                                        .locate(datum.location)
+                                        .into(),
+                                    "import" => Self::transform_import_decl(pair.into_iter())?
+                                        .locate(datum.location)
+                                        .into(),
+                                    "quote" => Self::transform_quote(pair.into_iter())?
+                                        .locate(datum.location)
+                                        .into(),
+                                    "set!" => self
+                                        .transform_assignment(pair.into_iter(), syntax_env)?
                                         .locate(datum.location)
-                                        .into()
+                                        .into(),
+                                    "assert" => self.transform_to_statement(
+                                        Self::assertion(pair.into_iter(), location)?,
+                                        syntax_env,
+                                    )?,
+                                    "cond-expand" => self.transform_to_statement(
+                                        Self::cond_expand_form(
+                                            self.cond_expand(pair.into_iter())?,
+                                            location,
+                                        ),
+                                        syntax_env,
+                                    )?,
+                                    "define-syntax" => Self::transform_syntax_definition(
+                                        pair.into_iter(),
+                                        syntax_env,
+                                    )?
+                                    .locate(datum.location)
+                                    .into(),
+                                    keyword => {
+                                        if let Some(SyntaxBinding::Keyword(transformer)) =
+                                            syntax_env.get(symbol).as_deref()
+                                        {
+                                            let remained = DatumBody::Pair(pair).locate(location);
+                                            let mut expanded_datum =
+                                                transformer.transform(keyword, remained)?;
+                                            // errors of the expansion itself, like those of the
+                                            // procedure it calls, are reported at the use of the
+                                            // macro rather than in its definition
+                                            expanded_datum.location = location;
+                                            if let DatumBody::Pair(expanded) =
+                                                &mut expanded_datum.data
+                                            {
+                                                if let GenericPair::Some(head, _) =
+                                                    expanded.as_mut()
+                                                {
+                                                    head.location = first.location.clone();
+                                                }
+                                            }
+                                            self.transform_to_statement(expanded_datum, syntax_env)?
+                                        } else {
+                                            self.transform_procedure_call(
+                                                first,
+                                                pair.into_iter(),
+                                                syntax_env,
+                                            )?
+                                            .locate(datum.location)
+                                            .into()
+                                        }
                                     }
                                 }
-                            },
-                            // Lambda expression
-                            _ => {
-                                Self::transform_procedure_call(first, pair.into_iter(), syntax_env)?
-                                    .locate(datum.location)
-                                    .into()
                             }
+                            // lambda expressions and variables
+                            _ => self
+                                .transform_procedure_call(first, pair.into_iter(), syntax_env)?
+                                .locate(datum.location)
+                                .into(),
                         }
                     }
                 }
             }
+            // vectors are self-evaluating like other data, their elements are not evaluated
             other => ExpressionBody::Datum(Datum {
                 data: other,
                 location,
@@ -605,16 +1004,12 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     }
 
     pub fn transform_to_expression(
+        &self,
         datum: Datum,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<Expression> {
-        match Self::transform_to_statement(datum, syntax_env)? {
-            Statement::Expression(expression) => Ok(expression),
-            _ => error!(SyntaxError::ExpectSomething(
-                "expression".to_string(),
-                "other statement".to_string(),
-            )),
-        }
+        self.transform_to_statement(datum, syntax_env)?
+            .expect_expression()
     }
 
     pub fn current_datum(&mut self) -> Result<Option<Datum>> {
@@ -632,22 +1027,46 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
                         location,
                     }
                     .into(),
-                    TokenData::LeftParen => Some(self.current_list_or_pair()?),
+                    TokenData::LeftParen => Some(self.nested(Self::current_list_or_pair)?),
                     TokenData::RightParen => {
                         return located_error!(SyntaxError::UnmatchedParentheses, location)
                     }
-                    TokenData::VecConsIntro => self.vector()?.into(),
-                    TokenData::Quote => {
-                        self.advance(1)?;
-                        self.parse_quoted()?
-                    }
-                    .into(),
+                    TokenData::VecConsIntro => self.nested(Self::vector)?.into(),
+                    TokenData::Quote => self
+                        .nested(|parser| parser.parse_abbreviation("quote"))?
+                        .into(),
+                    TokenData::Quasiquote => self
+                        .nested(|parser| parser.parse_abbreviation("quasiquote"))?
+                        .into(),
+                    TokenData::Unquote => self
+                        .nested(|parser| parser.parse_abbreviation("unquote"))?
+                        .into(),
+                    TokenData::UnquoteSplicing => self
+                        .nested(|parser| parser.parse_abbreviation("unquote-splicing"))?
+                        .into(),
                     other => return located_error!(SyntaxError::UnexpectedToken(other), location),
                 }),
             },
         }
     }
 
+    // parse a list or a vector one level deeper
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.nesting >= self.max_nesting {
+            return located_error!(
+                SyntaxError::TooDeeplyNested(self.max_nesting),
+                self.location
+            );
+        }
+        self.nesting += 1;
+        let result = stack::guard(|| parse(self));
+        if result.is_err() && self.unclosed.is_none() {
+            self.unclosed = Some(self.nesting);
+        }
+        self.nesting -= 1;
+        result
+    }
+
     pub fn unwrap_non_end<T>(op: Option<T>) -> Result<T> {
         op.ok_or(ErrorData::from(SyntaxError::UnexpectedEnd).no_locate())
     }
@@ -664,12 +1083,20 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
                         if encounter_period {
                             return located_error!(
                                 SyntaxError::UnexpectedToken(TokenData::Period),
-                                location.clone()
+                                *location
                             );
                         }
+                        // (. x) is not (x)
+                        if let DatumList::Empty = tail {
+                            return located_error!(SyntaxError::MisplacedPeriod, *location);
+                        }
                         encounter_period = true;
                         continue;
                     }
+                    // nor is (x .)
+                    TokenData::RightParen if encounter_period => {
+                        return located_error!(SyntaxError::MisplacedPeriod, *location)
+                    }
                     TokenData::RightParen => break,
                     _ => {
                         let element = Self::unwrap_non_end(self.current_datum()?)?;
@@ -701,11 +1128,69 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
         Ok(DatumBody::Pair(head).locate(list_location))
     }
 
+    /// parse next datum as data, without transforming it into statement
+    pub fn parse_datum(&mut self) -> Result<Option<Datum>> {
+        self.advance(1)?;
+        self.current_datum()
+    }
+
     pub fn parse_root(&mut self) -> Result<Option<Statement>> {
         self.parse(self.syntax_env.clone())
     }
 
-    fn statement(&mut self, syntax_env: &Rc<LexicalScope<Transformer>>) -> Result<Statement> {
+    /// Parse all statements without stopping at the first syntax error: the rest of the form an
+    /// error is raised in is skipped, up to its closing parenthesis or an open parenthesis
+    /// starting a line, and parsing goes on from there. Statements parsed and errors are returned
+    /// in the order of the source.
+    pub fn parse_all_recovering(&mut self) -> (Vec<Statement>, Vec<SchemeError>) {
+        let mut statements = vec![];
+        let mut errors = vec![];
+        loop {
+            self.unclosed = None;
+            match self.parse_root() {
+                Ok(Some(statement)) => statements.push(statement),
+                Ok(None) => break,
+                Err(error) => {
+                    errors.push(error);
+                    self.skip_unclosed();
+                }
+            }
+        }
+        (statements, errors)
+    }
+
+    // skip the rest of the form the last error is raised in
+    pub(crate) fn skip_unclosed(&mut self) {
+        let mut unclosed = self.unclosed.take().unwrap_or(0);
+        while unclosed > 0 {
+            match self.lexer.peek() {
+                None => break,
+                // tokens are located after their last character
+                Some(Ok(Token {
+                    data: TokenData::LeftParen,
+                    location: Some(location),
+                })) if location.column == 2 => break,
+                _ => (),
+            }
+            match self.lexer.next() {
+                Some(Ok(Token {
+                    data: TokenData::LeftParen,
+                    ..
+                }))
+                | Some(Ok(Token {
+                    data: TokenData::VecConsIntro,
+                    ..
+                })) => unclosed += 1,
+                Some(Ok(Token {
+                    data: TokenData::RightParen,
+                    ..
+                })) => unclosed -= 1,
+                _ => (),
+            }
+        }
+    }
+
+    fn statement(&mut self, syntax_env: &Rc<LexicalScope<SyntaxBinding>>) -> Result<Statement> {
         match self.parse_current(syntax_env)? {
             Some(statement) => Ok(statement),
             None => located_error!(SyntaxError::UnexpectedEnd, self.location),
@@ -713,30 +1198,279 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     }
     pub fn parse(
         &mut self,
-        syntax_env: Rc<LexicalScope<Transformer>>,
+        syntax_env: Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<Option<Statement>> {
-        self.advance(1)?;
-        self.parse_current(&syntax_env)
+        loop {
+            let datum = match self.pending.pop_front() {
+                Some(datum) => datum,
+                None => {
+                    self.advance(1)?;
+                    match self.current_datum()? {
+                        Some(datum) => datum,
+                        None => return Ok(None),
+                    }
+                }
+            };
+            if !Self::is_cond_expand(&datum, &syntax_env) {
+                return self.transform_to_statement(datum, &syntax_env).map(Some);
+            }
+            // the body of the selected clause is made of top-level forms
+            let location = datum.location;
+            let body = self
+                .cond_expand(Self::arguments(datum)?)
+                .map_err(|error| error.or_locate(location))?;
+            for datum in body.into_iter().rev() {
+                self.pending.push_front(datum);
+            }
+        }
+    }
+
+    fn is_cond_expand(datum: &Datum, syntax_env: &Rc<LexicalScope<SyntaxBinding>>) -> bool {
+        match &datum.data {
+            DatumBody::Pair(pair) => match pair.as_ref() {
+                GenericPair::Some(
+                    Datum {
+                        data: DatumBody::Symbol(keyword),
+                        ..
+                    },
+                    _,
+                ) => {
+                    keyword == "cond-expand"
+                        && !matches!(
+                            syntax_env.get(keyword).as_deref(),
+                            Some(SyntaxBinding::Variable)
+                        )
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // the elements of a form after its keyword
+    fn arguments(datum: Datum) -> Result<impl Iterator<Item = Datum>> {
+        let mut list = datum.expect_list()?;
+        list.pop_proper()?;
+        Ok(list.into_iter())
+    }
+
+    // the body of the first clause of `(cond-expand clause ...)` whose requirement is met,
+    // nothing if none is
+    fn cond_expand(&self, clauses: impl Iterator<Item = Datum>) -> Result<Vec<Datum>> {
+        for clause in clauses {
+            let location = clause.location;
+            let mut iter = clause.expect_list()?.into_iter();
+            let requirement = match iter.next() {
+                Some(requirement) => requirement,
+                None => return located_error!(SyntaxError::UnexpectedEnd, location),
+            };
+            let met = match &requirement.data {
+                DatumBody::Symbol(keyword) if keyword == "else" => true,
+                _ => self.requirement_met(requirement)?,
+            };
+            if met {
+                return Ok(iter.collect());
+            }
+        }
+        Ok(vec![])
+    }
+
+    // the expression or definition of a `cond-expand` form located at `location` whose selected
+    // clause has `body`, several forms are evaluated in a `begin`
+    fn cond_expand_form(mut body: Vec<Datum>, location: Option<Location>) -> Datum {
+        let symbol = |name: &str| Datum {
+            data: DatumBody::Symbol(name.into()),
+            location,
+        };
+        let list = |elements: Vec<Datum>| Datum {
+            data: DatumBody::Pair(Box::new(elements.into_iter().collect())),
+            location,
+        };
+        match body.len() {
+            0 => {
+                let false_datum = Datum {
+                    data: DatumBody::Primitive(Primitive::Boolean(false)),
+                    location,
+                };
+                list(vec![symbol("if"), false_datum.clone(), false_datum])
+            }
+            1 => body.pop().unwrap(),
+            _ => list(std::iter::once(symbol("begin")).chain(body).collect()),
+        }
+    }
+
+    // (assert expression [message]) located at `location` is
+    // (%assert expression 'expression "location" [message]), so that the error of a failed
+    // assertion shows the expression and where it is
+    fn assertion(
+        mut datums: impl Iterator<Item = Datum>,
+        location: Option<Location>,
+    ) -> Result<Datum> {
+        let expression = Self::unwrap_non_end(datums.next())?;
+        let message = datums.next();
+        if let Some(extra) = datums.next() {
+            let extra_location = extra.location;
+            return located_error!(SyntaxError::UnexpectedDatum(extra), extra_location);
+        }
+        let datum = |data| Datum { data, location };
+        let list =
+            |elements: Vec<Datum>| datum(DatumBody::Pair(Box::new(elements.into_iter().collect())));
+        let quoted = list(vec![
+            datum(DatumBody::Symbol("quote".into())),
+            expression.clone(),
+        ]);
+        let written_location = match location {
+            Some(location) => Primitive::String(location.to_string()),
+            None => Primitive::Boolean(false),
+        };
+        Ok(list(
+            vec![
+                datum(DatumBody::Symbol("%assert".into())),
+                expression,
+                quoted,
+                datum(DatumBody::Primitive(written_location)),
+            ]
+            .into_iter()
+            .chain(message)
+            .collect(),
+        ))
+    }
+
+    // r7rs 4.2.1 feature requirement: <feature identifier>, (and <requirement> ...),
+    // (or <requirement> ...), (not <requirement>) or (library <library name>)
+    fn requirement_met(&self, requirement: Datum) -> Result<bool> {
+        let location = requirement.location;
+        let illegal_requirement = |requirement: String| {
+            located_error!(
+                SyntaxError::ExpectSomething("feature requirement".to_string(), requirement),
+                location
+            )
+        };
+        match requirement.data {
+            DatumBody::Symbol(feature) => Ok(match &self.features {
+                Some(features) => features.has_feature(&feature),
+                None => default_features().iter().any(|f| feature == *f),
+            }),
+            DatumBody::Pair(pair) => {
+                let requirement_string = pair.to_string();
+                let mut iter = pair.into_iter();
+                let keyword = match iter.next() {
+                    Some(Datum {
+                        data: DatumBody::Symbol(keyword),
+                        ..
+                    }) => keyword,
+                    _ => return illegal_requirement(requirement_string),
+                };
+                match keyword.as_str() {
+                    "and" => {
+                        for requirement in iter {
+                            if !self.requirement_met(requirement)? {
+                                return Ok(false);
+                            }
+                        }
+                        Ok(true)
+                    }
+                    "or" => {
+                        for requirement in iter {
+                            if self.requirement_met(requirement)? {
+                                return Ok(true);
+                            }
+                        }
+                        Ok(false)
+                    }
+                    "not" => match (iter.next(), iter.next()) {
+                        (Some(requirement), None) => Ok(!self.requirement_met(requirement)?),
+                        _ => illegal_requirement(requirement_string),
+                    },
+                    "library" => match (iter.next(), iter.next()) {
+                        (Some(name), None) => {
+                            let name =
+                                Self::transform_library_name(name.expect_list()?.into_iter())?;
+                            Ok(match &self.features {
+                                Some(features) => features.has_library(&name),
+                                None => false,
+                            })
+                        }
+                        _ => illegal_requirement(requirement_string),
+                    },
+                    _ => illegal_requirement(requirement_string),
+                }
+            }
+            other => illegal_requirement(other.to_string()),
+        }
+    }
+
+    // `datums` with the `cond-expand` forms among them replaced by the bodies of their selected
+    // clauses, so that the definitions of the clauses are made where the form is
+    fn splice_cond_expand(
+        &self,
+        datums: impl Iterator<Item = Datum>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
+    ) -> Result<Vec<Datum>> {
+        let mut spliced = vec![];
+        for datum in datums {
+            if Self::is_cond_expand(&datum, syntax_env) {
+                let location = datum.location;
+                let body = self
+                    .cond_expand(Self::arguments(datum)?)
+                    .map_err(|error| error.or_locate(location))?;
+                spliced.extend(self.splice_cond_expand(body.into_iter(), syntax_env)?);
+            } else {
+                spliced.push(datum);
+            }
+        }
+        Ok(spliced)
     }
 
     fn transform_library(
+        &self,
         mut datums: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<LibraryDefinition> {
         let library_name = Self::transform_library_name(
             Self::unwrap_non_end(datums.next())?
                 .expect_list()?
                 .into_iter(),
         )?;
-        let library_declarations = datums
-            .map(|datum| Self::transform_library_declaration(datum, syntax_env))
+        let library_declarations: Vec<Located<LibraryDeclaration>> = datums
+            .map(|datum| self.transform_library_declaration(datum, syntax_env))
             .collect::<Result<_>>()?;
+        // definitions of all the begin declarations are in the same scope
+        let mut defined = HashMap::new();
+        for declaration in &library_declarations {
+            if let LibraryDeclaration::Begin(statements) = &declaration.data {
+                for statement in statements {
+                    if let Statement::Definition(definition) = statement {
+                        Self::define_once(&mut defined, definition)?;
+                    }
+                }
+            }
+        }
         Ok(LibraryDefinition(library_name, library_declarations))
     }
 
+    // a name may be defined once in a body or a library, unlike at the top level of programs
+    fn define_once(
+        defined: &mut HashMap<Symbol, Option<Location>>,
+        definition: &Definition,
+    ) -> Result<()> {
+        let name = &definition.data.0;
+        match defined.get(name) {
+            Some(first) => located_error!(
+                SyntaxError::DuplicateDefinition(name.clone(), *first),
+                definition.location
+            ),
+            None => {
+                defined.insert(name.clone(), definition.location);
+                Ok(())
+            }
+        }
+    }
+
     fn transform_library_declaration(
+        &self,
         datum: Datum,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<Located<LibraryDeclaration>> {
         let location = datum.location;
         let mut iter = datum.expect_list()?.into_iter().peekable();
@@ -747,8 +1481,9 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
                     .collect::<Result<_>>()?,
             ),
             DatumBody::Symbol(first) if first == "begin" => LibraryDeclaration::Begin(
-                iter.skip(1)
-                    .map(|datum| Self::transform_to_statement(datum, syntax_env))
+                self.splice_cond_expand(iter.skip(1), syntax_env)?
+                    .into_iter()
+                    .map(|datum| self.transform_to_statement(datum, syntax_env))
                     .collect::<Result<_>>()?,
             ),
             _ => LibraryDeclaration::ImportDeclaration(
@@ -759,8 +1494,9 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     }
 
     fn transform_export_spec(datum: Datum) -> Result<Located<ExportSpec>> {
+        let location = datum.location;
         Ok(match datum.data {
-            DatumBody::Symbol(ident) => ExportSpec::Direct(ident),
+            DatumBody::Symbol(ident) => ExportSpec::Direct(ident.to_string()),
             DatumBody::Pair(list) => {
                 let mut iter = list.into_iter();
                 match Self::unwrap_non_end(iter.next())? {
@@ -768,21 +1504,35 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
                         data: DatumBody::Symbol(ident),
                         ..
                     } if ident == "rename" => (),
-                    o => return error!(SyntaxError::UnexpectedDatum(o)),
+                    o => {
+                        let location = o.location;
+                        return located_error!(SyntaxError::UnexpectedDatum(o), location);
+                    }
                 };
                 ExportSpec::Rename(
-                    Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?,
-                    Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?,
+                    Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?.into(),
+                    Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?.into(),
                 )
             }
-            _ => return error!(SyntaxError::UnexpectedDatum(datum)),
+            _ => return located_error!(SyntaxError::UnexpectedDatum(datum), location),
         }
-        .locate(datum.location))
+        .locate(location))
     }
 
-    fn transform_identifier(datum: Datum) -> Result<String> {
+    fn transform_identifier(datum: Datum) -> Result<Symbol> {
         match datum.data {
-            DatumBody::Symbol(ident) => Ok(ident.clone()),
+            DatumBody::Symbol(ident) => Ok(ident),
+            other => located_error!(
+                SyntaxError::ExpectSomething("identifier".to_string(), other.to_string()),
+                datum.location
+            ),
+        }
+    }
+
+    // borrows the identifier when it is only compared
+    fn inspect_identifier(datum: &Datum) -> Result<&Symbol> {
+        match &datum.data {
+            DatumBody::Symbol(ident) => Ok(ident),
             other => located_error!(
                 SyntaxError::ExpectSomething("identifier".to_string(), other.to_string()),
                 datum.location
@@ -792,8 +1542,8 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
 
     fn transform_identifier_pair(datum: Datum) -> Result<(String, String)> {
         let mut iter = datum.expect_list()?.into_iter();
-        let car = Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?;
-        let cdr = Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?;
+        let car = Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?.into();
+        let cdr = Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?.into();
         Ok((car, cdr))
     }
 
@@ -839,7 +1589,7 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
 
     fn transform_formals(args: Datum) -> Result<ParameterFormals> {
         let location = args.location;
-        Ok(match args {
+        let formals = match args {
             Datum {
                 data: DatumBody::Pair(pair),
                 ..
@@ -854,17 +1604,40 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
             single => {
                 ParameterFormalsBody::Name(Self::transform_identifier(single)?).locate(location)
             }
-        })
+        };
+        // a parameter repeated among the fixed ones or as the variadic one
+        let mut names = HashSet::new();
+        let mut duplicate = None;
+        let variadic = formals.iter_to_last(|fixed| {
+            if !names.insert(fixed.as_name()) && duplicate.is_none() {
+                duplicate = Some(fixed.clone());
+            }
+        });
+        if let Some(variadic) = variadic {
+            if names.contains(&variadic.as_name()) && duplicate.is_none() {
+                duplicate = Some(variadic.clone());
+            }
+        }
+        match duplicate {
+            Some(duplicate) => located_error!(
+                SyntaxError::DuplicateParameter(duplicate.as_name()),
+                duplicate.location
+            ),
+            None => Ok(formals),
+        }
     }
 
-    fn parse_quoted(&mut self) -> Result<Datum> {
+    // 'x, `x, ,x and ,@x are the lists (quote x), (quasiquote x), (unquote x) and
+    // (unquote-splicing x), nested as deeply as lists are
+    fn parse_abbreviation(&mut self, keyword: &str) -> Result<Datum> {
+        self.advance(1)?;
         let quote_location = self.location;
         let inner = self.datum()?;
         Ok(Datum {
             location: quote_location,
             data: DatumBody::Pair(Box::new(list![
                 Datum {
-                    data: DatumBody::Symbol("quote".to_string()),
+                    data: DatumBody::Symbol(keyword.into()),
                     location: quote_location,
                 },
                 inner
@@ -878,50 +1651,88 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
         )?)))
     }
 
+    // a datum within a list or a vector, abbreviations like 'x are data as well
     fn datum(&mut self) -> Result<Datum> {
-        let location = self.location;
-        Ok(match &self.advance_unwrap(0)?.data {
-            TokenData::LeftParen => self.current_list_or_pair()?,
-            TokenData::VecConsIntro => {
-                DatumBody::Vector(self.repeat(Self::datum).collect::<Result<_>>()?).locate(location)
-            }
-            TokenData::Identifier(symbol) => DatumBody::Symbol(symbol.clone()).locate(location),
-            TokenData::Primitive(p) => DatumBody::Primitive(p.clone()).locate(location),
-            other => return located_error!(SyntaxError::UnexpectedToken(other.clone()), location),
-        })
+        match self.current_datum()? {
+            Some(datum) => Ok(datum),
+            None => located_error!(SyntaxError::UnexpectedEnd, self.location),
+        }
     }
 
     fn transform_lambda(
+        &self,
         mut datums: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<ExpressionBody> {
         let formals = Self::transform_formals(Self::unwrap_non_end(datums.next())?)?;
-        let lambda_syntax_env = Rc::new(LexicalScope::new_child(syntax_env.clone()));
+        let lambda_syntax_env = Self::procedure_scope(&formals, syntax_env);
         let (definitions, expressions) =
-            Self::transform_procedure_body(datums, &lambda_syntax_env)?;
+            self.transform_procedure_body(datums, &lambda_syntax_env)?;
         Ok(ExpressionBody::Procedure(SchemeProcedure(
             formals,
             definitions,
             expressions,
+            None,
         )))
     }
 
+    // the scope of the body of a procedure, where its parameters are variables
+    fn procedure_scope(
+        formals: &ParameterFormals,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
+    ) -> Rc<LexicalScope<SyntaxBinding>> {
+        let scope = Rc::new(LexicalScope::new_child(syntax_env.clone()));
+        let variadic = formals.iter_to_last(|fixed| {
+            scope.define(fixed.as_name(), SyntaxBinding::Variable);
+        });
+        if let Some(variadic) = variadic {
+            scope.define(variadic.as_name(), SyntaxBinding::Variable);
+        }
+        scope
+    }
+
     fn transform_procedure_body(
+        &self,
         datums: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<(Vec<Definition>, Vec<Expression>)> {
         let mut definitions = vec![];
         let mut expressions = vec![];
-        for datum in datums {
+        let mut defined = HashMap::new();
+        for datum in self.splice_cond_expand(datums, syntax_env)? {
             let location = datum.location;
-            let statement = Self::transform_to_statement(datum, syntax_env)?;
+            let statement = self.transform_to_statement(datum, syntax_env)?;
             match statement {
-                Statement::Definition(def) => {
+                Statement::Definition(mut def) => {
+                    Self::define_once(&mut defined, &def)?;
+                    // internal definitions are variables for the rest of the body
+                    syntax_env.define(def.data.0.clone(), SyntaxBinding::Variable);
                     if expressions.is_empty() {
                         definitions.push(def)
+                    } else if self.mixed_bodies {
+                        // (define name init) after expressions e ... becomes
+                        // (define name (begin e ... init))
+                        let DefinitionBody(name, init) = def.data;
+                        let location = init.location;
+                        expressions.push(init);
+                        let begin = ExpressionBody::Procedure(SchemeProcedure(
+                            ParameterFormals::new_non_located(std::iter::empty(), None),
+                            vec![],
+                            mem::take(&mut expressions),
+                            None,
+                        ))
+                        .locate(location);
+                        def.data = DefinitionBody(
+                            name,
+                            ExpressionBody::ProcedureCall(Box::new(begin), vec![]).locate(location),
+                        );
+                        definitions.push(def)
                     } else {
                         return located_error!(
-                            SyntaxError::InvalidDefinitionContext(def.data),
+                            SyntaxError::DefinitionAfterExpression(
+                                def.data.0,
+                                expressions[0].location
+                            ),
                             def.location
                         );
                     }
@@ -953,15 +1764,16 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     }
 
     fn transform_condition(
+        &self,
         mut asts: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<ExpressionBody> {
-        let test = Self::transform_to_expression(Self::unwrap_non_end(asts.next())?, syntax_env)?;
+        let test = self.transform_to_expression(Self::unwrap_non_end(asts.next())?, syntax_env)?;
         let consequent =
-            Self::transform_to_expression(Self::unwrap_non_end(asts.next())?, syntax_env)?;
+            self.transform_to_expression(Self::unwrap_non_end(asts.next())?, syntax_env)?;
         let alternative = asts
             .next()
-            .map(|datum| Self::transform_to_expression(datum, syntax_env))
+            .map(|datum| self.transform_to_expression(datum, syntax_env))
             .transpose()?;
         Ok(ExpressionBody::Conditional(Box::new((
             test,
@@ -973,7 +1785,7 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     fn transform_library_name_part(datum: Datum) -> Result<LibraryNameElement> {
         let location = datum.location;
         match datum.data {
-            DatumBody::Symbol(identifier) => Ok(LibraryNameElement::Identifier(identifier)),
+            DatumBody::Symbol(identifier) => Ok(LibraryNameElement::Identifier(identifier.into())),
             DatumBody::Primitive(Primitive::Integer(i)) if i >= 0 => {
                 Ok(LibraryNameElement::Integer(i as u32))
             }
@@ -993,12 +1805,12 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
         let mut iter = datum.expect_list()?.into_iter().peekable();
         let first = Self::unwrap_non_end(iter.peek())?;
         let location = first.location;
-        Ok(match Self::transform_identifier(first.clone())? {
+        Ok(match Self::inspect_identifier(first)?.clone() {
             spec if spec == "only" => {
                 iter.next();
                 let sub_import = Self::transform_import_set(Self::unwrap_non_end(iter.next())?)?;
                 let idents = iter
-                    .map(Self::transform_identifier)
+                    .map(|datum| Self::transform_identifier(datum).map(String::from))
                     .collect::<Result<_>>()?;
                 ImportSetBody::Only(Box::new(sub_import), idents)
             }
@@ -1006,7 +1818,7 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
                 iter.next();
                 let sub_import = Self::transform_import_set(Self::unwrap_non_end(iter.next())?)?;
                 let idents = iter
-                    .map(Self::transform_identifier)
+                    .map(|datum| Self::transform_identifier(datum).map(String::from))
                     .collect::<Result<_>>()?;
                 ImportSetBody::Except(Box::new(sub_import), idents)
             }
@@ -1014,7 +1826,7 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
                 iter.next();
                 let sub_import = Self::transform_import_set(Self::unwrap_non_end(iter.next())?)?;
                 let ident = Self::transform_identifier(Self::unwrap_non_end(iter.next())?)?;
-                ImportSetBody::Prefix(Box::new(sub_import), ident)
+                ImportSetBody::Prefix(Box::new(sub_import), ident.into())
             }
             spec if spec == "rename" => {
                 iter.next();
@@ -1030,33 +1842,44 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     }
 
     fn transform_definition(
+        &self,
         mut datums: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<DefinitionBody> {
         let first = Self::unwrap_non_end(datums.next())?;
         let location = first.location;
         match first.data {
             DatumBody::Symbol(symbol) => {
-                let body = Self::transform_to_expression(
-                    Self::unwrap_non_end(datums.next())?,
-                    syntax_env,
-                )?;
+                let mut body =
+                    self.transform_to_expression(Self::unwrap_non_end(datums.next())?, syntax_env)?;
+                // (define f (lambda ...)) names the procedure as (define (f ...) ...) does
+                if let ExpressionBody::Procedure(SchemeProcedure(.., name @ None)) = &mut body.data
+                {
+                    *name = Some(symbol.clone());
+                }
                 Ok(DefinitionBody(symbol, body))
             }
-            DatumBody::Pair(pair) => match *pair {
-                GenericPair::Some(name, formals) => {
+            DatumBody::Pair(pair) => match pair.into_parts() {
+                Some((name, formals)) => {
                     let location = name.location;
                     let name = Self::transform_identifier(name)?;
                     let formals = Self::transform_formals(formals)?;
-                    let (defs, exprs) = Self::transform_procedure_body(datums, syntax_env)?;
-                    let procedure =
-                        ExpressionBody::Procedure(SchemeProcedure(formals, defs, exprs))
-                            .locate(location);
+                    let (defs, exprs) = self.transform_procedure_body(
+                        datums,
+                        &Self::procedure_scope(&formals, syntax_env),
+                    )?;
+                    let procedure = ExpressionBody::Procedure(SchemeProcedure(
+                        formals,
+                        defs,
+                        exprs,
+                        Some(name.clone()),
+                    ))
+                    .locate(location);
                     Ok(DefinitionBody(name, procedure))
                 }
-                other => {
+                None => {
                     return located_error!(
-                        SyntaxError::InvalidDefinition(Datum::from(other)),
+                        SyntaxError::InvalidDefinition(Datum::from(GenericPair::Empty)),
                         location
                     )
                 }
@@ -1067,7 +1890,7 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
         }
     }
 
-    fn transform_transformer(keyword: &String, datum: Datum) -> Result<UserDefinedTransformer> {
+    fn transform_transformer(keyword: &Symbol, datum: Datum) -> Result<UserDefinedTransformer> {
         // Skipping symbol 'syntax-rules'
         let mut iter = datum.expect_list()?.into_iter().skip(1);
 
@@ -1105,17 +1928,20 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
 
     fn transform_syntax_definition(
         mut datums: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<SyntaxDefBody> {
         let keyword = Self::transform_identifier(Self::unwrap_non_end(datums.next())?)?;
         let syntax_body =
             Self::transform_transformer(&keyword, Self::unwrap_non_end(datums.next())?)?;
-        syntax_env.define(keyword.clone(), Transformer::Scheme(syntax_body.clone()));
+        syntax_env.define(
+            keyword.clone(),
+            SyntaxBinding::Keyword(Transformer::Scheme(syntax_body.clone())),
+        );
         Ok(SyntaxDefBody(keyword, syntax_body))
     }
 
     fn transform_syntax_rule(
-        keyword: &String,
+        keyword: &Symbol,
         datum: Datum,
     ) -> Result<(SyntaxPattern, SyntaxTemplate)> {
         let mut iter = datum.expect_list()?.into_iter();
@@ -1128,7 +1954,7 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     }
 
     fn transform_pattern_root(
-        keyword: &String,
+        keyword: &Symbol,
         mut datum_list: DatumList,
     ) -> Result<SyntaxPattern> {
         let first = Self::unwrap_non_end(datum_list.pop_proper()?)?;
@@ -1136,7 +1962,7 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
         let providing_keyword = first.expect_symbol()?;
         if keyword != &providing_keyword {
             return located_error!(
-                SyntaxError::MacroKeywordMissMatch(keyword.clone(), providing_keyword),
+                SyntaxError::MacroKeywordMissMatch(keyword.to_string(), providing_keyword.into()),
                 location
             );
         }
@@ -1240,8 +2066,9 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
     }
 
     fn transform_assignment(
+        &self,
         mut datums: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<ExpressionBody> {
         let symbol = match Self::unwrap_non_end(datums.next())? {
             Datum {
@@ -1250,19 +2077,21 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
             } => symbol,
             other => return error!(SyntaxError::DefineNonSymbol(other)),
         };
-        let body = Self::transform_to_expression(Self::unwrap_non_end(datums.next())?, syntax_env)?;
+        let body =
+            self.transform_to_expression(Self::unwrap_non_end(datums.next())?, syntax_env)?;
         Ok(ExpressionBody::Assignment(symbol, Box::new(body)))
     }
 
     fn transform_procedure_call(
+        &self,
         first: Datum,
         datum: impl Iterator<Item = Datum>,
-        syntax_env: &Rc<LexicalScope<Transformer>>,
+        syntax_env: &Rc<LexicalScope<SyntaxBinding>>,
     ) -> Result<ExpressionBody> {
         Ok(ExpressionBody::ProcedureCall(
-            Box::new(Self::transform_to_expression(first, syntax_env)?),
+            Box::new(self.transform_to_expression(first, syntax_env)?),
             datum
-                .map(|datum| Self::transform_to_expression(datum, syntax_env))
+                .map(|datum| self.transform_to_expression(datum, syntax_env))
                 .collect::<Result<Vec<_>>>()?,
         ))
     }
@@ -1328,7 +2157,20 @@ impl<TokenIter: Iterator<Item = Result<Token>>> Parser<TokenIter> {
 
 #[cfg(test)]
 pub fn simple_procedure(formals: ParameterFormals, expression: Expression) -> Expression {
-    ExpressionBody::Procedure(SchemeProcedure(formals, vec![], vec![expression])).into()
+    ExpressionBody::Procedure(SchemeProcedure(formals, vec![], vec![expression], None)).into()
+}
+pub fn named_procedure(
+    name: &str,
+    formals: ParameterFormals,
+    expression: Expression,
+) -> Expression {
+    ExpressionBody::Procedure(SchemeProcedure(
+        formals,
+        vec![],
+        vec![expression],
+        Some(name.into()),
+    ))
+    .into()
 }
 #[test]
 fn empty() -> Result<()> {
@@ -1356,6 +2198,12 @@ pub fn token_stream_to_parser(
         lexer: mapped.peekable(),
         syntax_env: Rc::new(LexicalScope::new()),
         location: None,
+        nesting: 0,
+        max_nesting: DEFAULT_MAX_NESTING,
+        unclosed: None,
+        mixed_bodies: false,
+        features: None,
+        pending: VecDeque::new(),
     }
 }
 
@@ -1396,12 +2244,12 @@ fn rational() -> Result<()> {
 
 #[test]
 fn identifier() -> Result<()> {
-    let tokens = convert_located(vec![TokenData::Identifier("test".to_string())]);
+    let tokens = convert_located(vec![TokenData::Identifier("test".into())]);
     let mut parser = token_stream_to_parser(tokens.into_iter());
     let ast = parser.parse_root()?;
     assert_eq!(
         ast,
-        expr_body_to_statement(ExpressionBody::Symbol("test".to_string()))
+        expr_body_to_statement(ExpressionBody::Symbol("test".into()))
     );
     Ok(())
 }
@@ -1447,7 +2295,7 @@ fn string() -> Result<()> {
 fn procedure_call() -> Result<()> {
     let tokens = convert_located(vec![
         TokenData::LeftParen,
-        TokenData::Identifier("+".to_string()),
+        TokenData::Identifier("+".into()),
         TokenData::Primitive(Primitive::Integer(1)),
         TokenData::Primitive(Primitive::Integer(2)),
         TokenData::Primitive(Primitive::Integer(3)),
@@ -1458,7 +2306,7 @@ fn procedure_call() -> Result<()> {
     assert_eq!(
         ast,
         expr_body_to_statement(ExpressionBody::ProcedureCall(
-            Box::new(ExpressionBody::Symbol("+".to_string()).into()),
+            Box::new(ExpressionBody::Symbol("+".into()).into()),
             vec![
                 Primitive::Integer(1).into(),
                 Primitive::Integer(2).into(),
@@ -1473,7 +2321,7 @@ fn procedure_call() -> Result<()> {
 fn unmatched_parantheses() {
     let tokens = convert_located(vec![
         TokenData::LeftParen,
-        TokenData::Identifier("+".to_string()),
+        TokenData::Identifier("+".into()),
         TokenData::Primitive(Primitive::Integer(1)),
         TokenData::Primitive(Primitive::Integer(2)),
         TokenData::Primitive(Primitive::Integer(3)),
@@ -1491,8 +2339,8 @@ fn definition() -> Result<()> {
         {
             let tokens = convert_located(vec![
                 TokenData::LeftParen,
-                TokenData::Identifier("define".to_string()),
-                TokenData::Identifier("a".to_string()),
+                TokenData::Identifier("define".into()),
+                TokenData::Identifier("a".into()),
                 TokenData::Primitive(Primitive::Integer(1)),
                 TokenData::RightParen,
             ]);
@@ -1500,25 +2348,22 @@ fn definition() -> Result<()> {
             let ast = parser.parse_root()?;
             assert_eq!(
                 ast,
-                def_body_to_statement(DefinitionBody(
-                    "a".to_string(),
-                    Primitive::Integer(1).into()
-                ))
+                def_body_to_statement(DefinitionBody("a".into(), Primitive::Integer(1).into()))
             );
         }
         {
             let tokens = convert_located(vec![
                 TokenData::LeftParen,
-                TokenData::Identifier("define".to_string()),
+                TokenData::Identifier("define".into()),
                 TokenData::LeftParen,
-                TokenData::Identifier("add".to_string()),
-                TokenData::Identifier("x".to_string()),
-                TokenData::Identifier("y".to_string()),
+                TokenData::Identifier("add".into()),
+                TokenData::Identifier("x".into()),
+                TokenData::Identifier("y".into()),
                 TokenData::RightParen,
                 TokenData::LeftParen,
-                TokenData::Identifier("+".to_string()),
-                TokenData::Identifier("x".to_string()),
-                TokenData::Identifier("y".to_string()),
+                TokenData::Identifier("+".into()),
+                TokenData::Identifier("x".into()),
+                TokenData::Identifier("y".into()),
                 TokenData::RightParen,
                 TokenData::RightParen,
             ]);
@@ -1527,14 +2372,15 @@ fn definition() -> Result<()> {
             assert_eq!(
                 ast,
                 def_body_to_statement(DefinitionBody(
-                    "add".to_string(),
-                    simple_procedure(
+                    "add".into(),
+                    named_procedure(
+                        "add",
                         param_fixed!["x", "y"],
                         ExpressionBody::ProcedureCall(
-                            Box::new(ExpressionBody::Symbol("+".to_string()).into()),
+                            Box::new(ExpressionBody::Symbol("+".into()).into()),
                             vec![
-                                ExpressionBody::Symbol("x".to_string()).into(),
-                                ExpressionBody::Symbol("y".to_string()).into(),
+                                ExpressionBody::Symbol("x".into()).into(),
+                                ExpressionBody::Symbol("y".into()).into(),
                             ]
                         )
                         .into()
@@ -1545,13 +2391,13 @@ fn definition() -> Result<()> {
         {
             let tokens = convert_located(vec![
                 TokenData::LeftParen,
-                TokenData::Identifier("define".to_string()),
+                TokenData::Identifier("define".into()),
                 TokenData::LeftParen,
-                TokenData::Identifier("add".to_string()),
+                TokenData::Identifier("add".into()),
                 TokenData::Period,
-                TokenData::Identifier("x".to_string()),
+                TokenData::Identifier("x".into()),
                 TokenData::RightParen,
-                TokenData::Identifier("x".to_string()),
+                TokenData::Identifier("x".into()),
                 TokenData::RightParen,
             ]);
             let mut parser = token_stream_to_parser(tokens.into_iter());
@@ -1559,10 +2405,11 @@ fn definition() -> Result<()> {
             assert_eq!(
                 ast,
                 def_body_to_statement(DefinitionBody(
-                    "add".to_string(),
-                    simple_procedure(
+                    "add".into(),
+                    named_procedure(
+                        "add",
                         append_variadic_param!(param_fixed![], "x"),
-                        ExpressionBody::Symbol("x".to_string()).into()
+                        ExpressionBody::Symbol("x".into()).into()
                     )
                 ))
             )
@@ -1575,10 +2422,10 @@ fn definition() -> Result<()> {
 fn nested_procedure_call() -> Result<()> {
     let tokens = convert_located(vec![
         TokenData::LeftParen,
-        TokenData::Identifier("+".to_string()),
+        TokenData::Identifier("+".into()),
         TokenData::Primitive(Primitive::Integer(1)),
         TokenData::LeftParen,
-        TokenData::Identifier("-".to_string()),
+        TokenData::Identifier("-".into()),
         TokenData::Primitive(Primitive::Integer(2)),
         TokenData::Primitive(Primitive::Integer(3)),
         TokenData::RightParen,
@@ -1589,11 +2436,11 @@ fn nested_procedure_call() -> Result<()> {
     assert_eq!(
         ast,
         expr_body_to_statement(ExpressionBody::ProcedureCall(
-            Box::new(ExpressionBody::Symbol("+".to_string()).into()),
+            Box::new(ExpressionBody::Symbol("+".into()).into()),
             vec![
                 Primitive::Integer(1).into(),
                 ExpressionBody::ProcedureCall(
-                    Box::new(ExpressionBody::Symbol("-".to_string()).into()),
+                    Box::new(ExpressionBody::Symbol("-".into()).into()),
                     vec![Primitive::Integer(2).into(), Primitive::Integer(3).into()]
                 )
                 .into(),
@@ -1608,15 +2455,15 @@ fn lambda() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("lambda".to_string()),
+            TokenData::Identifier("lambda".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("x".to_string()),
-            TokenData::Identifier("y".to_string()),
+            TokenData::Identifier("x".into()),
+            TokenData::Identifier("y".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("+".to_string()),
-            TokenData::Identifier("x".to_string()),
-            TokenData::Identifier("y".to_string()),
+            TokenData::Identifier("+".into()),
+            TokenData::Identifier("x".into()),
+            TokenData::Identifier("y".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -1627,10 +2474,10 @@ fn lambda() -> Result<()> {
             Some(Statement::Expression(simple_procedure(
                 param_fixed!["x", "y"],
                 ExpressionBody::ProcedureCall(
-                    Box::new(ExpressionBody::Symbol("+".to_string()).into()),
+                    Box::new(ExpressionBody::Symbol("+".into()).into()),
                     vec![
-                        ExpressionBody::Symbol("x".to_string()).into(),
-                        ExpressionBody::Symbol("y".to_string()).into()
+                        ExpressionBody::Symbol("x".into()).into(),
+                        ExpressionBody::Symbol("y".into()).into()
                     ]
                 )
                 .into()
@@ -1641,19 +2488,19 @@ fn lambda() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("lambda".to_string()),
+            TokenData::Identifier("lambda".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("x".to_string()),
+            TokenData::Identifier("x".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("define".to_string()),
-            TokenData::Identifier("y".to_string()),
+            TokenData::Identifier("define".into()),
+            TokenData::Identifier("y".into()),
             TokenData::Primitive(Primitive::Integer(1)),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("+".to_string()),
-            TokenData::Identifier("x".to_string()),
-            TokenData::Identifier("y".to_string()),
+            TokenData::Identifier("+".into()),
+            TokenData::Identifier("x".into()),
+            TokenData::Identifier("y".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -1665,17 +2512,18 @@ fn lambda() -> Result<()> {
                 ExpressionBody::Procedure(SchemeProcedure(
                     param_fixed!["x".to_string()],
                     vec![Definition::from(DefinitionBody(
-                        "y".to_string(),
+                        "y".into(),
                         Primitive::Integer(1).into()
                     ))],
                     vec![ExpressionBody::ProcedureCall(
-                        Box::new(ExpressionBody::Symbol("+".to_string()).into()),
+                        Box::new(ExpressionBody::Symbol("+".into()).into()),
                         vec![
-                            ExpressionBody::Symbol("x".to_string()).into(),
-                            ExpressionBody::Symbol("y".to_string()).into()
+                            ExpressionBody::Symbol("x".into()).into(),
+                            ExpressionBody::Symbol("y".into()).into()
                         ]
                     )
-                    .into()]
+                    .into()],
+                    None
                 ))
                 .into()
             ))
@@ -1685,13 +2533,13 @@ fn lambda() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("lambda".to_string()),
+            TokenData::Identifier("lambda".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("x".to_string()),
+            TokenData::Identifier("x".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("define".to_string()),
-            TokenData::Identifier("y".to_string()),
+            TokenData::Identifier("define".into()),
+            TokenData::Identifier("y".into()),
             TokenData::Primitive(Primitive::Integer(1)),
             TokenData::RightParen,
             TokenData::RightParen,
@@ -1707,16 +2555,16 @@ fn lambda() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("lambda".to_string()),
+            TokenData::Identifier("lambda".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("x".to_string()),
+            TokenData::Identifier("x".into()),
             TokenData::Period,
-            TokenData::Identifier("y".to_string()),
+            TokenData::Identifier("y".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("+".to_string()),
-            TokenData::Identifier("x".to_string()),
-            TokenData::Identifier("y".to_string()),
+            TokenData::Identifier("+".into()),
+            TokenData::Identifier("x".into()),
+            TokenData::Identifier("y".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -1729,13 +2577,14 @@ fn lambda() -> Result<()> {
                     append_variadic_param!(param_fixed!["x"], "y"),
                     vec![],
                     vec![ExpressionBody::ProcedureCall(
-                        Box::new(ExpressionBody::Symbol("+".to_string()).into()),
+                        Box::new(ExpressionBody::Symbol("+".into()).into()),
                         vec![
-                            ExpressionBody::Symbol("x".to_string()).into(),
-                            ExpressionBody::Symbol("y".to_string()).into()
+                            ExpressionBody::Symbol("x".into()).into(),
+                            ExpressionBody::Symbol("y".into()).into()
                         ]
                     )
-                    .into()]
+                    .into()],
+                    None
                 ))
                 .into()
             ))
@@ -1749,7 +2598,7 @@ fn lambda() -> Result<()> {
 fn conditional() -> Result<()> {
     let tokens = convert_located(vec![
         TokenData::LeftParen,
-        TokenData::Identifier("if".to_string()),
+        TokenData::Identifier("if".into()),
         TokenData::Primitive(Primitive::Boolean(true)),
         TokenData::Primitive(Primitive::Integer(1)),
         TokenData::Primitive(Primitive::Integer(2)),
@@ -1776,9 +2625,9 @@ fn import_set() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::Primitive(Primitive::Integer(5)),
             TokenData::RightParen,
             TokenData::RightParen,
@@ -1798,14 +2647,14 @@ fn import_set() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("only".to_string()),
+            TokenData::Identifier("only".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::Primitive(Primitive::Integer(5)),
             TokenData::RightParen,
-            TokenData::Identifier("a".to_string()),
+            TokenData::Identifier("a".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -1828,15 +2677,15 @@ fn import_set() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("except".to_string()),
+            TokenData::Identifier("except".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::Primitive(Primitive::Integer(5)),
             TokenData::RightParen,
-            TokenData::Identifier("a".to_string()),
-            TokenData::Identifier("b".to_string()),
+            TokenData::Identifier("a".into()),
+            TokenData::Identifier("b".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -1859,14 +2708,14 @@ fn import_set() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("prefix".to_string()),
+            TokenData::Identifier("prefix".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::Primitive(Primitive::Integer(5)),
             TokenData::RightParen,
-            TokenData::Identifier("a-".to_string()),
+            TokenData::Identifier("a-".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -1889,20 +2738,20 @@ fn import_set() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("rename".to_string()),
+            TokenData::Identifier("rename".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::Primitive(Primitive::Integer(5)),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("a".to_string()),
-            TokenData::Identifier("b".to_string()),
+            TokenData::Identifier("a".into()),
+            TokenData::Identifier("b".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("c".to_string()),
-            TokenData::Identifier("d".to_string()),
+            TokenData::Identifier("c".into()),
+            TokenData::Identifier("d".into()),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::RightParen,
@@ -1938,23 +2787,23 @@ fn import_declaration() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("only".to_string()),
+            TokenData::Identifier("only".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("example-lib".to_string()),
+            TokenData::Identifier("example-lib".into()),
             TokenData::RightParen,
-            TokenData::Identifier("a".to_string()),
-            TokenData::Identifier("b".to_string()),
+            TokenData::Identifier("a".into()),
+            TokenData::Identifier("b".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("rename".to_string()),
+            TokenData::Identifier("rename".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("example-lib".to_string()),
+            TokenData::Identifier("example-lib".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("old".to_string()),
-            TokenData::Identifier("new".to_string()),
+            TokenData::Identifier("old".into()),
+            TokenData::Identifier("new".into()),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2007,7 +2856,7 @@ fn literals() -> Result<()> {
             TokenData::Quote,
             TokenData::Primitive(Primitive::Integer(1)),
             TokenData::Quote,
-            TokenData::Identifier("a".to_string()),
+            TokenData::Identifier("a".into()),
             TokenData::Quote,
             TokenData::LeftParen,
             TokenData::Primitive(Primitive::Integer(1)),
@@ -2032,8 +2881,7 @@ fn literals() -> Result<()> {
                     .into()
                 ),
                 Statement::Expression(
-                    ExpressionBody::Quote(Box::new(DatumBody::Symbol("a".to_string()).into(),))
-                        .into()
+                    ExpressionBody::Quote(Box::new(DatumBody::Symbol("a".into()).into(),)).into()
                 ),
                 Statement::Expression(
                     ExpressionBody::Quote(Box::new(
@@ -2069,29 +2917,130 @@ fn literals() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn nested_abbreviations() -> Result<()> {
+    let symbol = |name: &str| DatumBody::Symbol(name.into()).no_locate();
+    let integer = |i| DatumBody::Primitive(Primitive::Integer(i)).no_locate();
+    let list = |data: Vec<Datum>| DatumBody::Pair(Box::new(data.into_iter().collect())).no_locate();
+    let abbreviation = |keyword: &str, datum| list(vec![symbol(keyword), datum]);
+    let vector = |data: Vec<Datum>| DatumBody::Vector(data).no_locate();
+    let parse = |tokens: Vec<TokenData>| {
+        token_stream_to_parser(convert_located(tokens).into_iter()).collect::<Result<Vec<_>>>()
+    };
+
+    // #('a (1 'b) c) 2
+    assert_eq!(
+        parse(vec![
+            TokenData::VecConsIntro,
+            TokenData::Quote,
+            TokenData::Identifier("a".into()),
+            TokenData::LeftParen,
+            TokenData::Primitive(Primitive::Integer(1)),
+            TokenData::Quote,
+            TokenData::Identifier("b".into()),
+            TokenData::RightParen,
+            TokenData::Identifier("c".into()),
+            TokenData::RightParen,
+            TokenData::Primitive(Primitive::Integer(2)),
+        ])?,
+        vec![
+            Statement::Expression(
+                ExpressionBody::Datum(vector(vec![
+                    abbreviation("quote", symbol("a")),
+                    list(vec![integer(1), abbreviation("quote", symbol("b"))]),
+                    symbol("c"),
+                ]))
+                .into()
+            ),
+            Statement::Expression(ExpressionBody::Primitive(Primitive::Integer(2)).into()),
+        ]
+    );
+    // '(a #(b 'c) d)
+    assert_eq!(
+        parse(vec![
+            TokenData::Quote,
+            TokenData::LeftParen,
+            TokenData::Identifier("a".into()),
+            TokenData::VecConsIntro,
+            TokenData::Identifier("b".into()),
+            TokenData::Quote,
+            TokenData::Identifier("c".into()),
+            TokenData::RightParen,
+            TokenData::Identifier("d".into()),
+            TokenData::RightParen,
+        ])?,
+        vec![Statement::Expression(
+            ExpressionBody::Quote(Box::new(list(vec![
+                symbol("a"),
+                vector(vec![symbol("b"), abbreviation("quote", symbol("c"))]),
+                symbol("d"),
+            ])))
+            .into()
+        )]
+    );
+    // '(`a ,b ,@c '#(d))
+    assert_eq!(
+        parse(vec![
+            TokenData::Quote,
+            TokenData::LeftParen,
+            TokenData::Quasiquote,
+            TokenData::Identifier("a".into()),
+            TokenData::Unquote,
+            TokenData::Identifier("b".into()),
+            TokenData::UnquoteSplicing,
+            TokenData::Identifier("c".into()),
+            TokenData::Quote,
+            TokenData::VecConsIntro,
+            TokenData::Identifier("d".into()),
+            TokenData::RightParen,
+            TokenData::RightParen,
+        ])?,
+        vec![Statement::Expression(
+            ExpressionBody::Quote(Box::new(list(vec![
+                abbreviation("quasiquote", symbol("a")),
+                abbreviation("unquote", symbol("b")),
+                abbreviation("unquote-splicing", symbol("c")),
+                abbreviation("quote", vector(vec![symbol("d")])),
+            ])))
+            .into()
+        )]
+    );
+    // an abbreviation needs a datum
+    assert_eq!(
+        parse(vec![
+            TokenData::VecConsIntro,
+            TokenData::Quote,
+            TokenData::RightParen,
+        ])
+        .map_err(|e| e.data),
+        Err(SyntaxError::UnmatchedParentheses.into())
+    );
+    Ok(())
+}
+
 #[test]
 fn macros() -> Result<()> {
     let tokens = convert_located(vec![
         TokenData::LeftParen,
-        TokenData::Identifier("define-syntax".to_string()),
-        TokenData::Identifier("begin".to_string()),
+        TokenData::Identifier("define-syntax".into()),
+        TokenData::Identifier("begin".into()),
         TokenData::LeftParen,
-        TokenData::Identifier("syntax-rules".to_string()),
+        TokenData::Identifier("syntax-rules".into()),
         TokenData::LeftParen,
         TokenData::RightParen,
         TokenData::LeftParen,
         TokenData::LeftParen,
-        TokenData::Identifier("begin".to_string()),
-        TokenData::Identifier("exp".to_string()),
-        TokenData::Identifier("...".to_string()),
+        TokenData::Identifier("begin".into()),
+        TokenData::Identifier("exp".into()),
+        TokenData::Identifier("...".into()),
         TokenData::RightParen,
         TokenData::LeftParen,
         TokenData::LeftParen,
-        TokenData::Identifier("lambda".to_string()),
+        TokenData::Identifier("lambda".into()),
         TokenData::LeftParen,
         TokenData::RightParen,
-        TokenData::Identifier("exp".to_string()),
-        TokenData::Identifier("...".to_string()),
+        TokenData::Identifier("exp".into()),
+        TokenData::Identifier("...".into()),
         TokenData::RightParen,
         TokenData::RightParen,
         TokenData::RightParen,
@@ -2105,20 +3054,20 @@ fn macros() -> Result<()> {
         asts,
         vec![Statement::SyntaxDefinition(
             SyntaxDefBody(
-                "begin".to_owned(),
+                "begin".into(),
                 UserDefinedTransformer {
                     ellipsis: None,
                     literals: HashSet::new(),
                     rules: vec![(
                         SyntaxPatternBody::Pair(Box::new(list![
-                            SyntaxPatternBody::Identifier("exp".to_string()).into(),
+                            SyntaxPatternBody::Identifier("exp".into()).into(),
                             SyntaxPatternBody::Ellipsis.into()
                         ]))
                         .into(),
                         SyntaxTemplateBody::Pair(Box::new(list![SyntaxTemplateElement(
                             SyntaxTemplateBody::Pair(Box::new(list![
                                 SyntaxTemplateElement(
-                                    SyntaxTemplateBody::Identifier("lambda".to_string()).into(),
+                                    SyntaxTemplateBody::Identifier("lambda".into()).into(),
                                     false
                                 ),
                                 SyntaxTemplateElement(
@@ -2126,7 +3075,7 @@ fn macros() -> Result<()> {
                                     false
                                 ),
                                 SyntaxTemplateElement(
-                                    SyntaxTemplateBody::Identifier("exp".to_string()).into(),
+                                    SyntaxTemplateBody::Identifier("exp".into()).into(),
                                     true
                                 )
                             ]))
@@ -2149,9 +3098,9 @@ fn library_name() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("f".to_string()),
+            TokenData::Identifier("f".into()),
             TokenData::Primitive(Primitive::Integer(5)),
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2171,9 +3120,9 @@ fn library_name() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("f".to_string()),
+            TokenData::Identifier("f".into()),
             TokenData::Primitive(Primitive::Integer(-5)),
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2185,9 +3134,9 @@ fn library_name() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("f".to_string()),
+            TokenData::Identifier("f".into()),
             TokenData::Primitive(Primitive::String("haha".to_string())),
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2203,13 +3152,13 @@ fn export_spec() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-library".to_string()),
+            TokenData::Identifier("define-library".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("export".to_string()),
-            TokenData::Identifier("a".to_string()),
+            TokenData::Identifier("export".into()),
+            TokenData::Identifier("a".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -2233,16 +3182,16 @@ fn export_spec() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-library".to_string()),
+            TokenData::Identifier("define-library".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("export".to_string()),
+            TokenData::Identifier("export".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("rename".to_string()),
-            TokenData::Identifier("a".to_string()),
-            TokenData::Identifier("b".to_string()),
+            TokenData::Identifier("rename".into()),
+            TokenData::Identifier("a".into()),
+            TokenData::Identifier("b".into()),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2268,16 +3217,16 @@ fn export_spec() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-library".to_string()),
+            TokenData::Identifier("define-library".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("export".to_string()),
+            TokenData::Identifier("export".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("c".to_string()),
-            TokenData::Identifier("a".to_string()),
-            TokenData::Identifier("b".to_string()),
+            TokenData::Identifier("c".into()),
+            TokenData::Identifier("a".into()),
+            TokenData::Identifier("b".into()),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2292,15 +3241,15 @@ fn library_declaration() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-library".to_string()),
+            TokenData::Identifier("define-library".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("a".to_string()),
-            TokenData::Identifier("b".to_string()),
+            TokenData::Identifier("a".into()),
+            TokenData::Identifier("b".into()),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2329,14 +3278,14 @@ fn library_declaration() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-library".to_string()),
+            TokenData::Identifier("define-library".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("export".to_string()),
-            TokenData::Identifier("a".to_string()),
-            TokenData::Identifier("b".to_string()),
+            TokenData::Identifier("export".into()),
+            TokenData::Identifier("a".into()),
+            TokenData::Identifier("b".into()),
             TokenData::RightParen,
             TokenData::RightParen,
         ]);
@@ -2360,15 +3309,15 @@ fn library_declaration() -> Result<()> {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-library".to_string()),
+            TokenData::Identifier("define-library".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("foo".to_string()),
+            TokenData::Identifier("foo".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("begin".to_string()),
+            TokenData::Identifier("begin".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("define".to_string()),
-            TokenData::Identifier("s".to_string()),
+            TokenData::Identifier("define".into()),
+            TokenData::Identifier("s".into()),
             TokenData::Primitive(Primitive::String("a".to_string())),
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2383,7 +3332,7 @@ fn library_declaration() -> Result<()> {
                     library_name!("foo").into(),
                     vec![LibraryDeclaration::Begin(vec![Statement::Definition(
                         DefinitionBody(
-                            "s".to_string(),
+                            "s".into(),
                             ExpressionBody::Primitive(Primitive::String("a".to_string()))
                                 .no_locate()
                         )
@@ -2415,46 +3364,46 @@ fn library() {
     {
         let tokens = convert_located(vec![
             TokenData::LeftParen,
-            TokenData::Identifier("define-library".to_string()),
+            TokenData::Identifier("define-library".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("lib-a".to_string()),
+            TokenData::Identifier("lib-a".into()),
             TokenData::Primitive(Primitive::Integer(0)),
-            TokenData::Identifier("base".to_string()),
+            TokenData::Identifier("base".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("import".to_string()),
+            TokenData::Identifier("import".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("lib-b".to_string()),
+            TokenData::Identifier("lib-b".into()),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("begin".to_string()),
+            TokenData::Identifier("begin".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("define".to_string()),
-            TokenData::Identifier("c".to_string()),
+            TokenData::Identifier("define".into()),
+            TokenData::Identifier("c".into()),
             TokenData::Primitive(Primitive::Integer(0)),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("define".to_string()),
-            TokenData::Identifier("d".to_string()),
+            TokenData::Identifier("define".into()),
+            TokenData::Identifier("d".into()),
             TokenData::Primitive(Primitive::Integer(1)),
             TokenData::RightParen,
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("export".to_string()),
-            TokenData::Identifier("c".to_string()),
+            TokenData::Identifier("export".into()),
+            TokenData::Identifier("c".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("rename".to_string()),
-            TokenData::Identifier("d".to_string()),
-            TokenData::Identifier("e".to_string()),
+            TokenData::Identifier("rename".into()),
+            TokenData::Identifier("d".into()),
+            TokenData::Identifier("e".into()),
             TokenData::RightParen,
-            TokenData::Identifier("f".to_string()),
+            TokenData::Identifier("f".into()),
             TokenData::RightParen,
             TokenData::LeftParen,
-            TokenData::Identifier("begin".to_string()),
+            TokenData::Identifier("begin".into()),
             TokenData::LeftParen,
-            TokenData::Identifier("define".to_string()),
-            TokenData::Identifier("f".to_string()),
+            TokenData::Identifier("define".into()),
+            TokenData::Identifier("f".into()),
             TokenData::Primitive(Primitive::Integer(2)),
             TokenData::RightParen,
             TokenData::RightParen,
@@ -2483,14 +3432,14 @@ fn library() {
                         LibraryDeclaration::Begin(vec![
                             Statement::Definition(
                                 DefinitionBody(
-                                    "c".to_string(),
+                                    "c".into(),
                                     ExpressionBody::Primitive(Primitive::Integer(0)).into()
                                 )
                                 .into()
                             ),
                             Statement::Definition(
                                 DefinitionBody(
-                                    "d".to_string(),
+                                    "d".into(),
                                     ExpressionBody::Primitive(Primitive::Integer(1)).into()
                                 )
                                 .into()
@@ -2505,7 +3454,7 @@ fn library() {
                         .into(),
                         LibraryDeclaration::Begin(vec![Statement::Definition(
                             DefinitionBody(
-                                "f".to_string(),
+                                "f".into(),
                                 ExpressionBody::Primitive(Primitive::Integer(2)).into()
                             )
                             .into()
@@ -2518,3 +3467,397 @@ fn library() {
         )
     }
 }
+
+#[test]
+fn pathological_nesting() {
+    let nested = |open: &str, depth: usize, close: &str| {
+        format!("{}x{}", open.repeat(depth), close.repeat(depth))
+    };
+    for source in &[
+        nested("(", 100_000, ")"),
+        nested("'", 100_000, ""),
+        nested("`,@", 100_000, ""),
+        nested("#(", 100_000, ")"),
+        nested("'(#(", 100_000, "))"),
+        nested("(lambda () ", 100_000, ")"),
+        "(".repeat(100_000),
+    ] {
+        let error = Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+            .parse_root()
+            .unwrap_err();
+        assert_eq!(
+            error.data,
+            SyntaxError::TooDeeplyNested(DEFAULT_MAX_NESTING).into(),
+            "{}",
+            &source[..20]
+        );
+    }
+    // data nested within the limit are parsed and evaluated
+    let depth = DEFAULT_MAX_NESTING.min(5_001) - 1;
+    let mut interpreter = crate::interpreter::Interpreter::<f32>::new();
+    for source in &[
+        format!("'{}", nested("(", depth, ")")),
+        nested("'", depth, ""),
+        format!("'{}", nested("#(", depth, ")")),
+    ] {
+        assert!(interpreter.eval_str(source).is_ok(), "{}", &source[..20]);
+    }
+}
+
+#[test]
+fn misplaced_period() {
+    let parse =
+        |source: &str| Parser::from_lexer(Lexer::from_char_stream(source.chars())).parse_root();
+    for (source, column) in &[("(lambda (. x) x)", 11), ("'(. 1)", 4), ("'(1 .)", 7)] {
+        assert_eq!(
+            parse(source),
+            Err(ErrorData::from(SyntaxError::MisplacedPeriod).locate(Some([1, *column].into()))),
+            "{}",
+            source
+        );
+    }
+    assert!(parse("(define (f . args) args)").is_ok());
+    assert!(parse("(lambda (x . rest) rest)").is_ok());
+}
+
+#[test]
+fn duplicate_parameters() {
+    let parse = |source: &str| {
+        Parser::from_lexer(Lexer::from_char_stream(source.chars())).collect::<Result<Vec<_>>>()
+    };
+    for (source, name, column) in &[
+        ("(lambda (x x) x)", "x", 13),
+        ("(define (f a b a) a)", "a", 17),
+        ("(lambda (x y . x) x)", "x", 17),
+        ("(let ((a 1) (a 2)) a)", "a", 15),
+    ] {
+        assert_eq!(
+            parse(source),
+            Err(
+                ErrorData::from(SyntaxError::DuplicateParameter((*name).into()))
+                    .locate(Some([1, *column].into()))
+            ),
+            "{}",
+            source
+        );
+    }
+    assert!(parse("(lambda (x y . z) x) (define (f a b) a) (let ((a 1) (b 2)) a)").is_ok());
+}
+
+#[test]
+fn duplicate_definitions() -> Result<()> {
+    let parse = |source: &str| {
+        Parser::from_lexer(Lexer::from_char_stream(source.chars())).collect::<Result<Vec<_>>>()
+    };
+    assert_eq!(
+        parse("(lambda ()\n  (define x 1)\n  (define x 2)\n  x)"),
+        Err(ErrorData::from(SyntaxError::DuplicateDefinition(
+            "x".into(),
+            Some(Location::from([2, 4]).with_length(1))
+        ))
+        .locate(Some(Location::from([3, 4]).with_length(1))))
+    );
+    let error = parse("(define-library (l) (begin (define x 1)) (begin (define (x) 2)))")
+        .unwrap_err()
+        .data;
+    assert_eq!(
+        error.to_string(),
+        "syntax error: duplicate definition of x, first defined at 1:29"
+    );
+    assert!(parse("(lambda () (define x 1) (define y 2) x)").is_ok());
+    // redefinitions at the top level of programs are allowed
+    let mut interpreter = crate::interpreter::Interpreter::<f32>::new();
+    assert_eq!(
+        interpreter.eval_str("(define x 1) (define x 2) x")?,
+        interpreter.eval_str("2")?
+    );
+    Ok(())
+}
+
+#[test]
+fn definition_in_expression_context() {
+    let parse = |source: &str| {
+        Parser::from_lexer(Lexer::from_char_stream(source.chars())).collect::<Result<Vec<_>>>()
+    };
+    for (source, column) in &[
+        ("(+ 1 (define x 2))", 7),
+        ("(if (define x 1) 1 2)", 6),
+        ("(if #t (define x 1))", 9),
+        ("(set! y (define x 1))", 10),
+        ("((define x 1))", 3),
+    ] {
+        assert_eq!(
+            parse(source),
+            Err(
+                ErrorData::from(SyntaxError::DefinitionInExpressionContext("x".into()))
+                    .locate(Some([1, *column].into()))
+            ),
+            "{}",
+            source
+        );
+    }
+    assert_eq!(
+        parse("(+ 1 (import (scheme base)))"),
+        Err(
+            ErrorData::from(SyntaxError::NotAnExpression("import".to_string()))
+                .locate(Some([1, 7].into()))
+        )
+    );
+    assert!(parse("(define x 1) (lambda () (define y x) (define (z) y) (z))").is_ok());
+}
+
+#[test]
+fn locations_are_kept() -> Result<()> {
+    use super::visit::*;
+    // descriptions of the nodes parsed without a location
+    #[derive(Default)]
+    struct Unlocated(Vec<String>);
+    impl Visitor for Unlocated {
+        fn visit_statement(&mut self, statement: &Statement) {
+            if statement.location().is_none() {
+                self.0.push(statement.to_string());
+            }
+            walk_statement(self, statement)
+        }
+        fn visit_definition(&mut self, definition: &Definition) {
+            if definition.location.is_none() {
+                self.0.push(definition.to_string());
+            }
+            walk_definition(self, definition)
+        }
+        fn visit_import_set(&mut self, import_set: &ImportSet) {
+            if import_set.location.is_none() {
+                self.0.push(format!("{:?}", import_set.data));
+            }
+            walk_import_set(self, import_set)
+        }
+        fn visit_expression(&mut self, expression: &Expression) {
+            if expression.location.is_none() {
+                self.0.push(expression.to_string());
+            }
+            walk_expression(self, expression)
+        }
+        fn visit_parameter(&mut self, parameter: &ParameterFormals) {
+            if parameter.location.is_none() {
+                self.0.push(parameter.to_string());
+            }
+        }
+        fn visit_datum(&mut self, datum: &Datum) {
+            if datum.location.is_none() {
+                self.0.push(datum.to_string());
+            }
+        }
+    }
+    let source = "
+        (import (only (prefix (scheme base) b:) b:car) (rename (scheme write) (display show)))
+        (define x 1)
+        (define (f a . rest) (define y a) (set! x y) (if (g a) 'quoted #(1 2)))
+        (define-library (l) (export f (rename g h)) (import (scheme base)) (begin (define g 2)))
+        ((lambda args (let ((z 1)) (cond ((= z 1) z) (else x)))) 1 2)";
+    let mut unlocated = Unlocated::default();
+    for statement in Parser::from_lexer(Lexer::from_char_stream(source.chars())) {
+        unlocated.visit_statement(&statement?);
+    }
+    assert_eq!(unlocated.0, Vec::<String>::new());
+    // and so are the errors of forms missing their parts
+    for (source, column, length) in &[
+        ("()", 2, 1),
+        ("(lambda (x))", 2, 1),
+        ("(+ 1 (if))", 7, 1),
+        ("(define-library (l) (export (foo a b)))", 33, 3),
+    ] {
+        let error = Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+            .parse_root()
+            .unwrap_err();
+        assert_eq!(
+            error.location,
+            Some(Location::from([1, *column]).with_length(*length)),
+            "{}",
+            source
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn definition_after_expression() -> Result<()> {
+    let source = "(lambda ()\n  (display 1)\n  (newline)\n  (define x 2)\n  x)";
+    let error = Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+        .parse_root()
+        .unwrap_err();
+    assert_eq!(
+        error,
+        ErrorData::from(SyntaxError::DefinitionAfterExpression(
+            "x".into(),
+            Some(Location::from([2, 4]).with_length(1))
+        ))
+        .locate(Some(Location::from([4, 4]).with_length(1)))
+    );
+    assert_eq!(
+        error.data.to_string(),
+        "syntax error: internal definitions must precede expressions in a body, \
+         the definition of x follows the expression at 2:4"
+    );
+
+    let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+    parser.set_mixed_bodies(true);
+    let mixed = parser.parse_root()?.unwrap();
+    assert_eq!(
+        mixed.to_string(),
+        "(lambda () (define x ((lambda () (display 1) (newline) 2))) x)"
+    );
+    // only bodies parsed by that parser are relaxed
+    assert!(Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+        .parse_root()
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn nesting_limit() -> Result<()> {
+    let parse = |source: &str| {
+        let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+        parser.set_max_nesting(3);
+        parser.parse_datum()
+    };
+    assert!(parse("(1 (2 #(3)))")?.is_some());
+    assert_eq!(
+        parse("(1 (2 #((3))))"),
+        Err(ErrorData::from(SyntaxError::TooDeeplyNested(3)).locate(Some([1, 10].into())))
+    );
+    assert_eq!(
+        parse("'((((x))))").map_err(|e| e.data),
+        Err(SyntaxError::TooDeeplyNested(3).into())
+    );
+    // deeper than the native stack would allow without a limit
+    let source = format!("{}{}", "(".repeat(100_000), ")".repeat(100_000));
+    assert_eq!(
+        Parser::from_lexer(Lexer::from_char_stream(source.chars()))
+            .parse_datum()
+            .map_err(|e| e.data),
+        Err(SyntaxError::TooDeeplyNested(DEFAULT_MAX_NESTING).into())
+    );
+    Ok(())
+}
+
+#[test]
+fn error_recovery() {
+    // the second form is not closed, which is only skipped up to the next one
+    let source = "(define a 1)
+(f . . x
+(define b
+  (g #z 2))
+(display b)
+)
+(if a 'yes 'no)";
+    let (statements, errors) =
+        Parser::from_lexer(Lexer::from_char_stream(source.chars())).parse_all_recovering();
+    assert_eq!(
+        statements
+            .iter()
+            .map(|statement| statement.location().map(|location| location.line))
+            .collect::<Vec<_>>(),
+        vec![Some(1), Some(5), Some(7)]
+    );
+    assert_eq!(
+        errors
+            .iter()
+            .map(|error| (
+                error.data.clone(),
+                error.location.map(|location| location.line)
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                SyntaxError::UnexpectedToken(TokenData::Period).into(),
+                Some(2)
+            ),
+            (SyntaxError::UnrecognizedToken.into(), Some(4)),
+            (SyntaxError::UnmatchedParentheses.into(), Some(6)),
+        ]
+    );
+}
+
+#[test]
+fn random_input() {
+    // a linear congruential generator, so that failures are reproducible
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 33) as usize
+    };
+    let alphabet = b"()#'`,@.+-/e0123456789\\\"|;xaf\\ \n";
+    for _ in 0..2000 {
+        let length = next() % 64;
+        let bytes = (0..length)
+            .map(|_| match next() % 4 {
+                // mostly characters meaningful to the lexer, so that inputs get past the first token
+                0 => next() as u8,
+                _ => alphabet[next() % alphabet.len()],
+            })
+            .collect::<Vec<_>>();
+        let source = String::from_utf8_lossy(&bytes);
+        for result in Parser::from_lexer(Lexer::from_char_stream(source.chars())) {
+            if result.is_err() {
+                break;
+            }
+        }
+        Parser::from_lexer(Lexer::from_char_stream(source.chars())).parse_all_recovering();
+    }
+}
+
+#[cfg(test)]
+fn expanded(source: &str) -> Result<String> {
+    Ok(expand_program(source.chars(), SourceId::string())?
+        .iter()
+        .join("\n"))
+}
+
+#[test]
+fn expand_derived_forms() -> Result<()> {
+    assert_eq!(
+        expanded("(let ((x 1) (y \"s\")) (+ x y))")?,
+        "((lambda (x y) (+ x y)) 1 \"s\")"
+    );
+    assert_eq!(
+        expanded(
+            "(define-syntax swap!
+               (syntax-rules ()
+                 ((swap! a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+             (swap! p q)"
+        )?,
+        "(define-syntax swap! (syntax-rules () ((swap! a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+((lambda (tmp) (set! p q) (set! q tmp)) p)"
+    );
+    assert_eq!(
+        expanded("(define (f . args) (define n 1) (cond ((null? args) n) (else 'x)))")?,
+        "(define (f . args) (define n 1) (if (null? args) ((lambda () n)) ((lambda () 'x))))"
+    );
+    Ok(())
+}
+
+#[test]
+fn expansion_round_trip() -> Result<()> {
+    let source = r#"
+        (import (scheme base) (only (prefix (scheme write) w:) w:display))
+        (define-library (lib numbers)
+          (export half (rename half demi))
+          (import (scheme base))
+          (begin (define (half x) (/ x 2))))
+        (define (fold f init . lists)
+          (define (loop acc l)
+            (if (null? l) acc (loop (f (car l) acc) (cdr l))))
+          (let* ((l (car lists))) (loop init l)))
+        (define v '#(1 "two" #\3 (a . b) |odd symbol|))
+        (case (fold + 0 '(1 2 3)) ((6) => (lambda (x) x)) (else 1.5))
+        (set! v (lambda args (and args (or (car args) "\"quoted\"\n"))))
+    "#;
+    let statements = expand_program(source.chars(), SourceId::string())?;
+    let printed = statements.iter().join("\n");
+    let read_back = expand_program(printed.chars(), SourceId::string())?;
+    assert_eq!(read_back, statements);
+    assert_eq!(read_back.iter().join("\n"), printed);
+    Ok(())
+}
@@ -0,0 +1,468 @@
+//! Traversals of syntax trees for tools analyzing or transforming programs.
+//!
+//! A `Visitor` reads a tree and a `Folder` rebuilds it. Every method has a default calling the
+//! `walk_*` (for visitors) or `rebuild_*` (for folders) function of its node, which goes on with
+//! the children, so implementations only override the nodes they are interested in and call the
+//! function themselves to keep descending. Folded nodes keep their locations.
+//!
+//! Quoted data and macro definitions are leaves, they are passed to `visit_datum` and
+//! `visit_syntax_definition` but not descended into.
+use super::{pair::*, *};
+use crate::{error::*, stack};
+use either::Either;
+use std::mem;
+
+pub trait Visitor {
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_definition(&mut self, definition: &Definition) {
+        walk_definition(self, definition)
+    }
+
+    fn visit_syntax_definition(&mut self, _definition: &SyntaxDef) {}
+
+    fn visit_library_definition(&mut self, library: &Located<LibraryDefinition>) {
+        walk_library_definition(self, library)
+    }
+
+    fn visit_import_set(&mut self, import_set: &ImportSet) {
+        walk_import_set(self, import_set)
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression)
+    }
+
+    fn visit_procedure(&mut self, procedure: &SchemeProcedure) {
+        walk_procedure(self, procedure)
+    }
+
+    /// a parameter name, including the variadic one
+    fn visit_parameter(&mut self, _parameter: &ParameterFormals) {}
+
+    fn visit_datum(&mut self, _datum: &Datum) {}
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::ImportDeclaration(declaration) => declaration
+            .0
+            .iter()
+            .for_each(|import_set| visitor.visit_import_set(import_set)),
+        Statement::Definition(definition) => visitor.visit_definition(definition),
+        Statement::SyntaxDefinition(definition) => visitor.visit_syntax_definition(definition),
+        Statement::Expression(expression) => visitor.visit_expression(expression),
+        Statement::LibraryDefinition(library) => visitor.visit_library_definition(library),
+    }
+}
+
+pub fn walk_definition<V: Visitor + ?Sized>(visitor: &mut V, definition: &Definition) {
+    visitor.visit_expression(&definition.1)
+}
+
+pub fn walk_library_definition<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    library: &Located<LibraryDefinition>,
+) {
+    for declaration in library.1.iter() {
+        match &declaration.data {
+            LibraryDeclaration::ImportDeclaration(declaration) => declaration
+                .0
+                .iter()
+                .for_each(|import_set| visitor.visit_import_set(import_set)),
+            LibraryDeclaration::Export(_) => (),
+            LibraryDeclaration::Begin(statements) => statements
+                .iter()
+                .for_each(|statement| visitor.visit_statement(statement)),
+        }
+    }
+}
+
+pub fn walk_import_set<V: Visitor + ?Sized>(visitor: &mut V, import_set: &ImportSet) {
+    match &import_set.data {
+        ImportSetBody::Direct(_) => (),
+        ImportSetBody::Only(inner, _)
+        | ImportSetBody::Except(inner, _)
+        | ImportSetBody::Prefix(inner, _)
+        | ImportSetBody::Rename(inner, _) => visitor.visit_import_set(inner),
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    stack::guard(|| match &expression.data {
+        ExpressionBody::Symbol(_)
+        | ExpressionBody::LocalVariable(..)
+        | ExpressionBody::Primitive(_)
+        | ExpressionBody::Period => (),
+        ExpressionBody::Assignment(_, value) | ExpressionBody::LocalAssignment(_, _, value) => {
+            visitor.visit_expression(value)
+        }
+        ExpressionBody::Procedure(procedure) => visitor.visit_procedure(procedure),
+        ExpressionBody::ProcedureCall(procedure, arguments) => {
+            visitor.visit_expression(procedure);
+            arguments
+                .iter()
+                .for_each(|argument| visitor.visit_expression(argument));
+        }
+        ExpressionBody::Conditional(conditional) => {
+            let (test, consequent, alternative) = conditional.as_ref();
+            visitor.visit_expression(test);
+            visitor.visit_expression(consequent);
+            if let Some(alternative) = alternative {
+                visitor.visit_expression(alternative);
+            }
+        }
+        ExpressionBody::Quote(datum) => visitor.visit_datum(datum),
+        ExpressionBody::Datum(datum) => visitor.visit_datum(datum),
+    })
+}
+
+pub fn walk_procedure<V: Visitor + ?Sized>(visitor: &mut V, procedure: &SchemeProcedure) {
+    let SchemeProcedure(formals, definitions, expressions, _) = procedure;
+    if let Some(variadic) = formals.iter_to_last(|fixed| visitor.visit_parameter(fixed)) {
+        visitor.visit_parameter(variadic);
+    }
+    definitions
+        .iter()
+        .for_each(|definition| visitor.visit_definition(definition));
+    expressions
+        .iter()
+        .for_each(|expression| visitor.visit_expression(expression));
+}
+
+pub trait Folder {
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        rebuild_statement(self, statement)
+    }
+
+    fn fold_definition(&mut self, definition: Definition) -> Definition {
+        rebuild_definition(self, definition)
+    }
+
+    fn fold_syntax_definition(&mut self, definition: SyntaxDef) -> SyntaxDef {
+        definition
+    }
+
+    fn fold_library_definition(
+        &mut self,
+        library: Located<LibraryDefinition>,
+    ) -> Located<LibraryDefinition> {
+        rebuild_library_definition(self, library)
+    }
+
+    fn fold_import_set(&mut self, import_set: ImportSet) -> ImportSet {
+        rebuild_import_set(self, import_set)
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        rebuild_expression(self, expression)
+    }
+
+    fn fold_procedure(&mut self, procedure: SchemeProcedure) -> SchemeProcedure {
+        rebuild_procedure(self, procedure)
+    }
+
+    /// a parameter name, including the variadic one
+    fn fold_parameter(&mut self, parameter: ParameterFormals) -> ParameterFormals {
+        parameter
+    }
+
+    fn fold_datum(&mut self, datum: Datum) -> Datum {
+        datum
+    }
+}
+
+fn rebuild_import_declaration<F: Folder + ?Sized>(
+    folder: &mut F,
+    declaration: Located<ImportDeclaration>,
+) -> Located<ImportDeclaration> {
+    Located {
+        data: ImportDeclaration(
+            declaration
+                .data
+                .0
+                .into_iter()
+                .map(|import_set| folder.fold_import_set(import_set))
+                .collect(),
+        ),
+        location: declaration.location,
+    }
+}
+
+pub fn rebuild_statement<F: Folder + ?Sized>(folder: &mut F, statement: Statement) -> Statement {
+    match statement {
+        Statement::ImportDeclaration(declaration) => {
+            Statement::ImportDeclaration(rebuild_import_declaration(folder, declaration))
+        }
+        Statement::Definition(definition) => {
+            Statement::Definition(folder.fold_definition(definition))
+        }
+        Statement::SyntaxDefinition(definition) => {
+            Statement::SyntaxDefinition(folder.fold_syntax_definition(definition))
+        }
+        Statement::Expression(expression) => {
+            Statement::Expression(folder.fold_expression(expression))
+        }
+        Statement::LibraryDefinition(library) => {
+            Statement::LibraryDefinition(folder.fold_library_definition(library))
+        }
+    }
+}
+
+pub fn rebuild_definition<F: Folder + ?Sized>(
+    folder: &mut F,
+    definition: Definition,
+) -> Definition {
+    let Located {
+        data: DefinitionBody(name, expression),
+        location,
+    } = definition;
+    Located {
+        data: DefinitionBody(name, folder.fold_expression(expression)),
+        location,
+    }
+}
+
+pub fn rebuild_library_definition<F: Folder + ?Sized>(
+    folder: &mut F,
+    library: Located<LibraryDefinition>,
+) -> Located<LibraryDefinition> {
+    let Located {
+        data: LibraryDefinition(name, declarations),
+        location,
+    } = library;
+    let declarations = declarations
+        .into_iter()
+        .map(|declaration| Located {
+            data: match declaration.data {
+                LibraryDeclaration::ImportDeclaration(import) => {
+                    LibraryDeclaration::ImportDeclaration(rebuild_import_declaration(
+                        folder, import,
+                    ))
+                }
+                LibraryDeclaration::Export(specs) => LibraryDeclaration::Export(specs),
+                LibraryDeclaration::Begin(statements) => LibraryDeclaration::Begin(
+                    statements
+                        .into_iter()
+                        .map(|statement| folder.fold_statement(statement))
+                        .collect(),
+                ),
+            },
+            location: declaration.location,
+        })
+        .collect();
+    Located {
+        data: LibraryDefinition(name, declarations),
+        location,
+    }
+}
+
+pub fn rebuild_import_set<F: Folder + ?Sized>(folder: &mut F, import_set: ImportSet) -> ImportSet {
+    let mut fold_inner = |mut inner: Box<ImportSet>| {
+        *inner = folder.fold_import_set(*inner);
+        inner
+    };
+    Located {
+        data: match import_set.data {
+            ImportSetBody::Direct(name) => ImportSetBody::Direct(name),
+            ImportSetBody::Only(inner, names) => ImportSetBody::Only(fold_inner(inner), names),
+            ImportSetBody::Except(inner, names) => ImportSetBody::Except(fold_inner(inner), names),
+            ImportSetBody::Prefix(inner, prefix) => {
+                ImportSetBody::Prefix(fold_inner(inner), prefix)
+            }
+            ImportSetBody::Rename(inner, renames) => {
+                ImportSetBody::Rename(fold_inner(inner), renames)
+            }
+        },
+        location: import_set.location,
+    }
+}
+
+pub fn rebuild_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression: Expression,
+) -> Expression {
+    // boxed children are folded in place
+    fn fold_boxed<F: Folder + ?Sized>(
+        folder: &mut F,
+        mut boxed: Box<Expression>,
+    ) -> Box<Expression> {
+        *boxed = folder.fold_expression(*boxed);
+        boxed
+    }
+    let Located { data, location } = expression;
+    let data = stack::guard(|| match data {
+        ExpressionBody::Symbol(_)
+        | ExpressionBody::LocalVariable(..)
+        | ExpressionBody::Primitive(_)
+        | ExpressionBody::Period => data,
+        ExpressionBody::Assignment(name, value) => {
+            ExpressionBody::Assignment(name, fold_boxed(folder, value))
+        }
+        ExpressionBody::LocalAssignment(name, address, value) => {
+            ExpressionBody::LocalAssignment(name, address, fold_boxed(folder, value))
+        }
+        ExpressionBody::Procedure(procedure) => {
+            ExpressionBody::Procedure(folder.fold_procedure(procedure))
+        }
+        ExpressionBody::ProcedureCall(procedure, arguments) => ExpressionBody::ProcedureCall(
+            fold_boxed(folder, procedure),
+            arguments
+                .into_iter()
+                .map(|argument| folder.fold_expression(argument))
+                .collect(),
+        ),
+        ExpressionBody::Conditional(mut conditional) => {
+            let (test, consequent, alternative) = *conditional;
+            *conditional = (
+                folder.fold_expression(test),
+                folder.fold_expression(consequent),
+                alternative.map(|alternative| folder.fold_expression(alternative)),
+            );
+            ExpressionBody::Conditional(conditional)
+        }
+        ExpressionBody::Quote(mut datum) => {
+            *datum = folder.fold_datum(*datum);
+            ExpressionBody::Quote(datum)
+        }
+        ExpressionBody::Datum(datum) => ExpressionBody::Datum(folder.fold_datum(datum)),
+    });
+    Located { data, location }
+}
+
+// parameters are folded in place, so that the pairs of the list keep their locations
+fn rebuild_formals<F: Folder + ?Sized>(
+    folder: &mut F,
+    mut formals: ParameterFormals,
+) -> ParameterFormals {
+    let mut fold = |parameter: &mut ParameterFormals| {
+        let placeholder = ParameterFormals::new_non_located(std::iter::empty(), None);
+        *parameter = folder.fold_parameter(mem::replace(parameter, placeholder));
+    };
+    let mut next = &mut formals;
+    loop {
+        match next.either_pair_mut() {
+            Either::Left(GenericPair::Some(fixed, rest)) => {
+                fold(fixed);
+                next = rest;
+            }
+            Either::Left(GenericPair::Empty) => break,
+            Either::Right(variadic) => {
+                fold(variadic);
+                break;
+            }
+        }
+    }
+    formals
+}
+
+pub fn rebuild_procedure<F: Folder + ?Sized>(
+    folder: &mut F,
+    procedure: SchemeProcedure,
+) -> SchemeProcedure {
+    let SchemeProcedure(formals, definitions, expressions, name) = procedure;
+    SchemeProcedure(
+        rebuild_formals(folder, formals),
+        definitions
+            .into_iter()
+            .map(|definition| folder.fold_definition(definition))
+            .collect(),
+        expressions
+            .into_iter()
+            .map(|expression| folder.fold_expression(expression))
+            .collect(),
+        name,
+    )
+}
+
+#[cfg(test)]
+fn parse(source: &str) -> Result<Vec<Statement>> {
+    Parser::from_lexer(Lexer::from_char_stream(source.chars())).collect()
+}
+
+#[test]
+fn count_identifiers() -> Result<()> {
+    #[derive(Default)]
+    struct Identifiers(usize);
+    impl Visitor for Identifiers {
+        fn visit_expression(&mut self, expression: &Expression) {
+            if let ExpressionBody::Symbol(_) = &expression.data {
+                self.0 += 1;
+            }
+            walk_expression(self, expression)
+        }
+    }
+    let mut identifiers = Identifiers::default();
+    for statement in parse(
+        "(define (f x) (if (> x 0) (g 'quoted x) 1))
+         (define-library (lib) (export h) (begin (define (h y) (set! y (f y)) y)))",
+    )? {
+        identifiers.visit_statement(&statement);
+    }
+    // >, x, g, x, f, y, y; quoted data and the assigned name are not references
+    assert_eq!(identifiers.0, 7);
+    Ok(())
+}
+
+#[test]
+fn rename_variable() -> Result<()> {
+    use crate::symbol::Symbol;
+    struct Rename(Symbol, Symbol);
+    impl Rename {
+        fn rename(&self, name: Symbol) -> Symbol {
+            match name == self.0 {
+                true => self.1.clone(),
+                false => name,
+            }
+        }
+    }
+    impl Folder for Rename {
+        fn fold_definition(&mut self, definition: Definition) -> Definition {
+            let Located { data, location } = rebuild_definition(self, definition);
+            Located {
+                data: DefinitionBody(self.rename(data.0), data.1),
+                location,
+            }
+        }
+        fn fold_expression(&mut self, expression: Expression) -> Expression {
+            let Located { data, location } = rebuild_expression(self, expression);
+            let data = match data {
+                ExpressionBody::Symbol(name) => ExpressionBody::Symbol(self.rename(name)),
+                ExpressionBody::Assignment(name, value) => {
+                    ExpressionBody::Assignment(self.rename(name), value)
+                }
+                data => data,
+            };
+            Located { data, location }
+        }
+        fn fold_parameter(&mut self, parameter: ParameterFormals) -> ParameterFormals {
+            ParameterFormalsBody::Name(self.rename(parameter.as_name())).locate(parameter.location)
+        }
+    }
+    let mut rename = Rename("x".into(), "renamed".into());
+    let renamed = parse("(define x 1) (define (f y . x) (set! x (+ x y 'x)) x)")?
+        .into_iter()
+        .map(|statement| rename.fold_statement(statement))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        renamed,
+        parse(
+            "(define renamed 1)
+             (define (f y . renamed) (set! renamed (+ renamed y 'x)) renamed)"
+        )?
+    );
+    // the locations of the original program are kept
+    let f = match &renamed[1] {
+        Statement::Definition(definition) => match &definition.1.data {
+            ExpressionBody::Procedure(procedure) => procedure.clone(),
+            other => panic!("unexpected expression {:?}", other),
+        },
+        other => panic!("unexpected statement {:?}", other),
+    };
+    let location = f.0.iter_to_last(|_| ()).unwrap().location.unwrap();
+    assert_eq!((location.line, location.column), (1, 30));
+    assert_eq!(f.2[1].location.unwrap().column, 53);
+    Ok(())
+}
@@ -0,0 +1,523 @@
+// r7rs 6.13. Input and output
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{self, Debug, Display, Formatter},
+    io::{self, BufRead, Write},
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    error::*,
+    interpreter::error::LogicError,
+    parser::{Datum, Lexer, Parser},
+};
+
+type Result<T> = std::result::Result<T, SchemeError>;
+
+/// where an input port reads its characters from, line by line
+pub trait LineSource {
+    /// append the next line, including its line ending, to `line`, return 0 at the end
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize>;
+}
+
+impl<T: BufRead> LineSource for T {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        BufRead::read_line(self, line)
+    }
+}
+
+/// the standard input of the process. Lines are read through the buffer of `io::stdin()`, which
+/// the REPL reads piped input from as well, so that a program reading stdin never takes lines
+/// the REPL has not read yet.
+pub struct Console;
+
+impl LineSource for Console {
+    fn read_line(&mut self, line: &mut String) -> io::Result<usize> {
+        io::stdin().read_line(line)
+    }
+}
+
+/// whether stdin has data or has reached its end, checked without waiting. Terminals in canonical
+/// mode only have data after the line is entered.
+#[cfg(all(unix, feature = "native"))]
+pub fn stdin_ready() -> io::Result<bool> {
+    // `Console` reads lines through the buffer of io::stdin(), which may hold the next ones, it is
+    // only filled from the descriptor if empty, which does not wait while it is non-blocking
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let flags = unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_GETFL) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+    let ready = match stdin.fill_buf() {
+        // an empty buffer is the end of input, reading it does not wait either
+        Ok(_) => Ok(true),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+            ) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    };
+    unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_SETFL, flags) };
+    ready
+}
+
+/// elsewhere stdin cannot be polled, it is reported ready and reading it may wait
+#[cfg(not(all(unix, feature = "native")))]
+pub fn stdin_ready() -> io::Result<bool> {
+    Ok(true)
+}
+
+type Readiness = Box<dyn FnMut() -> io::Result<bool>>;
+
+pub struct InputPort {
+    name: String,
+    buffer: Vec<char>,
+    position: usize,
+    // string ports have no source, all their characters live in buffer
+    source: Option<Box<dyn LineSource>>,
+    // whether reading source would not wait, sources without it never wait
+    ready: Option<Readiness>,
+    closed: bool,
+}
+
+impl InputPort {
+    pub fn from_string(string: &str) -> Self {
+        Self {
+            name: "string".to_string(),
+            buffer: string.chars().collect(),
+            position: 0,
+            source: None,
+            ready: None,
+            closed: false,
+        }
+    }
+
+    pub fn from_reader(name: impl Into<String>, reader: impl BufRead + 'static) -> Self {
+        Self::from_source(name, reader, None)
+    }
+
+    /// a port reading `source`, which may wait for its input like pipes and terminals do,
+    /// `ready` tells whether it has input or has reached its end
+    pub fn from_source(
+        name: impl Into<String>,
+        source: impl LineSource + 'static,
+        ready: Option<Readiness>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            buffer: vec![],
+            position: 0,
+            source: Some(Box::new(source)),
+            ready,
+            closed: false,
+        }
+    }
+
+    /// the standard input of the process
+    pub fn stdin() -> Self {
+        Self::from_source("stdin", Console, Some(Box::new(stdin_ready)))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.closed
+    }
+
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.source = None;
+        self.ready = None;
+        self.buffer.clear();
+        self.position = 0;
+    }
+
+    fn check_open(&self) -> Result<()> {
+        match self.closed {
+            true => error!(LogicError::PortClosed(self.name.clone())),
+            false => Ok(()),
+        }
+    }
+
+    // pull one more line from source into buffer, return false when source reaches its end
+    fn fill(&mut self) -> Result<bool> {
+        match &mut self.source {
+            None => Ok(false),
+            Some(source) => {
+                if self.position == self.buffer.len() {
+                    self.buffer.clear();
+                    self.position = 0;
+                }
+                let mut line = String::new();
+                match source.read_line(&mut line) {
+                    Ok(0) => Ok(false),
+                    Ok(_) => {
+                        self.buffer.extend(line.chars());
+                        Ok(true)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+        }
+    }
+
+    pub fn peek_char(&mut self) -> Result<Option<char>> {
+        self.check_open()?;
+        while self.position == self.buffer.len() {
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.buffer[self.position]))
+    }
+
+    pub fn read_char(&mut self) -> Result<Option<char>> {
+        let c = self.peek_char()?;
+        if c.is_some() {
+            self.position += 1;
+        }
+        Ok(c)
+    }
+
+    // read chars until line ending, the line ending itself ("\n" or "\r\n") is consumed but dropped
+    pub fn read_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            match self.read_char()? {
+                Some('\n') => break,
+                Some('\r') if self.peek_char()? == Some('\n') => {
+                    self.read_char()?;
+                    break;
+                }
+                Some(c) => line.push(c),
+                None if line.is_empty() => return Ok(None),
+                None => break,
+            }
+        }
+        Ok(Some(line))
+    }
+
+    pub fn read_string(&mut self, k: usize) -> Result<Option<String>> {
+        let mut string = String::new();
+        for _ in 0..k {
+            match self.read_char()? {
+                Some(c) => string.push(c),
+                None => break,
+            }
+        }
+        Ok(match string.is_empty() && k > 0 {
+            true => None,
+            false => Some(string),
+        })
+    }
+
+    // string ports, buffered chars and sources that never wait are always ready, including at
+    // their end, the others are asked without reading them. A source may have part of a line
+    // only, read-char then waits for the rest of the line.
+    pub fn char_ready(&mut self) -> Result<bool> {
+        self.check_open()?;
+        if self.position < self.buffer.len() {
+            return Ok(true);
+        }
+        match &mut self.ready {
+            Some(ready) => Ok(ready()?),
+            None => Ok(true),
+        }
+    }
+
+    /// parse next datum from port, reusing the program parser,
+    /// the port is left right after the datum so that sequential reads resume from there.
+    pub fn read_datum(&mut self) -> Result<Option<Datum>> {
+        self.check_open()?;
+        let refill = Cell::new(true);
+        let io_error = RefCell::new(None);
+        let mut lexer = Lexer::from_char_stream(PortChars {
+            port: self,
+            refill: &refill,
+            io_error: &io_error,
+        });
+        let datum = Parser::from_lexer(&mut lexer).parse_datum();
+        // lexer may have looked one character ahead, give it back to port
+        refill.set(false);
+        let unread = lexer.peekable_char_stream.peek().is_some();
+        drop(lexer);
+        if unread {
+            self.position -= 1;
+        }
+        if let Some(e) = io_error.into_inner() {
+            return Err(e);
+        }
+        datum
+    }
+}
+
+struct PortChars<'a> {
+    port: &'a mut InputPort,
+    refill: &'a Cell<bool>,
+    io_error: &'a RefCell<Option<SchemeError>>,
+}
+
+impl<'a> Iterator for PortChars<'a> {
+    type Item = char;
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.refill.get() && self.port.position == self.port.buffer.len() {
+            return None;
+        }
+        match self.port.read_char() {
+            Ok(c) => c,
+            Err(e) => {
+                self.io_error.replace(Some(e));
+                None
+            }
+        }
+    }
+}
+
+enum OutputSink {
+    String(String),
+    Writer(Box<dyn Write>),
+}
+
+struct SharedWriter(Rc<RefCell<dyn Write>>);
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+pub struct OutputPort {
+    name: String,
+    sink: OutputSink,
+    closed: bool,
+}
+
+impl OutputPort {
+    pub fn new_string() -> Self {
+        Self {
+            name: "string".to_string(),
+            sink: OutputSink::String(String::new()),
+            closed: false,
+        }
+    }
+
+    pub fn from_writer(name: impl Into<String>, writer: impl Write + 'static) -> Self {
+        Self {
+            name: name.into(),
+            sink: OutputSink::Writer(Box::new(writer)),
+            closed: false,
+        }
+    }
+
+    /// port writing to a writer shared with the host application
+    pub fn from_shared_writer(name: impl Into<String>, writer: Rc<RefCell<dyn Write>>) -> Self {
+        Self::from_writer(name, SharedWriter(writer))
+    }
+
+    /// accumulated characters of a string port, None for other ports
+    pub fn get_string(&self) -> Option<&str> {
+        match &self.sink {
+            OutputSink::String(string) => Some(string),
+            OutputSink::Writer(_) => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.closed
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if let OutputSink::Writer(writer) = &mut self.sink {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        if !self.closed {
+            self.flush()?;
+            self.closed = true;
+            // release underlying file handle
+            if let OutputSink::Writer(writer) = &mut self.sink {
+                *writer = Box::new(io::sink());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_str(&mut self, string: &str) -> Result<()> {
+        if self.closed {
+            return error!(LogicError::PortClosed(self.name.clone()));
+        }
+        match &mut self.sink {
+            OutputSink::String(buffer) => buffer.push_str(string),
+            OutputSink::Writer(writer) => writer.write_all(string.as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+/// The output ports opened by an interpreter, see `Interpreter::flush_output_ports`. Buffered
+/// data is dropped silently if the process exits without flushing them.
+#[derive(Default)]
+pub struct OutputPorts(RefCell<Vec<Weak<RefCell<OutputPort>>>>);
+
+impl OutputPorts {
+    /// a port writing to `port`, which `flush` flushes as long as it is alive
+    pub fn open(&self, port: OutputPort) -> Port {
+        let port = Rc::new(RefCell::new(port));
+        let mut ports = self.0.borrow_mut();
+        ports.retain(|port| port.strong_count() > 0);
+        ports.push(Rc::downgrade(&port));
+        Port::Output(port)
+    }
+
+    /// flush every alive output port
+    pub fn flush(&self) -> Result<()> {
+        for port in self.0.borrow().iter().filter_map(Weak::upgrade) {
+            port.borrow_mut().flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub enum Port {
+    Input(Rc<RefCell<InputPort>>),
+    Output(Rc<RefCell<OutputPort>>),
+}
+
+impl Port {
+    pub fn new_input(port: InputPort) -> Self {
+        Port::Input(Rc::new(RefCell::new(port)))
+    }
+
+    /// a port which is not flushed by `Interpreter::flush_output_ports`, which string ports need
+    /// not be, see `OutputPorts::open` for the others
+    pub fn new_output(port: OutputPort) -> Self {
+        Port::Output(Rc::new(RefCell::new(port)))
+    }
+
+    pub fn is_open(&self) -> bool {
+        match self {
+            Port::Input(port) => port.borrow().is_open(),
+            Port::Output(port) => port.borrow().is_open(),
+        }
+    }
+
+    pub fn close(&self) -> Result<()> {
+        match self {
+            Port::Input(port) => {
+                port.borrow_mut().close();
+                Ok(())
+            }
+            Port::Output(port) => port.borrow_mut().close(),
+        }
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Input(a), Self::Input(b)) => Rc::ptr_eq(a, b),
+            (Self::Output(a), Self::Output(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for Port {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl Display for Port {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Port::Input(port) => write!(f, "#<input-port {}>", port.borrow().name()),
+            Port::Output(port) => write!(f, "#<output-port {}>", port.borrow().name()),
+        }
+    }
+}
+
+impl Debug for Port {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[test]
+fn char_ready() -> Result<()> {
+    let mut port = InputPort::from_string("a");
+    assert!(port.char_ready()?);
+    assert_eq!(port.read_char()?, Some('a'));
+    // ready at the end too, read-char returns the eof object without waiting
+    assert!(port.char_ready()?);
+
+    // a pipe whose writer has written "ab\n" then waits
+    let written = Rc::new(Cell::new(false));
+    let ready = written.clone();
+    let mut port = InputPort::from_source(
+        "pipe",
+        io::Cursor::new("ab\n"),
+        Some(Box::new(move || Ok(ready.get()))),
+    );
+    assert!(!port.char_ready()?);
+    written.set(true);
+    assert!(port.char_ready()?);
+    assert_eq!(port.read_char()?, Some('a'));
+    written.set(false);
+    // the rest of the line is buffered
+    assert!(port.char_ready()?);
+    assert_eq!(port.read_line()?, Some("b".to_string()));
+    assert!(!port.char_ready()?);
+    port.close();
+    assert_eq!(
+        port.char_ready(),
+        error!(LogicError::PortClosed("pipe".to_string()))
+    );
+    Ok(())
+}
+
+#[test]
+fn read_sequential_datum() -> Result<()> {
+    use crate::parser::{DatumBody, Primitive};
+    let mut port = InputPort::from_string("foo (1 . 2)");
+    assert_eq!(
+        port.read_datum()?.map(|datum| datum.data),
+        Some(DatumBody::Symbol("foo".into()))
+    );
+    assert!(matches!(
+        port.read_datum()?.map(|datum| datum.data),
+        Some(DatumBody::Pair(_))
+    ));
+    assert_eq!(port.read_datum()?, None);
+    assert_eq!(port.read_char()?, None);
+
+    let mut port = InputPort::from_string("1\"a\"");
+    assert_eq!(
+        port.read_datum()?.map(|datum| datum.data),
+        Some(DatumBody::Primitive(Primitive::Integer(1)))
+    );
+    assert_eq!(port.read_char()?, Some('"'));
+    port.close();
+    assert_eq!(
+        port.read_char(),
+        error!(LogicError::PortClosed("string".to_string()))
+    );
+    Ok(())
+}
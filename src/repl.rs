@@ -1,24 +1,850 @@
-use crate::interpreter::Interpreter;
-use crate::values::Value;
+use crate::completion::{complete, Completion};
+use crate::environment::Environment;
+use crate::error::{ErrorData, SchemeError, SourceId};
+use crate::interpreter::{resolve_statement, Breakpoint, Interpreter};
+use crate::parser::pair::GenericPair;
+use crate::parser::{error::SyntaxError, ImportSetBody, Lexer, Parser, Statement};
+use crate::port::Port;
+use crate::values::{Number, Procedure, RealNumberInternalTrait, Value};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::env;
+use std::fs;
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::exit;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
 
+use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::highlight::{Highlighter, MatchingBracketHighlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Config, Context, EditMode, Editor, Helper};
 
-fn check_bracket_closed(chars: impl Iterator<Item = char>) -> bool {
-    let mut count = 0;
-    let mut in_comment = false;
-    for c in chars {
-        match (c, in_comment) {
-            ('(', false) => count = count + 1,
-            (')', false) => count = count - 1,
-            (';', false) => in_comment = true,
-            ('\n', true) => in_comment = false,
-            _ => (),
+/// whether `source` is a prefix of valid input which needs more lines to complete,
+/// like an unclosed list or string. Complete and invalid input are both not incomplete.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+    loop {
+        match parser.parse_datum() {
+            Ok(Some(_)) => continue,
+            Ok(None) => return false,
+            Err(e) => {
+                return matches!(
+                    e.data,
+                    ErrorData::Syntax(SyntaxError::UnexpectedEnd)
+                        | ErrorData::Syntax(SyntaxError::TokenMisMatch(_, None))
+                        | ErrorData::Syntax(SyntaxError::ImcompleteQuotedIdent(_))
+                )
+            }
+        }
+    }
+}
+
+#[test]
+fn incomplete_input() {
+    assert!(is_incomplete("(define (f x)"));
+    assert!(is_incomplete("(display \"a)\n"));
+    assert!(is_incomplete("(car '"));
+    assert!(is_incomplete("(a . "));
+    assert!(is_incomplete("|quoted"));
+    assert!(is_incomplete("(f) (g"));
+    assert!(!is_incomplete(""));
+    assert!(!is_incomplete("(f) ; (g"));
+    assert!(!is_incomplete("(define (f x) x)"));
+    assert!(!is_incomplete("(f))"));
+    assert!(!is_incomplete("(a . b c"));
+}
+
+// results of the latest inputs are bound to $1 (also $), $2, ... $RESULT_VARIABLES
+const RESULT_VARIABLES: usize = 3;
+
+fn bind_result<R: RealNumberInternalTrait>(it: &Interpreter<R>, value: Value<R>) {
+    for index in (1..RESULT_VARIABLES).rev() {
+        let older = it
+            .env
+            .get(&format!("${}", index))
+            .map(|value| value.clone());
+        if let Some(older) = older {
+            it.env.define(format!("${}", index + 1), older);
+        }
+    }
+    it.env.define("$1".to_string(), value.clone());
+    it.env.define("$".to_string(), value);
+}
+
+/// The inputs entered at a REPL, the source of the nth one is `<repl-n>`. They are kept to
+/// render the errors raised later by the procedures they define, the REPL shares them with its
+/// debugger.
+#[derive(Default)]
+pub struct Inputs(RefCell<Vec<(SourceId, String)>>);
+
+impl Inputs {
+    /// keep `source`, return the source its locations refer to
+    pub fn add(&self, source: &str) -> SourceId {
+        let mut inputs = self.0.borrow_mut();
+        let id = SourceId::repl(inputs.len() + 1);
+        inputs.push((id, source.to_string()));
+        id
+    }
+
+    /// the text of the input of source `id`, None if it is not an input
+    pub fn get(&self, id: SourceId) -> Option<String> {
+        self.0
+            .borrow()
+            .iter()
+            .find(|(input, _)| *input == id)
+            .map(|(_, source)| source.clone())
+    }
+}
+
+/// evaluate a complete input of the REPL, which is added to `inputs`, return how its value is
+/// echoed, which is None for definitions and unspecified values
+pub fn eval_input<R: RealNumberInternalTrait>(
+    it: &mut Interpreter<R>,
+    inputs: &Inputs,
+    source: &str,
+) -> Result<Option<String>, SchemeError> {
+    let value = eval_statements(it, inputs, source, |_| ())?;
+    Ok(echo(it, value))
+}
+
+/// like `eval_input`, with the lines confirming the definitions and imports of the input before
+/// the echoed value, like `; defined square`
+pub fn eval_input_with_feedback<R: RealNumberInternalTrait>(
+    it: &mut Interpreter<R>,
+    inputs: &Inputs,
+    source: &str,
+) -> Result<Vec<String>, SchemeError> {
+    let mut lines = vec![];
+    let value = eval_statements(it, inputs, source, |statement| {
+        lines.extend(feedback(statement))
+    })?;
+    lines.extend(echo(it, value));
+    Ok(lines)
+}
+
+// evaluate the statements of `source` like `Interpreter::eval_source`, each is passed to
+// `evaluated` once it is evaluated
+fn eval_statements<R: RealNumberInternalTrait>(
+    it: &mut Interpreter<R>,
+    inputs: &Inputs,
+    source: &str,
+    mut evaluated: impl FnMut(&Statement),
+) -> Result<Option<Value<R>>, SchemeError> {
+    let mut lexer = Lexer::from_char_stream(source.chars());
+    lexer.set_source(inputs.add(source));
+    it.parser(lexer).try_fold(None, |_, statement| {
+        let mut statement = statement?;
+        resolve_statement(&mut statement);
+        let value = it.eval_root_ast(&statement)?;
+        evaluated(&statement);
+        Ok(value)
+    })
+}
+
+fn echo<R: RealNumberInternalTrait>(
+    it: &Interpreter<R>,
+    value: Option<Value<R>>,
+) -> Option<String> {
+    match value {
+        None | Some(Value::Void) => None,
+        Some(value) => {
+            let output = value.written().to_string();
+            bind_result(it, value);
+            Some(output)
+        }
+    }
+}
+
+// what a definition or an import statement binds, nothing for expressions
+fn feedback(statement: &Statement) -> Option<String> {
+    match statement {
+        Statement::Definition(definition) => Some(format!("; defined {}", definition.0)),
+        Statement::SyntaxDefinition(definition) => {
+            Some(format!("; defined syntax {}", definition.0))
+        }
+        Statement::LibraryDefinition(library) => Some(format!("; defined library {}", library.0)),
+        Statement::ImportDeclaration(imports) => Some(format!(
+            "; imported {}",
+            imports
+                .0
+                .iter()
+                .map(|import_set| match &import_set.data {
+                    ImportSetBody::Only(inner, names) => {
+                        format!("{} from {}", names.join(" "), inner.library_name())
+                    }
+                    other => other.library_name().to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        Statement::Expression(_) => None,
+    }
+}
+
+#[test]
+fn definition_feedback() -> Result<(), SchemeError> {
+    let mut it = Interpreter::<f32>::new();
+    let inputs = Inputs::default();
+    assert_eq!(
+        eval_input_with_feedback(
+            &mut it,
+            &inputs,
+            "(import (scheme base) (only (scheme write) display))"
+        )?,
+        ["; imported (scheme base), display from (scheme write)"]
+    );
+    assert_eq!(
+        eval_input_with_feedback(&mut it, &inputs, "(define (square x) (* x x)) (square 3)")?,
+        ["; defined square", "9"]
+    );
+    assert_eq!(
+        eval_input_with_feedback(
+            &mut it,
+            &inputs,
+            "(define-syntax twice (syntax-rules () ((twice e) (begin e e))))"
+        )?,
+        ["; defined syntax twice"]
+    );
+    assert_eq!(
+        eval_input_with_feedback(&mut it, &inputs, "(set! square car)")?,
+        Vec::<String>::new()
+    );
+    // no feedback without a terminal
+    assert_eq!(
+        eval_input(&mut it, &inputs, "(define (cube x) (* x x x))")?,
+        None
+    );
+    Ok(())
+}
+
+#[test]
+fn result_variables() -> Result<(), SchemeError> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    let inputs = Inputs::default();
+    assert_eq!(eval_input(&mut it, &inputs, "(define a \"a\")")?, None);
+    assert_eq!(eval_input(&mut it, &inputs, "(if #f #f)")?, None);
+    assert_eq!(
+        eval_input(&mut it, &inputs, "(list a #\\b \"c\\\"\")")?,
+        Some("(\"a\" #\\b \"c\\\"\")".to_string())
+    );
+    assert_eq!(eval_input(&mut it, &inputs, "1")?, Some("1".to_string()));
+    assert_eq!(eval_input(&mut it, &inputs, "2")?, Some("2".to_string()));
+    assert_eq!(
+        eval_input(&mut it, &inputs, "(list $ $1 $2 (car $3))")?,
+        Some("(2 2 1 \"a\")".to_string())
+    );
+    assert_eq!(
+        eval_input(&mut it, &inputs, "(car $)")?,
+        Some("2".to_string())
+    );
+    assert_eq!(
+        eval_input(&mut it, &inputs, "$2")?,
+        Some("(2 2 1 \"a\")".to_string())
+    );
+    Ok(())
+}
+
+const COMMANDS: &str = ",env [prefix], ,load file, ,type expr, ,clear, ,quit";
+
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    Env(Option<&'a str>),
+    Load(&'a str),
+    Type(&'a str),
+    Clear,
+    Quit,
+}
+
+// lines starting with a comma are REPL commands, None for other lines
+fn parse_command(line: &str) -> Option<Result<Command, String>> {
+    let line = line.trim();
+    if !line.starts_with(',') {
+        return None;
+    }
+    let (name, argument) = match line[1..].find(char::is_whitespace) {
+        Some(index) => (&line[1..index + 1], Some(line[index + 1..].trim())),
+        None => (&line[1..], None),
+    };
+    Some(match (name, argument) {
+        ("env", prefix) => Ok(Command::Env(prefix)),
+        ("load", Some(file)) => Ok(Command::Load(file)),
+        ("type", Some(expression)) => Ok(Command::Type(expression)),
+        ("clear", None) => Ok(Command::Clear),
+        ("quit", None) => Ok(Command::Quit),
+        ("load", None) => Err("usage: ,load file".to_string()),
+        ("type", None) => Err("usage: ,type expr".to_string()),
+        _ => Err(format!(
+            "unknown command {}, available commands: {}",
+            line, COMMANDS
+        )),
+    })
+}
+
+fn describe_type<R: RealNumberInternalTrait>(value: &Value<R>) -> String {
+    match value {
+        Value::Number(Number::Integer(_)) => "integer".to_string(),
+        Value::Number(Number::Rational(..)) => "rational".to_string(),
+        Value::Number(Number::Real(_)) => "real".to_string(),
+        Value::Boolean(_) => "boolean".to_string(),
+        Value::Character(_) => "character".to_string(),
+        Value::String(_) => "string".to_string(),
+        Value::Symbol(_) => "symbol".to_string(),
+        Value::Procedure(Procedure::Builtin(builtin)) => {
+            format!("procedure (builtin {})", builtin.name)
+        }
+        Value::Procedure(Procedure::Parameter(_)) => "parameter".to_string(),
+        Value::Procedure(procedure) => format!("procedure {}", procedure.signature()),
+        Value::Vector(_) => "vector".to_string(),
+        Value::Pair(pair) => match pair.as_ref() {
+            GenericPair::Empty => "empty list".to_string(),
+            GenericPair::Some(..) => "pair".to_string(),
+        },
+        Value::Transformer(_) => "syntax".to_string(),
+        Value::Port(Port::Input(_)) => "input port".to_string(),
+        Value::Port(Port::Output(_)) => "output port".to_string(),
+        Value::ErrorObject(_) => "error object".to_string(),
+        Value::Eof => "eof object".to_string(),
+        Value::Uninitialized => "uninitialized".to_string(),
+        Value::Void => "unspecified".to_string(),
+    }
+}
+
+// output of a command, the interpreter is reset by ,clear
+fn run_command<R: RealNumberInternalTrait>(
+    it: &mut Interpreter<R>,
+    command: Command,
+) -> Result<Option<String>, SchemeError> {
+    Ok(match command {
+        Command::Env(prefix) => {
+            let mut names = it.env.names();
+            names.retain(|name| name.starts_with(prefix.unwrap_or("")));
+            Some(names.join("\n"))
+        }
+        Command::Load(file) => {
+            // loading a file does not change where libraries are searched from
+            let program_directory = it.program_directory.clone();
+            let result = it.eval_file(PathBuf::from(file));
+            it.program_directory = program_directory;
+            result?;
+            None
+        }
+        Command::Type(expression) => it
+            .eval(expression.chars())?
+            .map(|value| describe_type(&value)),
+        Command::Clear => {
+            it.env = Rc::new(Environment::new());
+            it.define_intrinsics(it.env.clone());
+            it.import_stdlib();
+            None
+        }
+        Command::Quit => None,
+    })
+}
+
+#[test]
+fn commands() -> Result<(), SchemeError> {
+    assert_eq!(parse_command("(+ 1 2)"), None);
+    assert_eq!(parse_command(",env"), Some(Ok(Command::Env(None))));
+    assert_eq!(
+        parse_command(" ,env  str "),
+        Some(Ok(Command::Env(Some("str"))))
+    );
+    assert_eq!(
+        parse_command(",load a b.scm"),
+        Some(Ok(Command::Load("a b.scm")))
+    );
+    assert_eq!(
+        parse_command(",type (car '(1))"),
+        Some(Ok(Command::Type("(car '(1))")))
+    );
+    assert_eq!(parse_command(",clear"), Some(Ok(Command::Clear)));
+    assert_eq!(parse_command(",quit"), Some(Ok(Command::Quit)));
+    assert_eq!(
+        parse_command(",load"),
+        Some(Err("usage: ,load file".to_string()))
+    );
+    assert_eq!(
+        parse_command(",help"),
+        Some(Err(format!(
+            "unknown command ,help, available commands: {}",
+            COMMANDS
+        )))
+    );
+
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval("(define string-x 1) (define (f x . y) x)".chars())?;
+    let names = run_command(&mut it, Command::Env(Some("string-")))?.unwrap();
+    assert!(names.split('\n').all(|name| name.starts_with("string-")));
+    assert!(names.split('\n').any(|name| name == "string-x"));
+    let type_of = |it: &mut Interpreter<f32>, expression| {
+        run_command(it, Command::Type(expression)).map(Option::unwrap)
+    };
+    assert_eq!(type_of(&mut it, "1")?, "integer");
+    assert_eq!(type_of(&mut it, "+")?, "procedure (builtin +)");
+    assert_eq!(type_of(&mut it, "f")?, "procedure (f x . y)");
+    assert_eq!(type_of(&mut it, "'(\"a\")")?, "pair");
+    assert_eq!(type_of(&mut it, "'()")?, "empty list");
+
+    let file = std::env::temp_dir().join("ruschm_repl_load_command.scm");
+    std::fs::write(&file, "(define loaded 42)").unwrap();
+    run_command(&mut it, Command::Load(file.to_str().unwrap()))?;
+    assert_eq!(it.program_directory, None);
+    assert_eq!(
+        it.eval("loaded".chars())?,
+        Some(Value::Number(Number::Integer(42)))
+    );
+    std::fs::remove_file(file).unwrap();
+
+    run_command(&mut it, Command::Clear)?;
+    assert!(it.eval("string-x".chars()).is_err());
+    assert_eq!(
+        it.eval("(+ 1 2)".chars())?,
+        Some(Value::Number(Number::Integer(3)))
+    );
+    Ok(())
+}
+
+/// an error raised at the REPL, with the line it is located at underlined, taken from the input
+/// of the location or from its file
+pub fn render_error(e: &SchemeError, inputs: &Inputs, colored: bool) -> String {
+    let source = match e.location {
+        Some(location) => inputs
+            .get(location.source)
+            .or_else(|| fs::read_to_string(location.source.name()).ok())
+            .unwrap_or_default(),
+        None => String::new(),
+    };
+    match colored {
+        true => e.render_colored(&source),
+        false => e.render(&source),
+    }
+}
+
+#[test]
+fn rendered_errors() {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    let inputs = Inputs::default();
+    let mut render = |source: &str| {
+        render_error(
+            &eval_input(&mut it, &inputs, source).unwrap_err(),
+            &inputs,
+            false,
+        )
+    };
+    assert_eq!(
+        render("(define x 1) (if)"),
+        "error: syntax error: unexpect end of input\n --> <repl-1>:1:14\n  |\n1 | (define x 1) (if)\n  |              ^\n"
+    );
+    // runtime errors, on the line of the continued input they are raised by
+    assert_eq!(
+        render("(define (f y)\n  (car y))\n(f 1)"),
+        "error: car: expect a pair, got 1\n --> <repl-2>:2:4\n  |\n2 |   (car y))\n  |    ^^^\n"
+    );
+    // or of an earlier input defining the procedure raising them
+    assert_eq!(
+        render("(begin\n  (f 1) (display \"zzzzzzz\"))"),
+        "error: car: expect a pair, got 1\n --> <repl-2>:2:4\n  |\n2 |   (car y))\n  |    ^^^\n"
+    );
+    assert_eq!(
+        render("(undefined)"),
+        "error: unbound symbol undefined\n --> <repl-4>:1:2\n  |\n1 | (undefined)\n  |  ^^^^^^^^^\n"
+    );
+}
+
+const DEBUG_COMMANDS: &str = ",continue, ,backtrace, ,abort";
+
+/// the nested REPL of a `(debug)` breakpoint, where inputs read by `read_line`, given the prompt,
+/// are evaluated in the environment of the breakpoint. `,continue` or the end of input resume
+/// evaluation, `,abort` makes `(debug)` raise `ErrorData::DebugAborted` and `,backtrace` shows
+/// the call stack. Values and errors are passed to `output` like the REPL prints them, inputs
+/// are added to the `inputs` of the REPL.
+pub fn debug_session<R: RealNumberInternalTrait>(
+    breakpoint: Breakpoint<R>,
+    inputs: &Inputs,
+    mut read_line: impl FnMut(&str) -> Option<String>,
+    mut output: impl FnMut(&str),
+) -> Result<(), SchemeError> {
+    let Breakpoint { env, backtrace } = breakpoint;
+    match backtrace.frames.first().and_then(|frame| frame.location) {
+        Some(location) => output(&format!("debug: paused at {}", location)),
+        None => output("debug: paused"),
+    }
+    output(&format!("commands: {}", DEBUG_COMMANDS));
+    let mut source = String::new();
+    loop {
+        let prompt = match source.is_empty() {
+            true => "debug> ",
+            false => "debug... ",
+        };
+        let line = match read_line(prompt) {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        if line.is_empty() {
+            source.clear();
+            continue;
+        }
+        if source.is_empty() && line.trim_start().starts_with(',') {
+            match line.trim() {
+                ",continue" => return Ok(()),
+                ",abort" => return error!(ErrorData::DebugAborted),
+                ",backtrace" => output(&backtrace.to_string()),
+                other => output(&format!(
+                    "unknown command {}, available commands: {}",
+                    other, DEBUG_COMMANDS
+                )),
+            }
+            continue;
+        }
+        source.push_str(line);
+        if is_incomplete(&source) {
+            source.push('\n');
+            continue;
+        }
+        let mut lexer = Lexer::from_char_stream(source.chars());
+        lexer.set_source(inputs.add(&source));
+        let result = Parser::from_lexer(lexer).try_fold(None, |_, statement| {
+            let mut statement = statement?;
+            resolve_statement(&mut statement);
+            Interpreter::eval_statement(&statement, &env)
+        });
+        match result {
+            Ok(None) | Ok(Some(Value::Void)) => (),
+            Ok(Some(value)) => output(&value.written().to_string()),
+            // leave the debugger with the evaluation it is in
+            Err(
+                e @ SchemeError {
+                    data: ErrorData::Exit { .. } | ErrorData::Interrupted,
+                    ..
+                },
+            ) => return Err(e),
+            Err(e) => output(render_error(&e, inputs, false).trim_end()),
+        }
+        source.clear();
+    }
+}
+
+#[test]
+fn debug_sessions() -> Result<(), SchemeError> {
+    let mut it = Interpreter::<f32>::new_with_stdlib();
+    it.eval_str(
+        "(define (f x)
+  (define y (* x 2))
+  (debug)
+  (+ x y))",
+    )?;
+    let session = |it: &mut Interpreter<f32>, lines: &'static [&'static str]| {
+        let output = Rc::new(RefCell::new(vec![]));
+        let written = output.clone();
+        let mut lines = lines.iter();
+        let inputs = Inputs::default();
+        it.set_debugger(Box::new(move |breakpoint| {
+            let written = written.clone();
+            debug_session(
+                breakpoint,
+                &inputs,
+                |_| lines.next().map(|line| line.to_string()),
+                move |line| written.borrow_mut().push(line.to_string()),
+            )
+        }));
+        let result = it.eval_str("(f 1)");
+        let output = output.borrow().clone();
+        (result, output)
+    };
+    let (result, output) = session(
+        &mut it,
+        &[
+            "(list x",
+            " y)",
+            "(set! x 10)",
+            "(car x)",
+            ",backtrace",
+            ",step",
+            ",continue",
+        ],
+    );
+    assert_eq!(result?, Some(Value::Number(Number::Integer(12))));
+    assert_eq!(
+        output,
+        [
+            "debug: paused at <string>:3:9",
+            "commands: ,continue, ,backtrace, ,abort",
+            "(1 2)",
+            "error: car: expect a pair, got 10\n --> <repl-3>:1:2\n  |\n1 | (car x)\n  |  ^^^",
+            "backtrace:\n  0: debug at <string>:3:9\n  1: f at <string>:1:3",
+            "unknown command ,step, available commands: ,continue, ,backtrace, ,abort",
+        ]
+    );
+    // the end of input continues
+    assert_eq!(
+        session(&mut it, &[]).0?,
+        Some(Value::Number(Number::Integer(3)))
+    );
+    let (result, _) = session(&mut it, &["x", ",abort"]);
+    let error = result.unwrap_err();
+    assert_eq!(error.data, ErrorData::DebugAborted);
+    assert_eq!(error.backtrace.unwrap().frames.len(), 2);
+    Ok(())
+}
+
+fn report_error(e: &SchemeError, inputs: &Inputs) {
+    eprint!("{}", render_error(e, inputs, io::stderr().is_terminal()));
+    if let Some(backtrace) = &e.backtrace {
+        eprintln!("{}", backtrace);
+    }
+}
+
+/// where the history of the REPL is kept: `RUSCHM_HISTORY`, otherwise `ruschm/history` in the
+/// XDG state directory, `~/.local/state` by default, or in the local application data directory
+/// on Windows
+pub fn history_file() -> Option<PathBuf> {
+    env::var_os("RUSCHM_HISTORY")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("XDG_STATE_HOME")
+                .map(PathBuf::from)
+                .filter(|directory| directory.is_absolute())
+                .or_else(|| {
+                    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+                })
+                .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+                .map(|directory| directory.join("ruschm").join("history"))
+        })
+}
+
+pub const DEFAULT_HISTORY_SIZE: usize = 5000;
+
+/// how the REPL keeps the lines entered on a terminal, input read from a pipe is never kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryConfig {
+    /// loaded when the REPL starts and saved when it exits, no history is kept without a file
+    pub file: Option<PathBuf>,
+    /// the number of entries kept, the oldest ones are dropped first
+    pub size: usize,
+}
+
+impl Default for HistoryConfig {
+    /// `history_file()`, and the size given by `RUSCHM_HISTORY_SIZE` or `DEFAULT_HISTORY_SIZE`
+    fn default() -> Self {
+        Self {
+            file: history_file(),
+            size: env::var("RUSCHM_HISTORY_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(DEFAULT_HISTORY_SIZE),
+        }
+    }
+}
+
+// characters which cannot be part of an identifier being completed
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()'`,\";|".contains(c)
+}
+
+/// the candidates among `completions`, as ordered by `complete`, completing the identifier which
+/// ends at byte `pos` of `line`, and where the identifier starts
+pub fn complete_identifier(
+    line: &str,
+    pos: usize,
+    completions: &[Completion],
+) -> (usize, Vec<Completion>) {
+    let start = line[..pos]
+        .rfind(is_delimiter)
+        .map(|index| index + line[index..].chars().next().unwrap().len_utf8())
+        .unwrap_or(0);
+    let prefix = &line[start..pos];
+    let candidates = match prefix.is_empty() {
+        true => vec![],
+        false => completions
+            .iter()
+            .filter(|completion| completion.name.starts_with(prefix))
+            .cloned()
+            .collect(),
+    };
+    (start, candidates)
+}
+
+#[test]
+fn identifier_completion() {
+    use crate::completion::CompletionKind;
+
+    let completions = ["list", "list-tail", "string-append", "λ"]
+        .iter()
+        .map(|name| Completion {
+            name: name.to_string(),
+            kind: CompletionKind::Variable,
+            signature: None,
+            user: true,
+        })
+        .collect::<Vec<_>>();
+    let names = |line: &str, pos| {
+        let (start, candidates) = complete_identifier(line, pos, &completions);
+        let names = candidates
+            .into_iter()
+            .map(|completion| completion.name)
+            .collect::<Vec<_>>();
+        (start, names)
+    };
+    assert_eq!(
+        names("(map (lambda (x) x) (li", 23),
+        (21, vec!["list".to_string(), "list-tail".to_string()])
+    );
+    assert_eq!(
+        names("(string-append \"a\" 'st", 22),
+        (20, vec!["string-append".to_string()])
+    );
+    assert_eq!(names("(λ", 3), (1, vec!["λ".to_string()]));
+    assert_eq!(names("(list ", 6), (6, vec![]));
+    assert_eq!(names("lis) x", 3).1.len(), 2);
+}
+
+// completes identifiers with `complete`, listing procedures with their signatures, and
+// highlights the parenthesis matching the one at the cursor
+struct ReplHelper {
+    // every completion of the environment lines are read in, updated before each line
+    completions: Vec<Completion>,
+    brackets: MatchingBracketHighlighter,
+}
+
+impl ReplHelper {
+    // `env` is a local environment in the debugger
+    fn update_completions<R: RealNumberInternalTrait>(&mut self, env: &Environment<R>) {
+        self.completions = complete("", env);
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = complete_identifier(line, pos, &self.completions);
+        let candidates = candidates
+            .into_iter()
+            .map(|completion| Pair {
+                display: completion.to_string(),
+                replacement: completion.name,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        self.brackets.highlight(line, pos)
+    }
+
+    fn highlight_char(&self, line: &str, pos: usize) -> bool {
+        self.brackets.highlight_char(line, pos)
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+// lines are edited by rustyline on terminals, otherwise read from stdin as they are, so that
+// piped input is not echoed with prompts
+enum Input {
+    Editor(Box<Editor<ReplHelper>>, Option<PathBuf>),
+    Pipe(io::Stdin),
+}
+
+impl Input {
+    fn new(history: &HistoryConfig) -> Self {
+        match io::stdin().is_terminal() {
+            true => Self::editor(history),
+            false => Input::Pipe(io::stdin()),
+        }
+    }
+
+    // consecutive identical entries are kept once, a history file which cannot be read is
+    // reported and replaced by the history of this session
+    fn editor(history: &HistoryConfig) -> Self {
+        let config = Config::builder()
+            .edit_mode(EditMode::Emacs)
+            .auto_add_history(false)
+            .max_history_size(history.size)
+            .history_ignore_dups(true)
+            .build();
+        let mut editor = Editor::with_config(config);
+        editor.set_helper(Some(ReplHelper {
+            completions: vec![],
+            brackets: MatchingBracketHighlighter::new(),
+        }));
+        if let Some(file) = &history.file {
+            match editor.load_history(file) {
+                Ok(()) => (),
+                // there is no history before the first session
+                Err(ReadlineError::Io(e)) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => {
+                    eprintln!(
+                        "cannot read history from {}: {}, starting without history",
+                        file.display(),
+                        e
+                    );
+                    editor.clear_history();
+                }
+            }
+        }
+        Input::Editor(Box::new(editor), history.file.clone())
+    }
+
+    // identifiers bound in `env` are completed
+    fn readline<R: RealNumberInternalTrait>(
+        &mut self,
+        env: &Environment<R>,
+        prompt: &str,
+    ) -> rustyline::Result<String> {
+        match self {
+            Input::Editor(editor, _) => {
+                if let Some(helper) = editor.helper_mut() {
+                    helper.update_completions(env);
+                }
+                editor.readline(prompt)
+            }
+            Input::Pipe(stdin) => {
+                let mut line = String::new();
+                match stdin.read_line(&mut line)? {
+                    0 => Err(ReadlineError::Eof),
+                    _ => Ok(line),
+                }
+            }
+        }
+    }
+
+    fn add_history_entry(&mut self, entry: &str) {
+        if let Input::Editor(editor, _) = self {
+            editor.add_history_entry(entry);
+        }
+    }
+
+    fn save_history(&mut self) {
+        if let Input::Editor(editor, Some(history)) = self {
+            if let Some(directory) = history.parent() {
+                // reported by saving if it is not created
+                let _ = fs::create_dir_all(directory);
+            }
+            if let Err(e) = editor.save_history(history) {
+                eprintln!("cannot save history to {}: {}", history.display(), e);
+            }
         }
     }
-    count <= 0
 }
 
 pub fn run() {
@@ -27,47 +853,116 @@ pub fn run() {
     run_with_interpreter(it);
 }
 
-pub fn run_with_interpreter(mut it: Interpreter<f32>) {
-    let mut rl = Editor::<()>::new();
+pub fn run_with_interpreter(it: Interpreter<f32>) {
+    run_with_history(it, HistoryConfig::default())
+}
+
+pub fn run_with_history(mut it: Interpreter<f32>, history: HistoryConfig) {
+    // shared with the debugger, which reads lines while an input is evaluated
+    let input = Rc::new(RefCell::new(Input::new(&history)));
+    let debug_input = input.clone();
+    let inputs = Rc::new(Inputs::default());
+    let debug_inputs = inputs.clone();
+    it.set_debugger(Box::new(move |breakpoint| {
+        let mut input = debug_input.borrow_mut();
+        let env = breakpoint.env.clone();
+        debug_session(
+            breakpoint,
+            &debug_inputs,
+            |prompt| {
+                let line = input.readline(&env, prompt).ok()?;
+                input.add_history_entry(&line);
+                Some(line)
+            },
+            |output| println!("{}", output),
+        )
+    }));
     io::stdout().flush().unwrap();
     let mut source = String::new();
 
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
     println!("Ruschm Version {}", VERSION);
+    // the line editor reads ctrl-c at the prompt as a key, so the signal only arrives
+    // while evaluating, where it interrupts the evaluation instead of killing the process
+    let interrupt = it.interrupt_handle();
+    if let Err(e) = ctrlc::set_handler(move || interrupt.store(true, Ordering::Relaxed)) {
+        eprintln!("cannot install ctrl-c handler: {}", e);
+    }
     loop {
+        if let Err(e) = it.flush_output_ports() {
+            eprintln!("{}", e);
+        }
         let readline = match &source.is_empty() {
-            true => rl.readline("> "),
-            false => rl.readline(""),
+            true => input.borrow_mut().readline(&it.env, "> "),
+            false => input.borrow_mut().readline(&it.env, "... "),
         };
         match readline {
             Ok(line) => {
+                // lines read from a non-terminal input keep their line break
+                let line = line.trim_end_matches(&['\r', '\n'][..]);
                 if line.is_empty() {
+                    // an empty line abandons the pending input
+                    source.clear();
                     continue;
                 }
-                source.push_str(line.as_str());
-                if check_bracket_closed(source.chars()) {
-                    match it.eval(source.chars()) {
-                        Ok(opt) => {
-                            if let Some(value) = opt {
-                                match value {
-                                    Value::Void => (),
-                                    _ => println!("{}", value),
-                                }
+                if source.is_empty() {
+                    match parse_command(line) {
+                        Some(Ok(Command::Quit)) => {
+                            println!("exited. have a nice day.");
+                            break;
+                        }
+                        Some(Ok(command)) => {
+                            match run_command(&mut it, command) {
+                                Ok(Some(output)) => println!("{}", output),
+                                Ok(None) => (),
+                                Err(e) => report_error(&e, &inputs),
                             }
+                            input.borrow_mut().add_history_entry(line);
+                            continue;
+                        }
+                        Some(Err(message)) => {
+                            eprintln!("{}", message);
+                            continue;
                         }
-                        Err(e) => eprintln!("{}", e),
+                        None => (),
                     }
-                    rl.add_history_entry(source.clone());
+                }
+                source.push_str(line);
+                if !is_incomplete(&source) {
+                    // definitions are only confirmed to people typing them
+                    let editor = matches!(*input.borrow(), Input::Editor(..));
+                    let output = match editor {
+                        true => eval_input_with_feedback(&mut it, &inputs, &source),
+                        false => eval_input(&mut it, &inputs, &source)
+                            .map(|output| output.into_iter().collect()),
+                    };
+                    match output {
+                        Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+                        Err(SchemeError {
+                            data: ErrorData::Exit { code, .. },
+                            ..
+                        }) => {
+                            let mut input = input.borrow_mut();
+                            input.add_history_entry(&source);
+                            input.save_history();
+                            if let Err(e) = it.flush_output_ports() {
+                                eprintln!("{}", e);
+                            }
+                            exit(code)
+                        }
+                        Err(e) => report_error(&e, &inputs),
+                    }
+                    input.borrow_mut().add_history_entry(&source);
                     source.clear();
+                } else {
+                    source.push('\n');
                 }
-				else {
-					source.push('\n');
-				}
             }
             Err(ReadlineError::Interrupted) => {
+                // ctrl-c cancels the line being edited, ctrl-d exits
                 source.clear();
-                println!("Interrupted input by ctrl-c, use ctrl-d to exit.");
+                println!("input cancelled, type ,quit or press ctrl-d to exit");
                 continue;
             }
             Err(ReadlineError::Eof) => {
@@ -80,4 +975,49 @@ pub fn run_with_interpreter(mut it: Interpreter<f32>) {
             }
         }
     }
+    input.borrow_mut().save_history();
+}
+
+#[test]
+fn history() {
+    let directory = env::temp_dir().join(format!("ruschm-history-{}", std::process::id()));
+    let file = directory.join("state").join("history");
+    let config = HistoryConfig {
+        file: Some(file.clone()),
+        size: 3,
+    };
+    let session = |entries: &[&str]| {
+        let mut input = Input::editor(&config);
+        for entry in entries {
+            input.add_history_entry(entry);
+        }
+        input.save_history();
+    };
+    let entries = || {
+        fs::read_to_string(&file)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    // the directory of the file is created, consecutive duplicates are kept once
+    session(&["(define x 1)", "x", "x", "(display\n x)"]);
+    assert_eq!(entries(), ["(define x 1)", "x", "(display\\n x)"]);
+    // the oldest entries are dropped beyond the size
+    session(&["(+ x 1)"]);
+    assert_eq!(entries(), ["x", "(display\\n x)", "(+ x 1)"]);
+    // a history which cannot be read is replaced
+    fs::write(&file, b"#V2\n\xff\xfe\n").unwrap();
+    session(&["(car '(1))"]);
+    assert_eq!(entries(), ["(car '(1))"]);
+    // without a file nothing is written
+    fs::remove_dir_all(&directory).unwrap();
+    let mut input = Input::editor(&HistoryConfig {
+        file: None,
+        size: 3,
+    });
+    input.add_history_entry("x");
+    input.save_history();
+    assert!(!directory.exists());
 }
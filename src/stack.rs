@@ -0,0 +1,21 @@
+// Passes over syntax trees and the evaluator recurse once per level of nesting, and a level takes
+// several kilobytes of native stack in debug builds. Recursive calls go through `guard`, which
+// continues on a stack segment allocated on the heap when the current one is nearly used up,
+// so nesting is limited by the parser and interpreter limits instead of the thread stack size.
+// Without the native feature the stack does not grow, and embedders should lower the limits.
+
+// larger than the stack used between two guarded calls
+#[cfg(feature = "native")]
+const RED_ZONE: usize = 256 * 1024;
+#[cfg(feature = "native")]
+const SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+#[cfg(feature = "native")]
+pub(crate) fn guard<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(RED_ZONE, SEGMENT_SIZE, f)
+}
+
+#[cfg(not(feature = "native"))]
+pub(crate) fn guard<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
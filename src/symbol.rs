@@ -0,0 +1,174 @@
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    fmt,
+    hash::{BuildHasherDefault, Hash, Hasher},
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+// symbols are never freed, like the symbol table of most scheme implementations
+static SYMBOL_TABLE: Mutex<Option<HashSet<Arc<str>>>> = Mutex::new(None);
+
+/// An interned identifier or symbol.
+///
+/// Symbols with the same name share one allocation, so cloning is a reference count increment,
+/// and equality and hashing compare pointers instead of strings. The table is shared by all
+/// threads, which keeps syntax trees and errors `Send`. Symbols are serialized as their names.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", from = "String")
+)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        let mut table = SYMBOL_TABLE.lock().unwrap();
+        let table = table.get_or_insert_with(HashSet::new);
+        match table.get(name) {
+            Some(interned) => Self(interned.clone()),
+            None => {
+                let interned: Arc<str> = Arc::from(name);
+                table.insert(interned.clone());
+                Self(interned)
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.as_ptr() as usize).hash(state)
+    }
+}
+
+/// Hasher for maps keyed by symbols: the hash of a symbol is already a unique address, so it
+/// only needs mixing instead of a full SipHash round.
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0.rotate_left(8) ^ *byte as u64).wrapping_mul(0x517c_c1b7_2722_0a95);
+        }
+    }
+    fn write_usize(&mut self, address: usize) {
+        self.0 = (address as u64).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+pub type BuildSymbolHasher = BuildHasherDefault<SymbolHasher>;
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Self::new(&name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<&Symbol> for Symbol {
+    fn from(symbol: &Symbol) -> Self {
+        symbol.clone()
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[test]
+fn interning() {
+    let a = Symbol::new("lambda");
+    let b = Symbol::from("lambda".to_string());
+    assert!(Arc::ptr_eq(&a.0, &b.0));
+    assert_eq!(a, b);
+    assert_ne!(a, Symbol::new("lambda2"));
+    assert_eq!(a, "lambda");
+    assert_eq!(format!("{} {:?}", a, a), "lambda \"lambda\"");
+}
@@ -1,10 +1,13 @@
 use either::Either;
 use std::{
+    any::{Any, TypeId},
     cell::RefCell,
     cell::RefMut,
     cmp::Ordering,
+    collections::HashMap,
+    convert::TryFrom,
     fmt::{self, Debug, Display, Formatter},
-    ops::Deref,
+    ops::{Deref, RangeInclusive},
     rc::Rc,
 };
 
@@ -15,13 +18,17 @@ use smallvec::SmallVec;
 use crate::{
     environment::*,
     error::*,
-    interpreter::error::LogicError,
+    interpreter::error::{wrong_type, LogicError},
+    interpreter::Lambda,
     parser::ParameterFormals,
-    parser::SchemeProcedure,
     parser::{
-        pair::{GenericPair, Pairable},
-        Transformer,
+        fmt_rational,
+        pair::{GenericPair, IntoPairIter, PairIterItem, Pairable},
+        written_character, written_symbol, Transformer,
     },
+    port::{InputPort, OutputPort, Port},
+    stack,
+    symbol::Symbol,
 };
 
 type Result<T> = std::result::Result<T, SchemeError>;
@@ -45,11 +52,45 @@ impl<R: RealNumberInternalTrait> Display for Number<R> {
         match self {
             Number::Integer(n) => write!(f, "{}", n),
             Number::Real(n) => write!(f, "{:?}", n),
-            Number::Rational(a, b) => write!(f, "{}/{}", a, b),
+            Number::Rational(a, b) => fmt_rational(f, *a as i64, *b as i64),
         }
     }
 }
 
+#[test]
+fn display_rational() {
+    let rationals = [
+        ((1, 4), "1/4"),
+        ((2, 8), "1/4"),
+        ((1, -3), "-1/3"),
+        ((-1, -3), "1/3"),
+        ((-4, 6), "-2/3"),
+        ((6, 3), "2"),
+        ((-6, 3), "-2"),
+        ((0, 5), "0"),
+        ((0, -5), "0"),
+        ((i32::MIN, -1), "2147483648"),
+    ];
+    for ((a, b), displayed) in rationals.iter() {
+        assert_eq!(Number::<f32>::Rational(*a, *b).to_string(), *displayed);
+        assert_eq!(
+            Value::<f32>::Number(Number::Rational(*a, *b)).to_string(),
+            *displayed
+        );
+        assert_eq!(
+            Value::<f32>::Number(Number::Rational(*a, *b))
+                .written()
+                .to_string(),
+            *displayed
+        );
+    }
+    // literals are read unreduced
+    use crate::parser::Primitive;
+    assert_eq!(Primitive::Rational(2, 8).to_string(), "1/4");
+    assert_eq!(Primitive::Rational(-3, 6).written(), "-1/2");
+    assert_eq!(Primitive::Rational(4, 2).to_string(), "2");
+}
+
 impl<R: RealNumberInternalTrait> Number<R> {
     pub(crate) fn exact_eqv(&self, other: &Self) -> bool {
         match (self, other) {
@@ -253,54 +294,297 @@ impl<R: RealNumberInternalTrait> Number<R> {
         Number::Real(self.as_real().atan2(x.as_real()))
     }
 
+    // floor, ceiling, round and truncate keep the exactness of their argument: exact numbers are
+    // rounded to integers and reals to integral reals
     pub fn floor(self) -> Self {
         match self {
-            Number::Integer(num) => Number::Integer(num),
             Number::Real(num) => Number::Real(num.floor()),
-            Number::Rational(a, b) => Number::Integer({
-                let quot = a / b;
-                if quot >= 0 || quot * b == a {
-                    quot
-                } else {
-                    quot - 1
-                }
-            }),
+            exact => exact.round_exact(|quotient, _, _| quotient),
         }
     }
 
     pub fn ceiling(self) -> Self {
         match self {
-            Number::Integer(num) => Number::Integer(num),
             Number::Real(num) => Number::Real(num.ceil()),
-            Number::Rational(a, b) => Number::Integer({
-                let quot = a / b;
-                if quot <= 0 || quot * b == a {
-                    quot
-                } else {
-                    quot + 1
+            exact => exact.round_exact(|quotient, remainder, _| match remainder {
+                0 => quotient,
+                _ => quotient + 1,
+            }),
+        }
+    }
+
+    // to the nearest integer, to the even one when halfway between two integers
+    pub fn round(self) -> Self {
+        match self {
+            Number::Real(num) => {
+                let two = R::one() + R::one();
+                let rounded = num.round();
+                Number::Real(match (rounded - num).abs() * two == R::one() {
+                    true => (num / two).round() * two,
+                    false => rounded,
+                })
+            }
+            exact => exact.round_exact(|quotient, remainder, denominator| {
+                match (2 * remainder).cmp(&denominator) {
+                    Ordering::Less => quotient,
+                    Ordering::Equal => quotient + (quotient & 1),
+                    Ordering::Greater => quotient + 1,
                 }
             }),
         }
     }
 
+    pub fn truncate(self) -> Self {
+        match self {
+            Number::Real(num) => Number::Real(num.trunc()),
+            exact => {
+                exact.round_exact(
+                    |quotient, remainder, _| match quotient < 0 && remainder != 0 {
+                        true => quotient + 1,
+                        false => quotient,
+                    },
+                )
+            }
+        }
+    }
+
+    // an exact number rounded by `rounding` from the floor of numerator / denominator, the
+    // remainder of the floor division and the denominator, made positive
+    fn round_exact(self, rounding: impl Fn(i64, i64, i64) -> i64) -> Self {
+        match self {
+            Number::Rational(a, b) => {
+                let (a, b) = match b < 0 {
+                    true => (-(a as i64), -(b as i64)),
+                    false => (a as i64, b as i64),
+                };
+                Number::Integer(rounding(a.div_euclid(b), a.rem_euclid(b), b) as i32)
+            }
+            integer => integer,
+        }
+    }
+
+    // the floor variants round the quotient toward negative infinity, so that the remainder has
+    // the sign of the divisor, the truncate variants round it toward zero, so that the remainder
+    // has the sign of the dividend. The results are exact if both operands are.
     pub fn floor_quotient(self, rhs: Self) -> Result<Self> {
-        Ok((self / rhs)?.floor())
+        Ok(self.integer_division("floor-quotient", rhs, Self::floor)?.0)
     }
 
     pub fn floor_remainder(self, rhs: Self) -> Result<Self> {
-        Ok(self - self.floor_quotient(rhs)? * rhs)
+        Ok(self
+            .integer_division("floor-remainder", rhs, Self::floor)?
+            .1)
+    }
+
+    pub fn truncate_quotient(self, rhs: Self) -> Result<Self> {
+        Ok(self
+            .integer_division("truncate-quotient", rhs, Self::truncate)?
+            .0)
+    }
+
+    pub fn truncate_remainder(self, rhs: Self) -> Result<Self> {
+        Ok(self
+            .integer_division("truncate-remainder", rhs, Self::truncate)?
+            .1)
     }
 
-    // Return an exact number that is numerically closest to the given number
+    // the quotient self / rhs rounded by `round` and the remainder self - quotient * rhs
+    fn integer_division(
+        self,
+        procedure: &str,
+        rhs: Self,
+        round: fn(Self) -> Self,
+    ) -> Result<(Self, Self)> {
+        let zero = match rhs {
+            Number::Integer(n) => n == 0,
+            Number::Rational(numerator, _) => numerator == 0,
+            Number::Real(r) => r == R::zero(),
+        };
+        if zero {
+            return error!(LogicError::DivisionByZeroIn(procedure.to_string()));
+        }
+        let quotient = round((self / rhs)?);
+        Ok((quotient, self - quotient * rhs))
+    }
+
+    // the exact number equal to a real, an error for infinities, NaNs and reals whose fraction
+    // does not fit in the exact representation
     pub fn exact(self) -> Result<Self> {
         match self {
-            Number::Real(num) => match num.round().to_i32() {
-                Some(i) => Ok(Number::Integer(i)),
+            Number::Real(num) => match num.to_f64().and_then(exact_fraction) {
+                Some((numerator, 1)) => Ok(Number::Integer(numerator)),
+                Some((numerator, denominator)) => Ok(Number::Rational(numerator, denominator)),
                 None => error!(LogicError::InExactConversion(num.to_string())),
             },
             exact => Ok(exact),
         }
     }
+
+    // exact numbers are divided as f64 first, like `#i1/3` is read, and then rounded to R
+    pub fn inexact(self) -> Self {
+        match self {
+            Number::Integer(i) => Number::Real(R::from(i).unwrap()),
+            Number::Rational(numerator, denominator) => {
+                Number::Real(R::from(numerator as f64 / denominator as f64).unwrap())
+            }
+            real => real,
+        }
+    }
+
+    // the numerator of the reduced fraction equal to the number, the denominator is positive.
+    // It is inexact for reals, whose fraction is the one given by `exact`.
+    pub fn numerator(self) -> Result<Self> {
+        let (numerator, _) = self.fraction()?;
+        Ok(self.with_exactness(Number::Integer(numerator)))
+    }
+
+    pub fn denominator(self) -> Result<Self> {
+        let (_, denominator) = self.fraction()?;
+        Ok(self.with_exactness(Number::Integer(denominator)))
+    }
+
+    fn fraction(self) -> Result<(i32, i32)> {
+        match self.exact()? {
+            Number::Rational(numerator, denominator) => {
+                let divisor =
+                    gcd(numerator as i128, denominator as i128) * denominator.signum() as i128;
+                match (
+                    i32::try_from(numerator as i128 / divisor),
+                    i32::try_from(denominator as i128 / divisor),
+                ) {
+                    (Ok(numerator), Ok(denominator)) => Ok((numerator, denominator)),
+                    _ => error!(LogicError::InExactConversion(self.to_string())),
+                }
+            }
+            Number::Integer(n) => Ok((n, 1)),
+            Number::Real(_) => unreachable!("exact numbers are integers or rationals"),
+        }
+    }
+
+    // `exact` made inexact if self is
+    fn with_exactness(self, exact: Self) -> Self {
+        match self {
+            Number::Real(_) => exact.inexact(),
+            _ => exact,
+        }
+    }
+
+    // the simplest rational differing from self by no more than tolerance, which has the
+    // smallest denominator among them. It is exact if both arguments are, like
+    // `(rationalize (exact x) 1/100)` giving a short fraction close to the real x, when the
+    // exact fraction of x fits in the exact representation.
+    pub fn rationalize(self, tolerance: Self) -> Result<Self> {
+        match (self, tolerance) {
+            (Number::Real(_), _) | (_, Number::Real(_)) => {
+                let (x, y) = (self.as_real(), tolerance.as_real().abs());
+                let nan = |r: R| r.partial_cmp(&r).is_none();
+                match nan(x) || nan(y) {
+                    true => Ok(Number::Real(x + y)),
+                    false => Ok(Number::Real(simplest_real(x - y, x + y))),
+                }
+            }
+            _ => {
+                let ((a, b), (c, d)) = (self.fraction()?, tolerance.fraction()?);
+                let (a, b, c, d) = (a as i128, b as i128, (c as i128).abs(), d as i128);
+                let (numerator, denominator) =
+                    simplest_rational((a * d - c * b, b * d), (a * d + c * b, b * d));
+                match (i32::try_from(numerator), i32::try_from(denominator)) {
+                    (Ok(numerator), Ok(1)) => Ok(Number::Integer(numerator)),
+                    (Ok(numerator), Ok(denominator)) => {
+                        Ok(Number::Rational(numerator, denominator))
+                    }
+                    _ => error!(LogicError::InExactConversion(self.to_string())),
+                }
+            }
+        }
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let remainder = a % b;
+        a = b;
+        b = remainder;
+    }
+    a
+}
+
+// the simplest fraction in [low, high], given as numerators and positive denominators, from the
+// continued fractions of the bounds: their integer parts while they are the same, then the
+// smallest integer between them
+fn simplest_rational(low: (i128, i128), high: (i128, i128)) -> (i128, i128) {
+    if low.0 <= 0 && high.0 >= 0 {
+        return (0, 1);
+    }
+    if high.0 < 0 {
+        let (numerator, denominator) = simplest_rational((-high.0, high.1), (-low.0, low.1));
+        return (-numerator, denominator);
+    }
+    let integer = low.0.div_euclid(low.1);
+    if low.0.rem_euclid(low.1) == 0 {
+        return (integer, 1);
+    }
+    if integer < high.0.div_euclid(high.1) {
+        return (integer + 1, 1);
+    }
+    // both fractional parts are in (0, 1), the simplest fraction between their inverses is
+    // the inverse of the simplest fraction between them
+    let (numerator, denominator) = simplest_rational(
+        (high.1, high.0 - integer * high.1),
+        (low.1, low.0 - integer * low.1),
+    );
+    let divisor = gcd(integer * numerator + denominator, numerator);
+    (
+        (integer * numerator + denominator) / divisor,
+        numerator / divisor,
+    )
+}
+
+// simplest_rational for reals, the continued fraction is cut when the bounds are no longer
+// distinguishable
+fn simplest_real<R: RealNumberInternalTrait>(low: R, high: R) -> R {
+    if low <= R::zero() && high >= R::zero() {
+        return R::zero();
+    }
+    if high < R::zero() {
+        return -simplest_real(-high, -low);
+    }
+    let integer = low.floor();
+    if integer == low || low == high {
+        return low;
+    }
+    if integer < high.floor() {
+        return integer + R::one();
+    }
+    integer + R::one() / simplest_real(R::one() / (high - integer), R::one() / (low - integer))
+}
+
+// the reduced fraction equal to a finite real, if its numerator and denominator fit in i32
+fn exact_fraction(real: f64) -> Option<(i32, i32)> {
+    if !real.is_finite() {
+        return None;
+    }
+    // real is sign * mantissa * 2^exponent
+    let (mantissa, exponent, sign) = num_traits::Float::integer_decode(real);
+    if mantissa == 0 {
+        return Some((0, 1));
+    }
+    let shift = match exponent < 0 {
+        true => mantissa.trailing_zeros().min(-exponent as u32),
+        false => 0,
+    };
+    let (mantissa, exponent) = (mantissa >> shift, exponent as i32 + shift as i32);
+    let (numerator, denominator) = match exponent >= 0 {
+        true if exponent < 32 => ((mantissa as i128) << exponent, 1),
+        true => return None,
+        false if exponent > -32 => (mantissa as i128, 1i128 << -exponent),
+        false => return None,
+    };
+    Some((
+        i32::try_from(sign as i128 * numerator).ok()?,
+        i32::try_from(denominator).ok()?,
+    ))
 }
 
 #[test]
@@ -318,6 +602,12 @@ fn number_floor() {
     assert_eq!(Number::<f32>::Rational(-15, 5).floor(), Number::Integer(-3));
     assert_eq!(Number::<f32>::Real(3.8).floor(), Number::Real(3.0));
     assert_eq!(Number::<f32>::Real(-5.3).floor(), Number::Real(-6.0));
+    assert_eq!(Number::<f32>::Rational(1, -3).floor(), Number::Integer(-1));
+    assert!(matches!(Number::<f32>::Real(2.7).floor(), Number::Real(r) if r == 2.0));
+    assert!(matches!(
+        Number::<f32>::Rational(-7, 2).floor(),
+        Number::Integer(-4)
+    ));
 }
 #[test]
 fn number_ceiling() {
@@ -336,7 +626,135 @@ fn number_ceiling() {
     );
     assert_eq!(Number::<f32>::Real(3.8).ceiling(), Number::Real(4.0));
     assert_eq!(Number::<f32>::Real(-5.3).ceiling(), Number::Real(-5.0));
+    assert_eq!(Number::<f32>::Rational(1, -3).ceiling(), Number::Integer(0));
+    assert!(matches!(Number::<f32>::Real(-2.7).ceiling(), Number::Real(r) if r == -2.0));
+    assert!(matches!(
+        Number::<f32>::Rational(-7, 2).ceiling(),
+        Number::Integer(-3)
+    ));
+}
+#[test]
+fn number_round() {
+    let cases = [
+        (Number::<f32>::Real(2.5), Number::Real(2.0)),
+        (Number::Real(3.5), Number::Real(4.0)),
+        (Number::Real(-2.5), Number::Real(-2.0)),
+        (Number::Real(-3.5), Number::Real(-4.0)),
+        (Number::Real(2.4), Number::Real(2.0)),
+        (Number::Real(-2.6), Number::Real(-3.0)),
+        (Number::Real(0.5), Number::Real(0.0)),
+        (Number::Integer(7), Number::Integer(7)),
+        (Number::Rational(5, 2), Number::Integer(2)),
+        (Number::Rational(7, 2), Number::Integer(4)),
+        (Number::Rational(-5, 2), Number::Integer(-2)),
+        (Number::Rational(-7, 2), Number::Integer(-4)),
+        (Number::Rational(7, -2), Number::Integer(-4)),
+        (Number::Rational(8, 3), Number::Integer(3)),
+        (Number::Rational(-8, 3), Number::Integer(-3)),
+        (Number::Rational(7, 3), Number::Integer(2)),
+    ];
+    for (number, rounded) in cases.iter() {
+        assert!(
+            matches!((number.round(), rounded), (Number::Real(a), Number::Real(b)) if a == *b)
+                || matches!((number.round(), rounded), (Number::Integer(a), Number::Integer(b)) if a == *b),
+            "(round {})",
+            number
+        );
+    }
+}
+
+#[test]
+fn number_truncate() {
+    let cases = [
+        (Number::<f32>::Real(2.7), Number::Real(2.0)),
+        (Number::Real(-2.7), Number::Real(-2.0)),
+        (Number::Real(-0.5), Number::Real(-0.0)),
+        (Number::Integer(-7), Number::Integer(-7)),
+        (Number::Rational(7, 2), Number::Integer(3)),
+        (Number::Rational(-7, 2), Number::Integer(-3)),
+        (Number::Rational(7, -2), Number::Integer(-3)),
+        (Number::Rational(-6, 3), Number::Integer(-2)),
+    ];
+    for (number, truncated) in cases.iter() {
+        assert!(
+            matches!((number.truncate(), truncated), (Number::Real(a), Number::Real(b)) if a == *b)
+                || matches!((number.truncate(), truncated), (Number::Integer(a), Number::Integer(b)) if a == *b),
+            "(truncate {})",
+            number
+        );
+    }
 }
+
+#[test]
+fn number_integer_division() {
+    // n1 n2 floor-quotient floor-remainder truncate-quotient truncate-remainder
+    let cases = [
+        (7, 3, 2, 1, 2, 1),
+        (-7, 3, -3, 2, -2, -1),
+        (7, -3, -3, -2, -2, 1),
+        (-7, -3, 2, -1, 2, -1),
+        (6, 3, 2, 0, 2, 0),
+        (-6, 3, -2, 0, -2, 0),
+        (6, -3, -2, 0, -2, 0),
+        (0, -3, 0, 0, 0, 0),
+    ];
+    let exact: fn(i32) -> Number<f32> = Number::Integer;
+    let rational: fn(i32) -> Number<f32> = |n| Number::Rational(n * 2, 2);
+    let real: fn(i32) -> Number<f32> = |n| Number::Real(n as f32);
+    // equal, and both exact or both inexact
+    let same = |a: Number<f32>, b: Number<f32>| {
+        a == b && matches!(a, Number::Real(_)) == matches!(b, Number::Real(_))
+    };
+    for (n1, n2, fq, fr, tq, tr) in cases.iter().cloned() {
+        for (make_n1, make_n2, result) in [
+            (exact, exact, exact),
+            (rational, exact, exact),
+            (exact, rational, exact),
+            (real, exact, real),
+            (exact, real, real),
+            (real, real, real),
+        ]
+        .iter()
+        {
+            let (a, b) = (make_n1(n1), make_n2(n2));
+            let results = [
+                ("floor-quotient", a.floor_quotient(b), fq),
+                ("floor-remainder", a.floor_remainder(b), fr),
+                ("truncate-quotient", a.truncate_quotient(b), tq),
+                ("truncate-remainder", a.truncate_remainder(b), tr),
+            ];
+            for (procedure, actual, expected) in results.iter() {
+                assert!(
+                    same(actual.clone().unwrap(), result(*expected)),
+                    "({} {} {}) is {:?}",
+                    procedure,
+                    a,
+                    b,
+                    actual
+                );
+            }
+        }
+    }
+    for zero in [
+        Number::<f32>::Integer(0),
+        Number::Rational(0, 3),
+        Number::Real(0.0),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            Number::Integer(7).floor_quotient(*zero),
+            error!(LogicError::DivisionByZeroIn("floor-quotient".to_string()))
+        );
+        assert_eq!(
+            Number::Real(7.0).truncate_remainder(*zero),
+            error!(LogicError::DivisionByZeroIn(
+                "truncate-remainder".to_string()
+            ))
+        );
+    }
+}
+
 #[test]
 fn number_floor_quotient() {
     assert_eq!(
@@ -435,10 +853,31 @@ fn number_floor_remainder() {
 #[test]
 fn number_exact() {
     assert_eq!(Number::<f32>::Integer(5).exact(), Ok(Number::Integer(5)));
-    assert_eq!(Number::<f32>::Real(5.3).exact(), Ok(Number::Integer(5)));
-    assert_eq!(Number::<f32>::Real(5.8).exact(), Ok(Number::Integer(6)));
-    assert_eq!(Number::<f32>::Real(-5.8).exact(), Ok(Number::Integer(-6)));
-    assert_eq!(Number::<f32>::Real(-5.3).exact(), Ok(Number::Integer(-5)));
+    assert_eq!(Number::<f32>::Real(5.0).exact(), Ok(Number::Integer(5)));
+    assert_eq!(
+        Number::<f32>::Real(5.5).exact(),
+        Ok(Number::Rational(11, 2))
+    );
+    assert_eq!(
+        Number::<f32>::Real(-5.25).exact(),
+        Ok(Number::Rational(-21, 4))
+    );
+    assert!(matches!(
+        Number::<f32>::Real(5.3).exact(),
+        Ok(Number::Rational(5557453, 1048576))
+    ));
+    assert_eq!(
+        Number::<f32>::Real(-65536.0 * 16384.0).exact(),
+        Ok(Number::Integer(-(1 << 30)))
+    );
+    assert_eq!(
+        Number::<f64>::Real(0.1).exact(),
+        error!(LogicError::InExactConversion(0.1.to_string())),
+    );
+    assert_eq!(
+        Number::<f32>::Real(f32::INFINITY).exact(),
+        error!(LogicError::InExactConversion(f32::INFINITY.to_string())),
+    );
     assert_eq!(
         Number::<f32>::Real(1e30).exact(),
         error!(LogicError::InExactConversion(1e30.to_string())),
@@ -449,6 +888,97 @@ fn number_exact() {
     );
 }
 
+#[test]
+fn number_exact_bits() {
+    // the exact fraction of the bits of the real, not the one of its shortest decimal
+    assert!(matches!(
+        Number::<f32>::Real(0.1).exact(),
+        Ok(Number::Rational(13421773, 134217728))
+    ));
+    assert!(matches!(
+        Number::<f64>::Real(0.375).exact(),
+        Ok(Number::Rational(3, 8))
+    ));
+    assert!(matches!(
+        Number::<f64>::Real(0.5f64.powi(30)).exact(),
+        Ok(Number::Rational(1, 1073741824))
+    ));
+    assert!(matches!(
+        Number::<f64>::Real(-1.0 * 2f64.powi(31)).exact(),
+        Ok(Number::Integer(i32::MIN))
+    ));
+    for real in [0.1, 1e30, 1e-30, 0.5f64.powi(31), 2f64.powi(31), f64::NAN].iter() {
+        assert_eq!(
+            Number::<f64>::Real(*real).exact(),
+            error!(LogicError::InExactConversion(real.to_string())),
+        );
+    }
+}
+
+#[test]
+fn number_rationalize() {
+    let exact = |a: Number<f32>, b: Number<f32>| match a.rationalize(b).unwrap() {
+        Number::Integer(n) => (n, 1),
+        Number::Rational(n, d) => (n, d),
+        real => panic!("({} {}) is the real {}", a, b, real),
+    };
+    assert_eq!(
+        exact(Number::Rational(3, 10), Number::Rational(1, 10)),
+        (1, 3)
+    );
+    assert_eq!(
+        exact(Number::Rational(-3, 10), Number::Rational(1, 10)),
+        (-1, 3)
+    );
+    assert_eq!(
+        exact(Number::Rational(3, 10), Number::Rational(-1, 10)),
+        (1, 3)
+    );
+    assert_eq!(exact(Number::Rational(1, 3), Number::Integer(0)), (1, 3));
+    assert_eq!(
+        exact(Number::Rational(22, 7), Number::Rational(1, 1000)),
+        (22, 7)
+    );
+    assert_eq!(exact(Number::Integer(5), Number::Integer(1)), (4, 1));
+    assert_eq!(exact(Number::Rational(1, 3), Number::Integer(1)), (0, 1));
+    assert_eq!(
+        exact(Number::Real(0.25).exact().unwrap(), Number::Rational(1, 10)),
+        (1, 3)
+    );
+    assert!(matches!(
+        Number::<f64>::Real(0.3).rationalize(Number::Rational(1, 10)),
+        Ok(Number::Real(r)) if r == 1.0 / 3.0
+    ));
+    assert!(matches!(
+        Number::<f64>::Real(0.5).rationalize(Number::Real(f64::INFINITY)),
+        Ok(Number::Real(r)) if r == 0.0
+    ));
+}
+
+#[test]
+fn number_fraction() {
+    let cases = [
+        (Number::<f32>::Rational(6, 4), 3, 2),
+        (Number::Rational(-6, 4), -3, 2),
+        (Number::Rational(6, -4), -3, 2),
+        (Number::Integer(0), 0, 1),
+        (Number::Integer(-7), -7, 1),
+    ];
+    for (number, numerator, denominator) in cases.iter() {
+        assert!(matches!(number.numerator(), Ok(Number::Integer(n)) if n == *numerator));
+        assert!(matches!(number.denominator(), Ok(Number::Integer(d)) if d == *denominator));
+    }
+    assert!(matches!(Number::<f32>::Real(0.75).numerator(), Ok(Number::Real(n)) if n == 3.0));
+    assert!(matches!(Number::<f32>::Real(0.75).denominator(), Ok(Number::Real(d)) if d == 4.0));
+}
+
+#[test]
+fn number_inexact() {
+    assert!(matches!(Number::<f32>::Integer(5).inexact(), Number::Real(r) if r == 5.0));
+    assert!(matches!(Number::<f64>::Rational(1, 3).inexact(), Number::Real(r) if r == 1.0 / 3.0));
+    assert!(matches!(Number::<f32>::Real(0.5).inexact(), Number::Real(r) if r == 0.5));
+}
+
 pub type ArgVec<R> = SmallVec<[Value<R>; 4]>;
 
 #[derive(Clone)]
@@ -495,10 +1025,38 @@ impl<R: RealNumberInternalTrait> Debug for BuiltinProcedure<R> {
     }
 }
 
+// r7rs 4.2.6. parameter object, returns its value when called with no argument
+pub struct Parameter<R: RealNumberInternalTrait> {
+    value: RefCell<Value<R>>,
+    converter: Option<Procedure<R>>,
+    parameters: ParameterFormals,
+}
+
+impl<R: RealNumberInternalTrait> Parameter<R> {
+    pub fn new(value: Value<R>, converter: Option<Procedure<R>>) -> Self {
+        Self {
+            value: RefCell::new(value),
+            converter,
+            parameters: ParameterFormals::new_non_located(std::iter::empty(), None),
+        }
+    }
+    pub fn get(&self) -> Value<R> {
+        self.value.borrow().clone()
+    }
+    // replace value with the new one, returns the old value
+    pub fn replace(&self, value: Value<R>) -> Value<R> {
+        self.value.replace(value)
+    }
+    pub fn converter(&self) -> Option<&Procedure<R>> {
+        self.converter.as_ref()
+    }
+}
+
 #[derive(Clone)]
 pub enum Procedure<R: RealNumberInternalTrait> {
-    User(SchemeProcedure, Rc<Environment<R>>),
-    Builtin(BuiltinProcedure<R>),
+    User(Rc<Lambda<R>>, Rc<Environment<R>>),
+    Builtin(Rc<BuiltinProcedure<R>>),
+    Parameter(Rc<Parameter<R>>),
 }
 
 impl<R: RealNumberInternalTrait> Debug for Procedure<R> {
@@ -506,6 +1064,7 @@ impl<R: RealNumberInternalTrait> Debug for Procedure<R> {
         match self {
             Self::User(p, _) => write!(f, "{:?}", p),
             Self::Builtin(b) => write!(f, "{:?}", b),
+            Self::Parameter(_) => write!(f, "{}", self),
         }
     }
 }
@@ -515,38 +1074,98 @@ impl<R: RealNumberInternalTrait> PartialEq for Procedure<R> {
         match (self, other) {
             (Self::User(a, _), Self::User(b, _)) => a == b,
             (Self::Builtin(a), Self::Builtin(b)) => a == b,
+            (Self::Parameter(a), Self::Parameter(b)) => Rc::ptr_eq(a, b),
             _ => false,
         }
     }
 }
 
 impl<R: RealNumberInternalTrait> Procedure<R> {
+    /// identity of procedures, as compared by `eqv?`: a procedure is only the same as itself,
+    /// not as another closure of the same lambda expression
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::User(a, a_env), Self::User(b, b_env)) => {
+                Rc::ptr_eq(a, b) && Rc::ptr_eq(a_env, b_env)
+            }
+            (Self::Builtin(a), Self::Builtin(b)) => a == b,
+            (Self::Parameter(a), Self::Parameter(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
     pub fn new_builtin_pure(
         name: String,
         parameters: ParameterFormals,
         function: fn(ArgVec<R>) -> Result<Value<R>>,
     ) -> Self {
-        Self::Builtin(BuiltinProcedure {
+        Self::Builtin(Rc::new(BuiltinProcedure {
             name,
             parameters,
             body: BuiltinProcedureBody::Pure(function),
-        })
+        }))
     }
     pub fn new_builtin_impure(
         name: String,
         parameters: ParameterFormals,
         pointer: impl Fn(ArgVec<R>, Rc<Environment<R>>) -> Result<Value<R>> + 'static,
     ) -> Self {
-        Self::Builtin(BuiltinProcedure {
+        Self::Builtin(Rc::new(BuiltinProcedure {
             name,
             parameters,
             body: BuiltinProcedureBody::Impure(Rc::new(pointer)),
-        })
+        }))
+    }
+    /// builtin procedure backed by a closure, which can capture state of the host application
+    pub fn new_builtin_closure(
+        name: String,
+        parameters: ParameterFormals,
+        closure: impl Fn(ArgVec<R>) -> Result<Value<R>> + 'static,
+    ) -> Self {
+        Self::new_builtin_impure(name, parameters, move |arguments, _| closure(arguments))
+    }
+    // (name parameter ...) for named procedures, (lambda formals) for anonymous ones
+    pub fn signature(&self) -> String {
+        let name = match self {
+            Procedure::User(lambda, _) => match &lambda.name {
+                Some(name) => name.as_str(),
+                None => return lambda.to_string(),
+            },
+            Procedure::Builtin(builtin) => builtin.name.as_str(),
+            Procedure::Parameter(_) => return self.to_string(),
+        };
+        let mut signature = format!("({}", name);
+        if let Some(variadic) = self
+            .get_parameters()
+            .iter_to_last(|fixed| signature.push_str(&format!(" {}", fixed)))
+        {
+            signature.push_str(&format!(" . {}", variadic));
+        }
+        signature.push(')');
+        signature
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            Procedure::User(lambda, _) => match &lambda.name {
+                Some(name) => name.as_str(),
+                None => "lambda",
+            },
+            Procedure::Builtin(builtin) => builtin.name.as_str(),
+            Procedure::Parameter(_) => "parameter",
+        }
+    }
+    // numbers of fixed parameters and whether there is a variadic one
+    pub fn arity(&self) -> (usize, bool) {
+        match self {
+            Procedure::User(lambda, ..) => (lambda.fixed, lambda.variadic),
+            _ => self.get_parameters().len(),
+        }
     }
     pub fn get_parameters(&self) -> &ParameterFormals {
         match &self {
-            Procedure::User(user, ..) => &user.0,
+            Procedure::User(lambda, ..) => &lambda.formals,
             Procedure::Builtin(builtin) => &builtin.parameters,
+            Procedure::Parameter(parameter) => &parameter.parameters,
         }
     }
 }
@@ -554,12 +1173,20 @@ impl<R: RealNumberInternalTrait> Procedure<R> {
 impl<R: RealNumberInternalTrait> Display for Procedure<R> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match &self {
-            Procedure::User(procedure, ..) => write!(f, "{}", procedure),
+            // the name given by the definition creating the procedure, not by later bindings
+            Procedure::User(lambda, ..) => match &lambda.name {
+                Some(name) => write!(f, "#<procedure {} {}>", name, lambda.formals),
+                None => write!(f, "#<procedure {}>", lambda.formals),
+            },
             Procedure::Builtin(fp) => write!(f, "{}", fp),
+            Procedure::Parameter(_) => write!(f, "<parameter>"),
         }
     }
 }
 
+/// vectors of literals and quoted data are immutable: they are shared with the program, which
+/// would see their mutations the next time it evaluates them. Pairs are copied and strings have
+/// no mutators, so they need no such flag.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ValueReference<T> {
     Immutable(Rc<T>),
@@ -596,7 +1223,9 @@ impl<T: Display> ValueReference<Vec<T>> {
     }
     pub fn as_mut<'a>(&'a self) -> Result<RefMut<'a, Vec<T>>> {
         match self {
-            ValueReference::Immutable(_) => error!(LogicError::RequiresMutable(self.to_string())),
+            ValueReference::Immutable(_) => {
+                error!(LogicError::LiteralMutation(format!("#({})", self)))
+            }
             ValueReference::Mutable(t) => Ok(t.borrow_mut()),
         }
     }
@@ -608,6 +1237,14 @@ impl<T: Display> ValueReference<Vec<T>> {
             _ => false,
         }
     }
+
+    // the address of the referenced value, the same for references sharing it
+    pub(crate) fn as_ptr(&self) -> *const () {
+        match self {
+            Self::Immutable(a) => Rc::as_ptr(a) as *const (),
+            Self::Mutable(a) => Rc::as_ptr(a) as *const (),
+        }
+    }
 }
 
 #[macro_export]
@@ -615,10 +1252,11 @@ macro_rules! match_expect_type {
     ($value:expr, $type:pat => $inner: expr, $type_name:expr) => {
         match $value {
             $type => Ok($inner),
-            _ => Err(
-                ErrorData::Logic(LogicError::TypeMisMatch($value.to_string(), $type_name))
-                    .no_locate(),
-            ),
+            _ => Err(ErrorData::Logic(LogicError::TypeMisMatch(
+                $value.written().to_string(),
+                $type_name,
+            ))
+            .no_locate()),
         }
     };
 }
@@ -651,6 +1289,8 @@ fn macro_match_expect_type() {
 pub enum Type {
     Number, // Non exhaustive, but ok
     Integer,
+    PositiveInteger,
+    NonNegativeInteger,
     Real,
     Rational,
     Boolean,
@@ -658,28 +1298,130 @@ pub enum Type {
     String,
     Symbol,
     Procedure,
+    Parameter,
     Vector,
     Pair,
+    List,
     EmptyList,
+    Port,
+    InputPort,
+    OutputPort,
+    ErrorObject,
     Void,
 }
 
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Number => "a number",
+            Type::Integer => "an integer",
+            Type::PositiveInteger => "a positive integer",
+            Type::NonNegativeInteger => "a non-negative integer",
+            Type::Real => "a real number",
+            Type::Rational => "a rational number",
+            Type::Boolean => "a boolean",
+            Type::Character => "a character",
+            Type::String => "a string",
+            Type::Symbol => "a symbol",
+            Type::Procedure => "a procedure",
+            Type::Parameter => "a parameter",
+            Type::Vector => "a vector",
+            Type::Pair => "a pair",
+            Type::List => "a list",
+            Type::EmptyList => "an empty list",
+            Type::Port => "a port",
+            Type::InputPort => "an input port",
+            Type::OutputPort => "an output port",
+            Type::ErrorObject => "an error object",
+            Type::Void => "void",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Numbers, booleans, characters, `Eof` and `Void` are stored inline and copied without
+/// allocating. The most common of them are cached, see `Value::from_i64`.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value<R: RealNumberInternalTrait> {
     Number(Number<R>),
     Boolean(bool),
     Character(char),
-    String(String),
-    Symbol(String),
+    // strings are immutable, so they are shared instead of copied
+    String(Rc<str>),
+    Symbol(Symbol),
     Procedure(Procedure<R>),
     Vector(ValueReference<Vec<Value<R>>>),
     Pair(Box<Pair<R>>),
     Transformer(Transformer),
+    Port(Port),
+    // an error caught by `guard`
+    ErrorObject(Rc<SchemeError>),
+    Eof,
+    // internal definitions are bound to this before their initializers run (letrec* semantics)
+    Uninitialized,
     Void,
 }
 
 pub type Pair<R> = GenericPair<Value<R>>;
 
+/// the integers `Value::from_i64` takes from the cache of common values, loop counters and
+/// indices mostly
+pub const CACHED_INTEGERS: RangeInclusive<i64> = -16..=256;
+
+// the values created the most, by loop counters and truth tests, built once per thread and type
+// of real numbers
+struct Constants<R: RealNumberInternalTrait> {
+    integers: Vec<Value<R>>,
+    booleans: [Value<R>; 2],
+    empty_list: Value<R>,
+}
+
+thread_local! {
+    // a Constants<R> for each R
+    static CONSTANTS: RefCell<HashMap<TypeId, Rc<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+fn constants<R: RealNumberInternalTrait>() -> Rc<Constants<R>> {
+    let constants = CONSTANTS.with(|constants| {
+        constants
+            .borrow_mut()
+            .entry(TypeId::of::<R>())
+            .or_insert_with(|| {
+                Rc::new(Constants::<R> {
+                    integers: CACHED_INTEGERS
+                        .map(|integer| Value::Number(Number::Integer(integer as i32)))
+                        .collect(),
+                    booleans: [Value::Boolean(false), Value::Boolean(true)],
+                    empty_list: Value::Pair(Box::new(GenericPair::Empty)),
+                })
+            })
+            .clone()
+    });
+    constants.downcast().unwrap()
+}
+
+impl<R: RealNumberInternalTrait> Value<R> {
+    /// an exact integer, or an inexact one if it does not fit, the integers in `CACHED_INTEGERS`
+    /// are reused rather than constructed
+    pub fn from_i64(integer: i64) -> Self {
+        if CACHED_INTEGERS.contains(&integer) {
+            return constants::<R>().integers[(integer - CACHED_INTEGERS.start()) as usize].clone();
+        }
+        match i32::try_from(integer) {
+            Ok(integer) => Value::Number(Number::Integer(integer)),
+            Err(_) => Value::Number(Number::Real(R::from(integer).unwrap())),
+        }
+    }
+    /// `#t` or `#f`, reused from the cache of common values
+    pub fn boolean(boolean: bool) -> Self {
+        constants::<R>().booleans[boolean as usize].clone()
+    }
+    /// `'()`, reused from the cache of common values
+    pub fn empty_list() -> Self {
+        constants::<R>().empty_list.clone()
+    }
+}
+
 impl<R: RealNumberInternalTrait> Pairable for Value<R> {
     impl_pairable!(Value);
 }
@@ -698,10 +1440,313 @@ impl<R: RealNumberInternalTrait> From<Pair<R>> for Value<R> {
 
 impl<R: RealNumberInternalTrait> From<i32> for Value<R> {
     fn from(integer: i32) -> Self {
-        Value::Number(Number::Integer(integer))
+        Value::from_i64(integer.into())
+    }
+}
+
+// integers which do not fit in an exact integer become inexact
+impl<R: RealNumberInternalTrait> From<i64> for Value<R> {
+    fn from(integer: i64) -> Self {
+        Value::from_i64(integer)
+    }
+}
+
+impl<R: RealNumberInternalTrait> From<usize> for Value<R> {
+    fn from(integer: usize) -> Self {
+        match i64::try_from(integer) {
+            Ok(integer) => Value::from_i64(integer),
+            Err(_) => Value::Number(Number::Real(R::from(integer).unwrap())),
+        }
     }
 }
 
+impl<R: RealNumberInternalTrait> From<f64> for Value<R> {
+    fn from(real: f64) -> Self {
+        Value::Number(Number::Real(R::from(real).unwrap()))
+    }
+}
+
+impl<R: RealNumberInternalTrait> From<Number<R>> for Value<R> {
+    fn from(number: Number<R>) -> Self {
+        Value::Number(number)
+    }
+}
+
+impl<R: RealNumberInternalTrait> From<Procedure<R>> for Value<R> {
+    fn from(procedure: Procedure<R>) -> Self {
+        Value::Procedure(procedure)
+    }
+}
+
+impl<R: RealNumberInternalTrait> From<bool> for Value<R> {
+    fn from(boolean: bool) -> Self {
+        Value::boolean(boolean)
+    }
+}
+
+impl<R: RealNumberInternalTrait> From<char> for Value<R> {
+    fn from(character: char) -> Self {
+        Value::Character(character)
+    }
+}
+
+impl<R: RealNumberInternalTrait> From<String> for Value<R> {
+    fn from(string: String) -> Self {
+        Value::String(string.into())
+    }
+}
+
+impl<R: RealNumberInternalTrait> From<&str> for Value<R> {
+    fn from(string: &str) -> Self {
+        Value::String(string.into())
+    }
+}
+
+// vectors become scheme vectors, collect into a `Pair` for a list instead
+impl<R: RealNumberInternalTrait, T: Into<Value<R>>> From<Vec<T>> for Value<R> {
+    fn from(vector: Vec<T>) -> Self {
+        Value::Vector(ValueReference::new_mutable(
+            vector.into_iter().map(Into::into).collect(),
+        ))
+    }
+}
+
+impl<R: RealNumberInternalTrait> TryFrom<Value<R>> for i32 {
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        match_expect_type!(value, Value::Number(Number::Integer(i)) => i, Type::Integer)
+    }
+}
+
+impl<R: RealNumberInternalTrait> TryFrom<Value<R>> for i64 {
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        i32::try_from(value).map(i64::from)
+    }
+}
+
+impl<R: RealNumberInternalTrait> TryFrom<Value<R>> for usize {
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        match value {
+            Value::Number(Number::Integer(i)) if i >= 0 => Ok(i as usize),
+            other => error!(LogicError::TypeMisMatch(
+                other.written().to_string(),
+                Type::NonNegativeInteger
+            )),
+        }
+    }
+}
+
+// any real number, exact numbers are converted to inexact
+impl<R: RealNumberInternalTrait> TryFrom<Value<R>> for f64 {
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        match_expect_type!(value, Value::Number(number) => match number {
+            Number::Integer(i) => i as f64,
+            Number::Rational(numerator, denominator) => numerator as f64 / denominator as f64,
+            Number::Real(r) => r.to_f64().unwrap(),
+        }, Type::Real)
+    }
+}
+
+impl<R: RealNumberInternalTrait> TryFrom<Value<R>> for bool {
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        value.expect_boolean()
+    }
+}
+
+impl<R: RealNumberInternalTrait> TryFrom<Value<R>> for char {
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        value.expect_character()
+    }
+}
+
+impl<R: RealNumberInternalTrait> TryFrom<Value<R>> for String {
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        value.expect_string().map(|string| string.to_string())
+    }
+}
+
+// from either a vector or a proper list
+impl<R: RealNumberInternalTrait, T: TryFrom<Value<R>, Error = SchemeError>> TryFrom<Value<R>>
+    for Vec<T>
+{
+    type Error = SchemeError;
+    fn try_from(value: Value<R>) -> Result<Self> {
+        match value {
+            Value::Vector(vector) => vector.as_ref().iter().cloned().map(T::try_from).collect(),
+            Value::Pair(list) => {
+                let list_string = list.to_string();
+                IntoPairIter::from(*list)
+                    .map(|item| match item {
+                        PairIterItem::Proper(element) => T::try_from(element),
+                        PairIterItem::Improper(_) => {
+                            error!(LogicError::InproperList(list_string.clone()))
+                        }
+                    })
+                    .collect()
+            }
+            other => error!(LogicError::TypeMisMatch(
+                other.written().to_string(),
+                Type::Pair
+            )),
+        }
+    }
+}
+
+#[test]
+fn cheap_clone() {
+    // strings and procedures are shared by clones, like vectors
+    let string = Value::<f32>::from("a long string");
+    match (&string, &string.clone()) {
+        (Value::String(a), Value::String(b)) => assert!(Rc::ptr_eq(a, b)),
+        _ => unreachable!(),
+    }
+    let builtin = Procedure::<f32>::new_builtin_pure(
+        "f".to_string(),
+        ParameterFormals::new_non_located(std::iter::empty(), None),
+        |_| Ok(Value::Void),
+    );
+    match (&builtin, &builtin.clone()) {
+        (Procedure::Builtin(a), Procedure::Builtin(b)) => assert!(Rc::ptr_eq(a, b)),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn common_constants() {
+    for integer in [-17, -16, 0, 256, 257, i64::from(i32::MIN)].iter().copied() {
+        assert_eq!(
+            Value::<f32>::from_i64(integer),
+            Value::Number(Number::Integer(integer as i32))
+        );
+    }
+    assert_eq!(
+        Value::<f64>::from_i64(i64::MAX),
+        Value::Number(Number::Real(i64::MAX as f64))
+    );
+    // each type of real numbers has its own cache
+    assert_eq!(Value::<f64>::from_i64(1), Value::Number(Number::Integer(1)));
+    assert_eq!(Value::<f32>::boolean(true), Value::Boolean(true));
+    assert_eq!(Value::<f32>::boolean(false), Value::Boolean(false));
+    assert_eq!(
+        Value::<f32>::empty_list(),
+        Value::Pair(Box::new(GenericPair::Empty))
+    );
+}
+
+#[test]
+fn conversions() {
+    use std::convert::TryInto;
+    assert_eq!(Value::<f32>::from(true), Value::Boolean(true));
+    assert_eq!(
+        Value::<f32>::from(-3i64),
+        Value::Number(Number::Integer(-3))
+    );
+    assert_eq!(
+        Value::<f64>::from(1i64 << 40),
+        Value::Number(Number::Real((1i64 << 40) as f64))
+    );
+    assert_eq!(
+        Value::<f32>::from(3usize),
+        Value::Number(Number::Integer(3))
+    );
+    assert_eq!(
+        Value::<f64>::from(usize::MAX),
+        Value::Number(Number::Real(usize::MAX as f64))
+    );
+    assert_eq!(Value::<f32>::from(0.5f64), Value::Number(Number::Real(0.5)));
+    assert_eq!(
+        Value::<f32>::from(Number::Rational(1, 2)),
+        Value::Number(Number::Rational(1, 2))
+    );
+    let procedure =
+        Procedure::<f32>::new_builtin_pure("f".to_string(), param_fixed![], |_| Ok(Value::Void));
+    assert_eq!(Value::from(procedure.clone()), Value::Procedure(procedure));
+    assert_eq!(Value::<f32>::from('a'), Value::Character('a'));
+    assert_eq!(Value::<f32>::from("a"), Value::String("a".into()));
+    assert_eq!(
+        Value::<f32>::from("a".to_string()),
+        Value::String("a".into())
+    );
+    assert_eq!(
+        Value::<f32>::from(vec![1, 2]),
+        Value::Vector(ValueReference::new_mutable(vec![
+            Value::Number(Number::Integer(1)),
+            Value::Number(Number::Integer(2))
+        ]))
+    );
+
+    assert_eq!(Value::<f32>::from(3).try_into(), Ok(3i32));
+    assert_eq!(Value::<f32>::from(3).try_into(), Ok(3i64));
+    assert_eq!(Value::<f32>::from(3).try_into(), Ok(3usize));
+    assert_eq!(Value::<f32>::from(3).try_into(), Ok(3.0f64));
+    assert_eq!(
+        Value::<f32>::Number(Number::Rational(1, 2)).try_into(),
+        Ok(0.5f64)
+    );
+    assert_eq!(
+        Value::<f32>::Number(Number::Real(1.5)).try_into(),
+        Ok(1.5f64)
+    );
+    assert_eq!(Value::<f32>::from(false).try_into(), Ok(false));
+    assert_eq!(Value::<f32>::from('a').try_into(), Ok('a'));
+    assert_eq!(Value::<f32>::from("a").try_into(), Ok("a".to_string()));
+    assert_eq!(Value::<f32>::from(vec![1, 2]).try_into(), Ok(vec![1, 2]));
+    assert_eq!(
+        Value::<f32>::from(list![Value::from("a"), Value::from("b")]).try_into(),
+        Ok(vec!["a".to_string(), "b".to_string()])
+    );
+
+    assert_eq!(
+        i32::try_from(Value::<f32>::from("1")),
+        error!(LogicError::TypeMisMatch("\"1\"".to_string(), Type::Integer))
+    );
+    assert_eq!(
+        usize::try_from(Value::<f32>::from(-1)),
+        error!(LogicError::TypeMisMatch(
+            "-1".to_string(),
+            Type::NonNegativeInteger
+        ))
+    );
+    assert_eq!(
+        f64::try_from(Value::<f32>::from(true)),
+        error!(LogicError::TypeMisMatch("#t".to_string(), Type::Real))
+    );
+    assert_eq!(
+        bool::try_from(Value::<f32>::from(1)),
+        error!(LogicError::TypeMisMatch("1".to_string(), Type::Boolean))
+    );
+    assert_eq!(
+        char::try_from(Value::<f32>::from("a")),
+        error!(LogicError::TypeMisMatch(
+            "\"a\"".to_string(),
+            Type::Character
+        ))
+    );
+    assert_eq!(
+        String::try_from(Value::<f32>::from('a')),
+        error!(LogicError::TypeMisMatch("#\\a".to_string(), Type::String))
+    );
+    assert_eq!(
+        Vec::<i32>::try_from(Value::<f32>::from(vec!["a"])),
+        error!(LogicError::TypeMisMatch("\"a\"".to_string(), Type::Integer))
+    );
+    assert_eq!(
+        Vec::<i32>::try_from(Value::<f32>::from(1)),
+        error!(LogicError::TypeMisMatch("1".to_string(), Type::Pair))
+    );
+    let improper = Value::<f32>::from(GenericPair::Some(Value::from(1), Value::from(2)));
+    assert_eq!(
+        Vec::<i32>::try_from(improper),
+        error!(LogicError::InproperList("(1 . 2)".to_string()))
+    );
+}
+
 impl<R: RealNumberInternalTrait> Value<R> {
     pub fn expect_number(self) -> Result<Number<R>> {
         match_expect_type!(self, Value::Number(number) => number, Type::Number)
@@ -718,11 +1763,36 @@ impl<R: RealNumberInternalTrait> Value<R> {
     pub fn expect_list(self) -> Result<Pair<R>> {
         match_expect_type!(self, Value::Pair(list) => *list, Type::Pair)
     }
-    pub fn expect_string(self) -> Result<String> {
+    /// the list given to `procedure` if it is proper, a pair owns its cdr so that lists cannot
+    /// be circular and the walk always ends
+    pub fn expect_proper_list(self, procedure: &str) -> Result<Pair<R>> {
+        let list = match self {
+            Value::Pair(list) => *list,
+            other => return error!(wrong_type(procedure, Type::List, &other)),
+        };
+        let mut rest = &list;
+        while let GenericPair::Some(_, cdr) = rest {
+            rest = match cdr.either_pair_ref() {
+                Either::Left(pair) => pair,
+                Either::Right(_) => {
+                    return error!(wrong_type(
+                        procedure,
+                        Type::List,
+                        &Value::Pair(Box::new(list))
+                    ))
+                }
+            };
+        }
+        Ok(list)
+    }
+    pub fn expect_character(self) -> Result<char> {
+        match_expect_type!(self, Value::Character(c) => c, Type::Character)
+    }
+    pub fn expect_string(self) -> Result<Rc<str>> {
         match_expect_type!(self, Value::String(string) => string, Type::String)
     }
-    pub fn expect_symbol(self) -> Result<String> {
-        match_expect_type!(self, Value::Symbol(string) => string, Type::Symbol)
+    pub fn expect_symbol(self) -> Result<Symbol> {
+        match_expect_type!(self, Value::Symbol(symbol) => symbol, Type::Symbol)
     }
     pub fn expect_procedure(self) -> Result<Procedure<R>> {
         match_expect_type!(self, Value::Procedure(procedure) => procedure, Type::Procedure)
@@ -730,12 +1800,84 @@ impl<R: RealNumberInternalTrait> Value<R> {
     pub fn expect_boolean(self) -> Result<bool> {
         match_expect_type!(self, Value::Boolean(condition) => condition, Type::Boolean)
     }
+    pub fn expect_port(self) -> Result<Port> {
+        match_expect_type!(self, Value::Port(port) => port, Type::Port)
+    }
+    pub fn expect_input_port(self) -> Result<Rc<RefCell<InputPort>>> {
+        match_expect_type!(self, Value::Port(Port::Input(port)) => port, Type::InputPort)
+    }
+    pub fn expect_output_port(self) -> Result<Rc<RefCell<OutputPort>>> {
+        match_expect_type!(self, Value::Port(Port::Output(port)) => port, Type::OutputPort)
+    }
+    pub fn expect_error_object(self) -> Result<Rc<SchemeError>> {
+        match_expect_type!(self, Value::ErrorObject(error) => error, Type::ErrorObject)
+    }
+    /// whether the value counts as true in a test, used by `if` and every form or procedure
+    /// testing a value, like `not`, `cond`, `and`, `or`, `when` and `unless`, which expand to
+    /// `if`. Following r7rs, `#f` is the only false value, `0`, `""`, `'()` and the unspecified
+    /// value are all true.
     pub fn as_boolean(&self) -> bool {
         match self {
             Value::Boolean(false) => false,
             _ => true,
         }
     }
+    /// format the value like `write`, which quotes strings, characters and symbols as they are
+    /// read back, at any depth. `Display` formats it like `display`, which shows them as they are.
+    pub fn written(&self) -> Written<'_, R> {
+        Written(self)
+    }
+}
+
+pub struct Written<'a, R: RealNumberInternalTrait>(&'a Value<R>);
+
+// writing recurses once per level of nesting of the cars and vectors
+impl<'a, R: RealNumberInternalTrait> Display for Written<'a, R> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        stack::guard(|| match self.0 {
+            Value::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Vector(vector) => write!(
+                f,
+                "#({})",
+                join(vector.as_ref().iter().map(|v| v.written()), " ")
+            ),
+            Value::Pair(pair) => {
+                write!(f, "(")?;
+                let mut current = pair.as_ref();
+                while let GenericPair::Some(car, cdr) = current {
+                    write!(f, "{}", car.written())?;
+                    match cdr.either_pair_ref() {
+                        Either::Left(GenericPair::Empty) => break,
+                        Either::Left(pair) => {
+                            write!(f, " ")?;
+                            current = pair;
+                        }
+                        Either::Right(value) => {
+                            write!(f, " . {}", value.written())?;
+                            break;
+                        }
+                    }
+                }
+                write!(f, ")")
+            }
+            Value::Character(c) => write!(f, "{}", written_character(*c)),
+            Value::Symbol(symbol) => write!(f, "{}", written_symbol(symbol)),
+            other => write!(f, "{}", other),
+        })
+    }
 }
 
 impl<R: RealNumberInternalTrait> Display for Value<R> {
@@ -747,11 +1889,15 @@ impl<R: RealNumberInternalTrait> Display for Value<R> {
             Value::Void => write!(f, "Void"),
             Value::Boolean(true) => write!(f, "#t"),
             Value::Boolean(false) => write!(f, "#f"),
-            Value::Character(c) => write!(f, "#\\{}", c),
+            Value::Character(c) => write!(f, "{}", c),
             Value::String(ref s) => write!(f, "{}", s),
             Value::Vector(vecref) => write!(f, "#({})", vecref),
             Value::Pair(list) => write!(f, "{}", list),
             Value::Transformer(transformer) => write!(f, "{}", transformer),
+            Value::Port(port) => write!(f, "{}", port),
+            Value::ErrorObject(error) => write!(f, "#<error-object {}>", error),
+            Value::Eof => write!(f, "#<eof>"),
+            Value::Uninitialized => write!(f, "#<uninitialized>"),
         }
     }
 }
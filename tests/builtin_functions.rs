@@ -1,14 +1,18 @@
 use ruschm::{
-    error::ToLocated,
     error::{ErrorData, SchemeError},
-    interpreter::{error::LogicError, Interpreter},
+    interpreter::{
+        error::{index_out_of_range, wrong_type, LogicError},
+        Interpreter,
+    },
     list,
     parser::pair::GenericPair,
     values::Number,
     values::Pair,
     values::Type,
     values::Value,
+    values::ValueReference,
 };
+use std::{error::Error, io};
 
 #[test]
 fn list() -> Result<(), SchemeError> {
@@ -63,7 +67,6 @@ fn list() -> Result<(), SchemeError> {
 
 #[test]
 fn vector() -> Result<(), SchemeError> {
-    use ruschm::values::ValueReference;
     let mut interpreter = Interpreter::<f32>::new_with_stdlib();
     assert_eq!(
         interpreter.eval("(vector 1 2 3)".chars())?,
@@ -89,7 +92,7 @@ fn apply() -> Result<(), SchemeError> {
     );
     assert_eq!(
         interpreter.eval("(apply + 1)".chars()),
-        Err(ErrorData::Logic(LogicError::TypeMisMatch("1".to_owned(), Type::Pair)).no_locate())
+        Err(ErrorData::Logic(wrong_type("apply", Type::List, &Value::<f32>::from(1))).no_locate())
     );
     Ok(())
 }
@@ -184,11 +187,634 @@ fn booleans() -> Result<(), SchemeError> {
         );
         assert_eq!(
             interpreter.eval("(boolean=? 1 1)".chars()),
-            Err(
-                ErrorData::Logic(LogicError::TypeMisMatch("1".to_string(), Type::Boolean))
-                    .no_locate()
-            )
+            Err(ErrorData::Logic(wrong_type(
+                "boolean=?",
+                Type::Boolean,
+                &Value::<f32>::from(1)
+            ))
+            .no_locate())
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn common_constants() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    for (program, expected) in [
+        ("(eqv? -16 (- 0 16))", true),
+        ("(eqv? 256 (* 16 16))", true),
+        ("(eqv? 100000 (* 1000 100))", true),
+        ("(eqv? 1 1.0)", false),
+        ("(eqv? #t (= 1 1))", true),
+        ("(eqv? #f (null? 1))", true),
+        ("(eqv? '() (list))", true),
+        ("(eqv? (if #f #f) (if #f #f))", true),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            interpreter.eval(program.chars())?,
+            Some(Value::Boolean(*expected)),
+            "{}",
+            program
         );
     }
+    // values are never shared between bindings
+    interpreter.eval("(define a 1) (define b a) (set! a (+ a 1))".chars())?;
+    assert_eq!(
+        interpreter.eval("(list a b)".chars())?,
+        Some(Value::Pair(Box::new(list!(
+            Value::Number(Number::Integer(2)),
+            Value::Number(Number::Integer(1))
+        ))))
+    );
+    // values taken from the cache are the same as the ones the program constructs
+    let env = interpreter.environment();
+    env.define("small", Value::from_i64(-16));
+    env.define("large", Value::from_i64(1000));
+    env.define("true", Value::boolean(true));
+    env.define("nil", Value::empty_list());
+    assert_eq!(
+        interpreter.eval(
+            "(list (eqv? small -16) (eqv? large 1000) (eqv? true #t) (eqv? nil '()))".chars()
+        )?,
+        Some(Value::Pair(Box::new(list!(
+            Value::Boolean(true),
+            Value::Boolean(true),
+            Value::Boolean(true),
+            Value::Boolean(true)
+        ))))
+    );
+    Ok(())
+}
+
+#[test]
+fn read() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval("(define p (open-input-string \"(a #(1 \\\"s\\\")) foo\"))".chars())?;
+    assert_eq!(
+        interpreter.eval("(read p)".chars())?,
+        Some(Value::Pair(Box::new(list!(
+            Value::Symbol("a".into()),
+            Value::Vector(ValueReference::new_immutable(vec![
+                Value::Number(Number::Integer(1)),
+                Value::String("s".into())
+            ]))
+        ))))
+    );
+    assert_eq!(
+        interpreter.eval("(read p)".chars())?,
+        Some(Value::Symbol("foo".into()))
+    );
+    assert_eq!(interpreter.eval("(read p)".chars())?, Some(Value::Eof));
+    assert_eq!(interpreter.eval("(read p)".chars())?, Some(Value::Eof));
+
+    assert_eq!(
+        interpreter.eval("(read (open-input-string \"(1 . 2)\"))".chars())?,
+        Some(Value::Pair(Box::new(Pair::Some(
+            Value::Number(Number::Integer(1)),
+            Value::Number(Number::Integer(2))
+        ))))
+    );
+    assert!(interpreter
+        .eval("(read (open-input-string \"(1 2\"))".chars())
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn exit() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        interpreter.eval("(exit)".chars()),
+        Err(ErrorData::Exit {
+            code: 0,
+            emergency: false
+        }
+        .no_locate())
+    );
+    assert_eq!(
+        interpreter.eval("(exit 3)".chars()),
+        Err(ErrorData::Exit {
+            code: 3,
+            emergency: false
+        }
+        .no_locate())
+    );
+    assert_eq!(
+        interpreter.eval("(exit #f)".chars()),
+        Err(ErrorData::Exit {
+            code: 1,
+            emergency: false
+        }
+        .no_locate())
+    );
+
+    interpreter.eval("(define v (vector 0))".chars())?;
+    assert_eq!(
+        interpreter.eval(
+            "(dynamic-wind (lambda () 1) (lambda () (exit 2)) (lambda () (vector-set! v 0 1)))"
+                .chars()
+        ),
+        Err(ErrorData::Exit {
+            code: 2,
+            emergency: false
+        }
+        .no_locate())
+    );
+    assert_eq!(
+        interpreter.eval("(vector-ref v 0)".chars())?,
+        Some(Value::Number(Number::Integer(1)))
+    );
+
+    interpreter.eval("(define v (vector 0))".chars())?;
+    assert_eq!(
+        interpreter.eval(
+            "(dynamic-wind (lambda () 1) (lambda () (emergency-exit)) (lambda () (vector-set! v 0 1)))"
+                .chars()
+        ),
+        Err(ErrorData::Exit {
+            code: 0,
+            emergency: true
+        }
+        .no_locate())
+    );
+    assert_eq!(
+        interpreter.eval("(vector-ref v 0)".chars())?,
+        Some(Value::Number(Number::Integer(0)))
+    );
+    Ok(())
+}
+
+#[test]
+fn process_context() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        interpreter.eval("(command-line)".chars())?,
+        Some(Value::Pair(Box::new(list!())))
+    );
+    interpreter.set_command_line(vec!["script.scm".to_string(), "-v".to_string()]);
+    assert_eq!(
+        interpreter.eval("(command-line)".chars())?,
+        Some(Value::Pair(Box::new(list!(
+            Value::String("script.scm".into()),
+            Value::String("-v".into())
+        ))))
+    );
+
+    std::env::set_var("RUSCHM_PROCESS_CONTEXT_TEST", "value");
+    assert_eq!(
+        interpreter.eval("(get-environment-variable \"RUSCHM_PROCESS_CONTEXT_TEST\")".chars())?,
+        Some(Value::String("value".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(get-environment-variable \"RUSCHM_PROCESS_CONTEXT_UNSET\")".chars())?,
+        Some(Value::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.eval("(string? (cdr (car (get-environment-variables))))".chars())?,
+        Some(Value::Boolean(true))
+    );
+    Ok(())
+}
+
+#[test]
+fn features() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    assert_eq!(
+        interpreter.eval("(pair? (memq 'ruschm (features)))".chars())?,
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(memq 'my-host-app (features))".chars())?,
+        Some(Value::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.eval("(cond-expand (my-host-app 1) (else 2))".chars())?,
+        Some(Value::Number(Number::Integer(2)))
+    );
+
+    interpreter.add_feature("my-host-app");
+    assert_eq!(
+        interpreter.eval("(pair? (memq 'my-host-app (features)))".chars())?,
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(cond-expand (my-host-app 1) (else 2))".chars())?,
+        Some(Value::Number(Number::Integer(1)))
+    );
+    assert_eq!(
+        interpreter.eval(
+            "(cond-expand ((and r7rs (not foo) (or foo my-host-app)) 'yes) (else 'no))".chars()
+        )?,
+        Some(Value::Symbol("yes".into()))
+    );
+
+    // the body of the selected clause replaces the form, so that it can define
+    interpreter.eval(
+        "(cond-expand
+            ((not ruschm) (define kind 'other))
+            (ruschm (define kind 'ruschm) (define (twice x) (* 2 x))))"
+            .chars(),
+    )?;
+    assert_eq!(
+        interpreter.eval("(list kind (twice 2))".chars())?,
+        interpreter.eval("'(ruschm 4)".chars())?
+    );
+    assert_eq!(
+        interpreter.eval(
+            "(define (f) (cond-expand (my-host-app (define y 1) (define z 2))) (+ y z)) (f)"
+                .chars()
+        )?,
+        Some(Value::Number(Number::Integer(3)))
+    );
+    assert_eq!(
+        interpreter.eval("(cond-expand (no-such-feature 1))".chars())?,
+        None
+    );
+
+    // library requirements are met by the libraries which can be imported
+    let directory = std::env::temp_dir().join(format!("ruschm-features-{}", std::process::id()));
+    std::fs::create_dir_all(directory.join("local"))?;
+    std::fs::write(
+        directory.join("local").join("lib.sld"),
+        "(define-library (local lib) (export x) (begin (define x 1)))",
+    )?;
+    let requirements = "(list (cond-expand ((library (scheme base)) 'base) (else #f))
+                              (cond-expand ((library (local lib)) 'local) (else #f))
+                              (cond-expand ((library (no such)) 'missing) (else #f)))";
+    assert_eq!(
+        interpreter.eval(requirements.chars())?,
+        interpreter.eval("'(base #f #f)".chars())?
+    );
+    interpreter.add_library_path(&directory);
+    assert_eq!(
+        interpreter.eval(requirements.chars())?,
+        interpreter.eval("'(base local #f)".chars())?
+    );
+    std::fs::remove_dir_all(&directory)?;
+
+    assert!(Interpreter::<f32>::new_with_stdlib()
+        .eval("(import (only (scheme base) %feature?))".chars())
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn file_port() -> Result<(), SchemeError> {
+    let path = std::env::temp_dir().join("ruschm_file_port_test.scm");
+    let path = path.to_str().unwrap();
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval(
+        format!(
+            "(call-with-output-file \"{}\" (lambda (p) (display '(1 . foo) p) (newline p)))",
+            path
+        )
+        .chars(),
+    )?;
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "(1 . foo)\n".to_string()
+    );
+    interpreter.eval(format!("(define p (open-input-file \"{}\"))", path).chars())?;
+    assert_eq!(
+        interpreter.eval("(input-port? p)".chars())?,
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(read p)".chars())?,
+        Some(Value::Pair(Box::new(Pair::Some(
+            Value::Number(Number::Integer(1)),
+            Value::Symbol("foo".into())
+        ))))
+    );
+    assert_eq!(interpreter.eval("(read p)".chars())?, Some(Value::Eof));
+    interpreter.eval("(close-port p)".chars())?;
+    assert_eq!(
+        interpreter.eval("(read p)".chars()),
+        Err(ErrorData::Logic(LogicError::PortClosed(path.to_string())).no_locate())
+    );
+    std::fs::remove_file(path).unwrap();
+    assert!(matches!(
+        interpreter.eval(format!("(open-input-file \"{}\")", path).chars()),
+        Err(SchemeError {
+            data: ErrorData::Logic(LogicError::FileError(..)),
+            ..
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn read_char() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval("(define p (open-input-string \"ab\\r\\ncd\\nef\"))".chars())?;
+    assert_eq!(
+        interpreter.eval("(peek-char p)".chars())?,
+        Some(Value::Character('a'))
+    );
+    assert_eq!(
+        interpreter.eval("(read-char p)".chars())?,
+        Some(Value::Character('a'))
+    );
+    assert_eq!(
+        interpreter.eval("(read-line p)".chars())?,
+        Some(Value::String("b".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(read-string 2 p)".chars())?,
+        Some(Value::String("cd".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(read-line p)".chars())?,
+        Some(Value::String("".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(read-string 5 p)".chars())?,
+        Some(Value::String("ef".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(char-ready? p)".chars())?,
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(interpreter.eval("(peek-char p)".chars())?, Some(Value::Eof));
+    assert_eq!(interpreter.eval("(read-char p)".chars())?, Some(Value::Eof));
+    assert_eq!(interpreter.eval("(read-line p)".chars())?, Some(Value::Eof));
+    assert_eq!(
+        interpreter.eval("(read-string 1 p)".chars())?,
+        Some(Value::Eof)
+    );
+    assert_eq!(
+        interpreter.eval("(eof-object? (read-char p))".chars())?,
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(eof-object? (eof-object))".chars())?,
+        Some(Value::Boolean(true))
+    );
+    Ok(())
+}
+
+#[test]
+fn write_to_string_port() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval("(define p (open-output-string))".chars())?;
+    interpreter.eval("(display '(1 2) p)".chars())?;
+    interpreter.eval("(newline p)".chars())?;
+    interpreter.eval("(write-char #\\x p)".chars())?;
+    interpreter.eval("(write-string \"hello\" p)".chars())?;
+    interpreter.eval("(write-string \"abcdef\" p 2)".chars())?;
+    interpreter.eval("(write-string \"abcdef\" p 1 3)".chars())?;
+    assert_eq!(
+        interpreter.eval("(get-output-string p)".chars())?,
+        Some(Value::String("(1 2)\nxhellocdefbc".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(write-string \"abc\" p 2 4)".chars()),
+        Err(ErrorData::Logic(index_out_of_range("write-string", 4, 3)).no_locate())
+    );
+    Ok(())
+}
+
+#[test]
+fn load() -> Result<(), SchemeError> {
+    let directory = std::env::temp_dir();
+    let helper = directory.join("ruschm_load_helper.scm");
+    let broken = directory.join("ruschm_load_broken.scm");
+    let cyclic = directory.join("ruschm_load_cyclic.scm");
+    std::fs::write(&helper, "(define (square x) (* x x))\n(define two 2)\n").unwrap();
+    std::fs::write(&broken, "(define a 1)\n(define b (+ a 1)\n").unwrap();
+    std::fs::write(
+        &cyclic,
+        format!("(load \"{}\")\n", cyclic.to_str().unwrap()),
+    )
+    .unwrap();
+
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval(format!("(load \"{}\")", helper.to_str().unwrap()).chars())?;
+    assert_eq!(
+        interpreter.eval("(square two)".chars())?,
+        Some(Value::Number(Number::Integer(4)))
+    );
+    // errors raised by loaded procedures are located in the loaded file
+    let location = interpreter
+        .eval("(square 'a)".chars())
+        .unwrap_err()
+        .location
+        .unwrap();
+    assert_eq!(location.source.name(), helper.to_str().unwrap());
+    assert_eq!([location.line, location.column], [1, 22]);
+
+    let error = interpreter
+        .eval(format!("(load \"{}\")", broken.to_str().unwrap()).chars())
+        .unwrap_err();
+    let message = error.to_string();
+    assert!(message.contains(broken.to_str().unwrap()));
+    assert!(message.contains(":2:"));
+
+    let error = interpreter
+        .eval(format!("(load \"{}\")", cyclic.to_str().unwrap()).chars())
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .ends_with(&LogicError::LoadCyclic(cyclic.to_str().unwrap().to_string()).to_string()));
+
+    for file in [helper, broken, cyclic].iter() {
+        std::fs::remove_file(file).unwrap();
+    }
+
+    // io errors are kept as the source
+    let missing = directory.join("ruschm_load_missing.scm");
+    let error = interpreter
+        .eval(format!("(load \"{}\")", missing.to_str().unwrap()).chars())
+        .unwrap_err();
+    let source = error.source().unwrap().downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::NotFound);
+    let error = interpreter.eval_file(&missing).unwrap_err();
+    let source = error.source().unwrap().source().unwrap();
+    assert_eq!(
+        source.downcast_ref::<io::Error>().unwrap().kind(),
+        io::ErrorKind::NotFound
+    );
+    Ok(())
+}
+
+#[test]
+fn parameterize() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval("(define radix (make-parameter 10 (lambda (x) (* x 2))))".chars())?;
+    assert_eq!(
+        interpreter.eval("(radix)".chars())?,
+        Some(Value::Number(Number::Integer(20)))
+    );
+    assert_eq!(
+        interpreter.eval("(parameterize ((radix 2)) (radix))".chars())?,
+        Some(Value::Number(Number::Integer(4)))
+    );
+    assert_eq!(
+        interpreter.eval("(radix)".chars())?,
+        Some(Value::Number(Number::Integer(20)))
+    );
+    assert_eq!(
+        interpreter.eval(
+            "(parameterize ((radix 1))
+                (list (radix) (parameterize ((radix 3)) (radix)) (radix)))"
+                .chars()
+        )?,
+        interpreter.eval("'(2 6 2)".chars())?
+    );
+    // parameters are restored when the body raises an error
+    assert_eq!(
+        interpreter.eval(
+            "(guard (e (#t (radix)))
+                (parameterize ((radix 1))
+                    (parameterize ((radix 3)) (car '()))))"
+                .chars()
+        )?,
+        Some(Value::Number(Number::Integer(20)))
+    );
+    assert!(Interpreter::<f32>::new_with_stdlib()
+        .eval("(import (only (scheme base) %parameterize))".chars())
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn current_output_port() -> Result<(), SchemeError> {
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval("(define outer (open-output-string))".chars())?;
+    interpreter.eval("(define inner (open-output-string))".chars())?;
+    interpreter.eval(
+        "
+        (parameterize ((current-output-port outer))
+            (display \"a\")
+            (parameterize ((current-output-port inner))
+                (display \"b\"))
+            (display \"c\"))"
+            .chars(),
+    )?;
+    assert_eq!(
+        interpreter.eval("(get-output-string outer)".chars())?,
+        Some(Value::String("ac".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(get-output-string inner)".chars())?,
+        Some(Value::String("b".into()))
+    );
+
+    interpreter.eval(
+        "
+        (parameterize ((current-output-port outer))
+            (guard (e (#t (display \"e\")))
+                (parameterize ((current-output-port inner))
+                    (display \"d\")
+                    (car 1))))"
+            .chars(),
+    )?;
+    assert_eq!(
+        interpreter.eval("(get-output-string outer)".chars())?,
+        Some(Value::String("ace".into()))
+    );
+    assert_eq!(
+        interpreter.eval("(output-port? (current-output-port))".chars())?,
+        Some(Value::Boolean(true))
+    );
+    assert_eq!(
+        interpreter.eval("(get-output-string inner)".chars())?,
+        Some(Value::String("bd".into()))
+    );
+    Ok(())
+}
+
+#[test]
+fn flush_output_port() -> Result<(), SchemeError> {
+    let flushed = std::env::temp_dir().join("ruschm_flush_test.txt");
+    let dropped = std::env::temp_dir().join("ruschm_drop_test.txt");
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval(
+        format!(
+            "(define p (open-output-file \"{}\"))",
+            flushed.to_str().unwrap()
+        )
+        .chars(),
+    )?;
+    interpreter.eval("(display \"buffered\" p)".chars())?;
+    interpreter.eval("(flush-output-port p)".chars())?;
+    assert_eq!(std::fs::read_to_string(&flushed).unwrap(), "buffered");
+    assert_eq!(
+        interpreter.eval("(output-port-open? p)".chars())?,
+        Some(Value::Boolean(true))
+    );
+    interpreter.eval("(display \" closed\" p)".chars())?;
+    interpreter.eval("(close-port p)".chars())?;
+    assert_eq!(
+        interpreter.eval("(output-port-open? p)".chars())?,
+        Some(Value::Boolean(false))
+    );
+    assert_eq!(
+        std::fs::read_to_string(&flushed).unwrap(),
+        "buffered closed"
+    );
+
+    interpreter.eval(
+        format!(
+            "((lambda (p) (display \"dropped\" p)) (open-output-file \"{}\"))",
+            dropped.to_str().unwrap()
+        )
+        .chars(),
+    )?;
+    assert_eq!(std::fs::read_to_string(&dropped).unwrap(), "dropped");
+
+    std::fs::remove_file(flushed).unwrap();
+    std::fs::remove_file(dropped).unwrap();
+    Ok(())
+}
+
+#[test]
+fn with_file() -> Result<(), SchemeError> {
+    let outer = std::env::temp_dir().join("ruschm_with_outer.txt");
+    let inner = std::env::temp_dir().join("ruschm_with_inner.txt");
+    let mut interpreter = Interpreter::<f32>::new_with_stdlib();
+    interpreter.eval(
+        format!(
+            "(with-output-to-file \"{}\"
+                (lambda ()
+                    (display \"a\")
+                    (with-output-to-file \"{}\" (lambda () (display \"b\")))
+                    (display \"c\")))",
+            outer.to_str().unwrap(),
+            inner.to_str().unwrap()
+        )
+        .chars(),
+    )?;
+    assert_eq!(std::fs::read_to_string(&outer).unwrap(), "ac");
+    assert_eq!(std::fs::read_to_string(&inner).unwrap(), "b");
+    assert_eq!(
+        interpreter.eval(
+            format!(
+                "(with-input-from-file \"{}\" (lambda () (read-char) (read-char)))",
+                outer.to_str().unwrap()
+            )
+            .chars()
+        )?,
+        Some(Value::Character('c'))
+    );
+
+    interpreter.eval("(define p (open-input-string \"x\"))".chars())?;
+    assert!(interpreter
+        .eval("(call-with-port p (lambda (p) (car 1)))".chars())
+        .is_err());
+    assert_eq!(
+        interpreter.eval("(input-port-open? p)".chars())?,
+        Some(Value::Boolean(false))
+    );
+    assert_eq!(
+        interpreter.eval("(call-with-port (open-input-string \"42\") read)".chars())?,
+        Some(Value::Number(Number::Integer(42)))
+    );
+
+    std::fs::remove_file(outer).unwrap();
+    std::fs::remove_file(inner).unwrap();
     Ok(())
 }
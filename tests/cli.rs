@@ -0,0 +1,447 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn ruschm(arguments: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ruschm"))
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn version() {
+    let output = ruschm(&["--version"], "");
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        format!("ruschm {}\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn expressions() {
+    let output = ruschm(
+        &[
+            "-e",
+            "(define x 2)",
+            "-e",
+            "(display (+ x 1))",
+            "-e",
+            "(list x \"a\")",
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "3(2 \"a\")\n");
+    let output = ruschm(&["-e", "(command-line)", "--", "a", "--version"], "");
+    assert_eq!(stdout(&output), "(\"-e\" \"a\" \"--version\")\n");
+    let output = ruschm(&["-e", "(car 1)"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("car: expect a pair, got 1"));
+    assert_eq!(ruschm(&["-e", "(exit 3)"], "").status.code(), Some(3));
+}
+
+#[test]
+fn scripts() {
+    let program = "(import (scheme base) (scheme write) (scheme process-context))
+                   (display (command-line))";
+    let output = ruschm(&["-", "a", "-e"], program);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "(- a -e)");
+    let output = ruschm(&["--", "-"], program);
+    assert_eq!(stdout(&output), "(-)");
+    let output = ruschm(&["-"], "(import (scheme base))\n(car 1)");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("<stdin>:2:5"));
+    // like a program piped without `-`, it does not need to import (scheme base)
+    let output = ruschm(&["-"], "(display 1)");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "1");
+}
+
+#[test]
+fn check() {
+    let path = std::env::temp_dir().join("ruschm-check.scm");
+    let file = path.to_str().unwrap();
+    // never run, or it would not terminate
+    std::fs::write(
+        &path,
+        "(import (scheme base))\n(define (loop) (loop))\n(loop)\n(define (add a b) (+ a b))",
+    )
+    .unwrap();
+    let output = ruschm(&["--check", file], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "");
+    std::fs::write(&path, "(define (add a b) a b)\n(add 1 2 3)").unwrap();
+    let output = ruschm(&["--check", file], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "{}:2:2: error: procedure add expects 2 arguments, got 3\n",
+            file
+        )
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn trace_calls() {
+    let output = ruschm(
+        &[
+            "--trace-calls",
+            "-e",
+            "(define (f x) (+ x 1))",
+            "-e",
+            "(* 2 (f 3))",
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "8\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "-> (f 3) at <command line>:1:8
+=> (+ 3 1) at <command line>:1:17
+<- 4
+-> (* 2 4) at <command line>:1:3
+<- 8
+"
+    );
+    // at the repl too
+    let output = ruschm(&["--interactive", "--trace-calls"], "(* 2 3)\n");
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("6\n"));
+    let traced = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        traced.contains("-> (* 2 3) at <repl-1>:1:3\n<- 6\n"),
+        "{}",
+        traced
+    );
+}
+
+#[test]
+fn char_ready() {
+    let output = ruschm(
+        &[
+            "-e",
+            "(display (char-ready?)) (write-char (read-char)) (display (read-line))",
+        ],
+        "ab\n",
+    );
+    assert_eq!(stdout(&output), "#tab");
+    // a pipe with no input yet, whose writer is still open
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ruschm"))
+        .args(&["-e", "(display (char-ready?))"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdin = child.stdin.take().unwrap();
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+    assert_eq!(stdout(&output), "#f");
+    // lines read ahead of the program are buffered by the process, not left in the pipe
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ruschm"))
+        .args(&["-e", "(read-line) (display (char-ready?))"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"a\nb\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+    assert_eq!(stdout(&output), "#t");
+    // the repl and the program read the same piped stdin
+    let output = ruschm(
+        &["--interactive"],
+        "(read-line)\nline for the program\n(+ 1 2)\n",
+    );
+    assert!(stdout(&output).contains("\"line for the program\""));
+    assert!(stdout(&output).contains('3'));
+}
+
+#[test]
+fn piped_program() {
+    let output = ruschm(&[], "(display (+ 1 2))\n(newline)\n(define x 4)\nx\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "3\n");
+    assert!(output.stderr.is_empty());
+    let output = ruschm(&["--no-interactive"], "(display 1)\n(car 1)\n(display 2)");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "1");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("<stdin>:2:5"));
+    assert_eq!(ruschm(&[], "(exit 4)").status.code(), Some(4));
+    // the repl echoes values and greets even when forced on a pipe
+    let output = ruschm(&["--interactive"], "(define (f) 3)\n(f)\n");
+    assert!(stdout(&output).starts_with("Ruschm Version"));
+    assert!(stdout(&output).contains("3\n"));
+    // definitions are only confirmed on terminals
+    assert!(!stdout(&output).contains("defined"));
+}
+
+#[test]
+fn debug_breakpoint() {
+    let program = "(define (f x)\n  (debug)\n  (* x 2))\n(display (f 3))\n";
+    let output = ruschm(
+        &["--interactive"],
+        &format!("{}x\n(set! x 5)\n,continue\n", program),
+    );
+    assert!(output.status.success());
+    let printed = stdout(&output);
+    assert!(
+        printed.contains("debug: paused at <repl-1>:2:9\n"),
+        "{}",
+        printed
+    );
+    assert!(printed.contains("\n3\n"));
+    assert!(printed.contains("10"));
+    let output = ruschm(&["--interactive"], &format!("{},abort\n", program));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("evaluation aborted in the debugger"));
+    // no session without the repl
+    let output = ruschm(&[], program);
+    assert_eq!(stdout(&output), "6");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("debug: no debugger"));
+}
+
+#[test]
+fn format_files() {
+    let directory = std::env::temp_dir().join(format!("ruschm-fmt-{}", std::process::id()));
+    let nested = directory.join("lib");
+    std::fs::create_dir_all(&nested).unwrap();
+    let unformatted = "(define  (f x)\n(* x   2))\n";
+    let formatted = "(define (f x)\n  (* x 2))\n";
+    let (program, library, broken, other) = (
+        directory.join("a.scm"),
+        nested.join("b.sld"),
+        directory.join("c.scm"),
+        directory.join("notes.txt"),
+    );
+    for file in &[&program, &library, &other] {
+        std::fs::write(file, unformatted).unwrap();
+    }
+    std::fs::write(&broken, "(define (g x)\n  (car x)").unwrap();
+    let read = |file: &std::path::Path| std::fs::read_to_string(file).unwrap();
+    let name = |file: &std::path::Path| file.to_str().unwrap().to_string();
+
+    // check lists the files which would change, touching none
+    let output = ruschm(&["fmt", "--check", &name(&program), &name(&library)], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        format!("{}\n{}\n", name(&program), name(&library))
+    );
+    assert_eq!(read(&program), unformatted);
+
+    // directories are searched for .scm and .sld files, files with syntax errors are reported
+    // and left untouched while the others are rewritten
+    let output = ruschm(&["fmt", &name(&directory)], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&name(&broken)));
+    assert_eq!(read(&program), formatted);
+    assert_eq!(read(&library), formatted);
+    assert_eq!(read(&broken), "(define (g x)\n  (car x)");
+    assert_eq!(read(&other), unformatted);
+
+    let output = ruschm(&["--format", "--check", &name(&program)], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "");
+
+    // - formats stdin to stdout
+    let output = ruschm(&["fmt", "-"], unformatted);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), formatted);
+    assert_eq!(
+        ruschm(&["fmt", "--check", "-"], unformatted).status.code(),
+        Some(1)
+    );
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn ast() {
+    let path = std::env::temp_dir().join("ruschm-ast.scm");
+    let file = path.to_str().unwrap();
+    std::fs::write(&path, "(define (sq x) (* x x))\n(display (sq 3))").unwrap();
+    let output = ruschm(&["--ast", file], "");
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "Definition sq @1:2
+  Procedure sq @1:12
+    Parameter x @1:14
+    ProcedureCall @1:17
+      Symbol * @1:18
+      Symbol x @1:20
+      Symbol x @1:22
+ProcedureCall @2:2
+  Symbol display @2:9
+  ProcedureCall @2:11
+    Symbol sq @2:13
+    Primitive 3 @2:15
+"
+    );
+    let output = ruschm(&["--ast=json", file], "");
+    if cfg!(feature = "serde") {
+        assert!(output.status.success());
+        assert!(stdout(&output).starts_with("[\n  {\n    \"Definition\""));
+    } else {
+        assert_eq!(output.status.code(), Some(64));
+    }
+    std::fs::write(&path, "(define (sq x)").unwrap();
+    let output = ruschm(&["--ast", file], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stdout(&output), "");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn exit_status() {
+    let path = std::env::temp_dir().join("ruschm-exit-status.scm");
+    let file = path.to_str().unwrap();
+    let status = |program: &str| {
+        std::fs::write(&path, program).unwrap();
+        ruschm(&[file], "").status.code()
+    };
+    assert_eq!(status("(import (scheme base))\n(+ 1 2)"), Some(0));
+    assert_eq!(
+        status("(import (scheme base) (scheme process-context))\n(exit 3)"),
+        Some(3)
+    );
+    assert_eq!(
+        status("(import (scheme base) (scheme process-context))\n(exit #f)"),
+        Some(1)
+    );
+    assert_eq!(status("(import (scheme base))\n(define (f x)"), Some(65));
+    assert_eq!(status("(import (scheme base))\n(if)"), Some(65));
+    assert_eq!(status("(import (scheme base))\n(car '())"), Some(70));
+    assert_eq!(
+        status("(import (scheme base))\n(undefined-variable)"),
+        Some(70)
+    );
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(ruschm(&[file], "").status.code(), Some(66));
+    // the output written to files before (exit) at the repl is flushed
+    let output = ruschm(
+        &["--interactive"],
+        &format!(
+            "(define port (open-output-file \"{}\"))\n(write 'written port) (exit 3)\n",
+            file
+        ),
+    );
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "written");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(ruschm(&["-e"], "").status.code(), Some(64));
+    assert_eq!(ruschm(&["-e", "(car"], "").status.code(), Some(65));
+    // the repl only stops with the code given to exit
+    assert_eq!(
+        ruschm(&["--interactive"], "(car 1)\n(exit 5)\n")
+            .status
+            .code(),
+        Some(5)
+    );
+    assert_eq!(
+        ruschm(&["--interactive"], "(car 1)\n").status.code(),
+        Some(0)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupted_status() {
+    let child = Command::new(env!("CARGO_BIN_EXE_ruschm"))
+        .args(&["-e", "(define (loop) (loop)) (display 1) (loop)"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    Command::new("kill")
+        .args(&["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(130));
+    // output written before the interruption is flushed
+    assert_eq!(stdout(&output), "1");
+}
+
+#[test]
+fn piped_repl_history() {
+    let path = std::env::temp_dir().join(format!("ruschm-history-{}", std::process::id()));
+    let file = path.to_str().unwrap();
+    let output = ruschm(&["--interactive", "--history-file", file], "(+ 1 2)\n");
+    assert!(stdout(&output).contains("3\n"));
+    assert!(!path.exists());
+    assert_eq!(
+        ruschm(&["--history-size", "many"], "").status.code(),
+        Some(64)
+    );
+}
+
+#[test]
+fn shebang() {
+    let directory = std::env::temp_dir();
+    let (script, loaded) = (
+        directory.join("ruschm-shebang.scm"),
+        directory.join("ruschm-shebang-loaded.scm"),
+    );
+    std::fs::write(
+        &script,
+        "#!/usr/bin/env ruschm\n(import (scheme base) (scheme write))\n(car 1)\n",
+    )
+    .unwrap();
+    let output = ruschm(&[script.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ruschm-shebang.scm:3:5"));
+    // and programs read from stdin, with or without `-`
+    let program = "#!/usr/bin/env ruschm\n(display 1)\n(car 1)\n";
+    for arguments in [&[][..], &["-"][..]].iter() {
+        let output = ruschm(arguments, program);
+        assert_eq!(output.status.code(), Some(70));
+        assert_eq!(stdout(&output), "1");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("<stdin>:3:5"));
+    }
+
+    // load skips it too
+    std::fs::write(&loaded, "#!/usr/bin/env ruschm\n(define loaded 2)\n").unwrap();
+    let output = ruschm(
+        &[
+            "-e",
+            &format!("(load {:?}) loaded", loaded.to_str().unwrap()),
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "2\n");
+
+    // only on the first line
+    std::fs::write(
+        &script,
+        "(import (scheme base))\n#!/usr/bin/env ruschm\n(+ 1 2)\n",
+    )
+    .unwrap();
+    let output = ruschm(&[script.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ruschm-shebang.scm:2:"));
+    std::fs::remove_file(script).unwrap();
+    std::fs::remove_file(loaded).unwrap();
+}
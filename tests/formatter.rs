@@ -0,0 +1,58 @@
+use ruschm::{
+    error::SchemeError,
+    formatter::format_source_with_width,
+    parser::{Datum, Lexer, Parser},
+};
+use std::{fs, path::PathBuf};
+
+fn samples() -> Vec<PathBuf> {
+    let mut samples = vec![];
+    for directory in &[
+        "examples",
+        "tests/test_macros",
+        "src/interpreter/library/include/scheme",
+    ] {
+        for entry in fs::read_dir(directory).unwrap() {
+            samples.push(entry.unwrap().path());
+        }
+    }
+    samples.sort();
+    samples
+}
+
+fn datums(source: &str) -> Result<Vec<Datum>, SchemeError> {
+    let mut parser = Parser::from_lexer(Lexer::from_char_stream(source.chars()));
+    let mut datums = vec![];
+    while let Some(datum) = parser.parse_datum()? {
+        datums.push(datum);
+    }
+    Ok(datums)
+}
+
+// formatting keeps the meaning of programs and a second formatting changes nothing
+#[test]
+fn idempotence() -> Result<(), SchemeError> {
+    let samples = samples();
+    assert!(!samples.is_empty());
+    for path in samples {
+        let source = fs::read_to_string(&path).unwrap();
+        for width in &[80, 40, 10] {
+            let formatted = format_source_with_width(&source, *width)?;
+            assert_eq!(
+                datums(&formatted)?,
+                datums(&source)?,
+                "{} formatted with width {}",
+                path.display(),
+                width
+            );
+            assert_eq!(
+                format_source_with_width(&formatted, *width)?,
+                formatted,
+                "{} formatted twice with width {}",
+                path.display(),
+                width
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use ruschm::fuzz::{eval_str_bounded, parse_str, run, DEFAULT_FUEL};
+use std::fs;
+
+// inputs which used to panic or not to terminate, each of them fails to parse or to evaluate
+#[test]
+fn corpus() {
+    let mut entries = fs::read_dir("tests/fuzz_corpus")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert!(!entries.is_empty());
+    for path in entries {
+        let source = fs::read_to_string(&path).unwrap();
+        assert!(
+            parse_str(&source).is_err() || eval_str_bounded(&source, DEFAULT_FUEL).is_err(),
+            "{} is accepted",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn deep_nesting() {
+    let source = format!("{}{}", "(".repeat(100_000), ")".repeat(100_000));
+    assert!(parse_str(&source).is_err());
+    run(source.as_bytes());
+    let source = format!("{}1{}", "#(".repeat(100_000), ")".repeat(100_000));
+    assert!(eval_str_bounded(&source, DEFAULT_FUEL).is_err());
+}
+
+#[test]
+fn side_effects() {
+    assert!(eval_str_bounded("(import (scheme file)) (open-output-file \"out\")", 10).is_err());
+    assert_eq!(
+        eval_str_bounded("(display \"discarded\") (read-line)", 10)
+            .unwrap()
+            .map(|value| value.to_string()),
+        Some("#<eof>".to_string())
+    );
+}
@@ -0,0 +1 @@
+(define-syntax f (syntax-rules () ((_ . a) a)))
@@ -0,0 +1 @@
+1/99999999999
@@ -0,0 +1 @@
+"an unterminated string
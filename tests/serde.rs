@@ -0,0 +1,69 @@
+#![cfg(feature = "serde")]
+use ruschm::{
+    error::{Located, Location, SourceId},
+    fuzz::parse_str,
+    parser::{Primitive, Statement},
+};
+
+const PROGRAM: &str = r#"
+(import (scheme base) (only (scheme write) display))
+(define-syntax swap!
+  (syntax-rules ()
+    ((swap! a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+(define (fold f init . lists)
+  (define (go acc l)
+    (if (null? l) acc (go (f (car l) acc) (cdr l))))
+  (go init (car lists)))
+(define-library (lib numbers)
+  (export half (rename half demi))
+  (import (scheme base))
+  (begin (define (half x) (/ x 2))))
+(display (cond ((fold + 0 '(1 2 3)) => (lambda (x) (* x 1.5)))
+               (else '#(1/2 #\a "s" (a . b)))))
+"#;
+
+fn locations(statements: &[Statement]) -> Vec<Option<Location>> {
+    statements.iter().map(Statement::location).collect()
+}
+
+#[test]
+fn round_trip() {
+    let statements = parse_str(PROGRAM).unwrap();
+    let json = serde_json::to_string(&statements).unwrap();
+    let deserialized: Vec<Statement> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, statements);
+    // equality of located nodes ignores their locations
+    assert_eq!(locations(&deserialized), locations(&statements));
+    assert_eq!(serde_json::to_string(&deserialized).unwrap(), json);
+}
+
+#[test]
+fn layout() {
+    let located = Located {
+        data: Primitive::Rational(1, 2),
+        location: Some(Location::new(SourceId::new("a.scm"), [3, 4]).with_length(3)),
+    };
+    assert_eq!(
+        serde_json::to_string(&located).unwrap(),
+        r#"{"data":{"Rational":[1,2]},"location":{"source":"a.scm","line":3,"column":4,"length":3}}"#
+    );
+    // the length of locations is optional
+    assert_eq!(
+        serde_json::from_str::<Location>(r#"{"source":"a.scm","line":3,"column":4}"#).unwrap(),
+        Location::new(SourceId::new("a.scm"), [3, 4])
+    );
+    let statement = parse_str("(f 'x)").unwrap().pop().unwrap();
+    assert_eq!(
+        serde_json::to_value(&statement).unwrap()["Expression"]["data"]["ProcedureCall"][1][0]
+            ["data"],
+        serde_json::json!({"Quote": {"data": {"Symbol": "x"}, "location": {"source": "<string>", "line": 1, "column": 6, "length": 1}}})
+    );
+}
+
+#[test]
+fn invariants() {
+    assert!(serde_json::from_str::<Primitive>(r#"{"Rational":[1,2]}"#).is_ok());
+    assert!(serde_json::from_str::<Primitive>(r#"{"Rational":[1,0]}"#).is_err());
+    assert!(serde_json::from_str::<Primitive>(r#"{"Real":"1.5"}"#).is_ok());
+    assert!(serde_json::from_str::<Primitive>(r#"{"Real":"one"}"#).is_err());
+}
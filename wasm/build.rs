@@ -0,0 +1,7 @@
+// the stack of a wasm module does not grow and is 1MB by default, which evaluating programs
+// nested as deep as `MAX_DEPTH` may overflow
+fn main() {
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("wasm32") {
+        println!("cargo:rustc-link-arg=-zstack-size=8388608");
+    }
+}
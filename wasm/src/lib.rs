@@ -0,0 +1,115 @@
+//! JavaScript bindings of the interpreter, built by `wasm-pack build --target web`:
+//!
+//! ```js
+//! import init, { Interpreter } from "./pkg/ruschm_wasm.js";
+//! await init();
+//! const it = new Interpreter();
+//! it.onOutput((text) => console.log(text));
+//! it.evalString("(display (+ 1 2))"); // "", and "3" is passed to the callback
+//! it.evalString("(+ 1 2)"); // "3"
+//! ```
+//!
+//! Programs cannot freeze the page: each call of `evalString` is bounded by the fuel of the
+//! interpreter, and nesting by `MAX_DEPTH`, as the stack of a module does not grow. The stack is
+//! 8MB instead of the default 1MB of wasm32, see `build.rs`.
+use ruschm::{interpreter::DefaultInterpreter, values::Value};
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+use wasm_bindgen::prelude::*;
+
+/// applications of procedures allowed to each call of `evalString`, see `setFuel`
+pub const DEFAULT_FUEL: u32 = 10_000_000;
+
+/// nesting of expressions allowed to programs, a level of evaluation can take tens of KB of the
+/// stack in debug builds
+pub const MAX_DEPTH: usize = 100;
+
+// the output of scheme programs, passed to the callback of onOutput when flushed
+#[derive(Default)]
+struct Output {
+    buffer: Vec<u8>,
+    callback: Option<js_sys::Function>,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.buffer).to_string();
+        self.buffer.clear();
+        match &self.callback {
+            Some(callback) => callback
+                .call1(&JsValue::NULL, &JsValue::from_str(&text))
+                .map(|_| ())
+                .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("{:?}", error))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// an interpreter with the standard libraries imported, definitions persist between calls
+#[wasm_bindgen]
+pub struct Interpreter {
+    interpreter: DefaultInterpreter<'static>,
+    output: Rc<RefCell<Output>>,
+    fuel: u32,
+}
+
+#[wasm_bindgen]
+impl Interpreter {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let mut interpreter = DefaultInterpreter::new_with_stdlib();
+        let output = Rc::new(RefCell::new(Output::default()));
+        interpreter.set_input(io::empty());
+        interpreter.set_output(output.clone());
+        interpreter.set_max_depth(MAX_DEPTH);
+        Self {
+            interpreter,
+            output,
+            fuel: DEFAULT_FUEL,
+        }
+    }
+
+    /// pass the output of programs to `callback`, a function taking a string, instead of
+    /// discarding it
+    #[wasm_bindgen(js_name = onOutput)]
+    pub fn on_output(&mut self, callback: js_sys::Function) {
+        self.output.borrow_mut().callback = Some(callback);
+    }
+
+    /// the applications of procedures allowed to each call of `evalString`
+    #[wasm_bindgen(js_name = setFuel)]
+    pub fn set_fuel(&mut self, fuel: u32) {
+        self.fuel = fuel;
+    }
+
+    /// evaluate a program, return the value of its last statement as `write` prints it, an empty
+    /// string if it has no value, or the error rendered with the line it is located at
+    #[wasm_bindgen(js_name = evalString)]
+    pub fn eval_string(&mut self, source: &str) -> String {
+        self.interpreter.set_fuel(self.fuel.into());
+        let result = self.interpreter.eval_str(source);
+        let flushed = self.interpreter.flush_output_ports();
+        match result.and_then(|value| flushed.map(|_| value)) {
+            Ok(None) | Ok(Some(Value::Void)) => String::new(),
+            Ok(Some(value)) => value.written().to_string(),
+            Err(error) => error.render(source),
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,77 @@
+//! run by `wasm-pack test --headless --firefox` (or `--chrome`, `--node`)
+use ruschm_wasm::Interpreter;
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn arithmetic() {
+    let mut it = Interpreter::new();
+    assert_eq!(it.eval_string("(+ 1 (* 2 3))"), "7");
+    assert_eq!(it.eval_string("(/ 1 2)"), "1/2");
+    assert_eq!(it.eval_string("(list 1 \"a\" #\\b)"), "(1 \"a\" #\\b)");
+}
+
+#[wasm_bindgen_test]
+fn definitions() {
+    let mut it = Interpreter::new();
+    assert_eq!(it.eval_string("(define (square x) (* x x))"), "");
+    assert_eq!(it.eval_string("(square 12)"), "144");
+}
+
+#[wasm_bindgen_test]
+fn output() {
+    let mut it = Interpreter::new();
+    let written = Rc::new(RefCell::new(String::new()));
+    let sink = written.clone();
+    let callback = Closure::wrap(
+        Box::new(move |text: String| sink.borrow_mut().push_str(&text)) as Box<dyn FnMut(String)>,
+    );
+    it.on_output(
+        callback
+            .as_ref()
+            .unchecked_ref::<js_sys::Function>()
+            .clone(),
+    );
+    assert_eq!(it.eval_string("(display \"hello\") (newline)"), "");
+    assert_eq!(*written.borrow(), "hello\n");
+}
+
+#[wasm_bindgen_test]
+fn errors() {
+    let mut it = Interpreter::new();
+    assert!(it
+        .eval_string("(car 1)")
+        .contains("car: expect a pair, got 1"));
+    it.set_fuel(1_000);
+    assert!(it
+        .eval_string("(define (loop) (loop)) (loop)")
+        .contains("evaluation budget exceeded"));
+    // the interpreter is usable after errors
+    assert_eq!(it.eval_string("(+ 1 2)"), "3");
+}
+
+#[wasm_bindgen_test]
+fn deep_nesting() {
+    let mut it = Interpreter::new();
+    let source = format!("{}{}", "(".repeat(100_000), ")".repeat(100_000));
+    assert!(it.eval_string(&source).contains("nesting deeper than"));
+    let source = format!("'{}1{}", "#(".repeat(100_000), ")".repeat(100_000));
+    assert!(it.eval_string(&source).contains("nesting deeper than"));
+    let source = format!("{}0{}", "(+ 1 ".repeat(100_000), ")".repeat(100_000));
+    assert!(it.eval_string(&source).contains("nesting deeper than"));
+    assert!(it
+        .eval_string("(define (count n) (if (= n 0) 0 (+ 1 (count (- n 1))))) (count 100000)")
+        .contains("recursion deeper than 100 levels"));
+    // data nested within the limits are evaluated
+    assert_eq!(it.eval_string("(count 50)"), "50");
+    assert_eq!(
+        it.eval_string(&format!(
+            "(length '{}1{})",
+            "(".repeat(150),
+            ")".repeat(150)
+        )),
+        "1"
+    );
+    assert_eq!(it.eval_string("(+ 1 2)"), "3");
+}